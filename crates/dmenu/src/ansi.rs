@@ -0,0 +1,124 @@
+//! Terminal escape sequences in items, for `--ansi`: the text without them,
+//! and how the SGR sequences among them style each stretch of it, as fzf
+//! reads them.
+
+use crate::drw::{Clr, Style, Styles};
+
+/// The colours numbered 0 to 15, as xterm draws them.
+const PALETTE: [Clr; 16] = [
+	0xff000000, 0xffcd0000, 0xff00cd00, 0xffcdcd00, 0xff0000ee, 0xffcd00cd,
+	0xff00cdcd, 0xffe5e5e5, 0xff7f7f7f, 0xffff0000, 0xff00ff00, 0xffffff00,
+	0xff5c5cff, 0xffff00ff, 0xff00ffff, 0xffffffff,
+];
+
+/// The text of `input` without its escape sequences, and the style of each
+/// stretch of it which is styled.
+pub fn parse(input: &str) -> (String, Styles) {
+	let mut text = String::with_capacity(input.len());
+	let mut styles = Styles::new();
+	let mut style = Style::default();
+	let mut rest = input;
+	while !rest.is_empty() {
+		if let Some(seq) = rest.strip_prefix('\x1b') {
+			let (params, end, after) = sequence(seq);
+			if end == Some('m') {
+				sgr(params, &mut style);
+			}
+			rest = after;
+			continue;
+		}
+		let end = rest.find('\x1b').unwrap_or(rest.len());
+		let start = text.len();
+		text.push_str(&rest[..end]);
+		rest = &rest[end..];
+		match styles.last_mut() {
+			Some((range, last)) if *last == style && range.end == start => {
+				range.end = text.len()
+			}
+			_ if style != Style::default() => {
+				styles.push((start..text.len(), style))
+			}
+			_ => {}
+		}
+	}
+	(text, styles)
+}
+
+/// The parameters and final character of the sequence `seq` begins with,
+/// following an escape, and what follows it. Only control sequences, begun
+/// with `[`, have either; any other escape is taken with the character
+/// after it.
+fn sequence(seq: &str) -> (&str, Option<char>, &str) {
+	let Some(body) = seq.strip_prefix('[') else {
+		let skip = seq.chars().next().map_or(0, char::len_utf8);
+		return ("", None, &seq[skip..]);
+	};
+	match body.find(|c| ('@'..='~').contains(&c)) {
+		Some(end) => {
+			let last = body[end..].chars().next();
+			(&body[..end], last, &body[end + 1..])
+		}
+		// A sequence cut short takes the rest of the text with it.
+		None => ("", None, ""),
+	}
+}
+
+/// Changes `style` as the parameters of an SGR sequence, as in `1;31`, do.
+fn sgr(params: &str, style: &mut Style) {
+	let mut codes = params.split([';', ':']).map(|p| p.parse().unwrap_or(0));
+	while let Some(code) = codes.next() {
+		match code {
+			0 => *style = Style::default(),
+			1 => style.bold = true,
+			2 => style.alpha = Some(0x80),
+			3 => style.italic = true,
+			4 => style.underline = true,
+			22 => (style.bold, style.alpha) = (false, None),
+			23 => style.italic = false,
+			24 => style.underline = false,
+			30..=37 => style.fg = Some(PALETTE[code as usize - 30]),
+			38 => style.fg = extended(&mut codes).or(style.fg),
+			39 => style.fg = None,
+			40..=47 => style.bg = Some(PALETTE[code as usize - 40]),
+			48 => style.bg = extended(&mut codes).or(style.bg),
+			49 => style.bg = None,
+			90..=97 => style.fg = Some(PALETTE[code as usize - 82]),
+			100..=107 => style.bg = Some(PALETTE[code as usize - 92]),
+			_ => {}
+		}
+	}
+}
+
+/// The colour of a 256-colour or true colour code, as in `5;208` or
+/// `2;255;128;0`, which follows a 38 or 48.
+fn extended(codes: &mut impl Iterator<Item = u32>) -> Option<Clr> {
+	match codes.next()? {
+		5 => Some(indexed(codes.next()?.min(255))),
+		2 => {
+			let (r, g, b) = (codes.next()?, codes.next()?, codes.next()?);
+			Some(0xff000000 | r.min(255) << 16 | g.min(255) << 8 | b.min(255))
+		}
+		_ => None,
+	}
+}
+
+/// The colour numbered `n` of the 256 xterm has: the 16 of the palette, a
+/// 6x6x6 cube, and 24 greys.
+fn indexed(n: u32) -> Clr {
+	match n {
+		0..16 => PALETTE[n as usize],
+		16..232 => {
+			let level = |i: u32| match i {
+				0 => 0,
+				i => 55 + i * 40,
+			};
+			let n = n - 16;
+			let (r, g, b) = (level(n / 36), level(n / 6 % 6), level(n % 6));
+			0xff000000 | r << 16 | g << 8 | b
+		}
+		_ => {
+			let grey = 8 + (n - 232) * 10;
+			0xff000000 | grey << 16 | grey << 8 | grey
+		}
+	}
+}
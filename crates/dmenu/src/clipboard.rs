@@ -0,0 +1,292 @@
+//! `--copy`, which leaves what is chosen on the clipboard once the menu has
+//! closed. Something has to hand the text to whoever pastes it for as long
+//! as it is on the clipboard, so dmenu runs itself again in the background
+//! to, reading the text from a pipe, until something else is copied.
+
+use std::{
+	env,
+	fs::File,
+	io::{self, Read, Write},
+	os::unix::process::CommandExt,
+	process::{Command, Stdio, exit},
+};
+
+use wayland_client::{
+	Connection,
+	Dispatch,
+	QueueHandle,
+	delegate_noop,
+	event_created_child,
+	globals::{GlobalListContents, registry_queue_init},
+	protocol::{wl_registry::WlRegistry, wl_seat::WlSeat},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+	zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+	zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+	zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+	zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+use x11rb::{
+	CURRENT_TIME,
+	NONE,
+	connection::Connection as _,
+	protocol::{
+		Event,
+		xproto::{
+			AtomEnum,
+			ConnectionExt as _,
+			CreateWindowAux,
+			EventMask,
+			PropMode,
+			SELECTION_NOTIFY_EVENT,
+			SelectionNotifyEvent,
+			WindowClass,
+		},
+	},
+	wrapper::ConnectionExt as _,
+};
+
+use crate::{die, wayland::MIME_TYPES};
+
+/// Set in the environment of the copy of dmenu which keeps the text on the
+/// clipboard, to the backend it is shown on.
+pub const SERVE: &str = "WMENU_CLIPBOARD";
+
+/// Puts `text` on the clipboard of the compositor, or with `x11` of the X
+/// server, in a process of its own which outlives the menu.
+pub fn copy(text: &str, x11: bool) {
+	let backend = if x11 { "x11" } else { "wayland" };
+	let spawned = env::current_exe().and_then(|exe| {
+		// In a process group of its own, it is left alone when the terminal
+		// the menu was run from closes.
+		let mut child = Command::new(exe)
+			.env(SERVE, backend)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.process_group(0)
+			.spawn()?;
+		let mut stdin = child.stdin.take().expect("stdin is piped");
+		stdin.write_all(text.as_bytes())
+	});
+	if let Err(err) = spawned {
+		eprintln!("cannot copy to the clipboard: {}", err);
+	}
+}
+
+/// Reads the text from stdin and keeps it on the clipboard of `backend`
+/// until something else is copied, then exits.
+pub fn serve(backend: &str) -> ! {
+	let mut text = Vec::new();
+	if let Err(err) = io::stdin().read_to_end(&mut text) {
+		die(&format!("cannot read what to copy: {}", err));
+	}
+	match backend {
+		"x11" => x11(&text),
+		_ => wayland(text),
+	}
+}
+
+/// What is on the clipboard, and whether it still is.
+struct Server {
+	text: Vec<u8>,
+	done: bool,
+}
+
+/// Sets the selection through the data control protocol, which clients
+/// without a surface, or focus, may use.
+fn wayland(text: Vec<u8>) -> ! {
+	let Ok(conn) = Connection::connect_to_env() else {
+		die("cannot connect to the compositor");
+	};
+	let Ok((globals, mut queue)) = registry_queue_init::<Server>(&conn) else {
+		die("cannot list globals");
+	};
+	let qh = queue.handle();
+	let Ok(seat) = globals.bind::<WlSeat, _, _>(&qh, 1..=1, ()) else {
+		die("wl_seat is not supported");
+	};
+	let Ok(manager) =
+		globals.bind::<ZwlrDataControlManagerV1, _, _>(&qh, 1..=2, ())
+	else {
+		die("zwlr_data_control_manager_v1 is not supported");
+	};
+	let source = manager.create_data_source(&qh, ());
+	for mime in MIME_TYPES {
+		source.offer(mime.to_string());
+	}
+	let device = manager.get_data_device(&seat, &qh, ());
+	device.set_selection(Some(&source));
+	let mut server = Server { text, done: false };
+	while !server.done {
+		if queue.blocking_dispatch(&mut server).is_err() {
+			break;
+		}
+	}
+	exit(0)
+}
+
+/// Owns the clipboard selection of the X server, answering requests for it
+/// in any of the types of text. Text too long to send in one request is
+/// not sent in parts, as nothing copied from a menu is.
+fn x11(text: &[u8]) -> ! {
+	let Ok((conn, screen)) = x11rb::connect(None) else {
+		die("cannot open display");
+	};
+	let root = conn.setup().roots[screen].root;
+	let intern = |name: &str| {
+		let cookie = conn.intern_atom(false, name.as_bytes()).ok()?;
+		Some(cookie.reply().ok()?.atom)
+	};
+	let (Some(clipboard), Some(targets), Some(utf8), Some(text_atom)) = (
+		intern("CLIPBOARD"),
+		intern("TARGETS"),
+		intern("UTF8_STRING"),
+		intern("TEXT"),
+	) else {
+		die("lost the connection to the X server");
+	};
+	let win = conn.generate_id().unwrap_or_else(|_| die("no window ids left"));
+	let aux = CreateWindowAux::new();
+	let owned = conn
+		.create_window(
+			0,
+			win,
+			root,
+			0,
+			0,
+			1,
+			1,
+			0,
+			WindowClass::INPUT_ONLY,
+			0,
+			&aux,
+		)
+		.and_then(|_| conn.set_selection_owner(win, clipboard, CURRENT_TIME))
+		.ok()
+		.and_then(|_| conn.get_selection_owner(clipboard).ok())
+		.and_then(|cookie| cookie.reply().ok());
+	if owned.is_none_or(|reply| reply.owner != win) {
+		die("cannot take the clipboard");
+	}
+	let string = AtomEnum::STRING.into();
+	while let Ok(event) = conn.wait_for_event() {
+		let request = match event {
+			Event::SelectionRequest(request) => request,
+			Event::SelectionClear(_) => break,
+			_ => continue,
+		};
+		// Clients from before the property was given name none, and are
+		// answered in the target itself.
+		let property = match request.property {
+			NONE => request.target,
+			property => property,
+		};
+		// A type the text is not held in is refused, as is one which
+		// cannot be set.
+		let sent = match request.target {
+			target if target == targets => conn
+				.change_property32(
+					PropMode::REPLACE,
+					request.requestor,
+					property,
+					AtomEnum::ATOM,
+					&[targets, utf8, string, text_atom],
+				)
+				.is_ok(),
+			target if [utf8, string, text_atom].contains(&target) => {
+				let kind = if target == string { string } else { utf8 };
+				conn.change_property8(
+					PropMode::REPLACE,
+					request.requestor,
+					property,
+					kind,
+					text,
+				)
+				.is_ok()
+			}
+			_ => false,
+		};
+		let notify = SelectionNotifyEvent {
+			response_type: SELECTION_NOTIFY_EVENT,
+			sequence: 0,
+			time: request.time,
+			requestor: request.requestor,
+			selection: request.selection,
+			target: request.target,
+			property: if sent { property } else { NONE },
+		};
+		let _ = conn.send_event(
+			false,
+			request.requestor,
+			EventMask::NO_EVENT,
+			notify,
+		);
+		let _ = conn.flush();
+	}
+	exit(0)
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for Server {
+	fn event(
+		server: &mut Self,
+		_: &ZwlrDataControlSourceV1,
+		event: zwlr_data_control_source_v1::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		match event {
+			// A client which stops reading early is of no concern.
+			zwlr_data_control_source_v1::Event::Send { fd, .. } => {
+				let _ = File::from(fd).write_all(&server.text);
+			}
+			zwlr_data_control_source_v1::Event::Cancelled => server.done = true,
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for Server {
+	event_created_child!(Server, ZwlrDataControlDeviceV1, [
+		zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE =>
+			(ZwlrDataControlOfferV1, ()),
+	]);
+
+	fn event(
+		server: &mut Self,
+		_: &ZwlrDataControlDeviceV1,
+		event: zwlr_data_control_device_v1::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		match event {
+			// What is offered, this text among it, is of no interest.
+			zwlr_data_control_device_v1::Event::Selection {
+				id: Some(offer),
+			}
+			| zwlr_data_control_device_v1::Event::PrimarySelection {
+				id: Some(offer),
+			} => offer.destroy(),
+			zwlr_data_control_device_v1::Event::Finished => server.done = true,
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Server {
+	fn event(
+		_: &mut Self,
+		_: &WlRegistry,
+		_: <WlRegistry as wayland_client::Proxy>::Event,
+		_: &GlobalListContents,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+	}
+}
+
+delegate_noop!(Server: ignore WlSeat);
+delegate_noop!(Server: ZwlrDataControlManagerV1);
+delegate_noop!(Server: ignore ZwlrDataControlOfferV1);
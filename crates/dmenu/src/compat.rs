@@ -0,0 +1,60 @@
+//! What the options rofi and bemenu have in place of dmenu's are given as,
+//! where it takes parsing to map them onto dmenu's: rofi's `-theme-str`,
+//! its fonts and its `-format`.
+
+/// How rofi's `-format` prints what is chosen, of the ways dmenu can.
+pub enum Format {
+	/// The text, or with `p`, the text without its markup, which dmenu
+	/// never prints.
+	Text,
+	/// The index, counting from zero, or -1 for text typed.
+	Index,
+	/// The text, quoted for the shell.
+	Quoted,
+}
+
+impl Format {
+	pub fn parse(format: &str) -> Option<Format> {
+		match format {
+			"s" | "p" => Some(Format::Text),
+			"i" => Some(Format::Index),
+			"q" => Some(Format::Quoted),
+			_ => None,
+		}
+	}
+}
+
+/// The properties set in the text of rofi's `-theme-str`, as in
+/// `window { width: 50%; }`, as their section, name and value, with
+/// strings unquoted. Nesting, comments and variables are not read.
+pub fn properties(text: &str) -> Vec<(&str, &str, &str)> {
+	let mut props = Vec::new();
+	for block in text.split('}') {
+		let Some((section, body)) = block.split_once('{') else {
+			continue;
+		};
+		let section = section.trim();
+		for prop in body.split(';') {
+			if let Some((name, value)) = prop.split_once(':') {
+				let value = value.trim();
+				let value = value
+					.strip_prefix('"')
+					.and_then(|v| v.strip_suffix('"'))
+					.unwrap_or(value);
+				props.push((section, name.trim(), value));
+			}
+		}
+	}
+	props
+}
+
+/// A font as rofi names it, as in `Iosevka Bold 12`, as dmenu does, as in
+/// `Iosevka Bold:size=12`.
+pub fn font(name: &str) -> String {
+	match name.rsplit_once(' ') {
+		Some((family, size)) if size.parse::<f32>().is_ok() => {
+			format!("{}:size={}", family, size)
+		}
+		_ => name.to_string(),
+	}
+}
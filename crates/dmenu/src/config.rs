@@ -0,0 +1,51 @@
+//! Default settings, which can be overridden by the theme file and on the
+//! command line.
+
+use crate::menu::{EXITS, SCHEMES};
+
+/// The font used when none is given, as a family name followed by
+/// `:`-separated attributes.
+pub const FONT: &str = "monospace:size=10";
+
+/// The foreground, background and colour of matched characters of normal
+/// items, of the selected item, of items chosen with `--multi`, of every
+/// other row of a vertical list, of the prompt and of the input.
+pub const COLORS: [[&str; 3]; SCHEMES] = [
+	["#bbbbbb", "#222222", "#ffc978"],
+	["#eeeeee", "#005577", "#ffc978"],
+	["#000000", "#00ffff", "#005577"],
+	["#bbbbbb", "#222222", "#ffc978"],
+	["#eeeeee", "#005577", "#ffc978"],
+	["#bbbbbb", "#222222", "#ffc978"],
+];
+
+/// The colours used instead with `NO_COLOR` set, where the selected item
+/// is shown inverted.
+pub const NO_COLORS: [[&str; 3]; SCHEMES] = [
+	["#bbbbbb", "#222222", "#bbbbbb"],
+	["#222222", "#bbbbbb", "#222222"],
+	["#ffffff", "#555555", "#ffffff"],
+	["#bbbbbb", "#222222", "#bbbbbb"],
+	["#222222", "#bbbbbb", "#222222"],
+	["#bbbbbb", "#222222", "#bbbbbb"],
+];
+
+/// The colour of the border drawn with `--border-width`, and with
+/// `NO_COLOR` set.
+pub const BORDER_COLOR: &str = "#005577";
+pub const NO_BORDER_COLOR: &str = "#bbbbbb";
+
+/// The characters which separate words, for deleting them with Ctrl+W.
+pub const WORD_DELIMITERS: &str = " ";
+
+/// How many kills are kept to be yanked with Ctrl+Y and Alt+Y.
+pub const KILLS: usize = 8;
+
+/// The text shown in place of each character typed with `-P`.
+pub const PASSWORD_MASK: &str = "*";
+
+/// The status exited with once an item is chosen, once text typed which is
+/// none is, once cancelled, and with nothing matched with `--only-match`.
+/// Cancelling fails as it does in dmenu, but typed text is told apart from
+/// an item, which dmenu exits with 0 for as well.
+pub const STATUSES: [i32; EXITS] = [0, 2, 1, 3];
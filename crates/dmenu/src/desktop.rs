@@ -0,0 +1,369 @@
+//! Applications read from the `.desktop` files in the XDG data directories,
+//! for `--desktop`, which are launched when chosen rather than printed.
+
+use std::{
+	collections::HashSet,
+	env,
+	fs,
+	io,
+	path::{Path, PathBuf},
+};
+
+use stest_core::FileTest;
+
+use crate::launch::{Scope, launch};
+
+pub struct Entry {
+	pub name: String,
+	/// The ID of the entry, without `.desktop`, which names the scope it is
+	/// launched in with `--scope`.
+	id: String,
+	/// The icon shown with `--icons`, by name or path.
+	pub icon: Option<String>,
+	/// The command line, with its field codes expanded, where it is run, and
+	/// whether it is run in a terminal.
+	exec: String,
+	path: Option<String>,
+	terminal: bool,
+}
+
+impl Entry {
+	/// Parses the text of a desktop entry, if it is an application which is
+	/// shown in menus, named as in the first of `locales` it is translated
+	/// for.
+	pub fn parse(text: &str, locales: &[String]) -> Option<Entry> {
+		let mut group = false;
+		let (mut name, mut exec, mut path, mut kind) = (None, None, None, None);
+		let mut icon = None;
+		let (mut shown, mut terminal) = (true, false);
+		// How far down `locales` the name chosen so far is, where the name
+		// which is not translated comes after them all.
+		let mut rank = usize::MAX;
+		for line in text.lines() {
+			let line = line.trim();
+			if line.starts_with('[') {
+				group = line == "[Desktop Entry]";
+				continue;
+			}
+			let Some((key, value)) = line.split_once('=') else {
+				continue;
+			};
+			if !group || line.starts_with('#') {
+				continue;
+			}
+			let value = unescape(value.trim());
+			let key = key.trim();
+			if let Some(locale) =
+				key.strip_prefix("Name[").and_then(|key| key.strip_suffix(']'))
+			{
+				if let Some(i) = locales.iter().position(|l| l == locale)
+					&& i < rank
+				{
+					(name, rank) = (Some(value), i);
+				}
+				continue;
+			}
+			match key {
+				"Name" if rank > locales.len() => {
+					(name, rank) = (Some(value), locales.len())
+				}
+				"Exec" => exec = Some(value),
+				"Path" if !value.is_empty() => path = Some(value),
+				"Icon" if !value.is_empty() => icon = Some(value),
+				"Type" => kind = Some(value),
+				"Terminal" => terminal = value == "true",
+				"NoDisplay" | "Hidden" if value == "true" => shown = false,
+				_ => {}
+			}
+		}
+		if !shown || kind.as_deref() != Some("Application") {
+			return None;
+		}
+		let name = name?;
+		let exec = expand(&exec?, &name, icon.as_deref());
+		let id = String::new();
+		Some(Entry { name, id, icon, exec, path, terminal })
+	}
+
+	/// Launches the application, in `scope` if given, and in `$TERMINAL`,
+	/// or else `xterm`, if it asks to be run in a terminal.
+	pub fn launch(&self, scope: Option<Scope>) -> io::Result<()> {
+		let dir = self.path.as_deref();
+		if !self.terminal {
+			return launch(&self.exec, dir, scope, &self.id);
+		}
+		let terminal = env::var("TERMINAL").ok().filter(|t| !t.is_empty());
+		let terminal = terminal.as_deref().unwrap_or("xterm");
+		let command = format!("{} -e {}", terminal, self.exec);
+		launch(&command, dir, scope, &self.id)
+	}
+}
+
+/// The locales names are looked up in, most specific first, from the first
+/// of `LC_ALL`, `LC_MESSAGES` and `LANG` to be set, as `lang_COUNTRY@MODIFIER`
+/// gives `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER` and `lang`.
+pub fn locales() -> Vec<String> {
+	let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+		.iter()
+		.find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()))
+		.unwrap_or_default();
+	// The encoding, as in `.UTF-8`, plays no part.
+	let (locale, modifier) = match locale.split_once('@') {
+		Some((locale, modifier)) => (locale, Some(modifier)),
+		None => (locale.as_str(), None),
+	};
+	let locale = locale.split('.').next().unwrap_or_default();
+	if locale.is_empty() || locale == "C" || locale == "POSIX" {
+		return Vec::new();
+	}
+	let (lang, country) = match locale.split_once('_') {
+		Some((lang, country)) => (lang, Some(country)),
+		None => (locale, None),
+	};
+	let mut locales = Vec::new();
+	if let Some(country) = country {
+		let base = format!("{}_{}", lang, country);
+		locales.extend(modifier.map(|m| format!("{}@{}", base, m)));
+		locales.push(base);
+	}
+	locales.extend(modifier.map(|m| format!("{}@{}", lang, m)));
+	locales.push(lang.to_string());
+	locales
+}
+
+/// Every application shown in menus, sorted by name. Where several files
+/// have the same ID, the one in the most important directory is used.
+pub fn scan() -> Vec<Entry> {
+	let tests = FileTest::new().name("*.desktop", false).file().readable();
+	let mut seen = HashSet::new();
+	let mut entries = Vec::new();
+	let locales = locales();
+	for dir in dirs() {
+		let dir = dir.join("applications");
+		walk(&dir, "", &tests, &locales, &mut seen, &mut entries);
+	}
+	entries.sort_by(|a, b| a.name.cmp(&b.name));
+	entries
+}
+
+/// The data directories, most important first.
+pub fn dirs() -> Vec<PathBuf> {
+	let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+	let home = var("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+		Some(PathBuf::from(var("HOME")?).join(".local").join("share"))
+	});
+	let data = var("XDG_DATA_DIRS")
+		.unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+	home.into_iter().chain(env::split_paths(&data)).collect()
+}
+
+/// Reads the entries under `dir`, whose IDs are their paths from the
+/// applications directory with `-` in place of `/`.
+fn walk(
+	dir: &Path,
+	prefix: &str,
+	tests: &FileTest,
+	locales: &[String],
+	seen: &mut HashSet<String>,
+	entries: &mut Vec<Entry>,
+) {
+	let Ok(children) = fs::read_dir(dir) else {
+		return;
+	};
+	for child in children.flatten() {
+		let path = child.path();
+		let id = format!("{}{}", prefix, child.file_name().to_string_lossy());
+		if child.file_type().is_ok_and(|kind| kind.is_dir()) {
+			let prefix = format!("{}-", id);
+			walk(&path, &prefix, tests, locales, seen, entries);
+			continue;
+		}
+		if !tests.test(&path) {
+			continue;
+		}
+		// Hidden entries still hide those with the same ID further on.
+		if seen.insert(id.clone())
+			&& let Ok(text) = fs::read_to_string(&path)
+			&& let Some(entry) = Entry::parse(&text, locales)
+		{
+			let id = id.strip_suffix(".desktop").unwrap_or(&id).to_string();
+			entries.push(Entry { id, ..entry });
+		}
+	}
+}
+
+/// Replaces the escapes allowed in values with what they stand for.
+fn unescape(value: &str) -> String {
+	let mut text = String::with_capacity(value.len());
+	let mut chars = value.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => match chars.next() {
+				Some('s') => text.push(' '),
+				Some('n') => text.push('\n'),
+				Some('t') => text.push('\t'),
+				Some('r') => text.push('\r'),
+				Some(c) => text.push(c),
+				None => text.push('\\'),
+			},
+			c => text.push(c),
+		}
+	}
+	text
+}
+
+/// Expands the field codes of a command line: `%c` to the application's
+/// `name` and `%i` to its `icon`, quoted for the shell, and `%%` to a
+/// literal `%`. The others, which stand for the files or URLs to open and
+/// the like, are left out.
+fn expand(exec: &str, name: &str, icon: Option<&str>) -> String {
+	let quote = |text: &str| format!("'{}'", text.replace('\'', "'\\''"));
+	let mut text = String::with_capacity(exec.len());
+	let mut chars = exec.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'%' => match chars.next() {
+				Some('%') => text.push('%'),
+				Some('c') => text.push_str(&quote(name)),
+				Some('i') => {
+					if let Some(icon) = icon {
+						text.push_str("--icon ");
+						text.push_str(&quote(icon));
+					}
+				}
+				_ => {}
+			},
+			c => text.push(c),
+		}
+	}
+	text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		process,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+
+	const FIREFOX: &str = "\
+[Desktop Entry]
+Version=1.0
+Name=Firefox
+Name[de]=Firefox-Webbrowser
+Name[fr_FR]=Navigateur Firefox
+Comment=Browse the World Wide Web
+Exec=firefox %u
+Icon=firefox
+Terminal=false
+Type=Application
+
+[Desktop Action new-window]
+Name=New Window
+Exec=firefox --new-window %u
+";
+
+	fn parse(text: &str) -> Option<Entry> {
+		Entry::parse(text, &[])
+	}
+
+	#[test]
+	fn entries_are_parsed() {
+		let entry = parse(FIREFOX).unwrap();
+		assert_eq!(entry.name, "Firefox");
+		assert_eq!(entry.exec, "firefox");
+		assert_eq!(entry.icon.as_deref(), Some("firefox"));
+		assert_eq!((entry.path, entry.terminal), (None, false));
+	}
+
+	#[test]
+	fn names_are_translated_for_the_first_locale_they_can_be() {
+		let locales = |names: &[&str]| {
+			let names: Vec<_> = names.iter().map(|n| n.to_string()).collect();
+			Entry::parse(FIREFOX, &names).unwrap().name
+		};
+		assert_eq!(locales(&["de_DE", "de"]), "Firefox-Webbrowser");
+		assert_eq!(locales(&["fr_FR", "fr"]), "Navigateur Firefox");
+		assert_eq!(locales(&["es_ES", "es"]), "Firefox");
+	}
+
+	#[test]
+	fn hidden_entries_and_others_than_applications_are_left_out() {
+		for extra in ["NoDisplay=true", "Hidden=true"] {
+			let text = FIREFOX.replace("Type=", &format!("{}\nType=", extra));
+			assert!(parse(&text).is_none(), "{}", extra);
+		}
+		let text = FIREFOX.replace("Type=", "NoDisplay=false\nType=");
+		assert!(parse(&text).is_some());
+		assert!(parse(&FIREFOX.replace("=Application", "=Link")).is_none());
+		assert!(parse(&FIREFOX.replace("Exec=firefox %u\n", "")).is_none());
+	}
+
+	#[test]
+	fn field_codes_are_expanded_or_left_out() {
+		let exec = |exec: &str| expand(exec, "Mr. O'Neil", Some("icon"));
+		assert_eq!(exec("app %U %f %F %u"), "app");
+		assert_eq!(exec("app --progress=50%%"), "app --progress=50%");
+		assert_eq!(
+			exec("app --class %c %i"),
+			"app --class 'Mr. O'\\''Neil' --icon 'icon'"
+		);
+		assert_eq!(expand("app %i", "App", None), "app");
+	}
+
+	#[test]
+	fn values_are_unescaped() {
+		let text =
+			FIREFOX.replace("Exec=firefox %u", "Exec=sh -c \"echo\\sa\\\\b\"");
+		assert_eq!(parse(&text).unwrap().exec, "sh -c \"echo a\\b\"");
+	}
+
+	#[test]
+	fn terminal_applications_and_their_directories_are_kept() {
+		let text =
+			FIREFOX.replace("Terminal=false", "Terminal=true\nPath=/tmp");
+		let entry = parse(&text).unwrap();
+		assert_eq!(
+			(entry.path.as_deref(), entry.terminal),
+			(Some("/tmp"), true)
+		);
+	}
+
+	#[test]
+	fn entries_are_named_by_their_paths_and_the_first_is_kept() {
+		static N: AtomicUsize = AtomicUsize::new(0);
+		let n = N.fetch_add(1, Ordering::Relaxed);
+		let root = env::temp_dir().join(format!(
+			"wmenu-desktop-{}-{}",
+			process::id(),
+			n
+		));
+		let (home, system) = (root.join("home"), root.join("system"));
+		let write = |path: PathBuf, text: &str| {
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, text).unwrap();
+		};
+		write(
+			home.join("kde/firefox.desktop"),
+			&FIREFOX.replace("=Firefox\n", "=Mine\n"),
+		);
+		write(
+			home.join("hidden.desktop"),
+			&FIREFOX.replace("Type=", "Hidden=true\nType="),
+		);
+		write(system.join("kde/firefox.desktop"), FIREFOX);
+		write(system.join("hidden.desktop"), FIREFOX);
+		write(system.join("firefox.txt"), FIREFOX);
+		let tests = FileTest::new().name("*.desktop", false).file().readable();
+		let (mut seen, mut entries) = (HashSet::new(), Vec::new());
+		for dir in [&home, &system] {
+			walk(dir, "", &tests, &[], &mut seen, &mut entries);
+		}
+		fs::remove_dir_all(root).unwrap();
+		let entries: Vec<_> =
+			entries.iter().map(|e| (e.id.as_str(), e.name.as_str())).collect();
+		assert_eq!(entries, [("kde-firefox", "Mine")]);
+	}
+}
@@ -0,0 +1,1095 @@
+//! Drawing of rectangles and text into an in-memory pixmap, which is copied
+//! into the shared memory buffers handed to the compositor.
+
+use std::{
+	cell::RefCell,
+	collections::{HashMap, HashSet},
+	io,
+	ops::Range,
+};
+
+use fontdb::{Database, Family, Query};
+use fontdue::{Font, FontSettings, Metrics};
+use ttf_parser::RasterImageFormat;
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A colour as 32-bit ARGB, the layout of `wl_shm`'s `argb8888`.
+pub type Clr = u32;
+
+/// Indices into a colour scheme: the foreground, the background, and the
+/// foreground of text which is marked out.
+pub const COL_FG: usize = 0;
+pub const COL_BG: usize = 1;
+pub const COL_MARK: usize = 2;
+
+/// How a stretch of text is drawn, as styled by markup with `--markup` or
+/// escape sequences with `--ansi`.
+/// Fonts are only loaded in one face, so bold and italic text is drawn
+/// thicker and slanted.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Style {
+	pub fg: Option<Clr>,
+	/// The colour filled in behind the text.
+	pub bg: Option<Clr>,
+	/// The opacity of the text, out of 255.
+	pub alpha: Option<u8>,
+	pub bold: bool,
+	pub italic: bool,
+	pub underline: bool,
+}
+
+/// The style of each byte range of a text which is styled.
+pub type Styles = Vec<(Range<usize>, Style)>;
+
+/// Font sizes in points are converted to pixels at this resolution.
+const DPI: f32 = 96.0;
+
+/// Families tried, in order, for each of the generic family names.
+const MONOSPACE: &[&str] =
+	&["DejaVu Sans Mono", "Noto Sans Mono", "Liberation Mono", "Courier New"];
+const SANS_SERIF: &[&str] =
+	&["DejaVu Sans", "Noto Sans", "Liberation Sans", "Arial"];
+const SERIF: &[&str] =
+	&["DejaVu Serif", "Noto Serif", "Liberation Serif", "Times New Roman"];
+
+/// Parses a `#RRGGBB` colour, or a `#RRGGBBAA` one with an alpha channel.
+pub fn clr_create(name: &str) -> Option<Clr> {
+	let hex = name.strip_prefix('#')?;
+	if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None;
+	}
+	let clr = u32::from_str_radix(hex, 16).ok()?;
+	match hex.len() {
+		6 => Some(0xff000000 | clr),
+		8 => Some(clr.rotate_right(8)),
+		_ => None,
+	}
+}
+
+/// The colour halfway between `a` and `b`, in each channel.
+pub fn clr_mix(a: Clr, b: Clr) -> Clr {
+	let channel = |shift: u32| ((a >> shift & 0xff) + (b >> shift & 0xff)) / 2;
+	[0, 8, 16, 24].into_iter().fold(0, |clr, s| clr | channel(s) << s)
+}
+
+/// A font drawn with, and the data it was loaded from where it has raster
+/// images, such as colour emoji, which are drawn in place of outlines.
+struct Face {
+	font: Font,
+	images: Option<(Vec<u8>, u32)>,
+}
+
+impl Face {
+	fn load(data: &[u8], index: u32, px: f32) -> Option<Face> {
+		let settings = FontSettings {
+			collection_index: index,
+			scale: px,
+			..FontSettings::default()
+		};
+		let font = Font::from_bytes(data, settings).ok()?;
+		let face = ttf_parser::Face::parse(data, index).ok()?;
+		let tables = face.tables();
+		let images = (tables.cbdt.is_some() || tables.sbix.is_some())
+			.then(|| (data.to_vec(), index));
+		Some(Face { font, images })
+	}
+
+	fn has(&self, c: char) -> bool {
+		self.font.lookup_glyph_index(c) != 0
+	}
+
+	/// The image the font has for `c`, scaled to `px`, if there is one.
+	fn image(&self, c: char, px: f32) -> Option<Glyph> {
+		let (data, index) = self.images.as_ref()?;
+		let face = ttf_parser::Face::parse(data, *index).ok()?;
+		let id = face.glyph_index(c)?;
+		let image = face.glyph_raster_image(id, px.round() as u16)?;
+		if image.format != RasterImageFormat::PNG {
+			return None;
+		}
+		let (w, h, pixels) = decode(image.data)?;
+		let scale = px / image.pixels_per_em.max(1) as f32;
+		let (sw, sh) = (
+			(w as f32 * scale).round().max(1.0) as usize,
+			(h as f32 * scale).round().max(1.0) as usize,
+		);
+		let metrics = Metrics {
+			xmin: (image.x as f32 * scale).round() as i32,
+			ymin: (image.y as f32 * scale).round() as i32,
+			width: sw,
+			height: sh,
+			..self.font.metrics(c, px)
+		};
+		let bitmap = Bitmap::Color(shrink(&pixels, (w, h), (sw, sh)));
+		Some(Glyph { metrics, bitmap })
+	}
+}
+
+/// Decodes a PNG image, giving its size and its pixels as RGBA.
+pub fn decode(data: &[u8]) -> Option<(usize, usize, Vec<[u8; 4]>)> {
+	let mut decoder = png::Decoder::new(io::Cursor::new(data));
+	decoder.set_transformations(
+		png::Transformations::normalize_to_color8()
+			| png::Transformations::ALPHA,
+	);
+	let mut reader = decoder.read_info().ok()?;
+	let mut buf = vec![0; reader.output_buffer_size()?];
+	let info = reader.next_frame(&mut buf).ok()?;
+	let pixels = match info.color_type {
+		png::ColorType::Rgba => {
+			buf.chunks_exact(4).map(|px| [px[0], px[1], px[2], px[3]]).collect()
+		}
+		png::ColorType::GrayscaleAlpha => {
+			buf.chunks_exact(2).map(|px| [px[0], px[0], px[0], px[1]]).collect()
+		}
+		_ => return None,
+	};
+	Some((info.width as usize, info.height as usize, pixels))
+}
+
+/// Scales an image of RGBA pixels from `w` by `h` to `sw` by `sh`, as
+/// colours in ARGB, averaging the pixels each new one covers.
+pub fn shrink(
+	pixels: &[[u8; 4]],
+	(w, h): (usize, usize),
+	(sw, sh): (usize, usize),
+) -> Vec<Clr> {
+	let span = |i: usize, to: usize, from: usize| {
+		let start = i * from / to;
+		start..((i + 1) * from / to).max(start + 1).min(from)
+	};
+	let mut out = Vec::with_capacity(sw * sh);
+	for y in 0..sh {
+		for x in 0..sw {
+			// Channels are weighted by alpha, so that transparent pixels
+			// do not darken the edges.
+			let (mut sum, mut n) = ([0u32; 4], 0);
+			for py in span(y, sh, h) {
+				for px in span(x, sw, w) {
+					let [r, g, b, a] = pixels[py * w + px].map(u32::from);
+					sum[0] += r * a;
+					sum[1] += g * a;
+					sum[2] += b * a;
+					sum[3] += a;
+					n += 1;
+				}
+			}
+			let a = sum[3] / n.max(1);
+			let c = |i: usize| sum[i].checked_div(sum[3]).unwrap_or(0);
+			out.push(a << 24 | c(0) << 16 | c(1) << 8 | c(2));
+		}
+	}
+	out
+}
+
+/// What a glyph is drawn from: how much of each pixel it covers, drawn in
+/// the colour of the text, or the colour of each.
+enum Bitmap {
+	Coverage(Vec<u8>),
+	Color(Vec<Clr>),
+}
+
+struct Glyph {
+	metrics: Metrics,
+	bitmap: Bitmap,
+}
+
+pub struct Fnt {
+	/// The fonts given, then those found since for characters none of them
+	/// have, in the order they are tried.
+	faces: RefCell<Vec<Face>>,
+	/// The fonts on the system, which are looked through for characters
+	/// missing from all of `faces`, and those which none of them have.
+	db: Database,
+	missing: RefCell<HashSet<char>>,
+	/// The size of the fonts in pixels, and the size glyphs are rasterized
+	/// at, which is that scaled for the output.
+	size: f32,
+	px: f32,
+	pub h: u32,
+	ascent: i32,
+	/// The width of a column, if the first font is monospaced.
+	cell: Option<u32>,
+	glyphs: RefCell<HashMap<char, Glyph>>,
+}
+
+impl Fnt {
+	/// Loads the fonts described by `names`, separated by commas, each a
+	/// family followed by attributes, as in `monospace:size=10` or `DejaVu
+	/// Sans:pixelsize=13`. The first sets the size of them all, and those
+	/// after it are used for characters it does not have.
+	pub fn create(names: &str) -> Option<Fnt> {
+		let mut names = names.split(',').map(str::trim);
+		let mut attrs = names.next()?.split(':');
+		let family = attrs.next().unwrap_or_default();
+		let mut px = 10.0 * DPI / 72.0;
+		for attr in attrs {
+			match attr.split_once('=') {
+				Some(("size", size)) => {
+					px = size.parse::<f32>().ok()? * DPI / 72.0
+				}
+				Some(("pixelsize", size)) => px = size.parse().ok()?,
+				// Anything else, such as a style, is not supported.
+				_ => {}
+			}
+		}
+
+		let mut db = Database::new();
+		db.load_system_fonts();
+		let query = |family: &str| {
+			let families = match family {
+				"monospace" | "mono" => MONOSPACE,
+				"sans-serif" | "sans" => SANS_SERIF,
+				"serif" => SERIF,
+				family => &[family][..],
+			};
+			let families = families
+				.iter()
+				.map(|&name| Family::Name(name))
+				.collect::<Vec<_>>();
+			db.query(&Query { families: &families, ..Query::default() })
+		};
+		let id = query(family)?;
+		let monospaced = db.face(id)?.monospaced;
+		let mut faces =
+			vec![db.with_face_data(id, |data, index| {
+				Face::load(data, index, px)
+			})??];
+		for name in names.filter(|name| !name.is_empty()) {
+			let family = name.split(':').next().unwrap_or_default();
+			let face = query(family).and_then(|id| {
+				db.with_face_data(id, |data, index| {
+					Face::load(data, index, px)
+				})?
+			});
+			match face {
+				Some(face) => faces.push(face),
+				None => eprintln!("cannot load font '{}'", name),
+			}
+		}
+		let mut fnt = Fnt {
+			faces: RefCell::new(faces),
+			db,
+			missing: RefCell::default(),
+			size: px,
+			px,
+			h: 0,
+			ascent: 0,
+			cell: monospaced.then_some(0),
+			glyphs: RefCell::default(),
+		};
+		fnt.set_scale(1.0).then_some(fnt)
+	}
+
+	/// Rasterizes the fonts at `scale` times their size from now on.
+	pub fn set_scale(&mut self, scale: f32) -> bool {
+		let px = self.size * scale;
+		let faces = self.faces.get_mut();
+		let Some(metrics) = faces[0].font.horizontal_line_metrics(px) else {
+			return false;
+		};
+		self.px = px;
+		self.h = (metrics.ascent - metrics.descent).ceil() as u32;
+		self.ascent = metrics.ascent.round() as i32;
+		if let Some(cell) = &mut self.cell {
+			*cell = faces[0].font.metrics('0', px).advance_width.round() as u32;
+		}
+		self.glyphs.get_mut().clear();
+		true
+	}
+
+	/// The index in `faces` of the first font which has `c`, looking
+	/// through the fonts on the system for one if none of them do.
+	fn face(&self, c: char) -> Option<usize> {
+		let found = self.faces.borrow().iter().position(|face| face.has(c));
+		if found.is_some() || self.missing.borrow().contains(&c) {
+			return found;
+		}
+		let face = self.db.faces().find_map(|info| {
+			self.db.with_face_data(info.id, |data, index| {
+				let face = ttf_parser::Face::parse(data, index).ok()?;
+				face.glyph_index(c)?;
+				Face::load(data, index, self.size)
+			})?
+		});
+		let Some(face) = face else {
+			self.missing.borrow_mut().insert(c);
+			return None;
+		};
+		let mut faces = self.faces.borrow_mut();
+		faces.push(face);
+		Some(faces.len() - 1)
+	}
+
+	/// Calls `f` with the glyph for `c`, rasterizing it on first use. A
+	/// character no font has is drawn as the first font's missing glyph.
+	fn glyph<T>(&self, c: char, f: impl FnOnce(&Glyph) -> T) -> T {
+		let mut glyphs = self.glyphs.borrow_mut();
+		let glyph = glyphs.entry(c).or_insert_with(|| {
+			let i = self.face(c).unwrap_or(0);
+			let face = &self.faces.borrow()[i];
+			face.image(c, self.px).unwrap_or_else(|| {
+				let (metrics, bitmap) = face.font.rasterize(c, self.px);
+				Glyph { metrics, bitmap: Bitmap::Coverage(bitmap) }
+			})
+		});
+		f(glyph)
+	}
+
+	/// The characters of the grapheme `g` which are drawn, all in the same
+	/// place: the first, and any combining marks a font has.
+	fn drawn(&self, g: &str) -> impl Iterator<Item = char> {
+		let mut chars = g.chars();
+		chars.next().into_iter().chain(
+			chars.filter(|&c| c.width() == Some(0) && self.face(c).is_some()),
+		)
+	}
+
+	/// The advance of the grapheme `g`, rounded to whole pixels so that text
+	/// is measured exactly as it is drawn. In a monospaced font, it takes
+	/// as many columns as it is wide by East Asian width, so that wide
+	/// characters line up.
+	fn advance(&self, g: &str) -> u32 {
+		match (self.cell, g.chars().next()) {
+			(Some(cell), _) => cell * g.width() as u32,
+			(None, Some(c)) => self
+				.glyph(c, |glyph| glyph.metrics.advance_width.round() as u32),
+			(None, None) => 0,
+		}
+	}
+
+	pub fn getexts(&self, text: &str) -> u32 {
+		text.graphemes(true).map(|g| self.advance(g)).sum()
+	}
+
+	/// The width of `text`, or `n` if it is wider, measuring no further
+	/// than the grapheme which takes it past `n`.
+	pub fn getexts_clamp(&self, text: &str, n: u32) -> u32 {
+		let mut w = 0;
+		for g in text.graphemes(true) {
+			w += self.advance(g);
+			if w >= n {
+				return n;
+			}
+		}
+		w
+	}
+}
+
+pub struct Drw {
+	/// The size of what is drawn on, inside the border.
+	pub w: u32,
+	pub h: u32,
+	pixmap: Vec<Clr>,
+	/// The pixmap as it was when last damaged, to find what has changed
+	/// since, or empty if all of it has.
+	shown: Vec<Clr>,
+	scheme: [Clr; 3],
+	/// The width and colour of the border, drawn around the pixmap when it
+	/// is mapped.
+	border: u32,
+	border_clr: Clr,
+	/// The radius the corners of the buffer are rounded to, outside which
+	/// it is transparent.
+	radius: u32,
+	pub fonts: Fnt,
+}
+
+impl Drw {
+	pub fn new(fonts: Fnt) -> Self {
+		Drw {
+			w: 0,
+			h: 0,
+			pixmap: Vec::new(),
+			shown: Vec::new(),
+			scheme: [0; 3],
+			border: 0,
+			border_clr: 0,
+			radius: 0,
+			fonts,
+		}
+	}
+
+	/// The size of the buffer, border included.
+	pub fn size(&self) -> (u32, u32) {
+		(self.w + 2 * self.border, self.h + 2 * self.border)
+	}
+
+	pub fn setborder(&mut self, w: u32, clr: Clr, radius: u32) {
+		self.border = w;
+		self.border_clr = clr;
+		self.radius = radius;
+		self.shown.clear();
+	}
+
+	pub fn resize(&mut self, w: u32, h: u32) {
+		self.w = w;
+		self.h = h;
+		self.pixmap = vec![0; w as usize * h as usize];
+		self.shown.clear();
+	}
+
+	/// Forgets what was last shown, so that all of the buffer is damaged
+	/// when it is next drawn, as when it has been lost.
+	pub fn invalidate(&mut self) {
+		self.shown.clear();
+	}
+
+	/// The areas of the buffer, as `[x, y, w, h]`, which have changed since
+	/// this was last called: a band over each run of rows which have, as
+	/// wide as the changes in them, or the whole buffer if it is new.
+	pub fn damage(&mut self) -> Vec<[u32; 4]> {
+		let (bufw, bufh) = self.size();
+		if self.pixmap.is_empty() {
+			return Vec::new();
+		}
+		if self.shown.len() != self.pixmap.len() {
+			self.shown.clone_from(&self.pixmap);
+			return vec![[0, 0, bufw, bufh]];
+		}
+		let (w, b) = (self.w as usize, self.border);
+		let mut bands: Vec<[u32; 4]> = Vec::new();
+		let rows = self.pixmap.chunks_exact(w).zip(self.shown.chunks_exact(w));
+		for (y, (row, old)) in rows.enumerate() {
+			let changed = |(new, old): (&Clr, &Clr)| new != old;
+			let Some(x0) = row.iter().zip(old).position(changed) else {
+				continue;
+			};
+			let x1 =
+				w - row.iter().zip(old).rev().position(changed).unwrap_or(0);
+			let (x0, x1, y) = (x0 as u32 + b, x1 as u32 + b, y as u32 + b);
+			match bands.last_mut() {
+				Some(band) if band[1] + band[3] == y => {
+					let right = (band[0] + band[2]).max(x1);
+					band[0] = band[0].min(x0);
+					band[2] = right - band[0];
+					band[3] += 1;
+				}
+				_ => bands.push([x0, y, x1 - x0, 1]),
+			}
+		}
+		self.shown.copy_from_slice(&self.pixmap);
+		bands
+	}
+
+	pub fn setscheme(&mut self, scm: [Clr; 3]) {
+		self.scheme = scm;
+	}
+
+	pub fn rect(
+		&mut self,
+		x: i32,
+		y: i32,
+		w: u32,
+		h: u32,
+		filled: bool,
+		invert: bool,
+	) {
+		let clr = self.scheme[if invert { COL_BG } else { COL_FG }];
+		if filled {
+			self.fill(x, y, w, h, clr);
+		} else if w > 0 && h > 0 {
+			self.fill(x, y, w, 1, clr);
+			self.fill(x, y + h as i32 - 1, w, 1, clr);
+			self.fill(x, y, 1, h, clr);
+			self.fill(x + w as i32 - 1, y, 1, h, clr);
+		}
+	}
+
+	/// Blends a square image of `size` by `size` ARGB pixels onto the
+	/// pixmap, with its top left corner at `x` and `y`.
+	pub fn image(&mut self, x: i32, y: i32, size: usize, pixels: &[Clr]) {
+		let (x0, x1) = self.clip(x, size as u32, self.w);
+		let (y0, y1) = self.clip(y, size as u32, self.h);
+		for py in y0..y1 {
+			for px in x0..x1 {
+				let (ix, iy) =
+					((px as i32 - x) as usize, (py as i32 - y) as usize);
+				let src = pixels[iy * size + ix];
+				let i = py * self.w as usize + px;
+				self.pixmap[i] =
+					blend(self.pixmap[i], src | 0xff000000, (src >> 24) as u8);
+			}
+		}
+	}
+
+	fn fill(&mut self, x: i32, y: i32, w: u32, h: u32, clr: Clr) {
+		let (x0, x1) = self.clip(x, w, self.w);
+		let (y0, y1) = self.clip(y, h, self.h);
+		for y in y0..y1 {
+			let row = y * self.w as usize;
+			self.pixmap[row + x0..row + x1].fill(clr);
+		}
+	}
+
+	/// Clips the span of `len` from `start` to `0..max`.
+	fn clip(&self, start: i32, len: u32, max: u32) -> (usize, usize) {
+		let end = (start as i64 + len as i64).clamp(0, max as i64);
+		(start.clamp(0, max as i32) as usize, end as usize)
+	}
+
+	/// Draws `text` over a box filled with the background colour, cutting it
+	/// short with an ellipsis if it does not fit. Returns the x coordinate
+	/// after the box.
+	#[allow(clippy::too_many_arguments)]
+	pub fn text(
+		&mut self,
+		x: i32,
+		y: i32,
+		w: u32,
+		h: u32,
+		lpad: u32,
+		text: &str,
+		marks: &[Range<usize>],
+		styles: &[(Range<usize>, Style)],
+		invert: bool,
+	) -> i32 {
+		let (fg, bg) = match invert {
+			false => (self.scheme[COL_FG], self.scheme[COL_BG]),
+			true => (self.scheme[COL_BG], self.scheme[COL_FG]),
+		};
+		self.fill(x, y, w, h, bg);
+		if w < lpad {
+			return x + w as i32;
+		}
+		let (tx, tw) = (x + lpad as i32, w - lpad);
+
+		let ellipsis_w = self.fontset_getwidth("...");
+		// Where the text is cut, and how wide it is there, if it overflows.
+		let mut cut = None;
+		let (mut ew, mut fits) = (0, (0, 0));
+		for (i, g) in text.grapheme_indices(true) {
+			if ew + ellipsis_w <= tw {
+				fits = (i, ew);
+			}
+			let cw = self.fonts.advance(g);
+			if ew + cw > tw {
+				cut = Some(fits);
+				break;
+			}
+			ew += cw;
+		}
+
+		let ty = y + (h as i32 - self.fonts.h as i32) / 2 + self.fonts.ascent;
+		let clip = (tx, y, tw, h);
+		let mark = self.scheme[COL_MARK];
+		match cut {
+			None => {
+				self.marked(tx, ty, text, marks, styles, (fg, mark), clip);
+			}
+			Some((end, ew)) => {
+				let text = &text[..end];
+				let x =
+					self.marked(tx, ty, text, marks, styles, (fg, mark), clip);
+				if ew + ellipsis_w <= tw {
+					let style = Style::default();
+					self.glyphs(x, ty, "...", fg, style, clip, false);
+				}
+			}
+		}
+		x + w as i32
+	}
+
+	/// Blends the glyphs of `text` onto the pixmap in the first colour of
+	/// `clrs`, and those in the byte ranges `marks` in the second, in the
+	/// styles of the byte ranges of `styles`.
+	#[allow(clippy::too_many_arguments)]
+	fn marked(
+		&mut self,
+		mut x: i32,
+		y: i32,
+		text: &str,
+		marks: &[Range<usize>],
+		styles: &[(Range<usize>, Style)],
+		clrs: (Clr, Clr),
+		clip: (i32, i32, u32, u32),
+	) -> i32 {
+		// The text is drawn a stretch at a time, over which neither marks
+		// nor styles change. Those past a cut in the text are cut with it.
+		let ranges = marks.iter().chain(styles.iter().map(|(range, _)| range));
+		let cuts = ranges
+			.flat_map(|range| [range.start, range.end])
+			.filter(|&i| i < text.len())
+			.collect::<Vec<_>>();
+		// Each run is drawn in the order it is read, with the stretches of a
+		// run read right to left drawn from its end.
+		for (run, rtl) in runs(text) {
+			let mut stretches = cuts
+				.iter()
+				.copied()
+				.filter(|&i| i > run.start && i < run.end)
+				.chain([run.start, run.end])
+				.collect::<Vec<_>>();
+			stretches.sort_unstable();
+			stretches.dedup();
+			let mut stretches = stretches
+				.windows(2)
+				.map(|stretch| stretch[0]..stretch[1])
+				.collect::<Vec<_>>();
+			if rtl {
+				stretches.reverse();
+			}
+			for Range { start, end } in stretches {
+				let style = styles
+					.iter()
+					.find(|(range, _)| range.contains(&start))
+					.map_or(Style::default(), |&(_, style)| style);
+				let clr = match marks.iter().any(|mark| mark.contains(&start)) {
+					true => clrs.1,
+					false => style.fg.unwrap_or(clrs.0),
+				};
+				let text = &text[start..end];
+				x = self.glyphs(x, y, text, clr, style, clip, rtl);
+			}
+		}
+		x
+	}
+
+	/// How far into `text` as it is drawn the byte `at` of it is, which for
+	/// text read right to left is from the right of the character after it.
+	pub fn offset(&self, text: &str, at: usize) -> u32 {
+		let mut x = 0;
+		for (run, rtl) in runs(text) {
+			// The end of the text is where its last run ends.
+			if run.contains(&at) || at == run.end && at == text.len() {
+				return x + match rtl {
+					true => self.fonts.getexts(&text[at..run.end]),
+					false => self.fonts.getexts(&text[run.start..at]),
+				};
+			}
+			x += self.fonts.getexts(&text[run]);
+		}
+		x
+	}
+
+	/// Blends the glyphs of `text` onto the pixmap with their baseline at
+	/// `y`, inside the `clip` box, from its end if it is read right to left.
+	/// Returns the x coordinate after the text.
+	#[allow(clippy::too_many_arguments)]
+	fn glyphs(
+		&mut self,
+		mut x: i32,
+		y: i32,
+		text: &str,
+		clr: Clr,
+		style: Style,
+		clip: (i32, i32, u32, u32),
+		rtl: bool,
+	) -> i32 {
+		let (cx0, cx1) = self.clip(clip.0, clip.2, self.w);
+		let (cy0, cy1) = self.clip(clip.1, clip.3, self.h);
+		let alpha = style.alpha.unwrap_or(255) as u32;
+		let start = x;
+		if let Some(bg) = style.bg {
+			let w = self.fonts.getexts(text);
+			let (x0, x1) = self.clip(x, w, self.w);
+			let (x0, x1) = (x0.max(cx0), x1.min(cx1));
+			for py in cy0..cy1 {
+				let row = py * self.w as usize;
+				for px in &mut self.pixmap[row + x0..row + x1.max(x0)] {
+					*px = blend(*px, bg, (bg >> 24) as u8);
+				}
+			}
+		}
+		let mut graphemes = text.graphemes(true).collect::<Vec<_>>();
+		if rtl {
+			graphemes.reverse();
+		}
+		for g in graphemes {
+			for c in self.fonts.drawn(g) {
+				self.fonts.glyph(c, |glyph| {
+					let metrics = &glyph.metrics;
+					let gx = x + metrics.xmin;
+					let gy = y - metrics.height as i32 - metrics.ymin;
+					let width = metrics.width.max(1);
+					for row in 0..metrics.height {
+						let py = gy + row as i32;
+						if py < cy0 as i32 || py >= cy1 as i32 {
+							continue;
+						}
+						// Italic rows lean further right the higher above
+						// the baseline they are.
+						let lean = match style.italic {
+							true => (y - py) / 4,
+							false => 0,
+						};
+						for col in 0..metrics.width {
+							// Colour glyphs keep their own colours.
+							let i = row * width + col;
+							let (clr, a) = match &glyph.bitmap {
+								Bitmap::Coverage(coverage) => {
+									(clr, coverage[i])
+								}
+								Bitmap::Color(pixels) => (
+									pixels[i] | 0xff000000,
+									(pixels[i] >> 24) as u8,
+								),
+							};
+							let a = (a as u32 * alpha / 255) as u8;
+							// Bold glyphs are drawn twice, a pixel apart.
+							for dx in 0..=style.bold as i32 {
+								let px = gx + col as i32 + lean + dx;
+								if a == 0 || px < cx0 as i32 || px >= cx1 as i32
+								{
+									continue;
+								}
+								let i =
+									py as usize * self.w as usize + px as usize;
+								self.pixmap[i] = blend(self.pixmap[i], clr, a);
+							}
+						}
+					}
+				});
+			}
+			x += self.fonts.advance(g) as i32;
+		}
+		// Underlines are drawn halfway down the descent.
+		let descent = self.fonts.h as i32 - self.fonts.ascent;
+		let py = y + (descent / 2).max(1);
+		if style.underline && py >= cy0 as i32 && py < cy1 as i32 {
+			let (x0, x1) = self.clip(start, (x - start) as u32, self.w);
+			let (x0, x1) = (x0.max(cx0), x1.min(cx1));
+			let row = py as usize * self.w as usize;
+			for px in &mut self.pixmap[row + x0..row + x1.max(x0)] {
+				*px = blend(*px, clr, alpha as u8);
+			}
+		}
+		x
+	}
+
+	pub fn fontset_getwidth(&self, text: &str) -> u32 {
+		self.fonts.getexts(text)
+	}
+
+	pub fn fontset_getwidth_clamp(&self, text: &str, n: u32) -> u32 {
+		self.fonts.getexts_clamp(text, n)
+	}
+
+	/// Splits `text` into the byte ranges of rows at most `w` wide, breaking
+	/// after the last space in a row where there is one.
+	pub fn wrap(&self, text: &str, w: u32) -> Vec<Range<usize>> {
+		let mut rows = Vec::new();
+		let (mut start, mut width, mut space) = (0, 0, None);
+		for (i, g) in text.grapheme_indices(true) {
+			let cw = self.fonts.advance(g);
+			// A row always has at least one character, however narrow.
+			if width + cw > w && i > start {
+				let end = space.unwrap_or(i);
+				rows.push(start..end);
+				width = self.fontset_getwidth(&text[end..i]);
+				(start, space) = (end, None);
+			}
+			width += cw;
+			if g == " " {
+				space = Some(i + 1);
+			}
+		}
+		rows.push(start..text.len());
+		rows
+	}
+
+	/// Copies the `area` of the buffer, of the size given by `size` with the
+	/// pixmap inside the border, into `buf` from its first byte, a row of
+	/// `stride` bytes at a time. It is copied in `argb8888`, which unlike the
+	/// pixmap has its alpha premultiplied.
+	pub fn map(&self, buf: &mut [u8], stride: usize, area: [u32; 4]) {
+		let [left, top, aw, ah] = area.map(|n| n as usize);
+		for y in top..top + ah {
+			let row = &mut buf[(y - top) * stride..][..aw * 4];
+			for (x, dst) in (left..).zip(row.chunks_exact_mut(4)) {
+				dst.copy_from_slice(&self.pixel(x, y).to_ne_bytes());
+			}
+		}
+	}
+
+	/// The pixel of the buffer at `x`, `y`, with its alpha premultiplied:
+	/// of the pixmap inside the border, and cut away outside the corners
+	/// where they are rounded, each curve smoothed over the pixels it
+	/// passes through.
+	fn pixel(&self, x: usize, y: usize) -> Clr {
+		let (w, b) = (self.w as usize, self.border as usize);
+		let inside = x >= b && x < b + w && y >= b && y < b + self.h as usize;
+		let src = match inside {
+			true => self.pixmap[(y - b) * w + x - b],
+			false => self.border_clr,
+		};
+		let Some(d) = self.corner(x, y) else {
+			return premultiply(src);
+		};
+		// The border curves around the same centre as the corner does.
+		let r = self.radius() as f32;
+		let cover = |r: f32| (r - d + 0.5).clamp(0.0, 1.0);
+		let (outer, inner) = (cover(r), cover(r - b as f32));
+		match inside {
+			true => add(
+				scale(premultiply(src), inner),
+				scale(premultiply(self.border_clr), outer - inner),
+			),
+			false => scale(premultiply(src), outer),
+		}
+	}
+
+	/// The radius of the corners, which is at most half of either side.
+	fn radius(&self) -> u32 {
+		let (w, h) = self.size();
+		self.radius.min(w / 2).min(h / 2)
+	}
+
+	/// How far the centre of the pixel at `x`, `y` is from that of the curve
+	/// of the corner it is in, if it is in one.
+	fn corner(&self, x: usize, y: usize) -> Option<f32> {
+		let (w, h) = self.size();
+		let r = self.radius() as usize;
+		let centre = |at: usize, len: usize| match at {
+			at if at < r => Some(r),
+			at if at >= len - r => Some(len - r),
+			_ => None,
+		};
+		let (cx, cy) = (centre(x, w as usize)?, centre(y, h as usize)?);
+		let (dx, dy) = (x as f32 + 0.5 - cx as f32, y as f32 + 0.5 - cy as f32);
+		Some(dx.hypot(dy))
+	}
+
+	/// The rows of the buffer which are seen, as `[x, y, w, h]`, where its
+	/// corners are rounded: a row each where they are, and one rectangle
+	/// between them. Windows which cannot be translucent are cut to these.
+	pub fn shape(&self) -> Vec<[u32; 4]> {
+		let (w, h) = self.size();
+		let r = self.radius();
+		let mut rows = Vec::new();
+		for y in (0..r).chain(h - r..h) {
+			// Pixels more than half covered are kept.
+			let x = (0..r).find(|&x| {
+				self.corner(x as usize, y as usize)
+					.is_none_or(|d| d <= r as f32)
+			});
+			let x = x.unwrap_or(r);
+			rows.push([x, y, w - 2 * x, 1]);
+		}
+		rows.push([0, r, w, h - 2 * r]);
+		rows
+	}
+}
+
+/// The byte ranges of `text` in the order they are drawn, left to right,
+/// and whether each is read right to left, as the Unicode bidirectional
+/// algorithm orders them.
+fn runs(text: &str) -> Vec<(Range<usize>, bool)> {
+	let whole = vec![(0..text.len(), false)];
+	if text.is_ascii() {
+		return whole;
+	}
+	let info = BidiInfo::new(text, None);
+	if !info.has_rtl() {
+		return whole;
+	}
+	let mut runs = Vec::new();
+	for para in &info.paragraphs {
+		let (levels, visual) = info.visual_runs(para, para.range.clone());
+		runs.extend(
+			visual
+				.into_iter()
+				.map(|run| (run.clone(), levels[run.start].is_rtl())),
+		);
+	}
+	runs
+}
+
+/// Scales each channel of a premultiplied `clr`, alpha too, by `f`.
+fn scale(clr: Clr, f: f32) -> Clr {
+	let channel = |shift: u32| {
+		((((clr >> shift) & 0xff) as f32 * f).round() as u32) << shift
+	};
+	channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+/// The sum of two premultiplied colours, which cover a pixel between them.
+fn add(a: Clr, b: Clr) -> Clr {
+	let channel = |shift: u32| {
+		((((a >> shift) & 0xff) + ((b >> shift) & 0xff)).min(0xff)) << shift
+	};
+	channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+/// Scales the channels of `clr` by its alpha, as the compositor expects.
+fn premultiply(clr: Clr) -> Clr {
+	let alpha = clr >> 24;
+	let mul = |shift: u32| ((clr >> shift & 0xff) * alpha / 255) << shift;
+	alpha << 24 | mul(16) | mul(8) | mul(0)
+}
+
+/// Mixes `src` into `dst` by `alpha`, per channel.
+fn blend(dst: Clr, src: Clr, alpha: u8) -> Clr {
+	let alpha = alpha as u32;
+	let mix = |shift: u32| {
+		let (d, s) = ((dst >> shift) & 0xff, (src >> shift) & 0xff);
+		((s * alpha + d * (255 - alpha)) / 255) << shift
+	};
+	mix(24) | mix(16) | mix(8) | mix(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn colours_are_opaque_without_alpha() {
+		assert_eq!(clr_create("#222222"), Some(0xff222222));
+		assert_eq!(clr_create("#005577"), Some(0xff005577));
+		assert_eq!(clr_create("#EEeeEE"), Some(0xffeeeeee));
+	}
+
+	#[test]
+	fn colours_take_alpha_last() {
+		assert_eq!(clr_create("#00557780"), Some(0x80005577));
+		assert_eq!(clr_create("#ffffff00"), Some(0x00ffffff));
+	}
+
+	#[test]
+	fn malformed_colours_are_rejected() {
+		for name in [
+			"",
+			"#",
+			"222222",
+			"#22222",
+			"#2222222",
+			"#222222222",
+			"#gggggg",
+			"#+22222",
+			"# 22222",
+			"red",
+		] {
+			assert_eq!(clr_create(name), None, "{:?}", name);
+		}
+	}
+
+	#[test]
+	fn translucent_colours_are_premultiplied() {
+		assert_eq!(premultiply(0xff336699), 0xff336699);
+		assert_eq!(premultiply(0x80ffffff), 0x80808080);
+		assert_eq!(premultiply(0x00ffffff), 0);
+	}
+
+	#[test]
+	fn fonts_are_sized_in_points_or_pixels() {
+		let points = Fnt::create("monospace:size=12").unwrap();
+		assert_eq!(points.px, 16.0);
+		let pixels = Fnt::create("monospace:pixelsize=13").unwrap();
+		assert_eq!(pixels.px, 13.0);
+		let default = Fnt::create("monospace").unwrap();
+		assert_eq!(default.px, 10.0 * DPI / 72.0);
+	}
+
+	#[test]
+	fn fonts_are_as_tall_as_their_size() {
+		let small = Fnt::create("monospace:pixelsize=10").unwrap();
+		let large = Fnt::create("monospace:pixelsize=30").unwrap();
+		assert!(small.h >= 10 && small.h < 20, "{}", small.h);
+		assert!(large.h >= 3 * small.h - 3 && large.h <= 3 * small.h + 3);
+	}
+
+	#[test]
+	fn fonts_cannot_be_created_from_bad_specs() {
+		assert!(Fnt::create("monospace:size=big").is_none());
+		assert!(Fnt::create("no such family at all").is_none());
+		assert!(Fnt::create("").is_none());
+	}
+
+	#[test]
+	fn fallback_fonts_are_loaded_after_the_first() {
+		let fnt = Fnt::create("monospace:size=10, sans-serif, no such family")
+			.unwrap();
+		assert_eq!(fnt.faces.borrow().len(), 2);
+	}
+
+	fn drw() -> Drw {
+		Drw::new(Fnt::create("monospace:pixelsize=12").unwrap())
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn text_which_fits_is_one_row() {
+		let drw = drw();
+		let w = drw.fontset_getwidth("short text");
+		assert_eq!(drw.wrap("short text", w), [0..10]);
+		assert_eq!(drw.wrap("", 10), [0..0]);
+	}
+
+	#[test]
+	fn text_is_wrapped_after_spaces() {
+		let drw = drw();
+		let text = "the quick brown fox jumps";
+		let w = drw.fontset_getwidth("the quick bro");
+		let rows = drw.wrap(text, w);
+		let rows: Vec<_> = rows.into_iter().map(|row| &text[row]).collect();
+		assert_eq!(rows, ["the quick ", "brown fox ", "jumps"]);
+	}
+
+	#[test]
+	fn words_too_long_for_a_row_are_broken() {
+		let drw = drw();
+		let w = drw.fontset_getwidth("abcd");
+		assert_eq!(drw.wrap("abcdefghij", w), [0..4, 4..8, 8..10]);
+		// Each row has a character, however narrow it is.
+		assert_eq!(drw.wrap("ab", 0), [0..1, 1..2]);
+	}
+
+	#[test]
+	fn rows_are_no_wider_than_asked() {
+		let drw = drw();
+		let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit";
+		for w in [40, 70, 100, 150] {
+			for row in drw.wrap(text, w) {
+				let row = text[row].trim_end();
+				assert!(drw.fontset_getwidth(row) <= w, "{:?}", row);
+			}
+		}
+	}
+
+	#[test]
+	fn wide_characters_take_two_cells_of_a_monospace_font() {
+		let fnt = Fnt::create("monospace:pixelsize=12").unwrap();
+		let cell = fnt.getexts("a");
+		assert!(cell > 0);
+		assert_eq!(fnt.getexts("abc"), 3 * cell);
+		assert_eq!(fnt.getexts("日本語"), 6 * cell);
+		// Marks are drawn over the character they follow.
+		assert_eq!(fnt.getexts("e\u{301}"), cell);
+	}
+
+	/// The colour of the buffer at `x`, `y`, as mapped.
+	fn mapped(drw: &Drw, x: u32, y: u32) -> Clr {
+		let mut buf = [0; 4];
+		drw.map(&mut buf, 4, [x, y, 1, 1]);
+		u32::from_ne_bytes(buf)
+	}
+
+	#[test]
+	fn borders_surround_the_pixmap() {
+		let mut drw = drw();
+		drw.resize(4, 2);
+		drw.setborder(2, 0xff112233, 0);
+		assert_eq!(drw.size(), (8, 6));
+		drw.setscheme([0xff445566, 0, 0]);
+		drw.rect(0, 0, 4, 2, true, false);
+		for (x, y) in [(0, 0), (1, 3), (6, 2), (7, 5), (3, 1), (4, 4)] {
+			assert_eq!(mapped(&drw, x, y), 0xff112233, "at {}, {}", x, y);
+		}
+		for (x, y) in [(2, 2), (5, 3), (3, 2)] {
+			assert_eq!(mapped(&drw, x, y), 0xff445566, "at {}, {}", x, y);
+		}
+	}
+
+	#[test]
+	fn damage_is_offset_by_the_border() {
+		let mut drw = drw();
+		drw.resize(4, 2);
+		drw.setborder(3, 0xff112233, 0);
+		assert_eq!(drw.damage(), [[0, 0, 10, 8]]);
+		drw.setscheme([0xff445566, 0, 0]);
+		drw.rect(1, 1, 2, 1, true, false);
+		assert_eq!(drw.damage(), [[4, 4, 2, 1]]);
+	}
+}
@@ -0,0 +1,170 @@
+//! Items listed by a command for each query, with `--dynamic`, in place of
+//! those read from stdin, and the preview a command prints of each item
+//! selected, with `--preview`. The command is run on a thread of its own
+//! once typing or moving the selection pauses, and what it prints for any
+//! but the latest is dropped.
+
+use std::{
+	io,
+	os::fd::{AsFd, BorrowedFd, OwnedFd},
+	process::{Command, Stdio},
+	sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+	thread,
+	time::{Duration, Instant},
+};
+
+use crate::feed::{drain, eventfd, signal};
+
+/// How long typing must pause before the command is run, and the longest a
+/// query waits for it to.
+const DEBOUNCE: Duration = Duration::from_millis(80);
+const MAX_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Default)]
+struct Shared {
+	/// The latest query, and its generation, if it has yet to be run for.
+	query: Option<(u64, String)>,
+	/// The lines listed for the query of a generation.
+	done: Option<(u64, Vec<String>)>,
+}
+
+struct Queue {
+	shared: Mutex<Shared>,
+	ready: Condvar,
+}
+
+impl Queue {
+	fn lock(&self) -> MutexGuard<'_, Shared> {
+		self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+pub struct Dynamic {
+	queue: Arc<Queue>,
+	generation: u64,
+	/// An eventfd which is readable when there are items to take.
+	fd: OwnedFd,
+}
+
+impl Dynamic {
+	/// Starts a thread running `command` for each query, which it is given
+	/// as its last argument and as `$WMENU_QUERY`.
+	pub fn spawn(command: String) -> io::Result<Dynamic> {
+		Dynamic::start(format!("{} \"$1\"", command), "WMENU_QUERY")
+	}
+
+	/// Starts a thread running `command` for each item selected, which it
+	/// is given in place of each `{}` in it, or else as its last argument,
+	/// and as `$WMENU_ITEM`.
+	pub fn preview(command: String) -> io::Result<Dynamic> {
+		let script = match command.contains("{}") {
+			true => command.replace("{}", "\"$1\""),
+			false => format!("{} \"$1\"", command),
+		};
+		Dynamic::start(script, "WMENU_ITEM")
+	}
+
+	/// Starts a thread running the shell `script` for each query, which it
+	/// is given as `$1` and as the variable `var`.
+	fn start(script: String, var: &'static str) -> io::Result<Dynamic> {
+		let fd = eventfd()?;
+		let queue = Arc::new(Queue {
+			shared: Mutex::new(Shared::default()),
+			ready: Condvar::new(),
+		});
+		let (shared, wake) = (queue.clone(), fd.try_clone()?);
+		thread::spawn(move || work(&shared, &script, var, wake.as_fd()));
+		Ok(Dynamic { queue, generation: 0, fd })
+	}
+
+	/// Runs the command for `query`, in place of any earlier one.
+	pub fn query(&mut self, query: &str) {
+		self.generation += 1;
+		self.queue.lock().query = Some((self.generation, query.to_string()));
+		self.queue.ready.notify_one();
+	}
+
+	/// Takes the lines listed for the latest query, if they are ready.
+	pub fn take(&mut self) -> Option<Vec<String>> {
+		drain(self.fd.as_fd());
+		let done = self.queue.lock().done.take();
+		match done {
+			Some((generation, lines)) if generation == self.generation => {
+				Some(lines)
+			}
+			_ => None,
+		}
+	}
+}
+
+impl AsFd for Dynamic {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+/// Runs `script` for each query sent, once they pause, signalling `wake`
+/// when what it printed is ready.
+fn work(queue: &Queue, script: &str, var: &str, wake: BorrowedFd) {
+	let mut shared = queue.lock();
+	loop {
+		let Some(mut query) = shared.query.take() else {
+			shared = queue
+				.ready
+				.wait(shared)
+				.unwrap_or_else(PoisonError::into_inner);
+			continue;
+		};
+		// The first query is run for at once, as there is nothing to show
+		// until it is.
+		let first = Instant::now();
+		while query.0 > 1 {
+			let wait = DEBOUNCE.min(MAX_DELAY.saturating_sub(first.elapsed()));
+			let (guard, timeout) = queue
+				.ready
+				.wait_timeout(shared, wait)
+				.unwrap_or_else(PoisonError::into_inner);
+			shared = guard;
+			match shared.query.take() {
+				Some(newer) => query = newer,
+				None if timeout.timed_out() => break,
+				None => {}
+			}
+		}
+		drop(shared);
+
+		let lines = run(script, var, &query.1);
+		shared = queue.lock();
+		// A newer query is run for next, and these lines are dropped.
+		if shared.query.is_none()
+			&& let Some(lines) = lines
+		{
+			shared.done = Some((query.0, lines));
+			signal(wake);
+		}
+	}
+}
+
+/// The lines `script` prints for `query`, which it is given as `$1` and as
+/// the variable `var`, or `None` if it cannot be run.
+fn run(script: &str, var: &str, query: &str) -> Option<Vec<String>> {
+	let output = Command::new("/bin/sh")
+		.arg("-c")
+		.arg(script)
+		.arg("sh")
+		.arg(query)
+		.env(var, query)
+		.stdin(Stdio::null())
+		.stderr(Stdio::inherit())
+		.output();
+	match output {
+		Ok(output) => {
+			let text = String::from_utf8_lossy(&output.stdout);
+			Some(text.lines().map(String::from).collect())
+		}
+		Err(err) => {
+			eprintln!("cannot run '{}': {}", script, err);
+			None
+		}
+	}
+}
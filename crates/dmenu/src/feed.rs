@@ -0,0 +1,155 @@
+//! Items read from stdin in the background with `-f`, so that the menu can
+//! be shown and typed into before the input ends.
+
+use std::{
+	io,
+	mem,
+	os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+	sync::{Arc, Mutex, PoisonError},
+	thread,
+};
+
+use crate::menu::Item;
+
+#[derive(Default)]
+struct Pending {
+	items: Vec<Item>,
+	done: bool,
+}
+
+pub struct Feed {
+	pending: Arc<Mutex<Pending>>,
+	/// An eventfd which is readable when there is something to take.
+	fd: OwnedFd,
+}
+
+impl Feed {
+	/// Starts `read` on a thread of its own, passing it a function to hand
+	/// each item over with.
+	pub fn spawn(
+		read: impl FnOnce(&mut dyn FnMut(Item)) + Send + 'static,
+	) -> io::Result<Feed> {
+		let fd = eventfd()?;
+		let pending = Arc::new(Mutex::new(Pending::default()));
+
+		let (shared, wake) = (pending.clone(), fd.try_clone()?);
+		// Hands an item over, or with none, says that there are no more.
+		let push = move |item: Option<Item>| {
+			let mut pending =
+				shared.lock().unwrap_or_else(PoisonError::into_inner);
+			// Only the first item since the last take needs to wake the menu;
+			// the rest are taken along with it.
+			let idle = pending.items.is_empty() && !pending.done;
+			match item {
+				Some(item) => pending.items.push(item),
+				None => pending.done = true,
+			}
+			if idle {
+				signal(wake.as_fd());
+			}
+		};
+		thread::spawn(move || {
+			read(&mut |item| push(Some(item)));
+			push(None);
+		});
+		Ok(Feed { pending, fd })
+	}
+
+	/// Takes the items which have arrived since the last call, and whether
+	/// they are the last.
+	pub fn take(&self) -> (Vec<Item>, bool) {
+		drain(self.fd.as_fd());
+		let mut pending =
+			self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+		(mem::take(&mut pending.items), pending.done)
+	}
+}
+
+impl AsFd for Feed {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+/// A non-blocking eventfd, for waking the menu from another thread.
+pub fn eventfd() -> io::Result<OwnedFd> {
+	// SAFETY: no pointers are passed, and the descriptor is owned by the
+	// caller alone.
+	match unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) } {
+		-1 => Err(io::Error::last_os_error()),
+		fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+	}
+}
+
+pub fn signal(fd: BorrowedFd) {
+	let one = 1u64;
+	// SAFETY: the buffer is as long as the length given.
+	unsafe { libc::write(fd.as_raw_fd(), (&raw const one).cast(), 8) };
+}
+
+/// Resets the counter of an eventfd, so that it is no longer readable,
+/// returning whether it was.
+pub fn drain(fd: BorrowedFd) -> bool {
+	let mut count = 0u64;
+	// SAFETY: the buffer is as long as the length given. The read only
+	// resets the counter, so failing because it is zero is harmless.
+	unsafe { libc::read(fd.as_raw_fd(), (&raw mut count).cast(), 8) == 8 }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{sync::mpsc, time::Duration};
+
+	use super::*;
+
+	/// Waits for `fd` to be readable, for at most a second.
+	fn wait(fd: BorrowedFd) -> bool {
+		let mut pollfd = libc::pollfd {
+			fd: fd.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		};
+		// SAFETY: a single pollfd is passed.
+		unsafe { libc::poll(&mut pollfd, 1, 1000) == 1 }
+	}
+
+	fn texts(items: &[Item]) -> Vec<&str> {
+		items.iter().map(|item| item.text.as_str()).collect()
+	}
+
+	#[test]
+	fn items_are_taken_as_they_arrive() {
+		let (tx, rx) = mpsc::channel::<()>();
+		let feed = Feed::spawn(move |push| {
+			push(Item::new("first".to_string()));
+			// The rest wait until the first has been taken.
+			let _ = rx.recv_timeout(Duration::from_secs(5));
+			push(Item::new("second".to_string()));
+			push(Item::new("third".to_string()));
+		})
+		.unwrap();
+		assert!(wait(feed.as_fd()));
+		let (items, done) = feed.take();
+		assert_eq!((texts(&items), done), (vec!["first"], false));
+		tx.send(()).unwrap();
+		let mut items = Vec::new();
+		while wait(feed.as_fd()) {
+			let (taken, done) = feed.take();
+			items.extend(taken);
+			if done {
+				break;
+			}
+		}
+		assert_eq!(texts(&items), ["second", "third"]);
+	}
+
+	#[test]
+	fn eventfds_are_readable_until_drained() {
+		let fd = eventfd().unwrap();
+		assert!(!drain(fd.as_fd()));
+		signal(fd.as_fd());
+		signal(fd.as_fd());
+		assert!(drain(fd.as_fd()));
+		assert!(!drain(fd.as_fd()));
+	}
+}
@@ -0,0 +1,250 @@
+//! Icons named by items, for `--icons`, found in the icon themes as the
+//! freedesktop icon theme specification sets out. Only PNG icons are
+//! drawn. Where each icon was found is kept in a cache file, so that later
+//! runs need not look through the themes again.
+
+use std::{
+	collections::HashMap,
+	env,
+	fs,
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	desktop,
+	drw::{self, Clr},
+};
+
+/// An icon scaled to be drawn.
+pub struct Image {
+	pub size: usize,
+	pub pixels: Vec<Clr>,
+}
+
+/// A directory of icons in a theme, and the sizes they suit.
+struct Dir {
+	path: String,
+	size: u32,
+	min: u32,
+	max: u32,
+}
+
+impl Dir {
+	/// The directory `path`, from the keys of its section, if it gives a
+	/// size.
+	fn new(
+		path: &str,
+		size: Option<u32>,
+		min: Option<u32>,
+		max: Option<u32>,
+		threshold: u32,
+		kind: &str,
+	) -> Option<Dir> {
+		let size = size?;
+		let (min, max) = match kind {
+			"Fixed" => (size, size),
+			"Scalable" => (min.unwrap_or(size), max.unwrap_or(size)),
+			_ => (size.saturating_sub(threshold), size + threshold),
+		};
+		Some(Dir { path: path.to_string(), size, min, max })
+	}
+
+	/// How far `size` is from those the icons suit.
+	fn distance(&self, size: u32) -> u32 {
+		match size {
+			size if size < self.min => self.min - size,
+			size if size > self.max => size - self.max,
+			_ => 0,
+		}
+	}
+}
+
+struct Theme {
+	dirs: Vec<Dir>,
+}
+
+pub struct Icons {
+	/// The directories themes are in, most important first.
+	bases: Vec<PathBuf>,
+	/// The theme chosen and those it inherits from, in the order looked in.
+	themes: Vec<(String, Theme)>,
+	/// The icons loaded, by name, or `None` for those which were not found.
+	loaded: HashMap<String, Option<Image>>,
+	/// Where icons were found before, by name and size.
+	found: HashMap<(String, u32), PathBuf>,
+	cache: Option<PathBuf>,
+}
+
+impl Icons {
+	/// Finds icons in the theme `name` and those it inherits from, falling
+	/// back on `hicolor`.
+	pub fn new(name: &str) -> Icons {
+		let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+		let home = var("HOME").map(|home| PathBuf::from(home).join(".icons"));
+		let bases = home
+			.into_iter()
+			.chain(desktop::dirs().into_iter().map(|dir| dir.join("icons")))
+			.collect::<Vec<_>>();
+		let mut icons = Icons {
+			bases,
+			themes: Vec::new(),
+			loaded: HashMap::new(),
+			found: HashMap::new(),
+			cache: var("XDG_CACHE_HOME")
+				.map(PathBuf::from)
+				.or_else(|| Some(PathBuf::from(var("HOME")?).join(".cache")))
+				.map(|dir| dir.join("wmenu").join("icons")),
+		};
+		let mut queue = vec![name.to_string()];
+		while let Some(name) = queue.pop() {
+			if icons.themes.iter().any(|(theme, _)| *theme == name) {
+				continue;
+			}
+			let Some((theme, inherits)) = icons.theme(&name) else {
+				continue;
+			};
+			icons.themes.push((name, theme));
+			queue.extend(inherits.into_iter().rev());
+		}
+		if !icons.themes.iter().any(|(theme, _)| theme == "hicolor")
+			&& let Some((theme, _)) = icons.theme("hicolor")
+		{
+			icons.themes.push(("hicolor".into(), theme));
+		}
+		icons.read_cache();
+		icons
+	}
+
+	/// Reads the `index.theme` of the theme `name`, giving its directories
+	/// and the themes it inherits from.
+	fn theme(&self, name: &str) -> Option<(Theme, Vec<String>)> {
+		let text = self.bases.iter().find_map(|base| {
+			fs::read_to_string(base.join(name).join("index.theme")).ok()
+		})?;
+		let mut dirs = Vec::new();
+		let mut inherits = Vec::new();
+		let mut section = String::new();
+		// The keys of each directory's section, as they are read.
+		let (mut size, mut min, mut max, mut threshold, mut kind) =
+			(None, None, None, 2, String::new());
+		for line in text.lines().map(str::trim) {
+			if let Some(name) =
+				line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+			{
+				dirs.extend(Dir::new(
+					&section, size, min, max, threshold, &kind,
+				));
+				section = name.to_string();
+				(size, min, max, threshold) = (None, None, None, 2);
+				kind.clear();
+				continue;
+			}
+			let Some((key, value)) = line.split_once('=') else {
+				continue;
+			};
+			let (key, value) = (key.trim(), value.trim());
+			if section == "Icon Theme" {
+				if key == "Inherits" {
+					inherits = value.split(',').map(String::from).collect();
+				}
+				continue;
+			}
+			match key {
+				"Size" => size = value.parse().ok(),
+				"MinSize" => min = value.parse().ok(),
+				"MaxSize" => max = value.parse().ok(),
+				"Threshold" => threshold = value.parse().unwrap_or(2),
+				"Type" => kind = value.to_string(),
+				_ => {}
+			}
+		}
+		dirs.extend(Dir::new(&section, size, min, max, threshold, &kind));
+		// Directories of larger icons are preferred where several suit.
+		dirs.sort_by_key(|dir| u32::MAX - dir.size);
+		Some((Theme { dirs }, inherits))
+	}
+
+	/// The icon `name`, which may also be a path, scaled to `size` pixels.
+	pub fn get(&mut self, name: &str, size: u32) -> Option<&Image> {
+		if !self.loaded.contains_key(name) {
+			let image = self.find(name, size).and_then(|path| {
+				let (w, h, pixels) = drw::decode(&fs::read(path).ok()?)?;
+				let pixels =
+					drw::shrink(&pixels, (w, h), (size as _, size as _));
+				Some(Image { size: size as usize, pixels })
+			});
+			self.loaded.insert(name.to_string(), image);
+		}
+		self.loaded[name].as_ref()
+	}
+
+	/// The file of the icon `name` best suited to `size`: the closest in
+	/// size in the first theme which has it, or else one in the pixmaps.
+	fn find(&mut self, name: &str, size: u32) -> Option<PathBuf> {
+		if Path::new(name).is_absolute() {
+			return Some(PathBuf::from(name));
+		}
+		let key = (name.to_string(), size);
+		if let Some(path) = self.found.get(&key).filter(|path| path.exists()) {
+			return Some(path.clone());
+		}
+		let file = format!("{}.png", name);
+		let file = &file;
+		let best = self.themes.iter().find_map(|(theme, dirs)| {
+			let mut found = dirs.dirs.iter().flat_map(|dir| {
+				self.bases.iter().filter_map(move |base| {
+					let path = base.join(theme).join(&dir.path).join(file);
+					path.is_file().then_some((dir.distance(size), path))
+				})
+			});
+			let first = found.next()?;
+			Some(found.fold(first, |best, next| match next.0 < best.0 {
+				true => next,
+				false => best,
+			}))
+		});
+		let path = best.map(|(_, path)| path).or_else(|| {
+			["/usr/share/pixmaps", "/usr/local/share/pixmaps"]
+				.iter()
+				.map(|dir| Path::new(dir).join(file))
+				.find(|path| path.is_file())
+		})?;
+		self.remember(key, &path);
+		Some(path)
+	}
+
+	/// Reads where icons were found in earlier runs.
+	fn read_cache(&mut self) {
+		let Some(text) = self
+			.cache
+			.as_ref()
+			.and_then(|cache| fs::read_to_string(cache).ok())
+		else {
+			return;
+		};
+		for line in text.lines() {
+			let mut fields = line.splitn(3, '\t');
+			if let (Some(size), Some(name), Some(path)) =
+				(fields.next(), fields.next(), fields.next())
+				&& let Ok(size) = size.parse()
+			{
+				self.found.insert((name.to_string(), size), path.into());
+			}
+		}
+	}
+
+	/// Adds where an icon was found to the cache file. A cache which cannot
+	/// be written is left as it is.
+	fn remember(&mut self, (name, size): (String, u32), path: &Path) {
+		if let Some(cache) = &self.cache
+			&& let Some(dir) = cache.parent()
+			&& fs::create_dir_all(dir).is_ok()
+			&& let Ok(mut file) =
+				fs::OpenOptions::new().create(true).append(true).open(cache)
+		{
+			let _ = writeln!(file, "{}\t{}\t{}", size, name, path.display());
+		}
+		self.found.insert((name, size), path.to_path_buf());
+	}
+}
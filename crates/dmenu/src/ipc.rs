@@ -0,0 +1,141 @@
+//! Commands sent to a running menu over a Unix socket with `--ipc`, one to
+//! a line, which change its items, prompt or selection while it is shown,
+//! or close it, and those of them given in line with the items on stdin.
+
+use std::{
+	fs,
+	io::{self, BufRead, BufReader},
+	mem,
+	os::{
+		fd::{AsFd, BorrowedFd, OwnedFd},
+		unix::net::{UnixListener, UnixStream},
+	},
+	path::PathBuf,
+	sync::{Arc, Mutex, PoisonError},
+	thread,
+};
+
+use crate::feed::{drain, eventfd, signal};
+
+pub enum Command {
+	/// Adds an item, read as those from stdin are.
+	Append(String),
+	/// Removes every item.
+	Clear,
+	Prompt(String),
+	/// Shows a message at the end of the input bar, or none if empty.
+	Message(String),
+	/// Adds a header, which is shown above the items after it while nothing
+	/// is typed, and cannot be chosen.
+	Header(String),
+	/// Selects the match at an index, counting from the first.
+	Select(usize),
+	Close,
+}
+
+impl Command {
+	/// Parses a line sent, as in `append text` or `close`.
+	fn parse(line: &str) -> Option<Command> {
+		let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+		match name {
+			"append" => Some(Command::Append(arg.to_string())),
+			"clear" => Some(Command::Clear),
+			"prompt" => Some(Command::Prompt(arg.to_string())),
+			"message" => Some(Command::Message(arg.to_string())),
+			"header" => Some(Command::Header(arg.to_string())),
+			"select" => arg.trim().parse().ok().map(Command::Select),
+			"close" => Some(Command::Close),
+			_ => None,
+		}
+	}
+
+	/// Parses a line of items naming a command after a NUL, as in
+	/// `\0prompt\x1ftext`, as rofi reads them. Only the prompt, message and
+	/// headers can be changed so.
+	pub fn control(line: &str) -> Option<Command> {
+		let (name, arg) = line.strip_prefix('\0')?.split_once('\x1f')?;
+		match name {
+			"prompt" => Some(Command::Prompt(arg.to_string())),
+			"message" => Some(Command::Message(arg.to_string())),
+			"header" => Some(Command::Header(arg.to_string())),
+			_ => None,
+		}
+	}
+}
+
+pub struct Ipc {
+	pending: Arc<Mutex<Vec<Command>>>,
+	/// An eventfd which is readable when there are commands to take.
+	fd: OwnedFd,
+	path: PathBuf,
+}
+
+impl Ipc {
+	/// Listens at `path`, taking the place of a socket left there by a
+	/// menu which has since exited.
+	pub fn bind(path: PathBuf) -> io::Result<Ipc> {
+		let listener = match UnixListener::bind(&path) {
+			Err(err)
+				if err.kind() == io::ErrorKind::AddrInUse
+					&& UnixStream::connect(&path).is_err() =>
+			{
+				fs::remove_file(&path)?;
+				UnixListener::bind(&path)?
+			}
+			result => result?,
+		};
+		let fd = eventfd()?;
+		let pending = Arc::new(Mutex::new(Vec::new()));
+
+		let (shared, wake) = (pending.clone(), Arc::new(fd.try_clone()?));
+		thread::spawn(move || {
+			// Each client is read on a thread of its own, so that one which
+			// is slow to send holds up no other.
+			for stream in listener.incoming().flatten() {
+				let (shared, wake) = (shared.clone(), wake.clone());
+				thread::spawn(move || {
+					for line in BufReader::new(stream).lines() {
+						let Ok(line) = line else {
+							break;
+						};
+						let Some(command) = Command::parse(&line) else {
+							eprintln!("unknown command '{}'", line);
+							continue;
+						};
+						let mut pending = shared
+							.lock()
+							.unwrap_or_else(PoisonError::into_inner);
+						// Only the first command since the last take needs to
+						// wake the menu.
+						if pending.is_empty() {
+							signal(wake.as_fd());
+						}
+						pending.push(command);
+					}
+				});
+			}
+		});
+		Ok(Ipc { pending, fd, path })
+	}
+
+	/// Takes the commands which have arrived since the last call, in the
+	/// order they were sent.
+	pub fn take(&self) -> Vec<Command> {
+		drain(self.fd.as_fd());
+		let mut pending =
+			self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+		mem::take(&mut *pending)
+	}
+}
+
+impl AsFd for Ipc {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+impl Drop for Ipc {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
@@ -0,0 +1,177 @@
+//! JSON, as items are read with `--json` and printed with `--json-output`.
+//! Only as much is parsed as items need: numbers are kept as the text they
+//! are written as.
+
+use std::{iter::Peekable, str::Chars};
+
+pub enum Value {
+	Null,
+	Bool(bool),
+	Number(String),
+	String(String),
+	/// An array, of which nothing is kept, as no part of an item is one.
+	Array,
+	Object(Vec<(String, Value)>),
+}
+
+impl Value {
+	/// The value of the member `name` of an object.
+	pub fn get(&self, name: &str) -> Option<&Value> {
+		match self {
+			Value::Object(members) => {
+				members.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+			}
+			_ => None,
+		}
+	}
+
+	/// The text of a string, or of a number as it is written.
+	pub fn text(&self) -> Option<&str> {
+		match self {
+			Value::String(text) | Value::Number(text) => Some(text),
+			_ => None,
+		}
+	}
+
+	/// Whether the value is true, as a missing one is not.
+	pub fn truthy(value: Option<&Value>) -> bool {
+		matches!(value, Some(Value::Bool(true)))
+	}
+}
+
+/// Parses `text`, which holds one value and nothing else but spaces.
+pub fn parse(text: &str) -> Option<Value> {
+	let mut chars = text.chars().peekable();
+	let value = value(&mut chars)?;
+	skip(&mut chars);
+	chars.peek().is_none().then_some(value)
+}
+
+fn value(chars: &mut Peekable<Chars>) -> Option<Value> {
+	skip(chars);
+	match chars.peek()? {
+		'"' => string(chars).map(Value::String),
+		'{' => {
+			chars.next();
+			let mut members = Vec::new();
+			skip(chars);
+			if chars.next_if_eq(&'}').is_some() {
+				return Some(Value::Object(members));
+			}
+			loop {
+				skip(chars);
+				let name = string(chars)?;
+				skip(chars);
+				chars.next_if_eq(&':')?;
+				members.push((name, value(chars)?));
+				skip(chars);
+				match chars.next()? {
+					',' => continue,
+					'}' => return Some(Value::Object(members)),
+					_ => return None,
+				}
+			}
+		}
+		'[' => {
+			chars.next();
+			skip(chars);
+			if chars.next_if_eq(&']').is_some() {
+				return Some(Value::Array);
+			}
+			loop {
+				value(chars)?;
+				skip(chars);
+				match chars.next()? {
+					',' => continue,
+					']' => return Some(Value::Array),
+					_ => return None,
+				}
+			}
+		}
+		'-' | '0'..='9' => {
+			let mut number = String::new();
+			while let Some(c) = chars.next_if(|c| {
+				c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')
+			}) {
+				number.push(c);
+			}
+			number.parse::<f64>().ok()?;
+			Some(Value::Number(number))
+		}
+		_ => {
+			let mut word = String::new();
+			while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+				word.push(c);
+			}
+			match word.as_str() {
+				"null" => Some(Value::Null),
+				"true" => Some(Value::Bool(true)),
+				"false" => Some(Value::Bool(false)),
+				_ => None,
+			}
+		}
+	}
+}
+
+/// Reads a string, from its opening quote to its closing one.
+fn string(chars: &mut Peekable<Chars>) -> Option<String> {
+	chars.next_if_eq(&'"')?;
+	let mut text = String::new();
+	loop {
+		match chars.next()? {
+			'"' => return Some(text),
+			'\\' => match chars.next()? {
+				'n' => text.push('\n'),
+				't' => text.push('\t'),
+				'r' => text.push('\r'),
+				'b' => text.push('\x08'),
+				'f' => text.push('\x0c'),
+				'u' => {
+					let mut unit = hex(chars)?;
+					// Characters past the first plane are written as a pair
+					// of surrogates.
+					if (0xd800..0xdc00).contains(&unit) {
+						chars.next_if_eq(&'\\')?;
+						chars.next_if_eq(&'u')?;
+						let low = hex(chars)?;
+						if !(0xdc00..0xe000).contains(&low) {
+							return None;
+						}
+						unit = 0x10000 + ((unit - 0xd800) << 10) + low - 0xdc00;
+					}
+					text.push(char::from_u32(unit).unwrap_or('\u{fffd}'));
+				}
+				c => text.push(c),
+			},
+			c => text.push(c),
+		}
+	}
+}
+
+/// Reads the four hex digits of a `\u` escape.
+fn hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+	(0..4).try_fold(0, |n, _| Some(n * 16 + chars.next()?.to_digit(16)?))
+}
+
+fn skip(chars: &mut Peekable<Chars>) {
+	while chars.next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r')).is_some() {}
+}
+
+/// `text` as a JSON string.
+pub fn quote(text: &str) -> String {
+	let mut out = String::with_capacity(text.len() + 2);
+	out.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\r' => out.push_str("\\r"),
+			c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
@@ -0,0 +1,176 @@
+//! Starting the commands chosen with `--run` or `--desktop`, which live on
+//! after the menu exits.
+
+use std::{
+	env,
+	io,
+	os::unix::process::CommandExt,
+	process::{self, Command, Stdio},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Characters which mean a command needs the shell to run it.
+const SHELL_CHARS: &str = "|&;<>()$`\\\"'*?[#~=%\t\n";
+
+/// What the menu was started with which is its own, and is not passed on
+/// to what it launches: the token it was activated with, and its own
+/// variables.
+const OWN_VARS: [&str; 2] = ["XDG_ACTIVATION_TOKEN", "DESKTOP_STARTUP_ID"];
+const OWN_PREFIX: &str = "WMENU_";
+
+/// What commands are launched in, with `--scope`, so that they are their
+/// own units of the user's service manager rather than part of the menu's.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+	/// A scope of its own, started with `systemd-run`.
+	Systemd,
+	/// A unit started with `uwsm app`, as under a session it manages.
+	Uwsm,
+}
+
+impl Scope {
+	pub fn parse(name: &str) -> Option<Scope> {
+		match name {
+			"systemd" => Some(Scope::Systemd),
+			"uwsm" => Some(Scope::Uwsm),
+			_ => None,
+		}
+	}
+
+	/// The command line which runs another in the scope, named for the
+	/// application `id` as the XDG names of units have it, as in
+	/// `app-wmenu-firefox-5e3a1b.scope`.
+	fn wrap(self, id: &str) -> Vec<String> {
+		match self {
+			Scope::Systemd => {
+				let id: String = id
+					.chars()
+					.map(|c| match c.is_ascii_alphanumeric() || c == '.' {
+						true => c,
+						false => '_',
+					})
+					.collect();
+				let now = SystemTime::now().duration_since(UNIX_EPOCH);
+				let random = now.unwrap_or_default().subsec_nanos()
+					^ process::id().rotate_left(16);
+				let unit = format!("app-wmenu-{}-{:08x}.scope", id, random);
+				["systemd-run", "--user", "--scope", "--quiet"]
+					.into_iter()
+					.map(String::from)
+					.chain([format!("--unit={}", unit)])
+					.chain(["--slice=app.slice".into(), "--".into()])
+					.collect()
+			}
+			Scope::Uwsm => vec!["uwsm".into(), "app".into(), "--".into()],
+		}
+	}
+}
+
+/// Runs `command`, in `dir` if given, in a process group of its own so that
+/// it is not killed along with the menu, and in `scope` if given, named for
+/// the application `id`.
+pub fn launch(
+	command: &str,
+	dir: Option<&str>,
+	scope: Option<Scope>,
+	id: &str,
+) -> io::Result<()> {
+	let argv = argv(command, scope, id)?;
+	let mut child = Command::new(&argv[0]);
+	child.args(&argv[1..]).stdin(Stdio::null()).process_group(0);
+	for (name, _) in env::vars_os() {
+		let own = name.to_str().is_some_and(|name| {
+			OWN_VARS.contains(&name) || name.starts_with(OWN_PREFIX)
+		});
+		if own {
+			child.env_remove(name);
+		}
+	}
+	if let Some(dir) = dir {
+		child.current_dir(dir);
+	}
+	child.spawn().map(drop)
+}
+
+/// The command line `command` is run with, in `scope` if given.
+///
+/// Commands which are only words separated by spaces are run directly, so
+/// that failing to run them can be reported; others are left to the shell.
+fn argv(
+	command: &str,
+	scope: Option<Scope>,
+	id: &str,
+) -> io::Result<Vec<String>> {
+	let mut argv: Vec<String> =
+		match command.contains(|c| SHELL_CHARS.contains(c)) {
+			true => {
+				let exec = format!("exec {}", command);
+				vec!["/bin/sh".into(), "-c".into(), exec]
+			}
+			false => {
+				let words = command.split(' ').filter(|word| !word.is_empty());
+				words.map(String::from).collect()
+			}
+		};
+	if argv.is_empty() {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"the command is empty",
+		));
+	}
+	if let Some(scope) = scope {
+		argv.splice(0..0, scope.wrap(id));
+	}
+	Ok(argv)
+}
+
+/// The names of the programs in `$PATH`, sorted, as `wmenu_path` lists
+/// them from its cache.
+pub fn commands() -> Vec<String> {
+	let path = env::var_os("PATH").unwrap_or_default();
+	wmenu_path::commands(&path, wmenu_path::cache_path().as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn plain_commands_are_run_directly() {
+		let argv = argv("  foot  -e htop ", None, "foot").unwrap();
+		assert_eq!(argv, ["foot", "-e", "htop"]);
+	}
+
+	#[test]
+	fn others_are_left_to_the_shell() {
+		let argv = argv("echo $HOME > out", None, "echo").unwrap();
+		assert_eq!(argv, ["/bin/sh", "-c", "exec echo $HOME > out"]);
+	}
+
+	#[test]
+	fn empty_commands_are_refused() {
+		let err = argv("   ", None, "").unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn commands_are_run_in_their_scope() {
+		let uwsm = argv("htop", Some(Scope::Uwsm), "htop").unwrap();
+		assert_eq!(uwsm, ["uwsm", "app", "--", "htop"]);
+		let systemd =
+			argv("firefox", Some(Scope::Systemd), "org.foo-bar").unwrap();
+		let run = ["systemd-run", "--user", "--scope", "--quiet"];
+		assert_eq!(systemd[..4], run);
+		let unit = systemd[4].strip_prefix("--unit=app-wmenu-org.foo_bar-");
+		let unit = unit.and_then(|unit| unit.strip_suffix(".scope"));
+		assert!(unit.is_some_and(|unit| unit.len() == 8), "{}", systemd[4]);
+		assert_eq!(systemd[5..], ["--slice=app.slice", "--", "firefox"]);
+	}
+
+	#[test]
+	fn scopes_are_parsed_by_name() {
+		assert!(Scope::parse("systemd") == Some(Scope::Systemd));
+		assert!(Scope::parse("uwsm") == Some(Scope::Uwsm));
+		assert!(Scope::parse("openrc").is_none());
+	}
+}
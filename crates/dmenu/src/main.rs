@@ -1,3 +1,677 @@
+use std::{
+	array,
+	env,
+	fs::File,
+	io::{self, BufRead},
+	mem,
+	os::fd::AsFd,
+	path::PathBuf,
+	process::exit,
+};
+
+use compat::Format;
+use drw::{COL_BG, COL_FG, COL_MARK, Drw};
+use feed::Feed;
+use ipc::Ipc;
+use launch::Scope;
+use menu::{
+	Anchor,
+	Item,
+	Menu,
+	Opts,
+	SCHEME_ALT,
+	SCHEME_INPUT,
+	SCHEME_NORM,
+	SCHEME_PROMPT,
+	SCHEME_SEL,
+	Size,
+};
+use reload::Reload;
+use theme::Theme;
+use wmenu_core::{History, Matcher, Mode, Sort};
+
+mod ansi;
+mod clipboard;
+mod compat;
+mod config;
+mod desktop;
+mod drw;
+mod dynamic;
+mod feed;
+mod icons;
+mod ipc;
+mod json;
+mod launch;
+mod markup;
+mod menu;
+mod pinentry;
+mod reload;
+mod theme;
+mod wayland;
+mod worker;
+mod x11;
+mod xkb;
+
+fn die(msg: &str) -> ! {
+	eprintln!("{}", msg);
+	exit(1);
+}
+
+fn usage() -> ! {
+	die(concat!(
+		"usage: dmenu [-bDfFiPSv] [--multi] [--[no-]tokens] [-l lines]\n",
+		"             [-p prompt] [-m output] [-w windowid] [-fn font]\n",
+		"             [-nb color] [-nf color] [-sb color] [-sf color]\n",
+		"             [-nhf color] [-shf color] [--print-index[=fd]] [--index]\n",
+		"             [--only-match] [--count] [--history file] [-d delim]\n",
+		"             [-it text] [--placeholder text] [--[no-]wrap]\n",
+		"             [--word-wrap] [--scroll] [--exclusive-zone pixels]\n",
+		"             [--keyboard mode] [--exclusive] [--inhibit-idle]\n",
+		"             [--run] [--scope systemd|uwsm]\n",
+		"             [--line-height pixels]\n",
+		"             [--padding pixels] [--theme file] [--auto-select]\n",
+		"             [--accept-exact] [--border-width pixels]\n",
+		"             [--border-color color] [--border-radius pixels]\n",
+		"             [--json] [--json-output] [--output-delimiter text] [--quote]\n",
+		"             [--no-input] [--no-input-filter] [--message text]\n",
+		"             [--copy] [--copy-only]\n",
+		"             [--input-delimiter byte] [-W factor] [-c]\n",
+		"             [--width size] [--height size]\n",
+		"             [-g columns] [--matching mode] [--sort order]\n",
+		"             [--normalize] [--mask text] [--anchor top|bottom|center]\n",
+		"             [--margin pixels] [--backend wayland|x11]\n",
+		"             [--color-part color] [--markup] [--ansi]\n",
+		"             [--icons] [--icon-field delim] [--icon-theme name]\n",
+		"             [--with-values] [--ipc[=path]] [--dynamic command]\n",
+		"             [--preview command]\n",
+		"             [--xkb-layout layout] [--xkb-variant variant]\n",
+		"             [--xkb-options options]\n",
+		"             [--bind key=action] [--exit name=status]\n",
+		"             [--pinentry [pinentry options]]",
+	));
+}
+
+/// Reads each line from stdin, ended by `delim`, as an item, passing it to
+/// `push`.
+fn readstdin(delim: u8, push: &mut dyn FnMut(Item)) {
+	for line in io::stdin().lock().split(delim) {
+		match line {
+			Ok(line) => push(Item::new(String::from_utf8_lossy(&line).into())),
+			Err(err) => die(&format!("cannot read stdin: {}", err)),
+		}
+	}
+}
+
+/// Whether stdin is a regular file, which is quick enough to read whole
+/// before the menu is shown. Pipes, whose writers may be slow, are read in
+/// the background.
+fn regular() -> bool {
+	let stdin = io::stdin().as_fd().try_clone_to_owned().map(File::from);
+	stdin.and_then(|file| file.metadata()).is_ok_and(|m| m.is_file())
+}
+
+/// The argument following an option.
+fn value(args: &mut impl Iterator<Item = String>) -> String {
+	args.next().unwrap_or_else(|| usage())
+}
+
+/// The argument following `opt`, as a number of pixels.
+fn pixels(opt: &str, args: &mut impl Iterator<Item = String>) -> u32 {
+	let arg = value(args);
+	arg.parse().unwrap_or_else(|_| {
+		die(&format!("{}: '{}' is not a non-negative number", opt, arg))
+	})
+}
+
+/// `arg` with the escapes \0, \n, \t and \\ in it replaced by the characters
+/// they stand for, as a NUL cannot be given as an argument.
+fn unescape(arg: &str) -> String {
+	let mut out = String::with_capacity(arg.len());
+	let mut chars = arg.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			out.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('0') => out.push('\0'),
+			Some('n') => out.push('\n'),
+			Some('t') => out.push('\t'),
+			Some(c) => out.push(c),
+			None => out.push('\\'),
+		}
+	}
+	out
+}
+
+/// The byte `arg`, unescaped as for --output-delimiter, is, as given to
+/// `opt`.
+fn byte(opt: &str, arg: &str) -> u8 {
+	match unescape(arg).as_bytes() {
+		&[byte] => byte,
+		_ => die(&format!("{}: '{}' is not a single byte", opt, arg)),
+	}
+}
+
+/// Sets what rofi's `-theme-str` sets, as in `window { width: 50%; }`, of
+/// what dmenu can: the width, lines, columns, placeholder, font and
+/// location. Anything else is ignored, as said on stderr.
+fn themestr(text: &str, theme: &mut Theme, opts: &mut Opts) {
+	for (section, name, value) in compat::properties(text) {
+		let number = value.parse().ok();
+		let anchor = match value {
+			"north" => Some(Anchor::Top),
+			"south" => Some(Anchor::Bottom),
+			"center" => Some(Anchor::Center),
+			_ => None,
+		};
+		match (section, name) {
+			("window", "width") if Size::parse(value).is_some() => {
+				opts.width = Size::parse(value)
+			}
+			("window", "location") if anchor.is_some() => theme.anchor = anchor,
+			("listview", "lines") if number.is_some() => theme.lines = number,
+			("listview", "columns") if number.is_some_and(|n| n > 1) => {
+				opts.grid = number
+			}
+			("entry", "placeholder") => opts.placeholder = value.to_string(),
+			(_, "font") => theme.font = Some(compat::font(value)),
+			_ => eprintln!(
+				"-theme-str: ignoring {} {{ {}: {} }}",
+				section, name, value
+			),
+		}
+	}
+}
+
 fn main() {
-	println!("Hello, world!");
+	// Run again with --copy, to keep what was chosen on the clipboard.
+	if let Ok(backend) = env::var(clipboard::SERVE) {
+		clipboard::serve(&backend);
+	}
+	let mut opts = Opts::default();
+	let mut fast = false;
+	let mut ipc = None;
+	let mut desktop = false;
+	let mut theme = Theme::default();
+	let mut theme_path = None;
+	let mut x11 = None;
+	let mut delim = b'\n';
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			// Prints version information.
+			"-v" => {
+				println!("dmenu-{}", env!("CARGO_PKG_VERSION"));
+				exit(0);
+			}
+			// Appears at the bottom of the screen.
+			"-b" | "--bottom" => theme.anchor = Some(Anchor::Bottom),
+			// Floats in the middle of the screen, as a launcher does, sized
+			// with --width and --height.
+			"-c" | "--center" => theme.anchor = Some(Anchor::Center),
+			// Appears at the top, bottom or center of the screen.
+			"--anchor" => {
+				let anchor = Anchor::parse(&value(&mut args));
+				theme.anchor = Some(anchor.unwrap_or_else(|| usage()));
+			}
+			// Space left between the menu and the edges of the screen.
+			"--margin" => {
+				let margin = value(&mut args);
+				match theme::margin(&margin) {
+					Some(margin) => theme.margin = Some(margin),
+					None => die(&format!(
+						"{}: '{}' is not one or four numbers of pixels",
+						arg, margin
+					)),
+				}
+			}
+			// Shows the menu before reading stdin, even from a file.
+			"-f" | "--fast" => fast = true,
+			// Launches applications rather than reading items from stdin.
+			"-D" | "--desktop" => desktop = true,
+			// Runs what is chosen as a command rather than printing it.
+			"--run" => opts.run = true,
+			// Launches and runs commands in a scope of their own, as units
+			// of the user's service manager.
+			"--scope" => {
+				let scope = Scope::parse(&value(&mut args));
+				opts.scope = Some(scope.unwrap_or_else(|| usage()));
+			}
+			// Fuzzy item matching.
+			"-F" | "--fuzzy" => theme.matching = Some(Mode::Fuzzy),
+			// Matches items as a substring, fuzzily, as a prefix, exactly or
+			// as a regular expression.
+			"--matching" => {
+				let mode = Mode::parse(&value(&mut args));
+				theme.matching = Some(mode.unwrap_or_else(|| usage()));
+			}
+			// Case-insensitive item matching.
+			"-i" => theme.icase = Some(true),
+			// Matches letters whatever their accents or other marks.
+			"--normalize" => theme.normalize = Some(true),
+			// Matches are listed in input order, however well they match.
+			"-S" | "--no-sort" => theme.sort = Some(Sort::None),
+			// Lists matches in the given order.
+			"--sort" => {
+				let sort = Sort::parse(&value(&mut args));
+				theme.sort = Some(sort.unwrap_or_else(|| usage()));
+			}
+			// Items match each word typed in any order, or the input as a
+			// whole.
+			"--tokens" => theme.tokens = Some(true),
+			"--no-tokens" => theme.tokens = Some(false),
+			// Masks the input, for reading passwords.
+			"-P" | "--password" => opts.password = true,
+			// Speaks the pinentry protocol on stdin and stdout, asking for
+			// each passphrase in a menu with the options before it. Those
+			// after it are as pinentry programs are given.
+			"--pinentry" => {
+				let menu = env::args().skip(1);
+				let menu = menu.take_while(|arg| arg != "--pinentry").collect();
+				pinentry::run(menu, args)
+			}
+			// Shown in place of each character of a password.
+			"--mask" => theme.mask = Some(value(&mut args)),
+			// Number of vertical listing lines.
+			"-l" | "--lines" => {
+				theme.lines =
+					Some(value(&mut args).parse().unwrap_or_else(|_| usage()))
+			}
+			// Lists items in a grid of the given number of columns, or of
+			// as many as fit with 0.
+			"-g" | "--grid" => {
+				opts.grid =
+					Some(value(&mut args).parse().unwrap_or_else(|_| usage()))
+			}
+			// Tab chooses several items.
+			"--multi" => opts.multi = true,
+			// Prints the index of the item chosen, to stderr or the given
+			// descriptor.
+			"--print-index" => opts.index_fd = Some(2),
+			// Shows how many items match, out of how many there are.
+			"--count" => opts.count = true,
+			// Accepts an item without Return once only one matches, or once
+			// the text typed is one.
+			"--auto-select" => opts.auto_select = true,
+			"--accept-exact" => opts.accept_exact = true,
+			// Accepts only items, never the text typed.
+			"--only-match" => opts.only_match = true,
+			// Prints the index of the item chosen in place of its text.
+			"--index" => opts.index = true,
+			arg if arg.starts_with("--print-index=") => {
+				let fd = arg["--print-index=".len()..].parse();
+				opts.index_fd = Some(fd.unwrap_or_else(|_| usage()));
+			}
+			// Takes commands over a socket at the given path, or in
+			// $XDG_RUNTIME_DIR.
+			"--ipc" => ipc = Some(None),
+			arg if arg.starts_with("--ipc=") => {
+				ipc = Some(Some(PathBuf::from(&arg["--ipc=".len()..])));
+			}
+			// Ranks items by how often and how recently they were chosen.
+			"--history" => {
+				let path = value(&mut args);
+				match History::load(path.clone().into()) {
+					Ok(history) => opts.history = Some(history),
+					Err(err) => die(&format!("cannot read {}: {}", path, err)),
+				}
+			}
+			// Matches only the part of each item after the delimiter.
+			"-d" => opts.delim = Some(value(&mut args)),
+			// Starts with the given text typed.
+			"-it" | "--initial-text" | "--query" => {
+				opts.initial = value(&mut args)
+			}
+			// Shows the given text dimmed while nothing is typed.
+			"--placeholder" => opts.placeholder = value(&mut args),
+			// Shown at the end of the input bar.
+			"--message" => opts.message = value(&mut args),
+			// Ends each item read with the given byte, as for
+			// --output-delimiter, rather than a newline.
+			"--input-delimiter" => delim = byte(&arg, &value(&mut args)),
+			// Fraction of the width of the output the menu takes, centred.
+			"-W" | "--width-factor" => {
+				let factor = value(&mut args);
+				match factor.parse() {
+					Ok(f) if f > 0.0 && f <= 1.0 => {
+						opts.width = Some(Size::Fraction(f))
+					}
+					_ => die(&format!(
+						"{}: '{}' is not a fraction from 0 to 1",
+						arg, factor
+					)),
+				}
+			}
+			// The size of the menu, as a percentage of the output or in
+			// pixels, as in 40% or 600, which --center floats in the middle
+			// of it.
+			"--width" | "--height" => {
+				let size = value(&mut args);
+				let Some(size) = Size::parse(&size) else {
+					die(&format!(
+						"{}: '{}' is not a percentage or a number of pixels",
+						arg, size
+					));
+				};
+				match arg.as_str() {
+					"--width" => opts.width = Some(size),
+					_ => opts.height = Some(size),
+				}
+			}
+			// Hides the input field, leaving items to be chosen with the
+			// arrow keys and the mouse, or as well with typing which filters
+			// them unseen.
+			"--no-input" => opts.no_input = true,
+			"--no-input-filter" => {
+				(opts.no_input, opts.hidden_filter) = (true, true)
+			}
+			// Moving past either end of the list wraps around, or stops.
+			"--wrap" => opts.wrap = Some(true),
+			"--no-wrap" => opts.wrap = Some(false),
+			// Wraps items too wide for a vertical list onto several lines.
+			"--word-wrap" => opts.word_wrap = true,
+			// Scrolls a vertical list an item at a time, rather than a page.
+			"--scroll" => opts.scroll = true,
+			// Draws items as Pango markup, and matches and prints them
+			// without it.
+			"--markup" => opts.markup = true,
+			// Draws the colours of the escape sequences in items, and
+			// matches and prints them without any.
+			"--ansi" => opts.ansi = true,
+			// Lists the items the command prints for the text typed, rather
+			// than reading them from stdin.
+			"--dynamic" => opts.dynamic = Some(value(&mut args)),
+			// Shows what the command prints for the item selected beside the
+			// list.
+			"--preview" => opts.preview = Some(value(&mut args)),
+			// Reads each item as the text shown, a tab and the value printed.
+			"--with-values" => opts.values = true,
+			// Shows an icon beside each item, named after a NUL as in rofi or
+			// before the given delimiter, from the given icon theme.
+			"--icons" => opts.icons = true,
+			"--icon-field" => {
+				opts.icons = true;
+				opts.icon_field = Some(value(&mut args));
+			}
+			"--icon-theme" => opts.icon_theme = Some(value(&mut args)),
+			// Reads each item as a JSON object, of its text and what else it
+			// has.
+			"--json" => opts.json_input = true,
+			// Prints what is chosen as JSON, with its index.
+			"--json-output" => opts.json = true,
+			// Ends each item printed with the given text, in which \0, \n and
+			// \t stand for a NUL, a newline and a tab, rather than a newline.
+			"--output-delimiter" => {
+				opts.output_delim = Some(unescape(&value(&mut args)))
+			}
+			// Copies what is chosen to the clipboard as well as printing
+			// it, or in place of printing it.
+			"--copy" => opts.copy = true,
+			"--copy-only" => (opts.copy, opts.copy_only) = (true, true),
+			// Quotes each item printed as the shell reads it.
+			"--quote" => opts.quote = true,
+			// Height of each line, and padding around text, in pixels.
+			"-H" | "--line-height" => {
+				theme.line_height = Some(pixels(&arg, &mut args))
+			}
+			"--padding" => theme.padding = Some(pixels(&arg, &mut args)),
+			// Width and color of the border around the menu.
+			"-B" | "--border-width" => {
+				theme.border_width = Some(pixels(&arg, &mut args))
+			}
+			"--border-color" => theme.border_color = Some(value(&mut args)),
+			// Radius the corners of the menu are rounded to, in pixels.
+			"-R" | "--border-radius" => {
+				theme.border_radius = Some(pixels(&arg, &mut args))
+			}
+			// Reads the font, colors, spacing, matching and keys from the
+			// given file.
+			"--theme" => theme_path = Some(PathBuf::from(value(&mut args))),
+			// Output to be displayed on.
+			"-m" | "--output" => opts.output = Some(value(&mut args)),
+			// Layout, variant and options of the keymap, in place of the
+			// compositor's.
+			"--xkb-layout" => opts.xkb.layout = Some(value(&mut args)),
+			"--xkb-variant" => opts.xkb.variant = Some(value(&mut args)),
+			"--xkb-options" => opts.xkb.options = Some(value(&mut args)),
+			// X window to be embedded in, by id in decimal or hex.
+			"-w" | "--embed" => {
+				let id = value(&mut args);
+				let parsed = match id.strip_prefix("0x") {
+					Some(hex) => u32::from_str_radix(hex, 16),
+					None => id.parse(),
+				};
+				match parsed {
+					Ok(id) => opts.embed = Some(id),
+					Err(_) => {
+						die(&format!("{}: '{}' is not a window id", arg, id))
+					}
+				}
+			}
+			// Space reserved for the menu, or -1 to overlap everything.
+			"--exclusive-zone" => {
+				let zone = value(&mut args);
+				match zone.parse() {
+					Ok(zone) if zone >= -1 => opts.exclusive_zone = Some(zone),
+					_ => die(&format!(
+						"{}: '{}' is not a number of pixels or -1",
+						arg, zone
+					)),
+				}
+			}
+			// Whether the keyboard is taken for as long as dmenu is shown.
+			"--keyboard" => match value(&mut args).as_str() {
+				"exclusive" => opts.on_demand = false,
+				"on-demand" => opts.on_demand = true,
+				_ => usage(),
+			},
+			// Takes the keyboard for as long as dmenu is shown, and cancels it
+			// if it is taken away.
+			"--exclusive" => opts.exclusive = true,
+			// Keeps the screen from blanking or locking while dmenu is shown.
+			"--inhibit-idle" => opts.inhibit_idle = true,
+			// Shown by the compositor, or on an X server.
+			"--backend" => {
+				x11 = Some(match value(&mut args).as_str() {
+					"wayland" => false,
+					"x11" => true,
+					_ => usage(),
+				})
+			}
+			// Adds prompt to left of input field.
+			"-p" => theme.prompt = Some(value(&mut args)),
+			// Font or font set.
+			"-fn" | "--fn" => theme.font = Some(value(&mut args)),
+			// Normal background color.
+			"-nb" | "--nb" => {
+				theme.colors[SCHEME_NORM][COL_BG] = Some(value(&mut args))
+			}
+			// Normal foreground color.
+			"-nf" | "--nf" => {
+				theme.colors[SCHEME_NORM][COL_FG] = Some(value(&mut args))
+			}
+			// Selected background color.
+			"-sb" | "--sb" | "--hb" => {
+				theme.colors[SCHEME_SEL][COL_BG] = Some(value(&mut args))
+			}
+			// Selected foreground color.
+			"-sf" | "--sf" | "--hf" => {
+				theme.colors[SCHEME_SEL][COL_FG] = Some(value(&mut args))
+			}
+			// Normal and selected colors of matched characters.
+			"-nhf" => {
+				theme.colors[SCHEME_NORM][COL_MARK] = Some(value(&mut args))
+			}
+			"-shf" => {
+				theme.colors[SCHEME_SEL][COL_MARK] = Some(value(&mut args))
+			}
+			// Binds a key to an action, as in ctrl+j=down, in place of what
+			// it otherwise does.
+			"--bind" => {
+				let arg = value(&mut args);
+				let binding = arg
+					.split_once('=')
+					.ok_or_else(|| format!("'{}' is not key=action", arg))
+					.and_then(|(key, action)| theme::binding(key, action));
+				match binding {
+					Ok(binding) => theme.bindings.push(binding),
+					Err(err) => die(&format!("--bind: {}", err)),
+				}
+			}
+			// Exits with the given status when the menu ends as named, as in
+			// custom=0.
+			"--exit" => {
+				let arg = value(&mut args);
+				let status = arg
+					.split_once('=')
+					.ok_or_else(|| format!("'{}' is not name=status", arg))
+					.and_then(|(end, status)| theme::status(end, status));
+				match status {
+					Ok((end, status)) => theme.statuses[end] = Some(status),
+					Err(err) => die(&format!("--exit: {}", err)),
+				}
+			}
+			// rofi's names for the options above, as it takes them with
+			// -dmenu, and what they stand for.
+			"-dmenu" => {}
+			"-no-custom" => opts.only_match = true,
+			"-password" => opts.password = true,
+			"-multi-select" => opts.multi = true,
+			"-markup-rows" => opts.markup = true,
+			"-mesg" => opts.message = value(&mut args),
+			"-sep" => delim = byte(&arg, &value(&mut args)),
+			"-format" => match Format::parse(&value(&mut args)) {
+				Some(Format::Text) => {}
+				Some(Format::Index) => opts.index = true,
+				Some(Format::Quoted) => opts.quote = true,
+				None => die("-format: only s, p, i and q are supported"),
+			},
+			"-theme-str" => themestr(&value(&mut args), &mut theme, &mut opts),
+			// bemenu's colours of its title, filter and alternate rows,
+			// which are the prompt, the input and every other row.
+			"--tb" | "--tf" | "--fb" | "--ff" | "--ab" | "--af" => {
+				let scm = match &arg[2..3] {
+					"t" => SCHEME_PROMPT,
+					"f" => SCHEME_INPUT,
+					_ => SCHEME_ALT,
+				};
+				let col = match &arg[3..] {
+					"b" => COL_BG,
+					_ => COL_FG,
+				};
+				theme.colors[scm][col] = Some(value(&mut args))
+			}
+			// Colours of each part of the menu, as in --color-selected-bg,
+			// and of the border.
+			arg if arg.starts_with("--color-") => {
+				let name = &arg["--color-".len()..];
+				match (name, theme::color(name)) {
+					("border", _) => {
+						theme.border_color = Some(value(&mut args))
+					}
+					(_, Some((scm, col))) => {
+						theme.colors[scm][col] = Some(value(&mut args))
+					}
+					_ => usage(),
+				}
+			}
+			_ => usage(),
+		}
+	}
+
+	if opts.exclusive && (opts.on_demand || opts.embed.is_some()) {
+		die("--exclusive cannot be given with -w or --keyboard on-demand");
+	}
+	// Nothing of a password is kept in the history, or in a core dump.
+	if opts.password {
+		opts.history = None;
+		// SAFETY: no pointers are passed.
+		unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) };
+	}
+	// SAFETY: no pointers are passed.
+	if let Some(fd) = opts.index_fd
+		&& (fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1)
+	{
+		die(&format!("cannot print the index to descriptor {}", fd));
+	}
+
+	// The theme file only sets what the command line leaves unset, and need
+	// not exist unless it was given. It is read again for the colours and
+	// sizes whenever it changes.
+	let given = theme_path.is_some();
+	let path = theme_path.or_else(Theme::path);
+	let command_line = theme.clone();
+	if let Some(path) = &path {
+		theme = theme.read(path, given).unwrap_or_else(|err| die(&err));
+	}
+	match Reload::new(command_line, path, given) {
+		Ok(reload) => opts.reload = Some(reload),
+		Err(err) => eprintln!("cannot watch the theme file: {}", err),
+	}
+	let matcher = Matcher {
+		mode: theme.matching.unwrap_or_default(),
+		icase: theme.icase.unwrap_or(false),
+		normalize: theme.normalize.unwrap_or(false),
+		tokens: theme.tokens.unwrap_or(true),
+		sort: theme.sort.unwrap_or_default(),
+	};
+	opts.prompt = theme.prompt.take().unwrap_or_default();
+	let mask = theme.mask.take();
+	opts.mask = mask.unwrap_or_else(|| config::PASSWORD_MASK.to_string());
+	opts.anchor = theme.anchor.unwrap_or_default();
+	opts.margin = theme.margin.unwrap_or_default();
+	opts.keys = mem::take(&mut theme.keys);
+	opts.bindings = mem::take(&mut theme.bindings);
+	opts.statuses = array::from_fn(|end| {
+		theme.statuses[end].unwrap_or(config::STATUSES[end])
+	});
+	let (fonts, look) = theme.look().unwrap_or_else(|err| die(&err));
+	if let Some(path) = ipc {
+		let runtime = env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty());
+		let path = path
+			.or_else(|| Some(PathBuf::from(runtime?).join("wmenu.sock")))
+			.unwrap_or_else(|| {
+				die("XDG_RUNTIME_DIR is not set, give --ipc a path")
+			});
+		match Ipc::bind(path.clone()) {
+			Ok(ipc) => opts.ipc = Some(ipc),
+			Err(err) => {
+				die(&format!("cannot listen at {}: {}", path.display(), err))
+			}
+		}
+	}
+	// Passwords are typed rather than chosen, and the command lists items
+	// with --dynamic, so there is nothing to read.
+	let mut items = Vec::new();
+	let mut feed = None;
+	let read = !opts.password && opts.dynamic.is_none();
+	if desktop && read {
+		let apps = desktop::scan();
+		let item = |text: &str, icon: &Option<String>| Item {
+			icon: icon.clone(),
+			..Item::new(text.to_string())
+		};
+		items = apps.iter().map(|app| item(&app.name, &app.icon)).collect();
+		// Run as commands, the programs in $PATH are listed too.
+		if opts.run {
+			let commands = launch::commands();
+			items.extend(commands.iter().map(|name| item(name, &None)));
+		}
+		opts.desktop = Some(apps);
+	} else if (fast || !regular()) && read {
+		match Feed::spawn(move |push| readstdin(delim, push)) {
+			Ok(f) => feed = Some(f),
+			Err(err) => die(&format!("cannot read stdin: {}", err)),
+		}
+	} else if read {
+		readstdin(delim, &mut |item| items.push(item));
+	}
+	let menu = Menu::new(items, Drw::new(fonts), matcher, look, opts);
+	// Without a compositor to connect to, the menu is shown on an X server.
+	let set = |name| env::var_os(name).is_some_and(|v| !v.is_empty());
+	match x11.unwrap_or(!set("WAYLAND_DISPLAY") && set("DISPLAY")) {
+		true => x11::run(menu, feed),
+		false => wayland::run(menu, feed),
+	}
 }
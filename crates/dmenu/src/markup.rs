@@ -0,0 +1,144 @@
+//! Pango markup in items, for `--markup`: the text without its tags, and
+//! how each stretch of it is styled.
+
+use crate::drw::{self, Style, Styles};
+
+/// The text of `markup` without its tags and with its entities replaced,
+/// and the style of each stretch of it which is styled, or `None` if it is
+/// not well formed.
+pub fn parse(markup: &str) -> Option<(String, Styles)> {
+	let mut text = String::with_capacity(markup.len());
+	let mut styles = Styles::new();
+	// The elements open, and the style inside each.
+	let mut open: Vec<(&str, Style)> = Vec::new();
+	let mut rest = markup;
+	while !rest.is_empty() {
+		let style = open.last().map_or(Style::default(), |&(_, style)| style);
+		let start = text.len();
+		if let Some(tag) = rest.strip_prefix('<') {
+			let end = tag.find('>')?;
+			let (tag, after) = (&tag[..end], &tag[end + 1..]);
+			rest = after;
+			if let Some(name) = tag.strip_prefix('/') {
+				if open.pop()?.0 != name.trim() {
+					return None;
+				}
+				continue;
+			}
+			let (tag, empty) = match tag.strip_suffix('/') {
+				Some(tag) => (tag, true),
+				None => (tag, false),
+			};
+			let (name, attrs) =
+				tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+			let style = element(name, attrs, style)?;
+			if !empty {
+				open.push((name, style));
+			}
+			continue;
+		}
+		if let Some(name) = rest.strip_prefix('&') {
+			let end = name.find(';')?;
+			text.push(entity(&name[..end])?);
+			rest = &name[end + 1..];
+		} else {
+			let end = rest.find(['<', '&']).unwrap_or(rest.len());
+			text.push_str(&rest[..end]);
+			rest = &rest[end..];
+		}
+		match styles.last_mut() {
+			Some((range, last)) if *last == style && range.end == start => {
+				range.end = text.len()
+			}
+			_ if style != Style::default() => {
+				styles.push((start..text.len(), style))
+			}
+			_ => {}
+		}
+	}
+	open.is_empty().then_some((text, styles))
+}
+
+/// The style inside an element named `name`, with the attributes `attrs`,
+/// where the text around it is in `style`.
+fn element(name: &str, attrs: &str, mut style: Style) -> Option<Style> {
+	match name {
+		"b" => style.bold = true,
+		"i" => style.italic = true,
+		"u" => style.underline = true,
+		"span" => {
+			for (key, value) in attributes(attrs)? {
+				match key {
+					"color" | "foreground" | "fgcolor" => {
+						style.fg = drw::clr_create(value).or(style.fg)
+					}
+					"background" | "bgcolor" => {
+						style.bg = drw::clr_create(value).or(style.bg)
+					}
+					"weight" | "font_weight" => {
+						style.bold =
+							matches!(
+								value,
+								"semibold" | "bold" | "ultrabold" | "heavy"
+							) || value.parse::<u32>().is_ok_and(|w| w >= 600)
+					}
+					"style" | "font_style" => {
+						style.italic = matches!(value, "italic" | "oblique")
+					}
+					"underline" => style.underline = value != "none",
+					"alpha" | "fgalpha" => style.alpha = alpha(value),
+					_ => {}
+				}
+			}
+		}
+		// Other elements, such as <big> and <tt>, are allowed but change
+		// nothing.
+		_ => {}
+	}
+	Some(style)
+}
+
+/// The attributes of a tag, as in `color="#ff0000" weight='bold'`.
+fn attributes(mut attrs: &str) -> Option<Vec<(&str, &str)>> {
+	let mut list = Vec::new();
+	loop {
+		attrs = attrs.trim_start();
+		if attrs.is_empty() {
+			return Some(list);
+		}
+		let (key, rest) = attrs.split_once('=')?;
+		let rest = rest.trim_start();
+		let quote = rest.chars().next().filter(|&q| q == '"' || q == '\'')?;
+		let (value, rest) = rest[1..].split_once(quote)?;
+		list.push((key.trim(), value));
+		attrs = rest;
+	}
+}
+
+/// An opacity given as a percentage or out of 65535, as 0 to 255.
+fn alpha(value: &str) -> Option<u8> {
+	let alpha = match value.strip_suffix('%') {
+		Some(percent) => percent.parse::<u32>().ok()?.min(100) * 255 / 100,
+		None => value.parse::<u32>().ok()?.min(65535) * 255 / 65535,
+	};
+	Some(alpha as u8)
+}
+
+/// The character an entity, named without its `&` and `;`, stands for.
+fn entity(name: &str) -> Option<char> {
+	match name {
+		"amp" => Some('&'),
+		"lt" => Some('<'),
+		"gt" => Some('>'),
+		"quot" => Some('"'),
+		"apos" => Some('\''),
+		_ => {
+			let code = name.strip_prefix('#')?;
+			let code = match code.strip_prefix(['x', 'X']) {
+				Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+				None => code.parse().ok()?,
+			};
+			char::from_u32(code)
+		}
+	}
+}
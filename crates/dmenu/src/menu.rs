@@ -0,0 +1,3534 @@
+//! The menu itself: the items, the text typed so far, the items matching it
+//! and which of them is selected, along with drawing it all.
+
+use std::{
+	cell::RefCell,
+	fs::File,
+	io::{self, Write},
+	mem::{self, ManuallyDrop, MaybeUninit},
+	ops::Range,
+	os::fd::{FromRawFd, RawFd},
+	ptr,
+};
+
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+use wmenu_core::{History, Matcher, Prior, Sort};
+use xkeysym::Keysym;
+
+use crate::{
+	ansi,
+	config,
+	desktop::Entry,
+	die,
+	drw::{COL_BG, COL_FG, Clr, Drw, Fnt, Style, Styles, clr_mix},
+	dynamic::Dynamic,
+	icons::Icons,
+	ipc::{Command, Ipc},
+	json::{self, Value},
+	launch::{Scope, launch},
+	markup,
+	reload::Reload,
+	worker::Worker,
+	xkb::Names,
+};
+
+/// Indices into the colour schemes.
+pub const SCHEME_NORM: usize = 0;
+pub const SCHEME_SEL: usize = 1;
+pub const SCHEME_OUT: usize = 2;
+pub const SCHEME_ALT: usize = 3;
+pub const SCHEME_PROMPT: usize = 4;
+pub const SCHEME_INPUT: usize = 5;
+pub const SCHEMES: usize = 6;
+
+/// The names of the schemes, as in `--color-selected-bg`.
+pub const SCHEME_NAMES: [&str; SCHEMES] =
+	["normal", "selected", "chosen", "alternate", "prompt", "input"];
+
+/// How the menu can end, each with a status of its own: with an item
+/// chosen, with text typed which is none, cancelled, or with nothing
+/// matched to choose from with `--only-match`.
+pub const EXIT_CHOSEN: usize = 0;
+pub const EXIT_CUSTOM: usize = 1;
+pub const EXIT_ABORT: usize = 2;
+pub const EXIT_NO_MATCH: usize = 3;
+pub const EXITS: usize = 4;
+
+/// The names of the ways the menu can end, as in `--exit custom=2`.
+pub const EXIT_NAMES: [&str; EXITS] = ["chosen", "custom", "abort", "no-match"];
+
+/// The width of the scrollbar of a vertical list, and the shortest its
+/// thumb can be.
+const SCROLLBAR_WIDTH: u32 = 3;
+const SCROLLBAR_MIN: i32 = 4;
+
+/// Lists of at least this many items are filtered in the background.
+const BACKGROUND_ITEMS: usize = 100_000;
+
+pub struct Item {
+	pub text: String,
+	/// Where the part of the text which is matched starts.
+	pub key: usize,
+	/// Whether the item has been chosen with `--multi`.
+	pub out: bool,
+	/// The styles of byte ranges of the text, as marked up with `--markup`
+	/// or `--ansi`.
+	pub styles: Styles,
+	/// The icon shown beside the item with `--icons`, by name or path.
+	pub icon: Option<String>,
+	/// What is printed in place of the text when the item is chosen, as
+	/// given after a tab with `--with-values`.
+	pub value: Option<String>,
+	/// Whether the item is a header over those after it, which is shown
+	/// only while nothing is typed and cannot be chosen.
+	pub header: bool,
+	/// What is shown dimmed after the text, as given with `--json`.
+	pub description: Option<String>,
+	/// Whether the item is shown dimmed and cannot be chosen, as given with
+	/// `--json`.
+	pub disabled: bool,
+}
+
+impl Item {
+	pub fn new(text: String) -> Item {
+		Item {
+			text,
+			key: 0,
+			out: false,
+			styles: Vec::new(),
+			icon: None,
+			value: None,
+			header: false,
+			description: None,
+			disabled: false,
+		}
+	}
+
+	pub fn header(text: String) -> Item {
+		Item { header: true, ..Item::new(text) }
+	}
+
+	/// Whether the item can be selected and chosen.
+	fn selectable(&self) -> bool {
+		!self.header && !self.disabled
+	}
+}
+
+/// How the menu is laid out and behaves, as set on the command line.
+#[derive(Default)]
+pub struct Opts {
+	/// Where on the output the menu is shown.
+	pub anchor: Anchor,
+	/// The space left between the menu and the top, right, bottom and left
+	/// edges of the output, in pixels.
+	pub margin: [i32; 4],
+	/// The width the menu takes of that between the margins, in the
+	/// middle of it, or all of it if unset.
+	pub width: Option<Size>,
+	/// The height of the menu, which lists as many lines as fit in it, in
+	/// place of `lines`.
+	pub height: Option<Size>,
+	/// The number of lines to list items on vertically, or zero to list them
+	/// across the bar.
+	pub lines: u32,
+	/// Mask the text typed, which is never left behind in memory.
+	pub password: bool,
+	/// Shown in place of each character typed with `password`, which may be
+	/// empty, hiding even how much was typed.
+	pub mask: String,
+	/// Shown to the left of the input field.
+	pub prompt: String,
+	/// The name or description of the output to show the menu on.
+	pub output: Option<String>,
+	/// What the keymap is compiled from, in place of the compositor's.
+	pub xkb: Names,
+	/// The X window to show the menu inside of, rather than over the
+	/// screen.
+	pub embed: Option<u32>,
+	/// The space the menu reserves at the edge of the output, or -1 to show
+	/// it over anything else reserving space there, which it does if unset.
+	pub exclusive_zone: Option<i32>,
+	/// Take the keyboard only when the menu is clicked or focused by the
+	/// compositor, rather than for as long as it is shown.
+	pub on_demand: bool,
+	/// Cancel the menu if the keyboard is taken from it, rather than going
+	/// on without it, so that nothing typed reaches another client.
+	pub exclusive: bool,
+	/// Keep the screen from blanking or locking while the menu is shown.
+	pub inhibit_idle: bool,
+	/// Let Tab choose several items for Return to print.
+	pub multi: bool,
+	/// Where to print the index of each item printed, counting from zero in
+	/// the order items were read.
+	pub index_fd: Option<RawFd>,
+	/// Print the index of each item chosen in place of its text, or -1 for
+	/// text typed.
+	pub index: bool,
+	/// Ranks items chosen before first, and records those chosen now.
+	pub history: Option<History>,
+	/// Match only the part of each item after this.
+	pub delim: Option<String>,
+	/// The text the input starts with.
+	pub initial: String,
+	/// Shown dimmed in the input field while nothing is typed.
+	pub placeholder: String,
+	/// Shown at the end of the input bar, until changed by a command.
+	pub message: String,
+	/// Hide the input field, leaving items to be chosen with the arrow keys
+	/// and the mouse, and whether what is typed still filters them unseen.
+	pub no_input: bool,
+	pub hidden_filter: bool,
+	/// Whether moving past either end of the list wraps around, if set.
+	pub wrap: Option<bool>,
+	/// Wrap items too wide for a vertical list onto several lines.
+	pub word_wrap: bool,
+	/// Scroll a vertical list an item at a time, rather than a page.
+	pub scroll: bool,
+	/// List items in a grid of this many columns, with `lines` rows, or of
+	/// as many as the widest item leaves room for with 0.
+	pub grid: Option<u32>,
+	/// The height of a line of text, which is the font's by default, and the
+	/// space left around it on each side.
+	pub line_height: Option<u32>,
+	pub padding: Option<u32>,
+	/// The width of the border around the menu, in pixels, its colour, and
+	/// the radius its corners are rounded to.
+	pub border_width: u32,
+	pub border_color: Clr,
+	pub border_radius: u32,
+	/// The application each item stands for with `--desktop`, by index,
+	/// which is launched rather than printed.
+	pub desktop: Option<Vec<Entry>>,
+	/// Show how many items match and how many there are, at the end of the
+	/// input bar.
+	pub count: bool,
+	/// Accept the item matched as soon as only one matches the text typed,
+	/// or as soon as the text typed is an item.
+	pub auto_select: bool,
+	pub accept_exact: bool,
+	/// Accept only items, leaving text typed which is none unaccepted.
+	pub only_match: bool,
+	/// The status exited with for each way the menu can end, indexed by
+	/// `EXIT_CHOSEN` and the rest.
+	pub statuses: [i32; EXITS],
+	/// Run what is chosen, or typed, as a command rather than printing it.
+	pub run: bool,
+	/// What what is launched or run is started in, rather than as a child
+	/// of the menu alone.
+	pub scope: Option<Scope>,
+	/// Print what is chosen as a JSON object with its index, and whether it
+	/// was typed rather than one of the items.
+	pub json: bool,
+	/// Each item is a JSON object, of its text and what else it has.
+	pub json_input: bool,
+	/// What follows each item printed, in place of a newline, and whether
+	/// items are quoted as the shell reads them.
+	pub output_delim: Option<String>,
+	pub quote: bool,
+	/// Copy what is chosen to the clipboard, as well as printing it or, with
+	/// `copy_only`, in place of it.
+	pub copy: bool,
+	pub copy_only: bool,
+	/// Keys which stand for others, as bound in the config file.
+	pub keys: Vec<(Chord, Chord)>,
+	/// Keys bound to actions, in place of what they otherwise do, with
+	/// those bound first taking precedence.
+	pub bindings: Vec<(Chord, Action)>,
+	/// Items are Pango markup, which is drawn styled and left out of what
+	/// is matched and printed.
+	pub markup: bool,
+	/// Items have terminal escape sequences in them, of which SGR ones are
+	/// drawn styled, and all are left out of what is matched and printed.
+	pub ansi: bool,
+	/// Items are shown with icons, named after a NUL as in rofi, as in
+	/// `text\0icon\x1fname`, or before `icon_field`, from the icon theme
+	/// given.
+	pub icons: bool,
+	pub icon_field: Option<String>,
+	pub icon_theme: Option<String>,
+	/// Each item is its text, a tab, and the value printed when it is
+	/// chosen.
+	pub values: bool,
+	/// The socket commands are sent to the menu over with `--ipc`.
+	pub ipc: Option<Ipc>,
+	/// What reads the theme file again when it changes.
+	pub reload: Option<Reload>,
+	/// The command which lists the items for each query, which are shown
+	/// as it lists them rather than matched.
+	pub dynamic: Option<String>,
+	/// The command which prints a preview of the item selected, shown
+	/// beside a vertical list.
+	pub preview: Option<String>,
+}
+
+/// The colours and sizes of the menu, which the theme file can change while
+/// it is shown.
+#[derive(Clone, Copy)]
+pub struct Look {
+	pub scheme: [[Clr; 3]; SCHEMES],
+	pub border_color: Clr,
+	pub line_height: Option<u32>,
+	pub padding: Option<u32>,
+	pub border_width: u32,
+	pub border_radius: u32,
+	pub lines: u32,
+}
+
+/// The edge of the output the menu is shown along, or neither.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Anchor {
+	#[default]
+	Top,
+	Bottom,
+	Center,
+}
+
+impl Anchor {
+	pub fn parse(name: &str) -> Option<Anchor> {
+		match name {
+			"top" => Some(Anchor::Top),
+			"bottom" => Some(Anchor::Bottom),
+			"center" => Some(Anchor::Center),
+			_ => None,
+		}
+	}
+}
+
+/// A width or height, as a fraction of the space there is or in pixels.
+#[derive(Clone, Copy)]
+pub enum Size {
+	Fraction(f64),
+	Pixels(u32),
+}
+
+impl Size {
+	/// Parses a size, as in `40%` or `600`.
+	pub fn parse(size: &str) -> Option<Size> {
+		match size.strip_suffix('%') {
+			Some(percent) => {
+				let n: f64 = percent.trim().parse().ok()?;
+				(n > 0.0 && n <= 100.0).then_some(Size::Fraction(n / 100.0))
+			}
+			None => size.parse().ok().filter(|&n| n > 0).map(Size::Pixels),
+		}
+	}
+
+	/// The size of `space` pixels this is, which is no more than all of it.
+	pub fn of(self, space: u32) -> u32 {
+		let size = match self {
+			Size::Fraction(f) => (space as f64 * f) as u32,
+			Size::Pixels(n) => n,
+		};
+		size.clamp(1, space.max(1))
+	}
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Mods {
+	pub ctrl: bool,
+	pub shift: bool,
+	pub alt: bool,
+}
+
+/// A key pressed with the modifiers held for it.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Chord {
+	pub ksym: Keysym,
+	pub mods: Mods,
+}
+
+impl Chord {
+	/// The same key, with letters in lower case, whether or not Shift made
+	/// them upper case.
+	fn fold(self) -> Chord {
+		let ksym = match self.ksym.key_char() {
+			Some(c) if c.is_alphabetic() => c.to_lowercase().next(),
+			_ => None,
+		};
+		let ksym = ksym.map_or(self.ksym, Keysym::from_char);
+		Chord { ksym, mods: self.mods }
+	}
+}
+
+/// What a key can be bound to do, as with `--bind`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+	/// Does nothing, which unbinds a key.
+	None,
+	Accept,
+	AcceptCustom,
+	Abort,
+	Complete,
+	ToggleMark,
+	CycleMatcher,
+	DeleteChar,
+	Backspace,
+	DeleteWord,
+	DeleteToStart,
+	DeleteToEnd,
+	Yank,
+	YankPop,
+	LineStart,
+	LineEnd,
+	WordLeft,
+	WordRight,
+	Home,
+	End,
+	First,
+	Last,
+	Left,
+	Right,
+	Up,
+	Down,
+	PageUp,
+	PageDown,
+}
+
+/// The names of the actions, as bound to keys.
+pub const ACTIONS: [(&str, Action); 28] = [
+	("none", Action::None),
+	("accept", Action::Accept),
+	("accept-custom", Action::AcceptCustom),
+	("abort", Action::Abort),
+	("complete", Action::Complete),
+	("toggle-mark", Action::ToggleMark),
+	("cycle-matcher", Action::CycleMatcher),
+	("delete-char", Action::DeleteChar),
+	("backspace", Action::Backspace),
+	("delete-word", Action::DeleteWord),
+	("delete-to-start", Action::DeleteToStart),
+	("delete-to-end", Action::DeleteToEnd),
+	("yank", Action::Yank),
+	("yank-pop", Action::YankPop),
+	("line-start", Action::LineStart),
+	("line-end", Action::LineEnd),
+	("word-left", Action::WordLeft),
+	("word-right", Action::WordRight),
+	("home", Action::Home),
+	("end", Action::End),
+	("first", Action::First),
+	("last", Action::Last),
+	("left", Action::Left),
+	("right", Action::Right),
+	("up", Action::Up),
+	("down", Action::Down),
+	("page-up", Action::PageUp),
+	("page-down", Action::PageDown),
+];
+
+/// What can be clicked on in the menu.
+#[derive(Clone, Copy)]
+enum Target {
+	/// The match at an index in `matches`.
+	Match(usize),
+	/// The arrows to the previous and the next page.
+	Prev,
+	Next,
+}
+
+pub struct Menu {
+	items: Vec<Item>,
+	text: String,
+	/// The byte offset of the cursor in `text`.
+	cursor: usize,
+	matcher: Matcher,
+	prior: Prior,
+	/// Indices into `items`, in the order they are shown.
+	matches: Vec<usize>,
+	/// The following are indices into `matches`: the selected item, the
+	/// first item shown, the first item of the previous page and the first
+	/// item of the next, if there is one.
+	sel: Option<usize>,
+	curr: usize,
+	prev: usize,
+	next: Option<usize>,
+	pub drw: Drw,
+	scheme: [[Clr; 3]; SCHEMES],
+	pub opts: Opts,
+	/// How many pixels of the buffer there are to each of the surface, which
+	/// all of the following are measured in.
+	scale: f64,
+	/// The height of a line, the width and height of the menu inside the
+	/// border, the width of the border, the widths of the prompt and the
+	/// input field, and the sum of the padding either side of text.
+	bh: i32,
+	mw: i32,
+	mh: i32,
+	bw: i32,
+	promptw: i32,
+	inputw: i32,
+	lrpad: i32,
+	/// The width of the widest item, which grid cells fit with `--grid 0`.
+	widest: i32,
+	/// What filters long lists, and the item to keep selected once it has.
+	worker: Option<Worker>,
+	keep: Option<usize>,
+	/// The icons shown beside items, with `--icons`.
+	icons: Option<Icons>,
+	/// What runs the command for each query with `--dynamic`.
+	dynamic: Option<Dynamic>,
+	/// What runs the command for each item selected with `--preview`, the
+	/// item it was last run for, and the lines it printed of it.
+	preview: Option<Dynamic>,
+	previewing: Option<usize>,
+	previewed: Vec<String>,
+	/// What was last drawn where, as the x and y of each and its width and
+	/// height, to find what is clicked on.
+	targets: Vec<([i32; 4], Target)>,
+	/// The text an input method is composing at the cursor, and where its
+	/// own cursor is in it, if shown.
+	preedit: (String, Option<usize>),
+	/// Where the cursor was last drawn, as for `targets`.
+	caret: [i32; 4],
+	/// The message shown at the end of the input bar, as given on stdin or
+	/// over `--ipc`.
+	message: String,
+	/// The text last deleted by words or lines, newest last, and where the
+	/// text last yanked starts and how far back in them it was.
+	kills: Vec<String>,
+	/// What has been chosen with `copy`, to be put on the clipboard as the
+	/// menu closes.
+	copied: Vec<String>,
+	/// Where what is chosen is printed.
+	out: RefCell<Box<dyn Write>>,
+	yanked: Option<(usize, usize)>,
+	/// What the last key pressed did, if not typing, so that kills in a row
+	/// are yanked as one and a yank can be cycled through older kills.
+	last: Option<Action>,
+	/// Whether the matches have changed since they were last looked at to
+	/// accept one without Return.
+	fresh: bool,
+	/// Whether as many lines are listed as fit in a height, which is kept
+	/// when the look changes.
+	fitted: bool,
+}
+
+impl Menu {
+	pub fn new(
+		mut items: Vec<Item>,
+		drw: Drw,
+		matcher: Matcher,
+		look: Look,
+		mut opts: Opts,
+	) -> Self {
+		let commands = controls(&mut items);
+		objects(&mut items, &opts);
+		icons(&mut items, &opts);
+		values(&mut items, &opts);
+		unmark(&mut items, &opts);
+		split(&mut items, &opts);
+		let text = mem::take(&mut opts.initial);
+		let message = mem::take(&mut opts.message);
+		let icons = opts.icons.then(|| {
+			Icons::new(opts.icon_theme.as_deref().unwrap_or("hicolor"))
+		});
+		let dynamic = opts.dynamic.clone().map(|command| {
+			Dynamic::spawn(command).unwrap_or_else(|err| {
+				die(&format!("cannot run the command: {}", err))
+			})
+		});
+		let preview = opts.preview.clone().map(|command| {
+			Dynamic::preview(command).unwrap_or_else(|err| {
+				die(&format!("cannot run the preview: {}", err))
+			})
+		});
+		let mut menu = Menu {
+			items,
+			cursor: text.len(),
+			text,
+			matcher,
+			prior: Prior::default(),
+			matches: Vec::new(),
+			sel: None,
+			curr: 0,
+			prev: 0,
+			next: None,
+			drw,
+			scheme: look.scheme,
+			opts,
+			scale: 1.0,
+			bh: 0,
+			mw: 0,
+			mh: 0,
+			bw: 0,
+			promptw: 0,
+			inputw: 0,
+			lrpad: 0,
+			widest: 0,
+			worker: None,
+			keep: None,
+			icons,
+			dynamic,
+			preview,
+			previewing: None,
+			previewed: Vec::new(),
+			targets: Vec::new(),
+			preedit: (String::new(), None),
+			caret: [0; 4],
+			message,
+			kills: Vec::new(),
+			copied: Vec::new(),
+			out: RefCell::new(Box::new(io::stdout())),
+			yanked: None,
+			last: None,
+			fresh: false,
+			fitted: false,
+		};
+		menu.apply(look);
+		for command in commands {
+			menu.command(command);
+		}
+		// The menu is first shown with every item, however long the list.
+		menu.filter_now();
+		if let Some(dynamic) = &mut menu.dynamic {
+			dynamic.query(&menu.text);
+		}
+		menu
+	}
+
+	/// Works out the height of lines and the padding of text from the font.
+	fn layout(&mut self) {
+		let px = |n: u32| (n as f64 * self.scale).round() as i32;
+		let font = self.drw.fonts.h as i32;
+		// By default, lines are a pixel taller than the font on each side,
+		// and text is padded by half the font's height at either end.
+		let line = self.opts.line_height.map_or(font, px);
+		self.bh = line + 2 * px(self.opts.padding.unwrap_or(1));
+		self.lrpad = self.opts.padding.map_or(font, |p| 2 * px(p));
+		self.mh = (self.opts.lines as i32 + 1) * self.bh;
+		self.bw = px(self.opts.border_width);
+	}
+
+	/// Colours and sizes the menu as `look` sets out, and lays it out again.
+	fn apply(&mut self, look: Look) {
+		self.scheme = look.scheme;
+		self.opts.border_color = look.border_color;
+		self.opts.line_height = look.line_height;
+		self.opts.padding = look.padding;
+		self.opts.border_width = look.border_width;
+		self.opts.border_radius = look.border_radius;
+		// A grid has a single row unless given more.
+		self.opts.lines = match self.opts.grid {
+			Some(_) => look.lines.max(1),
+			None => look.lines,
+		};
+		self.layout();
+	}
+
+	/// Shows the menu in `fonts` and as `look` sets out, as high as before
+	/// if it lists as many lines as fit. It then needs resizing.
+	fn restyle(&mut self, mut fonts: Fnt, look: Look) {
+		fonts.set_scale(self.scale as f32);
+		self.drw.fonts = fonts;
+		let height = self.height();
+		self.apply(look);
+		if self.fitted {
+			self.fit(height);
+		}
+	}
+
+	/// Reads the theme file again if it has changed or SIGUSR1 was sent,
+	/// returning whether the menu looks different for it. It then needs
+	/// resizing.
+	pub fn reload(&mut self) -> bool {
+		let Some(reload) = &self.opts.reload else {
+			return false;
+		};
+		match reload.take() {
+			Some(Ok((fonts, look))) => {
+				self.restyle(fonts, look);
+				true
+			}
+			Some(Err(err)) => {
+				eprintln!("{}", err);
+				false
+			}
+			None => false,
+		}
+	}
+
+	/// What reads the theme file again, which is readable when it is to.
+	pub fn reloads(&self) -> Option<&Reload> {
+		self.opts.reload.as_ref()
+	}
+
+	pub fn scale(&self) -> f64 {
+		self.scale
+	}
+
+	/// Draws the menu for an output with `scale` pixels to each of the
+	/// surface's. It then needs resizing.
+	pub fn set_scale(&mut self, scale: f64) {
+		if self.drw.fonts.set_scale(scale as f32) {
+			self.scale = scale;
+			self.layout();
+		}
+	}
+
+	/// The height of the surface.
+	pub fn height(&self) -> u32 {
+		((self.mh + 2 * self.bw) as f64 / self.scale).ceil() as u32
+	}
+
+	/// Lists as many lines as fit in `height` pixels, of which there is
+	/// always at least one.
+	pub fn fit(&mut self, height: u32) {
+		let px = (height as f64 * self.scale) as i32 - 2 * self.bw;
+		let lines = (px / self.bh.max(1) - 1).max(1);
+		self.opts.lines = lines as u32;
+		self.mh = (lines + 1) * self.bh;
+		self.fitted = true;
+	}
+
+	/// Lays the menu out for a new width of the surface.
+	pub fn resize(&mut self, width: u32) {
+		let px = |n: u32| (n as f64 * self.scale).round() as u32;
+		// The border is drawn around what is left of the surface.
+		let bw = self.bw as u32;
+		let mw = px(width).saturating_sub(2 * bw);
+		self.mw = mw as i32;
+		self.inputw = match self.opts.no_input {
+			true => 0,
+			false => self.mw / 3,
+		};
+		self.promptw = self.promptw();
+		self.drw.resize(mw, px(self.height()).saturating_sub(2 * bw));
+		let radius = px(self.opts.border_radius);
+		self.drw.setborder(bw, self.opts.border_color, radius);
+		self.widest = 0;
+		self.measure(0);
+		self.calcoffsets();
+	}
+
+	/// The width of the prompt, which when long is cut short rather than
+	/// crowding out the input.
+	fn promptw(&self) -> i32 {
+		match self.opts.prompt.is_empty() {
+			true => 0,
+			false => (self.textw(&self.opts.prompt) - self.lrpad / 4)
+				.min(self.mw / 2),
+		}
+	}
+
+	/// The width of the icons beside items, which are as high as a line.
+	fn iconw(&self) -> i32 {
+		match self.icons {
+			Some(_) => self.bh,
+			None => 0,
+		}
+	}
+
+	fn textw(&self, text: &str) -> i32 {
+		self.drw.fontset_getwidth(text) as i32 + self.lrpad
+	}
+
+	/// The width kept for the count of matches, which is that of the
+	/// widest it can be so that it does not move as it changes.
+	fn countw(&self) -> i32 {
+		match self.opts.count {
+			true => self.textw(&format!("{0}/{0}", self.items.len())),
+			false => 0,
+		}
+	}
+
+	/// The width of the message and the count, at the end of the input
+	/// bar. A long message is cut short at a third of the menu.
+	fn statusw(&self) -> i32 {
+		let messagew = match self.message.is_empty() {
+			true => 0,
+			false => self.textw_clamp(&self.message, self.mw / 3),
+		};
+		messagew + self.countw()
+	}
+
+	fn textw_clamp(&self, text: &str, n: i32) -> i32 {
+		let n = n.max(0);
+		let w = self.drw.fontset_getwidth_clamp(text, n as u32) as i32;
+		(w + self.lrpad).min(n)
+	}
+
+	/// The width of the item at `i` in `matches` in a horizontal list, with
+	/// its icon and description, of at most `n`.
+	fn itemw(&self, i: usize, n: i32) -> i32 {
+		let item = self.item(i);
+		let iconw = self.iconw();
+		let mut w = iconw + self.textw_clamp(&item.text, n - iconw);
+		if let Some(description) = &item.description {
+			w += self.textw_clamp(description, n - w);
+		}
+		w
+	}
+
+	/// The width of the preview beside a vertical list, which is half of
+	/// what the prompt leaves.
+	fn previeww(&self) -> i32 {
+		match self.preview.is_some() && self.opts.lines > 0 {
+			true => (self.mw - self.promptw) / 2,
+			false => 0,
+		}
+	}
+
+	/// Widens grid cells to fit the items from `from` on, if they are to.
+	/// No cell is wider than the menu, so no item is measured past it.
+	fn measure(&mut self, from: usize) {
+		if self.opts.grid != Some(0) {
+			return;
+		}
+		let (iconw, n) = (self.iconw(), self.mw.max(0) as u32);
+		let textw = |text: &str| {
+			self.drw.fontset_getwidth_clamp(text, n) as i32 + self.lrpad
+		};
+		for item in &self.items[from..] {
+			let descw = item.description.as_deref().map_or(0, textw);
+			let w = (textw(&item.text) + descw).min(n as i32) + iconw;
+			self.widest = self.widest.max(w);
+		}
+	}
+
+	/// How many items each page holds, where every page but the last holds
+	/// as many: those of a grid, and those of a vertical list of items on
+	/// a line each.
+	fn page(&self) -> Option<usize> {
+		match self.grid() {
+			Some((cols, _)) => Some(cols * self.opts.lines as usize),
+			None if self.opts.lines > 0 && !self.opts.word_wrap => {
+				Some(self.opts.lines as usize)
+			}
+			None => None,
+		}
+	}
+
+	/// The number of columns of the grid and the width of each, if items
+	/// are listed in one.
+	fn grid(&self) -> Option<(usize, i32)> {
+		let w =
+			self.mw - self.promptw - self.previeww() - SCROLLBAR_WIDTH as i32;
+		let cols = match self.opts.grid? {
+			0 => (w / self.widest.max(1)).max(1) as usize,
+			cols => cols as usize,
+		};
+		Some((cols, w / cols as i32))
+	}
+
+	/// The row of a vertical list or grid the item at `i` in `matches` is
+	/// on, counting from the first match.
+	fn row(&self, i: usize) -> usize {
+		match self.grid() {
+			Some((cols, _)) => i / cols,
+			None => i,
+		}
+	}
+
+	fn item(&self, i: usize) -> &Item {
+		&self.items[self.matches[i]]
+	}
+
+	/// The byte ranges of the item at `i` in `matches` on each line of a
+	/// vertical list, which is one unless it is wrapped.
+	fn rows(&self, i: usize) -> Vec<Range<usize>> {
+		let text = &self.item(i).text;
+		if !self.opts.word_wrap || self.opts.grid.is_some() {
+			let row = 0..text.len();
+			return vec![row];
+		}
+		// Room is left for the scrollbar whether or not it is shown, so that
+		// items are wrapped the same either way.
+		let w = self.mw
+			- self.promptw
+			- self.previeww()
+			- SCROLLBAR_WIDTH as i32
+			- self.lrpad
+			- self.iconw();
+		self.drw.wrap(text, w.max(0) as u32)
+	}
+
+	/// Works out which items begin the next and previous pages.
+	fn calcoffsets(&mut self) {
+		// Pages of a grid are all full but the last.
+		if let Some((cols, _)) = self.grid() {
+			let n = cols * self.opts.lines as usize;
+			self.next = Some(self.curr + n).filter(|&i| i < self.matches.len());
+			self.prev = self.curr.saturating_sub(n);
+			return;
+		}
+		let arrows = self.textw("<") + self.textw(">");
+		let n = match self.opts.lines {
+			0 => {
+				self.mw - (self.promptw + self.inputw + arrows + self.statusw())
+			}
+			lines => lines as i32 * self.bh,
+		};
+		// An item always fits a page, even if it is wrapped onto more lines.
+		let width = |i| match self.opts.lines {
+			0 => self.itemw(i, n),
+			lines => self.rows(i).len().min(lines as usize) as i32 * self.bh,
+		};
+
+		let mut w = 0;
+		let next = (self.curr..self.matches.len()).find(|&i| {
+			w += width(i);
+			w > n
+		});
+		let mut w = 0;
+		let mut prev = self.curr;
+		while prev > 0 {
+			w += width(prev - 1);
+			if w > n {
+				break;
+			}
+			prev -= 1;
+		}
+		(self.prev, self.next) = (prev, next);
+	}
+
+	fn filter(&mut self) {
+		self.search(None);
+	}
+
+	/// Filters the items for the text typed, on the worker for long lists,
+	/// and then selects the item at `keep` in `items` if it still matches.
+	fn search(&mut self, keep: Option<usize>) {
+		// The command lists the items for the query rather than them being
+		// matched, so they are shown as they are until it has.
+		if let Some(dynamic) = &mut self.dynamic {
+			dynamic.query(&self.text);
+			return;
+		}
+		self.keep = keep;
+		if self.items.len() < BACKGROUND_ITEMS {
+			self.filter_now();
+			return;
+		}
+		let worker = match &mut self.worker {
+			Some(worker) => worker,
+			None => {
+				let keys = self.items.iter().map(|item| &item.text[item.key..]);
+				match Worker::spawn(keys.collect()) {
+					Ok(worker) => self.worker.insert(worker),
+					Err(err) => die(&format!("cannot filter items: {}", err)),
+				}
+			}
+		};
+		worker.filter(self.matcher, &self.text);
+	}
+
+	fn filter_now(&mut self) {
+		let len = self.items.len();
+		let candidates = self.prior.candidates(&self.matcher, &self.text, len);
+		let items = candidates.iter().map(|&i| &self.items[i]);
+		let items = items.map(|item| &item.text[item.key..]);
+		let matches = self.matcher.filter(items, &self.text);
+		let matches: Vec<_> =
+			matches.into_iter().map(|i| candidates[i]).collect();
+		self.prior.record(&self.matcher, &self.text, len, &matches);
+		self.matched(matches);
+	}
+
+	/// Shows `matches`, the items matching the text typed.
+	fn matched(&mut self, matches: Vec<usize>) {
+		self.matches = matches;
+		// Headers head the whole list, so are left out of what is matched,
+		// unless the command lists the items for what is typed.
+		if !self.text.is_empty() && self.dynamic.is_none() {
+			self.matches.retain(|&i| !self.items[i].header);
+		}
+		if let Some(history) = &self.opts.history
+			&& self.matcher.sort == Sort::Score
+		{
+			// The sort is stable, so items never chosen keep their order.
+			let score = |&i: &usize| history.score(&self.items[i].text);
+			self.matches.sort_by(|a, b| score(b).total_cmp(&score(a)));
+		}
+		self.curr = 0;
+		self.sel = (!self.matches.is_empty()).then_some(0);
+		self.fresh = true;
+		self.calcoffsets();
+		let keep = self.keep.take();
+		let sel =
+			keep.and_then(|keep| self.matches.iter().position(|&i| i == keep));
+		if let Some(sel) = sel {
+			self.select(sel);
+		}
+		self.unheader(false);
+	}
+
+	/// Moves the selection off a header or disabled item on to the item
+	/// after it, or `back` to the one before, or the other way if there is
+	/// none. Nothing is selected if no match can be.
+	fn unheader(&mut self, back: bool) {
+		let Some(sel) = self.sel.filter(|&sel| !self.item(sel).selectable())
+		else {
+			return;
+		};
+		let item = |&i: &usize| self.item(i).selectable();
+		let after = (sel..self.matches.len()).find(item);
+		let before = (0..sel).rev().find(item);
+		match if back { before.or(after) } else { after.or(before) } {
+			Some(to) if self.scrolls() => {
+				self.sel = Some(to);
+				self.reveal(to);
+			}
+			Some(to) => self.select(to),
+			None => self.sel = None,
+		}
+	}
+
+	/// Selects the match at `sel`, showing the page it is on. Pages of as
+	/// many items each are skipped to, and only others laid out in turn.
+	fn select(&mut self, sel: usize) {
+		self.sel = Some(sel);
+		self.curr = match self.page() {
+			Some(n) => sel / n.max(1) * n,
+			None => 0,
+		};
+		self.calcoffsets();
+		while let Some(next) = self.next.filter(|&next| next <= sel) {
+			self.curr = next;
+			self.calcoffsets();
+		}
+	}
+
+	/// Shows the matches the worker has found, returning whether there were
+	/// any to show.
+	pub fn collect(&mut self) -> bool {
+		let previewed = self.preview.as_mut().and_then(Dynamic::take);
+		let changed = previewed.is_some();
+		if let Some(lines) = previewed {
+			self.previewed = lines;
+		}
+		if let Some(lines) = self.dynamic.as_mut().and_then(Dynamic::take) {
+			self.replace(lines);
+			return true;
+		}
+		match self.worker.as_mut().and_then(Worker::take) {
+			Some(matches) => {
+				self.matched(matches);
+				true
+			}
+			None => changed,
+		}
+	}
+
+	/// The worker, which is readable when it has matches to collect.
+	pub fn worker(&self) -> Option<&Worker> {
+		self.worker.as_ref()
+	}
+
+	/// Filters here and now if the worker has yet to, so that what is
+	/// chosen matches what was typed.
+	fn settle(&mut self) {
+		if self.worker.as_mut().is_some_and(Worker::cancel) {
+			self.filter_now();
+		}
+	}
+
+	/// Adds items which arrived after the menu was shown, keeping the same
+	/// item selected if it still matches.
+	pub fn extend(&mut self, mut items: Vec<Item>) {
+		let commands = controls(&mut items);
+		objects(&mut items, &self.opts);
+		icons(&mut items, &self.opts);
+		values(&mut items, &self.opts);
+		unmark(&mut items, &self.opts);
+		split(&mut items, &self.opts);
+		let sel = self.sel.map(|sel| self.matches[sel]);
+		let from = self.items.len();
+		if let Some(worker) = &mut self.worker {
+			worker.extend(items.iter().map(|item| &item.text[item.key..]));
+		}
+		self.items.extend(items);
+		self.measure(from);
+		self.search(sel);
+		for command in commands {
+			self.command(command);
+		}
+	}
+
+	/// Removes every item, as told to over `--ipc`.
+	fn clear(&mut self) {
+		self.items.clear();
+		// The applications no longer stand for any of the items.
+		self.opts.desktop = None;
+		self.worker = None;
+		self.prior = Prior::default();
+		self.widest = 0;
+		self.search(None);
+	}
+
+	/// Carries out the commands sent over `--ipc` since the last call,
+	/// returning whether there were any, and the status to exit with if
+	/// one closed the menu.
+	pub fn receive(&mut self) -> (bool, Option<i32>) {
+		let commands = match &self.opts.ipc {
+			Some(ipc) => ipc.take(),
+			None => return (false, None),
+		};
+		if commands.is_empty() {
+			return (false, None);
+		}
+		// Items appended one after another are added together.
+		let mut items = Vec::new();
+		for command in commands {
+			match command {
+				Command::Append(text) => items.push(Item::new(text)),
+				Command::Header(text) => items.push(Item::header(text)),
+				command => {
+					if !items.is_empty() {
+						self.extend(mem::take(&mut items));
+					}
+					if let Some(status) = self.command(command) {
+						return (true, Some(status));
+					}
+				}
+			}
+		}
+		if !items.is_empty() {
+			self.extend(items);
+		}
+		(true, None)
+	}
+
+	/// Carries out a command other than adding items, returning the status
+	/// to exit with if it closed the menu.
+	fn command(&mut self, command: Command) -> Option<i32> {
+		match command {
+			Command::Append(_) | Command::Header(_) => {}
+			Command::Clear => self.clear(),
+			Command::Prompt(prompt) => {
+				self.opts.prompt = prompt;
+				self.promptw = self.promptw();
+				self.calcoffsets();
+			}
+			Command::Message(message) => {
+				self.message = message;
+				self.calcoffsets();
+			}
+			Command::Select(sel) => {
+				self.settle();
+				if sel < self.matches.len() {
+					self.select(sel);
+					self.unheader(false);
+				}
+			}
+			Command::Close => return Some(self.cancel()),
+		}
+		None
+	}
+
+	/// What runs the command with `--preview`, which is readable when it
+	/// has printed a preview.
+	pub fn preview(&self) -> Option<&Dynamic> {
+		self.preview.as_ref()
+	}
+
+	/// What runs the command with `--dynamic`, which is readable when it
+	/// has listed items.
+	pub fn dynamic(&self) -> Option<&Dynamic> {
+		self.dynamic.as_ref()
+	}
+
+	/// Shows `lines`, listed by the command with `--dynamic`, in place of
+	/// the items, all of them and in the order listed.
+	fn replace(&mut self, lines: Vec<String>) {
+		let mut items: Vec<_> = lines.into_iter().map(Item::new).collect();
+		let commands = controls(&mut items);
+		objects(&mut items, &self.opts);
+		icons(&mut items, &self.opts);
+		values(&mut items, &self.opts);
+		unmark(&mut items, &self.opts);
+		split(&mut items, &self.opts);
+		self.items = items;
+		self.widest = 0;
+		self.measure(0);
+		self.matched((0..self.items.len()).collect());
+		for command in commands {
+			self.command(command);
+		}
+	}
+
+	/// The socket commands are sent over, which is readable when there are
+	/// some to carry out.
+	pub fn ipc(&self) -> Option<&Ipc> {
+		self.opts.ipc.as_ref()
+	}
+
+	/// What was drawn at `x` and `y` on the surface, if it can be clicked.
+	fn target(&self, x: f64, y: f64) -> Option<Target> {
+		let (x, y) = (x * self.scale, y * self.scale);
+		let (x, y) = (x as i32 - self.bw, y as i32 - self.bw);
+		self.targets.iter().find_map(|&([tx, ty, w, h], target)| {
+			(x >= tx && x < tx + w && y >= ty && y < ty + h).then_some(target)
+		})
+	}
+
+	/// Selects the match the pointer is over at `x` and `y` on the surface,
+	/// returning whether that changed the selection.
+	pub fn hover(&mut self, x: f64, y: f64) -> bool {
+		match self.target(x, y) {
+			Some(Target::Match(i)) if self.sel != Some(i) => {
+				self.sel = Some(i);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Chooses the match clicked on at `x` and `y` on the surface, or turns
+	/// the page for its arrows, returning the status to exit with if that
+	/// ends the menu.
+	pub fn click(&mut self, x: f64, y: f64) -> Option<i32> {
+		match self.target(x, y)? {
+			Target::Match(i) => {
+				self.sel = Some(i);
+				self.act(Action::Accept, None)
+			}
+			Target::Prev => self.act(Action::PageUp, None),
+			Target::Next => self.act(Action::PageDown, None),
+		}
+	}
+
+	/// Turns the page, as scrolling `down` or up does, or with `--scroll`
+	/// moves the list an item, keeping the selection on it.
+	pub fn scroll(&mut self, down: bool) {
+		if self.scrolls() {
+			match down {
+				true if self.next.is_some() => self.curr += 1,
+				false if self.curr > 0 => self.curr -= 1,
+				_ => return,
+			}
+			self.calcoffsets();
+			let last = self.next.unwrap_or(self.matches.len()) - 1;
+			self.sel = self.sel.map(|sel| sel.clamp(self.curr, last));
+			return;
+		}
+		let action = match down {
+			true => Action::PageDown,
+			false => Action::PageUp,
+		};
+		self.act(action, None);
+	}
+
+	/// Inserts pasted text at the cursor, leaving out line breaks, as the
+	/// input is a single line.
+	pub fn paste(&mut self, text: &str) {
+		let text = text.replace(['\n', '\r'], "");
+		self.last = None;
+		if !text.is_empty() {
+			self.insert(&text);
+		}
+	}
+
+	/// Shows `text`, which an input method is composing, at the cursor
+	/// without it being typed, with the cursor at `cursor` in it.
+	pub fn preedit(&mut self, text: String, cursor: Option<usize>) {
+		self.preedit = (text, cursor);
+	}
+
+	/// Deletes `before` and `after` bytes either side of the cursor, as an
+	/// input method does to replace what it composed from.
+	pub fn delete_surrounding(&mut self, before: usize, after: usize) {
+		let mut from = self.cursor.saturating_sub(before);
+		while !self.text.is_char_boundary(from) {
+			from -= 1;
+		}
+		let mut to = (self.cursor + after).min(self.text.len());
+		while !self.text.is_char_boundary(to) {
+			to += 1;
+		}
+		self.last = None;
+		if from < to {
+			self.cursor = to;
+			self.delete(from);
+		}
+	}
+
+	/// The text typed and where the cursor is in it, for an input method to
+	/// compose from, unless it is a password.
+	pub fn surrounding(&self) -> Option<(&str, usize)> {
+		(!self.opts.password).then_some((&self.text, self.cursor))
+	}
+
+	/// Where the cursor was last drawn on the surface, as its x and y and
+	/// its width and height, for an input method to show what it composes
+	/// by.
+	pub fn caret(&self) -> [i32; 4] {
+		let [x, y, w, h] = self.caret;
+		let px = |n: i32| (n as f64 / self.scale).round() as i32;
+		[px(x + self.bw), px(y + self.bw), px(w).max(1), px(h)]
+	}
+
+	fn insert(&mut self, text: &str) {
+		if self.opts.no_input && !self.opts.hidden_filter {
+			return;
+		}
+		let len = self.text.len() + text.len();
+		if self.opts.password && len > self.text.capacity() {
+			// Grow the buffer by hand, so that the old one can be wiped
+			// rather than freed with the text still in it.
+			let mut grown =
+				String::with_capacity(len.max(self.text.capacity() * 2));
+			// SAFETY: only the unused capacity is locked.
+			lock(unsafe { grown.as_mut_vec() }.spare_capacity_mut());
+			grown.push_str(&self.text);
+			zeroize(&mut self.text);
+			self.text = grown;
+		}
+		self.text.insert_str(self.cursor, text);
+		self.cursor = self.boundary(self.cursor + text.len());
+		self.filter();
+	}
+
+	/// Deletes the text between `to` and the cursor, which may be on either
+	/// side of it.
+	fn delete(&mut self, to: usize) {
+		let (from, to) = (to.min(self.cursor), to.max(self.cursor));
+		self.text.replace_range(from..to, "");
+		if self.opts.password {
+			// SAFETY: only the unused capacity is written to.
+			wipe(unsafe { self.text.as_mut_vec() }.spare_capacity_mut());
+		}
+		self.cursor = self.boundary(from);
+		self.filter();
+	}
+
+	/// Deletes the text between `to` and the cursor as `delete` does,
+	/// keeping it to be yanked. It is joined to what the key pressed before
+	/// killed, if it did, as readline does. Passwords are not kept.
+	fn kill(&mut self, to: usize, last: Option<Action>) {
+		if !self.opts.password {
+			let (from, end) = (to.min(self.cursor), to.max(self.cursor));
+			let text = &self.text[from..end];
+			let joined = matches!(
+				last,
+				Some(
+					Action::DeleteWord
+						| Action::DeleteToStart
+						| Action::DeleteToEnd
+				)
+			);
+			match self.kills.last_mut() {
+				Some(kill) if joined && to < self.cursor => {
+					kill.insert_str(0, text)
+				}
+				Some(kill) if joined => kill.push_str(text),
+				_ => {
+					if self.kills.len() == config::KILLS {
+						self.kills.remove(0);
+					}
+					self.kills.push(text.to_string());
+				}
+			}
+		}
+		self.delete(to);
+	}
+
+	/// Whether anything has been killed to be yanked, which Ctrl+Y does in
+	/// place of pasting the primary selection once there is.
+	pub fn killed(&self) -> bool {
+		!self.kills.is_empty()
+	}
+
+	/// The first grapheme boundary at or after `at`, which is not one when
+	/// what was typed or deleted before it joins a character to marks or a
+	/// joiner after it.
+	fn boundary(&self, at: usize) -> usize {
+		let mut cursor = GraphemeCursor::new(at, self.text.len(), true);
+		match cursor.is_boundary(&self.text, 0) {
+			Ok(false) => cursor
+				.next_boundary(&self.text, 0)
+				.ok()
+				.flatten()
+				.unwrap_or(self.text.len()),
+			_ => at,
+		}
+	}
+
+	/// The position of the grapheme boundary before the cursor, so that a
+	/// character and its combining marks, or an emoji sequence, are moved
+	/// over and deleted as one.
+	fn prevrune(&self) -> usize {
+		self.text[..self.cursor]
+			.grapheme_indices(true)
+			.next_back()
+			.map_or(0, |(i, _)| i)
+	}
+
+	/// The position of the grapheme boundary after the cursor.
+	fn nextrune(&self) -> usize {
+		self.text[self.cursor..]
+			.graphemes(true)
+			.next()
+			.map_or(self.cursor, |g| self.cursor + g.len())
+	}
+
+	/// The start of the word before the cursor, and of any delimiters after
+	/// it.
+	fn wordstart(&self) -> usize {
+		let mut graphemes =
+			self.text[..self.cursor].grapheme_indices(true).rev().peekable();
+		while graphemes.next_if(|&(_, g)| delimiter(g)).is_some() {}
+		while graphemes.next_if(|&(_, g)| !delimiter(g)).is_some() {}
+		graphemes.peek().map_or(0, |&(i, g)| i + g.len())
+	}
+
+	/// The end of the word after the cursor, and of any delimiters before
+	/// it.
+	fn wordend(&self) -> usize {
+		let mut graphemes =
+			self.text[self.cursor..].grapheme_indices(true).peekable();
+		while graphemes.next_if(|&(_, g)| delimiter(g)).is_some() {}
+		while graphemes.next_if(|&(_, g)| !delimiter(g)).is_some() {}
+		graphemes.peek().map_or(self.text.len(), |&(i, _)| self.cursor + i)
+	}
+
+	/// The key `pressed` stands for under the bindings of the config file.
+	pub fn rebind(&self, pressed: Chord) -> Chord {
+		let folded = pressed.fold();
+		self.opts
+			.keys
+			.iter()
+			.find(|(from, _)| from.fold() == folded)
+			.map_or(pressed, |&(_, to)| to)
+	}
+
+	/// Handles a key press, returning the status to exit with if it ends the
+	/// menu.
+	pub fn keypress(
+		&mut self,
+		ksym: Keysym,
+		mods: Mods,
+		buf: &str,
+	) -> Option<i32> {
+		let folded = Chord { ksym, mods }.fold();
+		let bound = self.opts.bindings.iter().find(|(c, _)| c.fold() == folded);
+		let action = bound
+			.map(|&(_, action)| action)
+			.or_else(|| self.action(ksym, mods));
+		let last = mem::replace(&mut self.last, action);
+		let status = match action {
+			Some(action) => self.act(action, last),
+			None => {
+				if buf.chars().next().is_some_and(|c| !c.is_control()) {
+					self.insert(buf);
+				}
+				None
+			}
+		};
+		status.or_else(|| self.decide())
+	}
+
+	/// Accepts what is matched once there is no choice left to make, with
+	/// `--auto-select` or `--accept-exact`, if the matches for the text
+	/// typed have come in since this was last called. Returns the status to
+	/// exit with if it did.
+	pub fn decide(&mut self) -> Option<i32> {
+		if !mem::take(&mut self.fresh) || self.text.is_empty() {
+			return None;
+		}
+		if self.opts.auto_select && self.matches.len() == 1 {
+			return self.accept(false);
+		}
+		let exact = self
+			.matches
+			.iter()
+			.position(|&i| self.items[i].text == self.text)
+			.filter(|_| self.opts.accept_exact)?;
+		self.sel = Some(exact);
+		self.accept(false)
+	}
+
+	/// The action a key does unless bound to another, or `None` if it types
+	/// what it stands for.
+	fn action(&self, ksym: Keysym, mods: Mods) -> Option<Action> {
+		let action = if mods.alt {
+			match ksym {
+				Keysym::b => Action::WordLeft,
+				Keysym::f => Action::WordRight,
+				Keysym::g => Action::First,
+				Keysym::G => Action::Last,
+				Keysym::h => Action::Up,
+				Keysym::j => Action::PageDown,
+				Keysym::k => Action::PageUp,
+				Keysym::l => Action::Down,
+				Keysym::y => Action::YankPop,
+				_ => Action::None,
+			}
+		} else if mods.ctrl {
+			// Control combinations stand in for other keys, as in dmenu, or
+			// edit the input, as in Emacs.
+			match ksym {
+				Keysym::Return | Keysym::KP_Enter => Action::AcceptCustom,
+				Keysym::c | Keysym::g | Keysym::bracketleft => Action::Abort,
+				Keysym::Tab | Keysym::ISO_Left_Tab => Action::CycleMatcher,
+				Keysym::Left | Keysym::KP_Left => Action::WordLeft,
+				Keysym::Right | Keysym::KP_Right => Action::WordRight,
+				Keysym::a => Action::LineStart,
+				Keysym::b => Action::Left,
+				Keysym::d => Action::DeleteChar,
+				Keysym::e => Action::LineEnd,
+				Keysym::f => Action::Right,
+				Keysym::h => Action::Backspace,
+				Keysym::i => return self.action(Keysym::Tab, Mods::default()),
+				Keysym::j | Keysym::m if !mods.shift => Action::Accept,
+				Keysym::j | Keysym::m | Keysym::J | Keysym::M => {
+					Action::AcceptCustom
+				}
+				Keysym::k => Action::DeleteToEnd,
+				Keysym::n => Action::Down,
+				Keysym::p => Action::Up,
+				Keysym::u => Action::DeleteToStart,
+				Keysym::w => Action::DeleteWord,
+				Keysym::y => Action::Yank,
+				_ => Action::None,
+			}
+		} else {
+			match ksym {
+				Keysym::Return | Keysym::KP_Enter if mods.shift => {
+					Action::AcceptCustom
+				}
+				Keysym::Return | Keysym::KP_Enter => Action::Accept,
+				Keysym::Escape => Action::Abort,
+				Keysym::Delete | Keysym::KP_Delete => Action::DeleteChar,
+				Keysym::BackSpace => Action::Backspace,
+				Keysym::Home | Keysym::KP_Home => Action::Home,
+				Keysym::End | Keysym::KP_End => Action::End,
+				Keysym::Left | Keysym::KP_Left => Action::Left,
+				Keysym::Right | Keysym::KP_Right => Action::Right,
+				Keysym::Up | Keysym::KP_Up => Action::Up,
+				Keysym::Down | Keysym::KP_Down => Action::Down,
+				Keysym::Prior | Keysym::KP_Prior => Action::PageUp,
+				Keysym::Next | Keysym::KP_Next => Action::PageDown,
+				// Shift+Tab chooses as Tab does.
+				Keysym::Tab | Keysym::ISO_Left_Tab if self.opts.multi => {
+					Action::ToggleMark
+				}
+				Keysym::Tab => Action::Complete,
+				_ => return None,
+			}
+		};
+		Some(action)
+	}
+
+	/// Does `action`, after the key pressed before did `last`, returning
+	/// the status to exit with if it ends the menu.
+	fn act(&mut self, action: Action, last: Option<Action>) -> Option<i32> {
+		// What is chosen must match what was typed, however far behind
+		// filtering is.
+		if matches!(
+			action,
+			Action::Accept
+				| Action::AcceptCustom
+				| Action::Complete
+				| Action::ToggleMark
+		) {
+			self.settle();
+		}
+		match action {
+			Action::None => {}
+			Action::Accept => return self.accept(false),
+			// The text typed is printed as it is.
+			Action::AcceptCustom => return self.accept(true),
+			// Cancelling prints nothing, and fails so that it can be told
+			// apart from choosing an empty item.
+			Action::Abort => return Some(self.cancel()),
+			// The item replaces the text typed, which can then be added to,
+			// so that with `--dynamic` the next level of a path is listed.
+			Action::Complete => {
+				if let Some(sel) = self.sel {
+					let text = self.item(sel).text.clone();
+					if self.opts.password {
+						zeroize(&mut self.text);
+					}
+					self.text = text;
+					self.cursor = self.text.len();
+					self.filter();
+				}
+			}
+			Action::ToggleMark => {
+				if let Some(sel) = self.sel {
+					let item = &mut self.items[self.matches[sel]];
+					item.out = !item.out;
+				}
+			}
+			// Switches to the next way of matching items.
+			Action::CycleMatcher => {
+				self.matcher.mode = self.matcher.mode.next();
+				self.filter();
+			}
+			Action::DeleteChar => {
+				if self.cursor < self.text.len() {
+					self.delete(self.nextrune());
+				}
+			}
+			Action::Backspace => {
+				if self.cursor > 0 {
+					self.delete(self.prevrune());
+				}
+			}
+			Action::DeleteWord
+			| Action::DeleteToStart
+			| Action::DeleteToEnd => {
+				let to = match action {
+					Action::DeleteToEnd => self.text.len(),
+					Action::DeleteToStart => 0,
+					_ => self.wordstart(),
+				};
+				if to != self.cursor {
+					self.kill(to, last);
+				}
+			}
+			Action::Yank => {
+				if let Some(text) = self.kills.last().cloned() {
+					self.yanked = Some((self.cursor, 0));
+					self.insert(&text);
+				}
+			}
+			// Replaces the text just yanked with the kill before it.
+			Action::YankPop => match self.yanked {
+				Some((start, back))
+					if matches!(last, Some(Action::Yank | Action::YankPop)) =>
+				{
+					let back = (back + 1) % self.kills.len();
+					let text = self.kills[self.kills.len() - 1 - back].clone();
+					self.delete(start);
+					self.yanked = Some((start, back));
+					self.insert(&text);
+				}
+				_ => {}
+			},
+			Action::LineStart => self.cursor = 0,
+			Action::LineEnd => self.cursor = self.text.len(),
+			Action::WordLeft => self.cursor = self.wordstart(),
+			Action::WordRight => self.cursor = self.wordend(),
+			Action::Home => {
+				if self.sel.is_none_or(|sel| sel == 0) {
+					self.cursor = 0;
+				} else {
+					self.first();
+				}
+			}
+			Action::End => {
+				if self.cursor < self.text.len() {
+					self.cursor = self.text.len();
+				} else {
+					self.last();
+				}
+			}
+			Action::First => self.first(),
+			Action::Last => self.last(),
+			Action::Left => {
+				// A vertical list leaves Left and Right to the cursor.
+				let vertical = self.opts.lines > 0 && self.opts.grid.is_none();
+				if self.cursor > 0
+					&& (vertical || self.sel.is_none_or(|sel| sel == 0))
+				{
+					self.cursor = self.prevrune();
+				} else if !vertical {
+					self.up();
+				}
+			}
+			Action::Right => {
+				if self.cursor < self.text.len() {
+					self.cursor = self.nextrune();
+				} else if self.opts.lines == 0 || self.opts.grid.is_some() {
+					self.down();
+				}
+			}
+			Action::Up if self.opts.grid.is_some() => self.uprow(),
+			Action::Up => self.up(),
+			Action::Down if self.opts.grid.is_some() => self.downrow(),
+			Action::Down => self.down(),
+			Action::PageUp => {
+				if self.sel.is_some() {
+					self.sel = Some(self.prev);
+					self.curr = self.prev;
+					self.calcoffsets();
+				}
+			}
+			Action::PageDown => {
+				if let Some(next) = self.next {
+					self.sel = Some(next);
+					self.curr = next;
+					self.calcoffsets();
+				}
+			}
+		}
+		let back = matches!(
+			action,
+			Action::Up | Action::Left | Action::Last | Action::End
+		);
+		self.unheader(back);
+		None
+	}
+
+	/// Prints, launches or runs what is chosen: the items chosen with
+	/// `--multi`, or else the one selected, or with `typed` or none
+	/// selected, the text typed. Returns the status to exit with, or `None`
+	/// if the text typed is left unaccepted, not being an item with
+	/// `--only-match` while some match.
+	fn accept(&mut self, typed: bool) -> Option<i32> {
+		let mut ok = true;
+		let mut end = EXIT_CHOSEN;
+		if !typed && self.items.iter().any(|item| item.out) {
+			for i in 0..self.items.len() {
+				if self.items[i].out {
+					ok &= self.print(i);
+				}
+			}
+		} else {
+			match self.sel.filter(|_| !typed) {
+				Some(sel) => ok = self.print(self.matches[sel]),
+				None => {
+					// Typed text may still be one of the items.
+					let text = &self.text;
+					let i = self
+						.items
+						.iter()
+						.position(|i| i.text == *text && i.selectable());
+					if self.opts.only_match && i.is_none() {
+						let status = self.opts.statuses[EXIT_NO_MATCH];
+						return self.matches.is_empty().then_some(status);
+					}
+					match i {
+						Some(i) => ok = self.print(i),
+						None => {
+							end = EXIT_CUSTOM;
+							ok = self.output(text, None);
+							self.copy(None);
+						}
+					}
+				}
+			}
+		}
+		if let Some(history) = &self.opts.history
+			&& let Err(err) = history.save()
+		{
+			eprintln!("cannot save the history: {}", err);
+		}
+		Some(if ok { self.opts.statuses[end] } else { 1 })
+	}
+
+	/// The status to exit with when the menu is cancelled.
+	pub fn cancel(&self) -> i32 {
+		self.opts.statuses[EXIT_ABORT]
+	}
+
+	/// Selects the first item.
+	fn first(&mut self) {
+		if self.sel.is_some() {
+			self.sel = Some(0);
+			self.curr = 0;
+			self.calcoffsets();
+		}
+	}
+
+	/// Selects the last item, showing the page which ends with it.
+	fn last(&mut self) {
+		if let Some((cols, _)) = self.grid()
+			&& !self.matches.is_empty()
+		{
+			let n = cols * self.opts.lines as usize;
+			self.curr = (self.matches.len() - 1) / n * n;
+			self.calcoffsets();
+		} else if self.next.is_some() {
+			// Jump to the end of the list and lay it out backwards.
+			self.curr = self.matches.len() - 1;
+			self.calcoffsets();
+			self.curr = self.prev;
+			self.calcoffsets();
+			while self.next.is_some() {
+				self.curr += 1;
+				self.calcoffsets();
+			}
+		}
+		self.sel = self.matches.len().checked_sub(1);
+	}
+
+	/// Prints the item at `i` in `items`, or launches or runs it, and its
+	/// index if asked to, and records it in the history. Returns whether it
+	/// could be launched or run.
+	fn print(&mut self, i: usize) -> bool {
+		// With `--run`, the commands in `$PATH` follow the applications.
+		match &self.opts.desktop {
+			Some(apps) if i < apps.len() => {
+				if let Err(err) = apps[i].launch(self.opts.scope) {
+					eprintln!("cannot launch {}: {}", apps[i].name, err);
+					return false;
+				}
+			}
+			_ => {
+				if !self.output(&self.items[i].text, Some(i)) {
+					return false;
+				}
+				self.copy(Some(i));
+			}
+		}
+		if let Some(history) = &mut self.opts.history {
+			history.record(&self.items[i].text);
+		}
+		if let Some(fd) = self.opts.index_fd {
+			// SAFETY: the descriptor was checked to be open at startup, and
+			// is not closed when the file is dropped.
+			let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+			if let Err(err) = writeln!(file, "{}", i) {
+				eprintln!("cannot print the index: {}", err);
+			}
+		}
+		true
+	}
+
+	/// Prints `text`, which is the item at `index` if there is one, or runs
+	/// it with `--run`, returning whether that succeeded. An item with a
+	/// value is printed or run as its value.
+	fn output(&self, text: &str, index: Option<usize>) -> bool {
+		let value = index.and_then(|i| self.items[i].value.as_deref());
+		if self.opts.run {
+			let text = value.unwrap_or(text);
+			// The scope is named for the program run.
+			let program = text.split(' ').find(|word| !word.is_empty());
+			let id = program.map_or("", |p| p.rsplit('/').next().unwrap_or(p));
+			return match launch(text, None, self.opts.scope, id) {
+				Ok(()) => true,
+				Err(err) => {
+					eprintln!("cannot run '{}': {}", text, err);
+					false
+				}
+			};
+		}
+		let end = self.opts.output_delim.as_deref().unwrap_or("\n");
+		if self.opts.copy_only {
+			return true;
+		}
+		let mut out = self.out.borrow_mut();
+		let printed = if self.opts.json {
+			// The value is given beside the text, rather than in its place.
+			let value = match self.opts.values || self.opts.json_input {
+				true => format!(
+					",\"value\":{}",
+					value.map_or("null".to_string(), json::quote)
+				),
+				false => String::new(),
+			};
+			write!(
+				out,
+				"{{\"text\":{}{},\"index\":{},\"custom\":{}}}{}",
+				json::quote(text),
+				value,
+				index.map_or("null".to_string(), |i| i.to_string()),
+				index.is_none(),
+				end
+			)
+		} else if self.opts.index {
+			write!(out, "{}{}", index.map_or(-1, |i| i as i64), end)
+		} else if self.opts.quote {
+			write!(out, "{}{}", quote(value.unwrap_or(text)), end)
+		} else {
+			write!(out, "{}{}", value.unwrap_or(text), end)
+		};
+		// Without a newline to end it, what is printed may still be
+		// buffered.
+		if let Err(err) = printed.and_then(|()| out.flush()) {
+			eprintln!("cannot print: {}", err);
+			return false;
+		}
+		true
+	}
+
+	/// Keeps the item at `index`, as its value if it has one, or else the
+	/// text typed, to be copied as the menu closes. A password typed is not
+	/// kept.
+	fn copy(&mut self, index: Option<usize>) {
+		if !self.opts.copy {
+			return;
+		}
+		let text = match index {
+			Some(i) => {
+				let item = &self.items[i];
+				item.value.as_deref().unwrap_or(&item.text)
+			}
+			None if self.opts.password => return,
+			None => &self.text,
+		};
+		self.copied.push(text.to_string());
+	}
+
+	/// What has been chosen to be copied, an item to a line, if anything.
+	pub fn copied(&mut self) -> Option<String> {
+		let copied = mem::take(&mut self.copied);
+		(!copied.is_empty()).then(|| copied.join("\n"))
+	}
+
+	/// Whether moving past either end of the list wraps around to the other,
+	/// which by default only a vertical list does.
+	fn wraps(&self) -> bool {
+		self.opts.wrap.unwrap_or(self.opts.lines > 0)
+	}
+
+	/// Whether a vertical list is scrolled an item at a time.
+	fn scrolls(&self) -> bool {
+		self.opts.scroll && self.opts.lines > 0 && self.grid().is_none()
+	}
+
+	/// Scrolls the list only as far as it takes for the match `sel` to be
+	/// on it.
+	fn reveal(&mut self, sel: usize) {
+		if sel < self.curr {
+			self.curr = sel;
+			self.calcoffsets();
+		}
+		while self.curr < sel && self.next.is_some_and(|next| next <= sel) {
+			self.curr += 1;
+			self.calcoffsets();
+		}
+	}
+
+	/// Moves the selection back an item, or to the last if wrapping.
+	fn up(&mut self) {
+		match self.sel {
+			Some(0) if self.wraps() => self.last(),
+			Some(sel) if sel > 0 => {
+				self.sel = Some(sel - 1);
+				if self.scrolls() {
+					self.reveal(sel - 1);
+				} else if sel == self.curr {
+					self.curr = self.prev;
+					self.calcoffsets();
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Moves the selection on an item, or to the first if wrapping.
+	fn down(&mut self) {
+		match self.sel {
+			Some(sel) if sel + 1 < self.matches.len() => {
+				self.sel = Some(sel + 1);
+				if self.scrolls() {
+					self.reveal(sel + 1);
+				} else if self.next == Some(sel + 1) {
+					self.curr = sel + 1;
+					self.calcoffsets();
+				}
+			}
+			Some(_) if self.wraps() => self.first(),
+			_ => {}
+		}
+	}
+
+	/// Moves the selection up a row of the grid.
+	fn uprow(&mut self) {
+		let Some((cols, _)) = self.grid() else {
+			return;
+		};
+		if let Some(sel) = self.sel.filter(|&sel| sel >= cols) {
+			self.sel = Some(sel - cols);
+			if sel - cols < self.curr {
+				self.curr = self.prev;
+				self.calcoffsets();
+			}
+		}
+	}
+
+	/// Moves the selection down a row of the grid, or to the last item if
+	/// there is none below it on the last row.
+	fn downrow(&mut self) {
+		let Some((cols, _)) = self.grid() else {
+			return;
+		};
+		let last = self.matches.len().saturating_sub(1);
+		if let Some(sel) = self.sel.filter(|&sel| sel / cols < last / cols) {
+			let sel = (sel + cols).min(last);
+			self.sel = Some(sel);
+			if let Some(next) = self.next.filter(|&next| next <= sel) {
+				self.curr = next;
+				self.calcoffsets();
+			}
+		}
+	}
+
+	/// Draws the part `row` of the text of the item at `i` in `matches`.
+	fn drawitem(
+		&mut self,
+		i: usize,
+		row: Range<usize>,
+		x: i32,
+		y: i32,
+		w: i32,
+	) -> i32 {
+		if self.item(i).selectable() {
+			self.targets.push(([x, y, w, self.bh], Target::Match(i)));
+		}
+		let scheme = if self.sel == Some(i) {
+			SCHEME_SEL
+		} else if self.item(i).header {
+			SCHEME_PROMPT
+		} else if self.item(i).out {
+			SCHEME_OUT
+		} else if self.opts.lines > 0 && self.row(i) % 2 == 1 {
+			SCHEME_ALT
+		} else {
+			SCHEME_NORM
+		};
+		let scheme = self.scheme[scheme];
+		let dimmed = dim(scheme);
+		let item = &self.items[self.matches[i]];
+		self.drw.setscheme(if item.disabled { dimmed } else { scheme });
+		// The matched characters are marked out, to show why it matched.
+		// Ranges of the whole text, as those of the row drawn.
+		let within = |range: &Range<usize>| {
+			let start = range.start.clamp(row.start, row.end);
+			let end = range.end.clamp(row.start, row.end);
+			start - row.start..end - row.start
+		};
+		let marks = self
+			.matcher
+			.ranges(&item.text[item.key..], &self.text)
+			.into_iter()
+			.map(|range| {
+				within(&(range.start + item.key..range.end + item.key))
+			})
+			.filter(|range| !range.is_empty())
+			.collect::<Vec<_>>();
+		let styles = item
+			.styles
+			.iter()
+			.map(|(range, style)| (within(range), *style))
+			.filter(|(range, _)| !range.is_empty())
+			.collect::<Vec<_>>();
+		let (bh, lpad) = (self.bh as u32, self.lrpad as u32 / 2);
+		// The icon is drawn beside the first line of the item, inside a
+		// margin of an eighth of its size.
+		let iconw = self.iconw();
+		if iconw > 0 {
+			self.drw.rect(x, y, iconw as u32, bh, true, true);
+			let pad = iconw / 8;
+			let size = (iconw - 2 * pad) as u32;
+			if row.start == 0
+				&& let Some(name) = &item.icon
+				&& let Some(icons) = &mut self.icons
+				&& let Some(image) = icons.get(name, size)
+			{
+				self.drw.image(x + pad, y + pad, image.size, &image.pixels);
+			}
+		}
+		let (x, w) = (x + iconw, (w - iconw).max(0) as u32);
+		let text = &item.text[row.clone()];
+		// The description follows the last line of the text, in what room
+		// it leaves.
+		let description = item.description.as_deref();
+		let Some(description) =
+			description.filter(|_| row.end == item.text.len())
+		else {
+			return self
+				.drw
+				.text(x, y, w, bh, lpad, text, &marks, &styles, false);
+		};
+		let tw = (self.drw.fontset_getwidth(text) + 2 * lpad).min(w);
+		let x = self.drw.text(x, y, tw, bh, lpad, text, &marks, &styles, false);
+		self.drw.setscheme(dimmed);
+		self.drw.text(x, y, w - tw, bh, lpad, description, &[], &[], false)
+	}
+
+	/// Draws the preview of the item selected beside a vertical list,
+	/// running the command again once the selection has moved.
+	fn drawpreview(&mut self) {
+		let w = self.previeww();
+		if w <= 0 {
+			return;
+		}
+		let selected = self.sel.map(|sel| self.matches[sel]);
+		if selected != self.previewing {
+			self.previewing = selected;
+			match (selected, self.preview.as_mut()) {
+				(Some(i), Some(preview)) => preview.query(&self.items[i].text),
+				_ => self.previewed.clear(),
+			}
+		}
+		let (bh, lpad) = (self.bh as u32, self.lrpad as u32 / 2);
+		self.drw.setscheme(self.scheme[SCHEME_NORM]);
+		let x = self.mw - w;
+		for (row, line) in
+			self.previewed.iter().take(self.opts.lines as usize).enumerate()
+		{
+			let y = self.bh * (1 + row as i32);
+			let line = plain(line);
+			self.drw.text(x, y, w as u32, bh, lpad, &line, &[], &[], false);
+		}
+	}
+
+	/// Draws the input field at `x`, and the cursor in it.
+	fn drawinput(&mut self, x: i32) {
+		let (bh, lpad) = (self.bh as u32, self.lrpad as u32 / 2);
+		self.drw.setscheme(self.scheme[SCHEME_INPUT]);
+		let w = match self.opts.lines > 0 || self.matches.is_empty() {
+			true => self.mw - x - self.statusw(),
+			false => self.inputw,
+		};
+		let (masked, composed, styles);
+		let (text, cursor, styles) = match self.opts.password {
+			true => {
+				let mask = &self.opts.mask;
+				let n = self.text[..self.cursor].graphemes(true).count();
+				let len = self.text.graphemes(true).count();
+				masked = mask.repeat(len);
+				(masked.as_str(), Some(n * mask.len()), &[][..])
+			}
+			// What an input method is composing is shown underlined at the
+			// cursor, with its own cursor in place of the input's.
+			false if !self.preedit.0.is_empty() => {
+				let (preedit, at) = &self.preedit;
+				let (before, after) = self.text.split_at(self.cursor);
+				composed = format!("{}{}{}", before, preedit, after);
+				let underline = Style { underline: true, ..Style::default() };
+				styles =
+					[(before.len()..before.len() + preedit.len(), underline)];
+				(composed.as_str(), at.map(|at| before.len() + at), &styles[..])
+			}
+			false
+				if self.text.is_empty()
+					&& !self.opts.placeholder.is_empty() =>
+			{
+				let dim = Style { alpha: Some(0x80), ..Style::default() };
+				styles = [(0..self.opts.placeholder.len(), dim)];
+				(self.opts.placeholder.as_str(), Some(0), &styles[..])
+			}
+			false => (self.text.as_str(), Some(self.cursor), &[][..]),
+		};
+		self.drw.text(
+			x,
+			0,
+			w.max(0) as u32,
+			bh,
+			lpad,
+			text,
+			&[],
+			styles,
+			false,
+		);
+		if let Some(cursor) = cursor {
+			let curpos =
+				self.drw.offset(text, cursor) as i32 + self.lrpad / 2 - 1;
+			self.caret = [x + curpos, 2, 2, self.bh - 4];
+			if curpos < w {
+				self.drw.rect(
+					x + curpos,
+					2,
+					2,
+					bh.saturating_sub(4),
+					true,
+					false,
+				);
+			}
+		}
+	}
+
+	pub fn draw(&mut self) {
+		let (bh, lpad) = (self.bh as u32, self.lrpad as u32 / 2);
+		let mut x = 0;
+		self.targets.clear();
+
+		self.drw.setscheme(self.scheme[SCHEME_NORM]);
+		self.drw.rect(0, 0, self.drw.w, self.drw.h, true, true);
+
+		if !self.opts.prompt.is_empty() {
+			self.drw.setscheme(self.scheme[SCHEME_PROMPT]);
+			let w = self.promptw as u32;
+			x = self.drw.text(
+				x,
+				0,
+				w,
+				bh,
+				lpad,
+				&self.opts.prompt,
+				&[],
+				&[],
+				false,
+			);
+		}
+
+		// The input field is left out with `--no-input`.
+		if !self.opts.no_input {
+			self.drawinput(x);
+		}
+
+		if self.opts.count {
+			let w = self.countw();
+			let count = format!("{}/{}", self.matches.len(), self.items.len());
+			self.drw.setscheme(self.scheme[SCHEME_NORM]);
+			let x = self.mw - w;
+			self.drw.text(x, 0, w as u32, bh, lpad, &count, &[], &[], false);
+		}
+		if !self.message.is_empty() {
+			let w = self.statusw() - self.countw();
+			self.drw.setscheme(self.scheme[SCHEME_PROMPT]);
+			let x = self.mw - self.statusw();
+			let message = &self.message;
+			self.drw.text(x, 0, w as u32, bh, lpad, message, &[], &[], false);
+		}
+		self.drawpreview();
+
+		if self.matches.is_empty() {
+			return;
+		}
+		let end = self.next.unwrap_or(self.matches.len());
+		if let Some((cols, cw)) = self.grid() {
+			// Items fill the grid a row at a time, and the scrollbar counts
+			// rows.
+			for i in self.curr..end {
+				let cell = (i - self.curr) as i32;
+				let x = x + cell % cols as i32 * cw;
+				let y = self.bh * (1 + cell / cols as i32);
+				let row = 0..self.item(i).text.len();
+				self.drawitem(i, row, x, y, cw);
+			}
+			let thumb = scrollbar(
+				self.opts.lines as i32 * self.bh,
+				self.opts.lines as usize,
+				self.matches.len().div_ceil(cols),
+				self.curr / cols,
+			);
+			if let Some((offset, size)) = thumb {
+				self.drw.setscheme(self.scheme[SCHEME_SEL]);
+				let right = self.mw - self.previeww();
+				let (x, y) = (right - SCROLLBAR_WIDTH as i32, self.bh + offset);
+				self.drw.rect(x, y, SCROLLBAR_WIDTH, size as u32, true, true);
+			}
+			return;
+		}
+		if self.opts.lines > 0 {
+			let track = self.opts.lines as i32 * self.bh;
+			let thumb = scrollbar(
+				track,
+				self.opts.lines as usize,
+				self.matches.len(),
+				self.curr,
+			);
+			let right = self.mw - self.previeww();
+			let w = match thumb {
+				Some(_) => right - x - SCROLLBAR_WIDTH as i32,
+				None => right - x,
+			};
+			// Items are listed one per line below the input field, or on as
+			// many lines as they are wrapped onto, up to the last.
+			let mut y = self.bh;
+			for i in self.curr..end {
+				for row in self.rows(i) {
+					if y >= self.mh {
+						break;
+					}
+					self.drawitem(i, row, x, y, w);
+					y += self.bh;
+				}
+			}
+			if let Some((offset, size)) = thumb {
+				self.drw.setscheme(self.scheme[SCHEME_SEL]);
+				let (x, y) = (right - SCROLLBAR_WIDTH as i32, self.bh + offset);
+				self.drw.rect(x, y, SCROLLBAR_WIDTH, size as u32, true, true);
+			}
+			return;
+		}
+		x += self.inputw;
+		let w = self.textw("<");
+		if self.curr > 0 {
+			self.drw.text(x, 0, w as u32, bh, lpad, "<", &[], &[], false);
+			self.targets.push(([x, 0, w, self.bh], Target::Prev));
+		}
+		x += w;
+		// The message and count are kept at the end, after the items.
+		let mw = self.mw - self.statusw();
+		for i in self.curr..end {
+			let (row, w) = (0..self.item(i).text.len(), self.textw(">"));
+			let w = self.itemw(i, mw - x - w);
+			x = self.drawitem(i, row, x, 0, w);
+		}
+		if self.next.is_some() {
+			let w = self.textw(">");
+			self.targets.push(([mw - w, 0, w, self.bh], Target::Next));
+			self.drw.setscheme(self.scheme[SCHEME_NORM]);
+			self.drw.text(mw - w, 0, w as u32, bh, lpad, ">", &[], &[], false);
+		}
+	}
+}
+
+/// Takes the lines of `items` which name a command after a NUL, as in
+/// `\0prompt\x1ftext`, out of them, returning the commands. Headers are
+/// left where they were given, as headers.
+fn controls(items: &mut Vec<Item>) -> Vec<Command> {
+	let mut commands = Vec::new();
+	items.retain_mut(|item| match Command::control(&item.text) {
+		Some(Command::Header(text)) => {
+			*item = Item::header(text);
+			true
+		}
+		Some(command) => {
+			commands.push(command);
+			false
+		}
+		None => true,
+	});
+	commands
+}
+
+/// Reads each item which is a JSON object as its text, value, icon,
+/// description and whether it is disabled. Items which are not objects
+/// with a text are shown as they are.
+fn objects(items: &mut [Item], opts: &Opts) {
+	if !opts.json_input {
+		return;
+	}
+	for item in items {
+		let Some(object) = json::parse(&item.text) else {
+			continue;
+		};
+		let field = |name| object.get(name).and_then(Value::text);
+		let Some(text) = field("text") else {
+			continue;
+		};
+		let owned = |text: Option<&str>| text.map(str::to_string);
+		*item = Item {
+			value: owned(field("value")),
+			icon: owned(field("icon")).filter(|icon| !icon.is_empty()),
+			description: owned(field("description"))
+				.filter(|description| !description.is_empty()),
+			disabled: Value::truthy(object.get("disabled")),
+			..Item::new(text.to_string())
+		};
+	}
+}
+
+/// Takes the name of each item's icon out of its text.
+fn icons(items: &mut [Item], opts: &Opts) {
+	if !opts.icons {
+		return;
+	}
+	let field = opts.icon_field.as_deref().filter(|d| !d.is_empty());
+	// Applications keep the icons their entries name.
+	for item in items.iter_mut().filter(|item| item.icon.is_none()) {
+		// Options follow a NUL, as names and values separated by 0x1f.
+		if let Some((text, options)) = item.text.split_once('\0') {
+			let mut options = options.split('\x1f');
+			while let (Some(name), Some(value)) =
+				(options.next(), options.next())
+			{
+				if name == "icon" && !value.is_empty() {
+					item.icon = Some(value.to_string());
+				}
+			}
+			item.text.truncate(text.len());
+		} else if let Some(field) = field
+			&& let Some((icon, text)) = item.text.split_once(field)
+		{
+			let icon = (!icon.is_empty()).then(|| icon.to_string());
+			(item.icon, item.text) = (icon, text.to_string());
+		}
+	}
+}
+
+/// Takes the value of each item, after its first tab, out of its text.
+fn values(items: &mut [Item], opts: &Opts) {
+	if !opts.values {
+		return;
+	}
+	for item in items {
+		if let Some((text, value)) = item.text.split_once('\t') {
+			item.value = Some(value.to_string());
+			item.text.truncate(text.len());
+		}
+	}
+}
+
+/// Replaces the markup or escape sequences of each item with the text they
+/// style, keeping how it is styled. Items which are not well formed markup
+/// are shown as they are.
+fn unmark(items: &mut [Item], opts: &Opts) {
+	if !opts.markup && !opts.ansi {
+		return;
+	}
+	for item in items {
+		let parsed = match opts.ansi {
+			true => Some(ansi::parse(&item.text)),
+			false => markup::parse(&item.text),
+		};
+		if let Some((text, styles)) = parsed {
+			(item.text, item.styles) = (text, styles);
+		}
+	}
+}
+
+/// Finds the part of each item to match, which follows the delimiter if
+/// there is one in it.
+fn split(items: &mut [Item], opts: &Opts) {
+	let Some(delim) = opts.delim.as_deref().filter(|d| !d.is_empty()) else {
+		return;
+	};
+	for item in items {
+		if let Some(i) = item.text.find(delim) {
+			item.key = i + delim.len();
+		}
+	}
+}
+
+/// `scheme` with its foreground halfway to its background, for what is
+/// shown dimmed.
+fn dim(scheme: [Clr; 3]) -> [Clr; 3] {
+	let mut dimmed = scheme;
+	dimmed[COL_FG] = clr_mix(scheme[COL_FG], scheme[COL_BG]);
+	dimmed
+}
+
+/// `text` quoted as the shell reads it, unless it is a word which needs no
+/// quoting.
+fn quote(text: &str) -> String {
+	let plain = |c: char| c.is_ascii_alphanumeric() || "_./:=@%+,-".contains(c);
+	if !text.is_empty() && text.chars().all(plain) {
+		return text.to_string();
+	}
+	format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// `line` as a preview shows it: with tabs as spaces, and without the escape
+/// sequences terminals take for colours.
+fn plain(line: &str) -> String {
+	let (text, _) = ansi::parse(line);
+	let mut out = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'\t' => out.push_str("    "),
+			c if c.is_control() => {}
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+/// The offset and size of the thumb of a scrollbar `track` pixels long, for
+/// a list of `total` items of which `lines` are shown from `top`. There is
+/// none if every item is shown.
+fn scrollbar(
+	track: i32,
+	lines: usize,
+	total: usize,
+	top: usize,
+) -> Option<(i32, i32)> {
+	if total <= lines || track <= 0 {
+		return None;
+	}
+	let (track, lines, total, top) =
+		(track as i64, lines as i64, total as i64, top as i64);
+	// The thumb is kept big enough to see, and inside the track.
+	let size = (track * lines / total).max(SCROLLBAR_MIN as i64).min(track);
+	let offset = (track * top / total).min(track - size);
+	Some((offset as i32, size as i32))
+}
+
+impl Drop for Menu {
+	fn drop(&mut self) {
+		if self.opts.password {
+			zeroize(&mut self.text);
+		}
+	}
+}
+
+/// Whether the grapheme `g` separates words, as a delimiter with any marks
+/// on it does.
+fn delimiter(g: &str) -> bool {
+	g.chars().next().is_some_and(|c| config::WORD_DELIMITERS.contains(c))
+}
+
+/// Empties `text` and overwrites every byte it had room for.
+fn zeroize(text: &mut String) {
+	// SAFETY: the text is emptied before its bytes are overwritten.
+	let buf = unsafe { text.as_mut_vec() };
+	buf.clear();
+	wipe(buf.spare_capacity_mut());
+}
+
+/// Keeps `buf` from being swapped out, as far as the limit on locked
+/// memory allows, past which it is left as it is.
+pub fn lock(buf: &[MaybeUninit<u8>]) {
+	// SAFETY: the range is that of the buffer, which outlives the lock.
+	unsafe { libc::mlock(buf.as_ptr().cast(), buf.len()) };
+}
+
+/// Overwrites `buf` with zeroes in a way that cannot be optimized away.
+pub fn wipe(buf: &mut [MaybeUninit<u8>]) {
+	for b in buf {
+		// SAFETY: the pointer comes from a reference, so is valid.
+		unsafe { ptr::write_volatile(b, MaybeUninit::new(0)) };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use wmenu_core::Mode;
+
+	use super::*;
+	use crate::theme::Theme;
+
+	/// What a menu prints, kept to be looked at.
+	#[derive(Clone, Default)]
+	struct Printed(Rc<RefCell<Vec<u8>>>);
+
+	impl Write for Printed {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.borrow_mut().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	impl Printed {
+		fn text(&self) -> String {
+			String::from_utf8(self.0.borrow().clone()).unwrap()
+		}
+	}
+
+	/// Keeps what `menu` prints from now on.
+	fn capture(menu: &mut Menu) -> Printed {
+		let printed = Printed::default();
+		menu.out = RefCell::new(Box::new(printed.clone()));
+		printed
+	}
+
+	/// A menu of `items` 600 pixels wide, drawn in the default font and
+	/// colours, listing as many lines as `opts` does.
+	fn menu(items: &[&str], matcher: Matcher, opts: Opts) -> Menu {
+		themed(items, Theme::default(), matcher, opts)
+	}
+
+	/// A menu as above, as `theme` sets it out.
+	fn themed(
+		items: &[&str],
+		mut theme: Theme,
+		matcher: Matcher,
+		opts: Opts,
+	) -> Menu {
+		theme.lines = Some(opts.lines);
+		let (fonts, look) = theme.look().unwrap();
+		let items = items.iter().map(|text| Item::new(text.to_string()));
+		let drw = Drw::new(fonts);
+		let mut menu = Menu::new(items.collect(), drw, matcher, look, opts);
+		menu.resize(600);
+		menu
+	}
+
+	/// A vertical list of `n` items, named after their numbers.
+	fn list(n: usize, opts: Opts) -> Menu {
+		let items: Vec<_> = (0..n).map(|i| i.to_string()).collect();
+		let items: Vec<_> = items.iter().map(String::as_str).collect();
+		menu(&items, Matcher::default(), opts)
+	}
+
+	/// The texts of the items shown.
+	fn shown(menu: &Menu) -> Vec<&str> {
+		let end = menu.next.unwrap_or(menu.matches.len());
+		(menu.curr..end).map(|i| menu.item(i).text.as_str()).collect()
+	}
+
+	fn selected(menu: &Menu) -> Option<&str> {
+		menu.sel.map(|sel| menu.item(sel).text.as_str())
+	}
+
+	/// The colour of the buffer at `x`, `y`, border included.
+	fn pixel(menu: &Menu, x: u32, y: u32) -> Clr {
+		let mut buf = [0; 4];
+		menu.drw.map(&mut buf, 4, [x, y, 1, 1]);
+		u32::from_ne_bytes(buf)
+	}
+
+	/// The bar along the top of the menu, as drawn.
+	fn bar(menu: &mut Menu) -> Vec<u8> {
+		menu.draw();
+		let (w, h) = (menu.drw.w, menu.bh as u32);
+		let mut buf = vec![0; w as usize * h as usize * 4];
+		menu.drw.map(&mut buf, w as usize * 4, [0, 0, w, h]);
+		buf
+	}
+
+	/// Types `text` a key at a time.
+	fn typed(menu: &mut Menu, text: &str) {
+		for c in text.chars() {
+			let ksym = Keysym::from_char(c);
+			menu.keypress(ksym, Mods::default(), &c.to_string());
+		}
+	}
+
+	fn press(menu: &mut Menu, action: Action, times: usize) {
+		for _ in 0..times {
+			menu.act(action, None);
+		}
+	}
+
+	#[test]
+	fn vertical_lists_show_a_page_of_lines() {
+		let mut menu = list(10, Opts { lines: 3, ..Opts::default() });
+		assert_eq!(shown(&menu), ["0", "1", "2"]);
+		press(&mut menu, Action::Down, 2);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("2"), vec!["0", "1", "2"])
+		);
+		press(&mut menu, Action::Down, 1);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("3"), vec!["3", "4", "5"])
+		);
+		press(&mut menu, Action::Up, 1);
+		assert_eq!(shown(&menu), ["0", "1", "2"]);
+		press(&mut menu, Action::PageDown, 3);
+		assert_eq!((selected(&menu), shown(&menu)), (Some("9"), vec!["9"]));
+		press(&mut menu, Action::PageUp, 1);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("6"), vec!["6", "7", "8"])
+		);
+	}
+
+	#[test]
+	fn vertical_lists_scroll_a_line_at_a_time() {
+		let opts = Opts { lines: 3, scroll: true, ..Opts::default() };
+		let mut menu = list(10, opts);
+		press(&mut menu, Action::Down, 4);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("4"), vec!["2", "3", "4"])
+		);
+		press(&mut menu, Action::Up, 3);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("1"), vec!["1", "2", "3"])
+		);
+	}
+
+	#[test]
+	fn vertical_lists_wrap_around_at_either_end() {
+		let mut menu = list(10, Opts { lines: 3, ..Opts::default() });
+		press(&mut menu, Action::Up, 1);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("9"), vec!["7", "8", "9"])
+		);
+		press(&mut menu, Action::Down, 1);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("0"), vec!["0", "1", "2"])
+		);
+	}
+
+	#[test]
+	fn vertical_lists_draw_the_selected_line() {
+		let mut menu = list(10, Opts { lines: 3, ..Opts::default() });
+		press(&mut menu, Action::Down, 1);
+		menu.draw();
+		let bh = menu.bh as u32;
+		let row = |line: u32| pixel(&menu, 1, bh * (1 + line) + bh / 2);
+		assert_eq!(row(0), menu.scheme[SCHEME_NORM][COL_BG]);
+		assert_eq!(row(1), menu.scheme[SCHEME_SEL][COL_BG]);
+		assert_eq!(row(2), menu.scheme[SCHEME_NORM][COL_BG]);
+	}
+
+	#[test]
+	fn passwords_are_drawn_masked_and_kept_as_typed() {
+		let password = || Opts {
+			password: true,
+			mask: "*".to_string(),
+			..Opts::default()
+		};
+		let mut secret = menu(&[], Matcher::default(), password());
+		typed(&mut secret, "hunter2");
+		let mut other = menu(&[], Matcher::default(), password());
+		typed(&mut other, "letmein");
+		let mut stars = menu(&[], Matcher::default(), Opts::default());
+		typed(&mut stars, "*******");
+		assert_eq!(secret.text, "hunter2");
+		assert_eq!(bar(&mut secret), bar(&mut other));
+		assert_eq!(bar(&mut secret), bar(&mut stars));
+	}
+
+	#[test]
+	fn zeroize_overwrites_what_the_text_had() {
+		let mut text = String::from("hunter2");
+		let (ptr, len) = (text.as_ptr(), text.len());
+		zeroize(&mut text);
+		assert!(text.is_empty());
+		// SAFETY: the text keeps its buffer, which it has emptied.
+		let wiped = unsafe { std::slice::from_raw_parts(ptr, len) };
+		assert_eq!(wiped, [0; 7]);
+	}
+
+	#[test]
+	fn items_are_drawn_in_the_colours_given() {
+		let mut theme = Theme::default();
+		theme.colors[SCHEME_NORM][COL_BG] = Some("#102030".to_string());
+		theme.colors[SCHEME_SEL][COL_BG] = Some("#405060".to_string());
+		let opts = Opts { lines: 2, ..Opts::default() };
+		let mut menu = themed(&["a", "b"], theme, Matcher::default(), opts);
+		menu.draw();
+		let bh = menu.bh as u32;
+		assert_eq!(pixel(&menu, 1, bh + bh / 2), 0xff405060);
+		assert_eq!(pixel(&menu, 1, 2 * bh + bh / 2), 0xff102030);
+	}
+
+	/// Where the input starts, from where its cursor is drawn with nothing
+	/// typed.
+	fn input_x(menu: &mut Menu) -> i32 {
+		menu.draw();
+		menu.caret[0] - (menu.lrpad / 2 - 1)
+	}
+
+	#[test]
+	fn prompts_push_the_input_right_by_their_width() {
+		let prompt = Opts { prompt: "Run:".to_string(), ..Opts::default() };
+		let mut menu = menu(&["a"], Matcher::default(), prompt);
+		let w = menu.drw.fontset_getwidth("Run:") as i32 + menu.lrpad;
+		assert_eq!(menu.promptw, w - menu.lrpad / 4);
+		assert_eq!(input_x(&mut menu), menu.promptw);
+	}
+
+	#[test]
+	fn empty_prompts_take_no_room() {
+		let mut menu = menu(&["a"], Matcher::default(), Opts::default());
+		assert_eq!(menu.promptw, 0);
+		assert_eq!(input_x(&mut menu), 0);
+	}
+
+	#[test]
+	fn long_prompts_take_at_most_half_the_menu() {
+		let prompt = "a very long prompt ".repeat(20);
+		let opts = Opts { prompt, ..Opts::default() };
+		let mut menu = menu(&["a"], Matcher::default(), opts);
+		assert_eq!(menu.promptw, menu.mw / 2);
+		assert_eq!(input_x(&mut menu), menu.mw / 2);
+	}
+
+	fn items(texts: &[&str]) -> Vec<Item> {
+		texts.iter().map(|text| Item::new(text.to_string())).collect()
+	}
+
+	#[test]
+	fn what_is_typed_before_items_arrive_filters_them() {
+		let mut menu = menu(&[], Matcher::default(), Opts::default());
+		typed(&mut menu, "fo");
+		assert_eq!(shown(&menu), Vec::<&str>::new());
+		menu.extend(items(&["foo", "bar"]));
+		assert_eq!(shown(&menu), ["foo"]);
+		typed(&mut menu, "b");
+		menu.extend(items(&["fob", "baz"]));
+		assert_eq!((menu.text.as_str(), shown(&menu)), ("fob", vec!["fob"]));
+	}
+
+	#[test]
+	fn items_arriving_keep_the_selection() {
+		let opts = Opts { lines: 5, ..Opts::default() };
+		let mut menu = menu(&["a1", "b", "a2"], Matcher::default(), opts);
+		typed(&mut menu, "a");
+		press(&mut menu, Action::Down, 1);
+		menu.extend(items(&["a0"]));
+		assert_eq!(shown(&menu), ["a1", "a2", "a0"]);
+		assert_eq!(selected(&menu), Some("a2"));
+	}
+
+	fn key(menu: &mut Menu, ksym: Keysym) -> Option<i32> {
+		menu.keypress(ksym, Mods::default(), "")
+	}
+
+	fn multi(items: &[&str]) -> Menu {
+		let opts = Opts { lines: 5, multi: true, ..Opts::default() };
+		menu(items, Matcher::default(), opts)
+	}
+
+	#[test]
+	fn tab_chooses_items_for_return_to_print() {
+		let mut menu = multi(&["a", "b", "c", "d"]);
+		let printed = capture(&mut menu);
+		key(&mut menu, Keysym::Down);
+		key(&mut menu, Keysym::Down);
+		key(&mut menu, Keysym::Tab);
+		key(&mut menu, Keysym::Up);
+		key(&mut menu, Keysym::Up);
+		key(&mut menu, Keysym::Tab);
+		key(&mut menu, Keysym::Down);
+		assert_eq!(key(&mut menu, Keysym::Return), Some(0));
+		// They are printed in the order they were read.
+		assert_eq!(printed.text(), "a\nc\n");
+	}
+
+	#[test]
+	fn tab_again_unchooses() {
+		let mut menu = multi(&["a", "b", "c"]);
+		let printed = capture(&mut menu);
+		key(&mut menu, Keysym::Tab);
+		key(&mut menu, Keysym::Down);
+		key(&mut menu, Keysym::Tab);
+		key(&mut menu, Keysym::Up);
+		key(&mut menu, Keysym::ISO_Left_Tab);
+		key(&mut menu, Keysym::Return);
+		assert_eq!(printed.text(), "b\n");
+	}
+
+	#[test]
+	fn return_prints_the_selection_if_nothing_is_chosen() {
+		let mut menu = multi(&["a", "b", "c"]);
+		let printed = capture(&mut menu);
+		key(&mut menu, Keysym::Down);
+		key(&mut menu, Keysym::Return);
+		assert_eq!(printed.text(), "b\n");
+	}
+
+	#[test]
+	fn chosen_items_are_drawn_in_their_own_colours() {
+		let mut theme = Theme::default();
+		theme.colors[SCHEME_OUT][COL_BG] = Some("#00ff00".to_string());
+		let opts = Opts { lines: 3, multi: true, ..Opts::default() };
+		let mut menu =
+			themed(&["a", "b", "c"], theme, Matcher::default(), opts);
+		key(&mut menu, Keysym::Tab);
+		key(&mut menu, Keysym::Down);
+		menu.draw();
+		let bh = menu.bh as u32;
+		assert_eq!(pixel(&menu, 1, bh + bh / 2), 0xff00ff00);
+		let norm = menu.scheme[SCHEME_NORM][COL_BG];
+		assert_eq!(pixel(&menu, 1, 3 * bh + bh / 2), norm);
+	}
+
+	#[test]
+	fn lines_are_as_tall_as_the_font() {
+		let font = |font: &str| Theme {
+			font: Some(font.to_string()),
+			..Theme::default()
+		};
+		let theme = font("monospace:pixelsize=12");
+		let small = themed(&[], theme, Matcher::default(), Opts::default());
+		let theme = font("monospace:pixelsize=36");
+		let large = themed(&[], theme, Matcher::default(), Opts::default());
+		for menu in [&small, &large] {
+			assert_eq!(menu.bh, menu.drw.fonts.h as i32 + 2);
+			assert_eq!(menu.lrpad, menu.drw.fonts.h as i32);
+			assert_eq!(menu.height(), menu.bh as u32);
+		}
+		assert!(large.bh > 2 * small.bh);
+	}
+
+	#[test]
+	fn indices_are_printed_as_items_were_read() {
+		let mut fds = [0; 2];
+		// SAFETY: the array has room for both ends of the pipe.
+		assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+		// SAFETY: the pipe is owned by the files alone.
+		let (mut read, write) =
+			unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+		let opts = Opts { index_fd: Some(fds[1]), ..Opts::default() };
+		let items = ["apple", "banana", "cherry", "blueberry"];
+		let mut menu = menu(&items, Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "b");
+		key(&mut menu, Keysym::Right);
+		key(&mut menu, Keysym::Return);
+		drop((menu, write));
+		let mut index = String::new();
+		io::Read::read_to_string(&mut read, &mut index).unwrap();
+		assert_eq!(
+			(printed.text(), index),
+			("blueberry\n".into(), "3\n".into())
+		);
+	}
+
+	#[test]
+	fn items_typed_out_are_printed_as_chosen_items_are() {
+		let mut fds = [0; 2];
+		// SAFETY: the array has room for both ends of the pipe.
+		assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+		// SAFETY: the pipe is owned by the files alone.
+		let (mut read, write) =
+			unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+		let path = std::env::temp_dir()
+			.join(format!("wmenu-menu-{}-typed-history", std::process::id()));
+		let history = History::load(path.clone()).unwrap();
+		let opts = Opts {
+			index_fd: Some(fds[1]),
+			history: Some(history),
+			..Opts::default()
+		};
+		let mut menu = menu(&["foobar", "foo"], Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "foo");
+		assert_eq!(selected(&menu), Some("foo"));
+		ctrl(&mut menu, Keysym::Return);
+		let history = menu.opts.history.take().unwrap();
+		assert!(history.score("foo") > history.score("foobar"));
+		std::fs::remove_file(path).unwrap();
+		drop((menu, write));
+		let mut index = String::new();
+		io::Read::read_to_string(&mut read, &mut index).unwrap();
+		assert_eq!((printed.text(), index), ("foo\n".into(), "1\n".into()));
+	}
+
+	#[test]
+	fn indices_can_be_printed_in_place_of_items() {
+		let opts = Opts { index: true, ..Opts::default() };
+		let mut menu = menu(&["a", "ba", "bb"], Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "bb");
+		key(&mut menu, Keysym::Return);
+		assert_eq!(printed.text(), "2\n");
+	}
+
+	#[test]
+	fn text_typed_has_no_index() {
+		let opts = Opts { index: true, ..Opts::default() };
+		let mut menu = menu(&["a"], Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "z");
+		key(&mut menu, Keysym::Return);
+		assert_eq!(printed.text(), "-1\n");
+	}
+
+	#[test]
+	fn pastes_go_in_at_the_cursor_and_filter() {
+		let items = ["foobar", "foobaz", "bar"];
+		let mut menu = menu(&items, Matcher::default(), Opts::default());
+		typed(&mut menu, "fr");
+		key(&mut menu, Keysym::Left);
+		menu.paste("ooba");
+		assert_eq!((menu.text.as_str(), menu.cursor), ("foobar", 5));
+		assert_eq!(shown(&menu), ["foobar"]);
+	}
+
+	#[test]
+	fn pastes_are_put_on_one_line() {
+		let items = ["foobar", "foo"];
+		let mut menu = menu(&items, Matcher::default(), Opts::default());
+		menu.paste("foo\r\nbar\n");
+		assert_eq!(menu.text, "foobar");
+		assert_eq!(shown(&menu), ["foobar"]);
+	}
+
+	#[test]
+	fn pasted_passwords_are_kept_whole() {
+		let opts = Opts { password: true, ..Opts::default() };
+		let mut menu = menu(&[], Matcher::default(), opts);
+		typed(&mut menu, "ab");
+		menu.paste(&"c".repeat(100));
+		assert_eq!(menu.text, format!("ab{}", "c".repeat(100)));
+	}
+
+	/// A history in which `text` was chosen just now.
+	fn chosen(text: &str) -> History {
+		let path = std::env::temp_dir()
+			.join(format!("wmenu-menu-{}-history", std::process::id()));
+		let mut history = History::load(path).unwrap();
+		history.record(text);
+		history
+	}
+
+	#[test]
+	fn items_chosen_before_are_ranked_first() {
+		let opts = Opts { history: Some(chosen("firefox")), ..Opts::default() };
+		let items = ["files", "fish", "firefox"];
+		let mut menu = menu(&items, Matcher::default(), opts);
+		typed(&mut menu, "fi");
+		assert_eq!(shown(&menu), ["firefox", "files", "fish"]);
+	}
+
+	#[test]
+	fn items_chosen_before_keep_their_place_unless_ranked() {
+		let opts = Opts { history: Some(chosen("firefox")), ..Opts::default() };
+		let matcher = Matcher { sort: Sort::None, ..Matcher::default() };
+		let items = ["files", "fish", "firefox"];
+		let mut menu = menu(&items, matcher, opts);
+		typed(&mut menu, "fi");
+		assert_eq!(shown(&menu), items);
+	}
+
+	#[test]
+	fn scrollbars_are_left_out_when_every_item_is_shown() {
+		assert_eq!(scrollbar(100, 5, 5, 0), None);
+		assert_eq!(scrollbar(100, 5, 3, 0), None);
+		assert_eq!(scrollbar(0, 5, 10, 0), None);
+	}
+
+	#[test]
+	fn scrollbar_thumbs_are_as_long_as_the_share_shown() {
+		assert_eq!(scrollbar(100, 5, 10, 0), Some((0, 50)));
+		assert_eq!(scrollbar(100, 5, 10, 5), Some((50, 50)));
+		assert_eq!(scrollbar(120, 10, 40, 10), Some((30, 30)));
+		assert_eq!(scrollbar(120, 10, 40, 30), Some((90, 30)));
+	}
+
+	#[test]
+	fn scrollbar_thumbs_stay_big_enough_to_see() {
+		assert_eq!(scrollbar(100, 5, 1000, 0), Some((0, SCROLLBAR_MIN)));
+		// The last page ends the thumb at the end of the track.
+		let last = scrollbar(100, 5, 1000, 995).unwrap();
+		assert_eq!(last, (100 - SCROLLBAR_MIN, SCROLLBAR_MIN));
+	}
+
+	#[test]
+	fn scrollbars_are_drawn_where_the_list_is() {
+		let mut menu = list(20, Opts { lines: 5, ..Opts::default() });
+		press(&mut menu, Action::PageDown, 1);
+		menu.draw();
+		let (bh, x) = (menu.bh, menu.mw as u32 - 1);
+		let (offset, size) = scrollbar(5 * bh, 5, 20, 5).unwrap();
+		// The thumb covers the second quarter of the track.
+		assert_eq!((offset, size), (5 * bh / 4, 5 * bh / 4));
+		let thumb = menu.scheme[SCHEME_SEL][COL_BG];
+		let at = |y: i32| pixel(&menu, x, (bh + y) as u32);
+		assert_ne!(at(offset - 1), thumb);
+		assert_eq!(at(offset), thumb);
+		assert_eq!(at(offset + size - 1), thumb);
+		assert_ne!(at(offset + size), thumb);
+	}
+
+	fn split_at(delim: &str) -> Opts {
+		Opts { lines: 5, delim: Some(delim.to_string()), ..Opts::default() }
+	}
+
+	#[test]
+	fn only_what_follows_the_delimiter_is_matched() {
+		let items = ["Browser | firefox", "Editor | vim", "Terminal"];
+		let mut menu = menu(&items, Matcher::default(), split_at(" | "));
+		typed(&mut menu, "Editor");
+		assert_eq!(shown(&menu), Vec::<&str>::new());
+		menu.act(Action::DeleteToStart, None);
+		typed(&mut menu, "fire");
+		assert_eq!(shown(&menu), ["Browser | firefox"]);
+	}
+
+	#[test]
+	fn items_without_the_delimiter_are_matched_whole() {
+		let items = ["Browser | firefox", "Terminal"];
+		let mut menu = menu(&items, Matcher::default(), split_at(" | "));
+		typed(&mut menu, "Term");
+		assert_eq!(shown(&menu), ["Terminal"]);
+	}
+
+	#[test]
+	fn items_split_at_a_delimiter_are_printed_whole() {
+		let items = ["Browser | firefox", "Editor | vim"];
+		let mut menu = menu(&items, Matcher::default(), split_at(" | "));
+		let printed = capture(&mut menu);
+		typed(&mut menu, "vim");
+		key(&mut menu, Keysym::Return);
+		assert_eq!(printed.text(), "Editor | vim\n");
+	}
+
+	fn initially(text: &str) -> Opts {
+		Opts { lines: 5, initial: text.to_string(), ..Opts::default() }
+	}
+
+	#[test]
+	fn initial_text_filters_the_first_matches() {
+		let items = ["alpha", "beta", "alphabet"];
+		let mut menu = menu(&items, Matcher::default(), initially("alp"));
+		assert_eq!(shown(&menu), ["alpha", "alphabet"]);
+		assert_eq!((menu.text.as_str(), menu.cursor), ("alp", 3));
+		menu.draw();
+		let caret = menu.caret[0] - (menu.lrpad / 2 - 1);
+		assert_eq!(caret, menu.drw.offset("alp", 3) as i32);
+	}
+
+	#[test]
+	fn initial_text_can_be_edited() {
+		let items = ["alpha", "beta", "alphabet"];
+		let mut menu = menu(&items, Matcher::default(), initially("alp"));
+		typed(&mut menu, "hab");
+		assert_eq!(shown(&menu), ["alphabet"]);
+		menu.act(Action::DeleteToStart, None);
+		assert_eq!(shown(&menu), items);
+	}
+
+	#[test]
+	fn lists_stop_at_either_end_without_wrap() {
+		let opts = Opts { lines: 3, wrap: Some(false), ..Opts::default() };
+		let mut menu = list(5, opts);
+		press(&mut menu, Action::Up, 1);
+		assert_eq!(selected(&menu), Some("0"));
+		press(&mut menu, Action::Down, 6);
+		assert_eq!(
+			(selected(&menu), shown(&menu)),
+			(Some("4"), vec!["3", "4"])
+		);
+	}
+
+	#[test]
+	fn bars_wrap_around_with_wrap() {
+		let mut menu = list(3, Opts { wrap: Some(true), ..Opts::default() });
+		press(&mut menu, Action::Up, 1);
+		assert_eq!(selected(&menu), Some("2"));
+		press(&mut menu, Action::Down, 1);
+		assert_eq!(selected(&menu), Some("0"));
+	}
+
+	#[test]
+	fn only_vertical_lists_wrap_by_default() {
+		let mut bar = list(3, Opts::default());
+		press(&mut bar, Action::Up, 1);
+		assert_eq!(selected(&bar), Some("0"));
+		press(&mut bar, Action::Down, 3);
+		assert_eq!(selected(&bar), Some("2"));
+		let mut vertical = list(3, Opts { lines: 3, ..Opts::default() });
+		press(&mut vertical, Action::Down, 3);
+		assert_eq!(selected(&vertical), Some("0"));
+	}
+
+	/// A menu which exits with a status of its own for each way it ends.
+	fn statuses(items: &[&str]) -> Menu {
+		let opts = Opts { statuses: [10, 11, 12, 13], ..Opts::default() };
+		menu(items, Matcher::default(), opts)
+	}
+
+	fn ctrl(menu: &mut Menu, ksym: Keysym) -> Option<i32> {
+		let mods = Mods { ctrl: true, ..Mods::default() };
+		menu.keypress(ksym, mods, "")
+	}
+
+	#[test]
+	fn ctrl_return_prints_the_text_typed() {
+		let mut menu = statuses(&["foobar", "food"]);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "foo");
+		assert_eq!(selected(&menu), Some("foobar"));
+		assert_eq!(ctrl(&mut menu, Keysym::Return), Some(11));
+		assert_eq!(printed.text(), "foo\n");
+	}
+
+	#[test]
+	fn return_prints_the_item_selected() {
+		let mut menu = statuses(&["foobar", "food"]);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "foo");
+		assert_eq!(key(&mut menu, Keysym::Return), Some(10));
+		assert_eq!(printed.text(), "foobar\n");
+	}
+
+	#[test]
+	fn return_prints_the_text_typed_if_nothing_matches() {
+		let mut menu = statuses(&["foobar"]);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "bar baz");
+		assert_eq!(key(&mut menu, Keysym::Return), Some(11));
+		assert_eq!(printed.text(), "bar baz\n");
+	}
+
+	#[test]
+	fn text_typed_which_is_an_item_is_chosen() {
+		let mut menu = statuses(&["foobar", "foo"]);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "foo");
+		assert_eq!(ctrl(&mut menu, Keysym::Return), Some(10));
+		assert_eq!(printed.text(), "foo\n");
+	}
+
+	/// Where each item is drawn, as `[x, y, w, h]`.
+	fn drawn(menu: &mut Menu) -> Vec<[i32; 4]> {
+		menu.draw();
+		menu.targets
+			.iter()
+			.filter(|(_, target)| matches!(target, Target::Match(_)))
+			.map(|&(area, _)| area)
+			.collect()
+	}
+
+	fn spaced(line_height: Option<u32>, padding: Option<u32>) -> Menu {
+		let theme = Theme { line_height, padding, ..Theme::default() };
+		let opts = Opts { lines: 3, ..Opts::default() };
+		themed(&["a", "b", "c"], theme, Matcher::default(), opts)
+	}
+
+	#[test]
+	fn lines_are_as_tall_as_given_with_padding_either_side() {
+		let mut menu = spaced(Some(30), Some(5));
+		assert_eq!((menu.bh, menu.lrpad), (40, 10));
+		let ys: Vec<_> = drawn(&mut menu).iter().map(|area| area[1]).collect();
+		assert_eq!(ys, [40, 80, 120]);
+		assert_eq!(menu.height(), 160);
+	}
+
+	#[test]
+	fn padding_alone_pads_the_font() {
+		let mut menu = spaced(None, Some(8));
+		let font = menu.drw.fonts.h as i32;
+		assert_eq!(menu.bh, font + 16);
+		let ys: Vec<_> = drawn(&mut menu).iter().map(|area| area[1]).collect();
+		assert_eq!(ys, [font + 16, 2 * (font + 16), 3 * (font + 16)]);
+	}
+
+	#[test]
+	fn lines_are_a_pixel_taller_than_the_font_by_default() {
+		let mut menu = spaced(None, None);
+		let bh = menu.drw.fonts.h as i32 + 2;
+		assert_eq!(menu.bh, bh);
+		let areas = drawn(&mut menu);
+		assert_eq!(areas[1], [0, 2 * bh, areas[1][2], bh]);
+	}
+
+	#[test]
+	fn buffers_are_scaled_from_the_surface() {
+		let mut menu = list(3, Opts { lines: 3, ..Opts::default() });
+		let (height, font) = (menu.height(), menu.drw.fonts.h);
+		assert_eq!(menu.drw.size(), (600, height));
+		menu.set_scale(1.5);
+		menu.resize(600);
+		let (w, h) = menu.drw.size();
+		assert_eq!(w, 900);
+		assert_eq!(h, (menu.height() as f64 * 1.5).round() as u32);
+		assert!(h.abs_diff(height * 3 / 2) <= 6, "{} {}", h, height);
+		// Text is rasterized at the scale, rather than stretched.
+		assert!(menu.drw.fonts.h.abs_diff(font * 3 / 2) <= 1);
+		assert_eq!(menu.bh, menu.drw.fonts.h as i32 + 2 * 2);
+	}
+
+	#[test]
+	fn borders_are_scaled_with_the_menu() {
+		let theme = Theme { border_width: Some(2), ..Theme::default() };
+		let mut menu =
+			themed(&["a"], theme, Matcher::default(), Opts::default());
+		menu.set_scale(1.5);
+		menu.resize(400);
+		assert_eq!(menu.bw, 3);
+		assert_eq!(menu.drw.size().0, 600);
+		assert_eq!(menu.drw.w, 600 - 2 * 3);
+	}
+
+	#[test]
+	fn escape_prints_nothing_and_exits_as_aborted() {
+		let mut menu = statuses(&["foo", "bar"]);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "foo");
+		assert_eq!(key(&mut menu, Keysym::Escape), Some(12));
+		assert_eq!(printed.text(), "");
+	}
+
+	#[test]
+	fn ctrl_c_aborts_as_escape_does() {
+		for ksym in [Keysym::c, Keysym::g, Keysym::bracketleft] {
+			let mut menu = statuses(&["foo"]);
+			let printed = capture(&mut menu);
+			assert_eq!(ctrl(&mut menu, ksym), Some(12));
+			assert_eq!(printed.text(), "");
+		}
+	}
+
+	#[test]
+	fn menus_abort_with_1_by_default() {
+		let opts = Opts { statuses: config::STATUSES, ..Opts::default() };
+		let mut menu = menu(&["foo"], Matcher::default(), opts);
+		assert_eq!(key(&mut menu, Keysym::Escape), Some(1));
+		assert_eq!(menu.cancel(), 1);
+	}
+
+	/// The status a menu of `statuses` and `--only-match` if `only` exits
+	/// with once `text` is typed and Return pressed.
+	fn ended(statuses: [i32; EXITS], only: bool, text: &str) -> Option<i32> {
+		let opts = Opts { statuses, only_match: only, ..Opts::default() };
+		let mut menu = menu(&["foo", "bar"], Matcher::default(), opts);
+		capture(&mut menu);
+		typed(&mut menu, text);
+		key(&mut menu, Keysym::Return)
+	}
+
+	#[test]
+	fn each_ending_has_a_status_of_its_own_by_default() {
+		assert_eq!(ended(config::STATUSES, false, "fo"), Some(0));
+		assert_eq!(ended(config::STATUSES, false, "baz"), Some(2));
+		assert_eq!(ended(config::STATUSES, true, "baz"), Some(3));
+	}
+
+	#[test]
+	fn statuses_are_remapped_by_name() {
+		let mut statuses = config::STATUSES;
+		for exit in ["custom=0", "no-match=5", "chosen=7"] {
+			let (end, status) = exit.split_once('=').unwrap();
+			let (end, status) = crate::theme::status(end, status).unwrap();
+			statuses[end] = status;
+		}
+		assert_eq!(ended(statuses, false, "fo"), Some(7));
+		assert_eq!(ended(statuses, false, "baz"), Some(0));
+		assert_eq!(ended(statuses, true, "baz"), Some(5));
+	}
+
+	/// A menu with `text` typed and the cursor at `cursor`.
+	fn editing(text: &str, cursor: usize) -> Menu {
+		let items = ["foo bar", "foo", "baz"];
+		let mut menu = menu(&items, Matcher::default(), initially(text));
+		menu.cursor = cursor;
+		menu
+	}
+
+	fn input(menu: &Menu) -> (&str, usize) {
+		(menu.text.as_str(), menu.cursor)
+	}
+
+	#[test]
+	fn ctrl_w_deletes_the_word_before_the_cursor() {
+		let mut menu = editing("foo bar  baz", 10);
+		ctrl(&mut menu, Keysym::w);
+		assert_eq!(input(&menu), ("foo bar  az", 9));
+		// The spaces after a word go with it.
+		ctrl(&mut menu, Keysym::w);
+		assert_eq!(input(&menu), ("foo az", 4));
+		ctrl(&mut menu, Keysym::w);
+		assert_eq!(input(&menu), ("az", 0));
+		ctrl(&mut menu, Keysym::w);
+		assert_eq!(input(&menu), ("az", 0));
+	}
+
+	#[test]
+	fn ctrl_u_and_ctrl_k_delete_to_either_end() {
+		let mut menu = editing("foo bar", 4);
+		ctrl(&mut menu, Keysym::k);
+		assert_eq!(input(&menu), ("foo ", 4));
+		assert_eq!(shown(&menu), ["foo bar"]);
+		key(&mut menu, Keysym::Left);
+		ctrl(&mut menu, Keysym::u);
+		assert_eq!(input(&menu), (" ", 0));
+		assert_eq!(shown(&menu), ["foo bar"]);
+		ctrl(&mut menu, Keysym::k);
+		assert_eq!(input(&menu), ("", 0));
+		assert_eq!(shown(&menu), ["foo bar", "foo", "baz"]);
+	}
+
+	#[test]
+	fn ctrl_a_and_ctrl_e_move_to_either_end() {
+		let mut menu = editing("foo bar", 3);
+		ctrl(&mut menu, Keysym::a);
+		assert_eq!(input(&menu), ("foo bar", 0));
+		ctrl(&mut menu, Keysym::e);
+		assert_eq!(input(&menu), ("foo bar", 7));
+	}
+
+	#[test]
+	fn arrows_move_the_cursor_a_character_at_a_time() {
+		let mut menu = editing("foo", 3);
+		key(&mut menu, Keysym::Left);
+		key(&mut menu, Keysym::Left);
+		assert_eq!(input(&menu), ("foo", 1));
+		key(&mut menu, Keysym::Right);
+		assert_eq!(input(&menu), ("foo", 2));
+		typed(&mut menu, "x");
+		assert_eq!(input(&menu), ("foxo", 3));
+		key(&mut menu, Keysym::BackSpace);
+		key(&mut menu, Keysym::Delete);
+		assert_eq!(input(&menu), ("fo", 2));
+	}
+
+	#[test]
+	fn words_are_moved_over_with_alt() {
+		let alt = |menu: &mut Menu, ksym| {
+			let mods = Mods { alt: true, ..Mods::default() };
+			menu.keypress(ksym, mods, "");
+		};
+		let mut menu = editing("foo bar baz", 11);
+		alt(&mut menu, Keysym::b);
+		assert_eq!(input(&menu), ("foo bar baz", 8));
+		ctrl(&mut menu, Keysym::Left);
+		assert_eq!(input(&menu), ("foo bar baz", 4));
+		alt(&mut menu, Keysym::f);
+		assert_eq!(input(&menu), ("foo bar baz", 7));
+	}
+
+	#[test]
+	fn kills_in_a_row_are_yanked_as_one() {
+		let mut menu = editing("foo bar baz", 11);
+		ctrl(&mut menu, Keysym::w);
+		ctrl(&mut menu, Keysym::w);
+		assert_eq!(input(&menu), ("foo ", 4));
+		ctrl(&mut menu, Keysym::y);
+		assert_eq!(input(&menu), ("foo bar baz", 11));
+	}
+
+	#[test]
+	fn the_cursor_is_drawn_where_it_is() {
+		let mut menu = editing("foo bar", 3);
+		menu.draw();
+		let x = menu.drw.offset("foo bar", 3) as i32 + menu.lrpad / 2 - 1;
+		assert_eq!(menu.caret[0], x);
+	}
+
+	#[test]
+	fn long_items_are_wrapped_onto_several_rows() {
+		let long = "word ".repeat(60);
+		let items = [long.trim_end(), "short", "other"];
+		let opts = Opts { lines: 4, word_wrap: true, ..Opts::default() };
+		let mut menu = menu(&items, Matcher::default(), opts);
+		let rows = menu.rows(0).len();
+		assert!(rows > 1, "{}", rows);
+		assert_eq!(menu.rows(1).len(), 1);
+		let areas = drawn(&mut menu);
+		let bh = menu.bh;
+		// The selected item is highlighted on every row it is wrapped onto.
+		let sel = menu.scheme[SCHEME_SEL][COL_BG];
+		for row in 0..rows.min(4) as i32 {
+			assert_eq!(areas[row as usize][1], bh * (1 + row));
+			assert_eq!(pixel(&menu, 1, (bh * (1 + row) + bh / 2) as u32), sel);
+		}
+	}
+
+	#[test]
+	fn wrapped_items_take_the_room_of_their_rows() {
+		let long = "word ".repeat(30);
+		let items = [long.trim_end(), "a", "b", "c", "d"];
+		let opts = Opts { lines: 4, word_wrap: true, ..Opts::default() };
+		let mut menu = menu(&items, Matcher::default(), opts);
+		let rows = menu.rows(0).len();
+		assert!((2..4).contains(&rows), "{}", rows);
+		assert_eq!(shown(&menu).len(), 1 + 4 - rows);
+		press(&mut menu, Action::PageDown, 1);
+		assert_eq!(shown(&menu)[0], items[1 + 4 - rows]);
+	}
+
+	#[test]
+	fn the_cursor_moves_over_flags_as_one_character() {
+		let flag = "\u{1f1e9}\u{1f1ea}";
+		let text = format!("a{}b", flag);
+		let mut menu = editing(&text, text.len());
+		key(&mut menu, Keysym::Left);
+		key(&mut menu, Keysym::Left);
+		assert_eq!(menu.cursor, 1);
+		key(&mut menu, Keysym::Right);
+		assert_eq!(menu.cursor, 1 + flag.len());
+		key(&mut menu, Keysym::BackSpace);
+		assert_eq!(input(&menu), ("ab", 1));
+	}
+
+	#[test]
+	fn the_cursor_moves_over_wide_characters() {
+		let mut menu = editing("日本語", 9);
+		key(&mut menu, Keysym::Left);
+		assert_eq!(menu.cursor, 6);
+		ctrl(&mut menu, Keysym::a);
+		key(&mut menu, Keysym::Delete);
+		assert_eq!(input(&menu), ("本語", 0));
+		// The cursor is drawn after the characters, however wide.
+		key(&mut menu, Keysym::Right);
+		menu.draw();
+		let x = menu.drw.fontset_getwidth("本") as i32 + menu.lrpad / 2 - 1;
+		assert_eq!(menu.caret[0], x);
+	}
+
+	#[test]
+	fn marks_and_joiners_are_moved_over_with_what_they_join() {
+		let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+		let text = format!("e\u{301}{}", family);
+		let mut menu = editing(&text, 0);
+		key(&mut menu, Keysym::Right);
+		assert_eq!(menu.cursor, 3);
+		key(&mut menu, Keysym::Right);
+		assert_eq!(menu.cursor, text.len());
+	}
+
+	#[test]
+	fn marks_typed_join_the_character_before_them() {
+		let mut menu = editing("", 0);
+		typed(&mut menu, "e\u{301}");
+		assert_eq!(input(&menu), ("e\u{301}", 3));
+		key(&mut menu, Keysym::BackSpace);
+		assert_eq!(input(&menu), ("", 0));
+	}
+
+	#[test]
+	fn unsorted_menus_show_matches_as_read() {
+		let items = ["fxixrxe", "firefox", "fire"];
+		let fuzzy = Matcher { mode: Mode::Fuzzy, ..Matcher::default() };
+		let mut ranked = menu(&items, fuzzy, Opts::default());
+		typed(&mut ranked, "fire");
+		assert_eq!(shown(&ranked), ["fire", "firefox", "fxixrxe"]);
+		let unsorted = Matcher { sort: Sort::None, ..fuzzy };
+		let mut menu = menu(&items, unsorted, Opts::default());
+		typed(&mut menu, "fire");
+		assert_eq!(shown(&menu), items);
+	}
+
+	#[test]
+	fn borders_take_their_width_from_the_content() {
+		let theme = Theme {
+			border_width: Some(3),
+			border_color: Some("#ff0000".into()),
+			..Theme::default()
+		};
+		let mut menu =
+			themed(&["a"], theme, Matcher::default(), Opts::default());
+		assert_eq!(menu.mw, 600 - 2 * 3);
+		assert_eq!(menu.drw.size().0, 600);
+		menu.draw();
+		let (_, h) = menu.drw.size();
+		assert_eq!(pixel(&menu, 0, 0), 0xffff0000);
+		assert_eq!(pixel(&menu, 599, h - 1), 0xffff0000);
+		assert_eq!(pixel(&menu, 2, h / 2), 0xffff0000);
+		assert_ne!(pixel(&menu, 3, 3), 0xffff0000);
+	}
+
+	#[test]
+	fn json_output_gives_the_index_of_what_is_chosen() {
+		let opts = Opts { json: true, ..Opts::default() };
+		let mut menu = menu(&["foo", "say \"hi\""], Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		key(&mut menu, Keysym::Down);
+		key(&mut menu, Keysym::Return);
+		let json = r#"{"text":"say \"hi\"","index":1,"custom":false}"#;
+		assert_eq!(printed.text(), format!("{}\n", json));
+	}
+
+	#[test]
+	fn json_output_marks_what_is_typed_as_custom() {
+		let opts = Opts { json: true, ..Opts::default() };
+		let mut menu = menu(&["foo", "bar"], Matcher::default(), opts);
+		let printed = capture(&mut menu);
+		typed(&mut menu, "baz");
+		key(&mut menu, Keysym::Return);
+		let json = r#"{"text":"baz","index":null,"custom":true}"#;
+		assert_eq!(printed.text(), format!("{}\n", json));
+	}
+
+	/// A grid of `cols` columns and two rows of ten items, "0" to "9".
+	fn grid(cols: u32) -> Menu {
+		list(10, Opts { lines: 2, grid: Some(cols), ..Opts::default() })
+	}
+
+	#[test]
+	fn grids_fill_a_row_at_a_time() {
+		let menu = grid(3);
+		assert_eq!(menu.grid().map(|(cols, _)| cols), Some(3));
+		assert_eq!([0, 2, 3, 5].map(|i| menu.row(i)), [0, 0, 1, 1]);
+		assert_eq!(shown(&menu), ["0", "1", "2", "3", "4", "5"]);
+	}
+
+	#[test]
+	fn up_and_down_move_a_row_of_the_grid() {
+		let mut menu = grid(3);
+		key(&mut menu, Keysym::Right);
+		assert_eq!(selected(&menu), Some("1"));
+		key(&mut menu, Keysym::Down);
+		assert_eq!(selected(&menu), Some("4"));
+		key(&mut menu, Keysym::Down);
+		assert_eq!(selected(&menu), Some("7"));
+		assert_eq!(shown(&menu), ["6", "7", "8", "9"]);
+		// There is nothing below the 7, so the last item is chosen.
+		key(&mut menu, Keysym::Down);
+		assert_eq!(selected(&menu), Some("9"));
+		key(&mut menu, Keysym::Down);
+		assert_eq!(selected(&menu), Some("9"));
+		key(&mut menu, Keysym::Up);
+		key(&mut menu, Keysym::Up);
+		assert_eq!(selected(&menu), Some("3"));
+		assert_eq!(shown(&menu), ["0", "1", "2", "3", "4", "5"]);
+		key(&mut menu, Keysym::Up);
+		assert_eq!(selected(&menu), Some("0"));
+		key(&mut menu, Keysym::Up);
+		assert_eq!(selected(&menu), Some("0"));
+	}
+
+	#[test]
+	fn grids_of_no_columns_fit_the_widest_item() {
+		let menu = grid(0);
+		let (cols, cw) = menu.grid().unwrap();
+		assert!(cols > 3, "{} columns", cols);
+		assert!(cw >= menu.widest);
+	}
+}
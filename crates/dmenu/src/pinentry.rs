@@ -0,0 +1,426 @@
+//! `--pinentry`, which speaks the Assuan protocol gpg-agent and others ask
+//! for passphrases in on stdin and stdout, as pinentry programs do. Each
+//! passphrase is typed into a menu of its own, run with `-P`, and each
+//! question is asked in one listing the buttons as items.
+
+use std::{
+	env,
+	fs::File,
+	io::{self, BufRead, Read, Write},
+	mem::ManuallyDrop,
+	os::fd::FromRawFd,
+	process::{self, Command, Stdio},
+};
+
+use crate::menu::{lock, wipe};
+
+/// The longest passphrase read, in bytes.
+const MAX_PIN: usize = 4096;
+
+/// How long each line of data sent may be, short of the 1000 bytes Assuan
+/// allows so that an escape is never split.
+const MAX_DATA: usize = 900;
+
+const CANCELLED: &str = "ERR 83886179 Operation cancelled <Pinentry>";
+const NOT_CONFIRMED: &str = "ERR 83886194 Not confirmed <Pinentry>";
+const TOO_LONG: &str = "ERR 83886147 Value too large <Pinentry>";
+const UNKNOWN: &str =
+	"ERR 536871187 Unknown IPC command <User defined source 1>";
+
+/// What the next question is asked with, as set by the commands before it.
+#[derive(Default)]
+struct Dialog {
+	desc: Option<String>,
+	prompt: Option<String>,
+	error: Option<String>,
+	ok: Option<String>,
+	cancel: Option<String>,
+	notok: Option<String>,
+}
+
+/// A passphrase, kept in memory which is not swapped out, as far as the
+/// limit on locked memory allows, and wiped when it is dropped.
+struct Secret {
+	buf: Vec<u8>,
+	len: usize,
+}
+
+impl Secret {
+	fn new(size: usize) -> Secret {
+		let mut buf = Vec::with_capacity(size);
+		lock(buf.spare_capacity_mut());
+		buf.resize(size, 0);
+		Secret { buf, len: 0 }
+	}
+
+	fn push(&mut self, b: u8) {
+		self.buf[self.len] = b;
+		self.len += 1;
+	}
+}
+
+impl Drop for Secret {
+	fn drop(&mut self) {
+		self.buf.clear();
+		wipe(self.buf.spare_capacity_mut());
+	}
+}
+
+/// Answers the commands read from stdin until it ends or is told `BYE`,
+/// running dmenu with `menu`, the options given before `--pinentry`, to
+/// ask each question. `args` are those given after it, as pinentry
+/// programs are, of which only `--display` is used.
+pub fn run(menu: Vec<String>, mut args: impl Iterator<Item = String>) -> ! {
+	let mut env = Vec::new();
+	while let Some(arg) = args.next() {
+		if arg == "--display"
+			&& let Some(display) = args.next()
+		{
+			env.push(("DISPLAY".to_string(), display));
+		}
+	}
+	let mut dialog = Dialog::default();
+	let mut stdout = io::stdout();
+	let mut reply = |line: &str| {
+		let sent = writeln!(stdout, "{}", line).and_then(|()| stdout.flush());
+		if sent.is_err() {
+			process::exit(1);
+		}
+	};
+	reply("OK Pleased to meet you");
+	for line in io::stdin().lock().lines() {
+		let Ok(line) = line else {
+			break;
+		};
+		let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
+		let text = || Some(unescape(arg)).filter(|text| !text.is_empty());
+		match command.to_ascii_uppercase().as_str() {
+			"SETDESC" => dialog.desc = text(),
+			"SETPROMPT" => dialog.prompt = text(),
+			"SETERROR" => dialog.error = text(),
+			"SETOK" => dialog.ok = text(),
+			"SETCANCEL" => dialog.cancel = text(),
+			"SETNOTOK" => dialog.notok = text(),
+			// The display and environment the menu is shown in.
+			"OPTION" => {
+				let option = arg.strip_prefix("--").unwrap_or(arg);
+				let (name, value) =
+					option.split_once('=').unwrap_or((option, ""));
+				match name {
+					"display" => {
+						env.push(("DISPLAY".to_string(), value.into()))
+					}
+					"putenv" => {
+						if let Some((name, value)) = value.split_once('=') {
+							env.push((name.to_string(), value.to_string()));
+						}
+					}
+					_ => {}
+				}
+			}
+			"GETINFO" => match arg {
+				"flavor" => reply("D wmenu"),
+				"version" => reply(&format!("D {}", env!("CARGO_PKG_VERSION"))),
+				"pid" => reply(&format!("D {}", process::id())),
+				_ => {}
+			},
+			"GETPIN" => {
+				let answer = getpin(&dialog, &menu, &env);
+				// An error is only shown the once.
+				dialog.error = None;
+				match answer {
+					Ok(Some(pin)) => {
+						if send(&pin).is_err() {
+							process::exit(1);
+						}
+					}
+					Ok(None) => {
+						reply(CANCELLED);
+						continue;
+					}
+					Err(err) => {
+						reply(&err);
+						continue;
+					}
+				}
+			}
+			"CONFIRM" | "MESSAGE" => {
+				let one = command.eq_ignore_ascii_case("MESSAGE")
+					|| arg == "--one-button";
+				let answer = confirm(&dialog, one, &menu, &env);
+				dialog.error = None;
+				if let Err(err) = answer {
+					reply(err);
+					continue;
+				}
+			}
+			"RESET" => dialog = Dialog::default(),
+			"BYE" => {
+				reply("OK closing connection");
+				process::exit(0);
+			}
+			// Ignored, as the menu has no use for them: timeouts, titles,
+			// keys, buttons of their own and the like.
+			command if command.starts_with("SET") => {}
+			"NOP" => {}
+			_ => {
+				reply(UNKNOWN);
+				continue;
+			}
+		}
+		reply("OK");
+	}
+	process::exit(0)
+}
+
+/// Asks for a passphrase, returning it, or none if the menu was cancelled.
+fn getpin(
+	dialog: &Dialog,
+	menu: &[String],
+	env: &[(String, String)],
+) -> Result<Option<Secret>, String> {
+	let prompt = dialog.prompt.as_deref().unwrap_or("PIN:");
+	let message = dialog.error.as_ref().or(dialog.desc.as_ref());
+	let mut args = statuses();
+	args.extend(["-P".to_string(), "-p".to_string(), prompt.into()]);
+	if let Some(message) = message {
+		args.extend(["--message".to_string(), message.replace('\n', " ")]);
+	}
+	let failed = |err: io::Error| format!("ERR 83886081 {} <Pinentry>", err);
+	let mut child = spawn(menu, &args, env, Stdio::null()).map_err(failed)?;
+	let mut pin = Secret::new(MAX_PIN);
+	let mut stdout = child.stdout.take().expect("stdout is piped");
+	let read = loop {
+		match stdout.read(&mut pin.buf[pin.len..]) {
+			Ok(0) => break Ok(()),
+			Ok(n) => pin.len += n,
+			Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+			Err(err) => break Err(err),
+		}
+		if pin.len == pin.buf.len() {
+			break Err(io::Error::other("too long"));
+		}
+	};
+	drop(stdout);
+	let status = child.wait().map_err(failed)?;
+	match read {
+		Err(_) if pin.len == pin.buf.len() => Err(TOO_LONG.to_string()),
+		Err(err) => Err(failed(err)),
+		Ok(()) if !status.success() => Ok(None),
+		Ok(()) => {
+			// What is typed is printed on a line of its own.
+			if pin.buf[..pin.len].ends_with(b"\n") {
+				pin.len -= 1;
+			}
+			Ok(Some(pin))
+		}
+	}
+}
+
+/// Asks a question, with the buttons as items, returning whether it was
+/// answered yes, or for a message, seen.
+fn confirm(
+	dialog: &Dialog,
+	one: bool,
+	menu: &[String],
+	env: &[(String, String)],
+) -> Result<(), &'static str> {
+	let ok = dialog.ok.as_deref().map_or("OK".to_string(), mnemonic);
+	let notok = dialog.notok.as_deref().map(mnemonic);
+	let cancel = dialog.cancel.as_deref().map_or("Cancel".into(), mnemonic);
+	let mut items = vec![ok.clone()];
+	if !one {
+		items.extend(notok.clone());
+		items.push(cancel);
+	}
+	let message = dialog.error.as_ref().or(dialog.desc.as_ref());
+	let mut args = statuses();
+	args.extend(["--only-match".to_string(), "--no-input".to_string()]);
+	if let Some(message) = message {
+		args.extend(["--message".to_string(), message.replace('\n', " ")]);
+	}
+	let run = || -> io::Result<(bool, String)> {
+		let mut child = spawn(menu, &args, env, Stdio::piped())?;
+		let mut stdin = child.stdin.take().expect("stdin is piped");
+		stdin.write_all(format!("{}\n", items.join("\n")).as_bytes())?;
+		drop(stdin);
+		let output = child.wait_with_output()?;
+		let chosen = String::from_utf8_lossy(&output.stdout);
+		Ok((output.status.success(), chosen.trim_end().to_string()))
+	};
+	match run() {
+		Ok((true, chosen)) if chosen == ok => Ok(()),
+		_ if one => Ok(()),
+		Ok((true, chosen)) if notok.as_ref() == Some(&chosen) => {
+			Err(NOT_CONFIRMED)
+		}
+		_ => Err(CANCELLED),
+	}
+}
+
+/// The options which have the menu exit successfully with what is chosen
+/// or typed, and not when cancelled, whatever the theme file sets.
+fn statuses() -> Vec<String> {
+	let statuses = ["chosen=0", "custom=0", "abort=1"];
+	statuses
+		.iter()
+		.flat_map(|s| ["--exit".to_string(), s.to_string()])
+		.collect()
+}
+
+/// Runs dmenu with the options it was given before `--pinentry`, then
+/// `args`, printing what is chosen to a pipe.
+fn spawn(
+	menu: &[String],
+	args: &[String],
+	env: &[(String, String)],
+	stdin: Stdio,
+) -> io::Result<process::Child> {
+	Command::new(env::current_exe()?)
+		.args(menu)
+		.args(args)
+		.envs(env.iter().map(|(name, value)| (name, value)))
+		.stdin(stdin)
+		.stdout(Stdio::piped())
+		.spawn()
+}
+
+/// Sends `pin` as data, written straight to stdout, so that the passphrase
+/// is not left in its buffer.
+fn send(pin: &Secret) -> io::Result<()> {
+	let data = data(pin);
+	// SAFETY: stdout is open, and is not closed when the file is dropped.
+	let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(1) });
+	file.write_all(&data.buf[..data.len])
+}
+
+/// The lines of data `pin` is sent as, escaped as Assuan escapes it and
+/// split into lines short enough to send.
+fn data(pin: &Secret) -> Secret {
+	let lines = pin.len * 3 / MAX_DATA + 1;
+	let mut data = Secret::new(pin.len * 3 + lines * 3);
+	let mut line = 0;
+	data.push(b'D');
+	data.push(b' ');
+	for &b in &pin.buf[..pin.len] {
+		if data.len - line >= MAX_DATA {
+			data.push(b'\n');
+			line = data.len;
+			data.push(b'D');
+			data.push(b' ');
+		}
+		if matches!(b, b'%' | b'\r' | b'\n') {
+			const HEX: &[u8; 16] = b"0123456789ABCDEF";
+			data.push(b'%');
+			data.push(HEX[b as usize >> 4]);
+			data.push(HEX[b as usize & 0xf]);
+		} else {
+			data.push(b);
+		}
+	}
+	data.push(b'\n');
+	data
+}
+
+/// Undoes the percent escapes of the text a command is given.
+fn unescape(arg: &str) -> String {
+	let mut bytes = Vec::with_capacity(arg.len());
+	let mut rest = arg.as_bytes();
+	while let Some((&b, after)) = rest.split_first() {
+		let hex = after.get(..2).and_then(|h| str::from_utf8(h).ok());
+		match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+			Some(byte) if b == b'%' => {
+				bytes.push(byte);
+				rest = &after[2..];
+			}
+			_ => {
+				bytes.push(b);
+				rest = after;
+			}
+		}
+	}
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A label without the underscores which mark out its access key, of which
+/// a doubled one stands for an underscore itself.
+fn mnemonic(label: &str) -> String {
+	let mut text = String::with_capacity(label.len());
+	let mut chars = label.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'_' => text.extend(chars.next()),
+			c => text.push(c),
+		}
+	}
+	text
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn secret(text: &[u8]) -> Secret {
+		let mut secret = Secret::new(text.len());
+		text.iter().for_each(|&b| secret.push(b));
+		secret
+	}
+
+	fn sent(pin: &[u8]) -> Vec<u8> {
+		let data = data(&secret(pin));
+		data.buf[..data.len].to_vec()
+	}
+
+	#[test]
+	fn mnemonics_lose_their_underscores() {
+		assert_eq!(mnemonic("_OK"), "OK");
+		assert_eq!(mnemonic("_Cancel"), "Cancel");
+		assert_eq!(mnemonic("Do _not save"), "Do not save");
+		assert_eq!(mnemonic("snake__case"), "snake_case");
+		assert_eq!(mnemonic("No"), "No");
+		assert_eq!(mnemonic("trailing_"), "trailing");
+	}
+
+	#[test]
+	fn percent_escapes_are_undone() {
+		assert_eq!(
+			unescape("Enter%20the%0Apassphrase"),
+			"Enter the\npassphrase"
+		);
+		assert_eq!(unescape("100%25"), "100%");
+		assert_eq!(unescape("50% off, 1%z"), "50% off, 1%z");
+		assert_eq!(unescape("%"), "%");
+	}
+
+	#[test]
+	fn data_is_escaped() {
+		assert_eq!(sent(b"p%ss\r\nw"), b"D p%25ss%0D%0Aw\n");
+		assert_eq!(sent(b""), b"D \n");
+	}
+
+	#[test]
+	fn long_data_is_split_into_lines() {
+		let pin = [b'a'; 2000];
+		let sent = sent(&pin);
+		let lines: Vec<&[u8]> =
+			sent.strip_suffix(b"\n").unwrap().split(|&b| b == b'\n').collect();
+		assert_eq!(lines.len(), 3);
+		for line in &lines {
+			assert!(line.starts_with(b"D "));
+			assert!(line.len() <= MAX_DATA + 2);
+		}
+		let data: usize = lines.iter().map(|line| line.len() - 2).sum();
+		assert_eq!(data, pin.len());
+	}
+
+	#[test]
+	fn escapes_are_not_split_across_lines() {
+		let pin = [b'%'; 1000];
+		let sent = sent(&pin);
+		for line in sent.strip_suffix(b"\n").unwrap().split(|&b| b == b'\n') {
+			let data = line.strip_prefix(b"D ").unwrap();
+			assert_eq!(data.len() % 3, 0);
+			assert!(data.chunks(3).all(|escape| escape == b"%25"));
+		}
+	}
+}
@@ -0,0 +1,165 @@
+//! Reading the theme file again while the menu is shown, once it has been
+//! saved or SIGUSR1 is sent, so that a menu follows a theme switched for
+//! the rest of the desktop. Only the fonts, colours and sizes are taken
+//! from it again; where the menu is, how it matches and its keys stay as
+//! they were.
+
+use std::{
+	ffi::OsStr,
+	fs::File,
+	io::{self, Read},
+	mem,
+	os::{
+		fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd},
+		unix::ffi::OsStrExt,
+	},
+	path::{Path, PathBuf},
+	ptr,
+	sync::atomic::{AtomicI32, Ordering},
+	thread,
+};
+
+use crate::{
+	drw::Fnt,
+	feed::{drain, eventfd, signal},
+	menu::Look,
+	theme::Theme,
+};
+
+/// The eventfd signalled on SIGUSR1, for as long as the menu runs.
+static SIGNALLED: AtomicI32 = AtomicI32::new(-1);
+
+pub struct Reload {
+	/// The settings given on the command line, which the file's are added
+	/// to again each time it is read.
+	theme: Theme,
+	path: Option<PathBuf>,
+	given: bool,
+	/// An eventfd which is readable when the file is to be read again.
+	fd: OwnedFd,
+}
+
+impl Reload {
+	/// Reads the file at `path` again whenever it is saved or SIGUSR1 is
+	/// sent, adding it to `theme`. The file need not exist unless it was
+	/// `given`, and its directory need not either, when only SIGUSR1 does.
+	pub fn new(
+		theme: Theme,
+		path: Option<PathBuf>,
+		given: bool,
+	) -> io::Result<Reload> {
+		let fd = eventfd()?;
+		SIGNALLED.store(fd.try_clone()?.into_raw_fd(), Ordering::Relaxed);
+		// SAFETY: the handler only writes to the eventfd, which is safe in a
+		// signal handler, and the action is zeroed but for what is set.
+		unsafe {
+			let mut action: libc::sigaction = mem::zeroed();
+			action.sa_sigaction =
+				signalled as extern "C" fn(libc::c_int) as libc::sighandler_t;
+			action.sa_flags = libc::SA_RESTART;
+			libc::sigemptyset(&mut action.sa_mask);
+			if libc::sigaction(libc::SIGUSR1, &action, ptr::null_mut()) == -1 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+		if let Some(path) = &path {
+			watch(path, fd.try_clone()?)?;
+		}
+		Ok(Reload { theme, path, given, fd })
+	}
+
+	/// The fonts and look the file now sets out, if it is to be read again
+	/// since last asked, or why they cannot be had.
+	pub fn take(&self) -> Option<Result<(Fnt, Look), String>> {
+		if !drain(self.fd.as_fd()) {
+			return None;
+		}
+		let theme = self.theme.clone();
+		let theme = match &self.path {
+			Some(path) => theme.read(path, self.given),
+			None => Ok(theme),
+		};
+		Some(theme.and_then(|mut theme| theme.look()))
+	}
+}
+
+impl AsFd for Reload {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+extern "C" fn signalled(_: libc::c_int) {
+	// SAFETY: errno is this thread's, and is put back as it was, so that
+	// whatever the signal interrupted does not see the write's.
+	unsafe {
+		let errno = *libc::__errno_location();
+		let one = 1u64;
+		let fd = SIGNALLED.load(Ordering::Relaxed);
+		libc::write(fd, (&raw const one).cast(), 8);
+		*libc::__errno_location() = errno;
+	}
+}
+
+/// Signals `wake` on a thread of its own whenever the file at `path` is
+/// saved, or replaced by another moved over it, as editors and tools which
+/// manage dotfiles often do. Its directory is watched, rather than the
+/// file, which would no longer be once replaced.
+fn watch(path: &Path, wake: OwnedFd) -> io::Result<()> {
+	let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+		return Ok(());
+	};
+	// SAFETY: no pointers are passed, and the descriptor is owned by the
+	// file alone.
+	let mut inotify = match unsafe { libc::inotify_init1(libc::IN_CLOEXEC) } {
+		-1 => return Err(io::Error::last_os_error()),
+		fd => unsafe { File::from_raw_fd(fd) },
+	};
+	let dir = match dir.as_os_str().is_empty() {
+		true => OsStr::new("."),
+		false => dir.as_os_str(),
+	};
+	let mut cdir = dir.as_bytes().to_vec();
+	cdir.push(0);
+	let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+	// SAFETY: the path is NUL-terminated.
+	let watched = unsafe {
+		libc::inotify_add_watch(inotify.as_raw_fd(), cdir.as_ptr().cast(), mask)
+	};
+	// A directory which does not exist has no file to watch.
+	if watched == -1 {
+		return Ok(());
+	}
+	let name = name.as_bytes().to_vec();
+	thread::spawn(move || {
+		// Events are as long as a header and the name after it, padded
+		// with NULs.
+		const HEADER: usize = mem::size_of::<libc::inotify_event>();
+		let mut buf = [0; 4096];
+		loop {
+			let n = match inotify.read(&mut buf) {
+				Ok(n) => n,
+				Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+					continue;
+				}
+				Err(_) => return,
+			};
+			let mut events = &buf[..n];
+			let mut saved = false;
+			while events.len() >= HEADER {
+				let len = &events[HEADER - 4..HEADER];
+				let len =
+					u32::from_ne_bytes(len.try_into().unwrap_or_default());
+				let end = (HEADER + len as usize).min(events.len());
+				let file = &events[HEADER..end];
+				let file = file.split(|&b| b == 0).next().unwrap_or_default();
+				saved |= file == name;
+				events = &events[end..];
+			}
+			if saved {
+				signal(wake.as_fd());
+			}
+		}
+	});
+	Ok(())
+}
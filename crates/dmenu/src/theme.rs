@@ -0,0 +1,548 @@
+//! The settings read from a file, which the command line overrides, and
+//! which override the defaults in `config`.
+//!
+//! Each line of the file sets an option by its name without the dash, as
+//! in `nb = #222222`, and lines starting with `#` are comments. Values may
+//! be quoted, as in TOML. Under a `[keys]` line, each line instead binds a
+//! key to another which it stands for, as in `"ctrl+j" = "Down"`, under a
+//! `[bindings]` line binds a key to an action, as in `"ctrl+j" = "down"`,
+//! under a `[colors]` line sets the colour of a part of the menu, named as
+//! after `--color-`, as in `selected-bg = "#005577"`, and under an `[exit]`
+//! line sets the status exited with, as in `custom = 2`.
+
+use std::{
+	env,
+	fs,
+	io,
+	path::{Path, PathBuf},
+};
+
+use wmenu_core::{Mode, Sort};
+
+use crate::{
+	config,
+	drw::{self, COL_BG, COL_FG, COL_MARK, Fnt},
+	menu::{
+		ACTIONS,
+		Action,
+		Anchor,
+		Chord,
+		EXIT_NAMES,
+		EXITS,
+		Look,
+		Mods,
+		SCHEME_ALT,
+		SCHEME_INPUT,
+		SCHEME_NAMES,
+		SCHEME_NORM,
+		SCHEME_PROMPT,
+		SCHEME_SEL,
+		SCHEMES,
+	},
+	xkb,
+};
+
+#[derive(Clone, Default)]
+pub struct Theme {
+	pub font: Option<String>,
+	/// Colours indexed by scheme, then by `COL_FG`, `COL_BG` or `COL_MARK`.
+	pub colors: [[Option<String>; 3]; SCHEMES],
+	pub line_height: Option<u32>,
+	pub padding: Option<u32>,
+	pub border_width: Option<u32>,
+	pub border_color: Option<String>,
+	pub border_radius: Option<u32>,
+	pub prompt: Option<String>,
+	/// Shown in place of each character typed with `-P`.
+	pub mask: Option<String>,
+	pub lines: Option<u32>,
+	pub anchor: Option<Anchor>,
+	pub margin: Option<[i32; 4]>,
+	/// How items are matched, as with `--matching`, `-i`, `--normalize`,
+	/// `--tokens` and `--sort`.
+	pub matching: Option<Mode>,
+	pub icase: Option<bool>,
+	pub normalize: Option<bool>,
+	pub tokens: Option<bool>,
+	pub sort: Option<Sort>,
+	/// Each key bound, and the key it stands for.
+	pub keys: Vec<(Chord, Chord)>,
+	/// Each key bound to an action, and the action.
+	pub bindings: Vec<(Chord, Action)>,
+	/// Statuses indexed by `EXIT_CHOSEN` and the rest.
+	pub statuses: [Option<i32>; EXITS],
+}
+
+impl Theme {
+	/// The file read when none is given: `wmenu/config.toml` in
+	/// `$XDG_CONFIG_HOME`, or in `~/.config` without it, or `wmenu/config`
+	/// if only that exists.
+	pub fn path() -> Option<PathBuf> {
+		let dir = env::var_os("XDG_CONFIG_HOME")
+			.map(PathBuf::from)
+			.filter(|dir| dir.is_absolute())
+			.or_else(|| {
+				Some(PathBuf::from(env::var_os("HOME")?).join(".config"))
+			})?
+			.join("wmenu");
+		let (toml, plain) = (dir.join("config.toml"), dir.join("config"));
+		match !toml.exists() && plain.exists() {
+			true => Some(plain),
+			false => Some(toml),
+		}
+	}
+
+	pub fn load(path: &Path) -> io::Result<Theme> {
+		let text = fs::read_to_string(path)?;
+		let mut theme = Theme::default();
+		let mut table = None;
+		for (n, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let invalid = |msg: String| {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("line {}: {}", n + 1, msg),
+				)
+			};
+			if let Some(header) = line.strip_prefix('[') {
+				table = match header.strip_suffix(']').map(str::trim) {
+					Some(name @ ("keys" | "bindings" | "colors" | "exit")) => {
+						Some(name)
+					}
+					_ => {
+						return Err(invalid(format!("unknown table {}", line)));
+					}
+				};
+				continue;
+			}
+			let Some((key, value)) = line.split_once('=') else {
+				return Err(invalid("expected 'name = value'".into()));
+			};
+			let (key, value) = (unquote(key), unquote(value));
+			if table == Some("colors") {
+				match (key.as_str(), color(&key)) {
+					("border", _) => theme.border_color = Some(value),
+					(_, Some((scm, col))) => {
+						theme.colors[scm][col] = Some(value)
+					}
+					_ => {
+						return Err(invalid(format!(
+							"unknown color '{}'",
+							key
+						)));
+					}
+				}
+				continue;
+			}
+			if table == Some("keys") {
+				let chord = |text: &str| {
+					chord(text).ok_or_else(|| {
+						invalid(format!("'{}' is not a key", text))
+					})
+				};
+				theme.keys.push((chord(&key)?, chord(&value)?));
+				continue;
+			}
+			if table == Some("exit") {
+				let (end, status) = status(&key, &value).map_err(invalid)?;
+				theme.statuses[end] = Some(status);
+				continue;
+			}
+			if table == Some("bindings") {
+				let binding = binding(&key, &value).map_err(invalid)?;
+				theme.bindings.push(binding);
+				continue;
+			}
+			let pixels = |value: String| {
+				value.parse().map_err(|_| {
+					invalid(format!("'{}' is not a non-negative number", value))
+				})
+			};
+			let bool = |value: String| match value.as_str() {
+				"true" => Ok(Some(true)),
+				"false" => Ok(Some(false)),
+				_ => Err(invalid(format!("'{}' is not true or false", value))),
+			};
+			let colors = &mut theme.colors;
+			match key.as_str() {
+				"fn" => theme.font = Some(value),
+				"nb" => colors[SCHEME_NORM][COL_BG] = Some(value),
+				"nf" => colors[SCHEME_NORM][COL_FG] = Some(value),
+				"sb" => colors[SCHEME_SEL][COL_BG] = Some(value),
+				"sf" => colors[SCHEME_SEL][COL_FG] = Some(value),
+				"nhf" => colors[SCHEME_NORM][COL_MARK] = Some(value),
+				"shf" => colors[SCHEME_SEL][COL_MARK] = Some(value),
+				"line-height" => theme.line_height = Some(pixels(value)?),
+				"padding" => theme.padding = Some(pixels(value)?),
+				"border-width" => theme.border_width = Some(pixels(value)?),
+				"border-radius" => theme.border_radius = Some(pixels(value)?),
+				"border-color" => theme.border_color = Some(value),
+				"p" => theme.prompt = Some(value),
+				"mask" => theme.mask = Some(value),
+				"l" => theme.lines = Some(pixels(value)?),
+				"b" | "bottom" => {
+					theme.anchor = bool(value)?.map(|bottom| match bottom {
+						true => Anchor::Bottom,
+						false => Anchor::Top,
+					})
+				}
+				"anchor" => match Anchor::parse(&value) {
+					Some(anchor) => theme.anchor = Some(anchor),
+					None => {
+						return Err(invalid(format!(
+							"'{}' is not top, bottom or center",
+							value
+						)));
+					}
+				},
+				"margin" => match margin(&value) {
+					Some(margin) => theme.margin = Some(margin),
+					None => {
+						return Err(invalid(format!(
+							"'{}' is not one or four numbers of pixels",
+							value
+						)));
+					}
+				},
+				"matching" => match Mode::parse(&value) {
+					Some(mode) => theme.matching = Some(mode),
+					None => {
+						return Err(invalid(format!(
+							"unknown mode '{}'",
+							value
+						)));
+					}
+				},
+				"F" | "fuzzy" => {
+					theme.matching = bool(value)?.map(|fuzzy| match fuzzy {
+						true => Mode::Fuzzy,
+						false => Mode::Substring,
+					})
+				}
+				"i" => theme.icase = bool(value)?,
+				"normalize" => theme.normalize = bool(value)?,
+				"tokens" => theme.tokens = bool(value)?,
+				"S" | "no-sort" => {
+					theme.sort = bool(value)?.map(|keep| match keep {
+						true => Sort::None,
+						false => Sort::Score,
+					})
+				}
+				"sort" => match Sort::parse(&value) {
+					Some(sort) => theme.sort = Some(sort),
+					None => {
+						return Err(invalid(format!(
+							"'{}' is not none, alpha, length or score",
+							value
+						)));
+					}
+				},
+				_ => return Err(invalid(format!("unknown option '{}'", key))),
+			}
+		}
+		Ok(theme)
+	}
+
+	/// This theme, with anything it leaves unset taken from the file at
+	/// `path`, which need not exist unless it was `given`.
+	pub fn read(self, path: &Path, given: bool) -> Result<Theme, String> {
+		match Theme::load(path) {
+			Ok(file) => Ok(self.or(file)),
+			Err(err) if err.kind() == io::ErrorKind::NotFound && !given => {
+				Ok(self)
+			}
+			Err(err) => Err(format!("cannot read {}: {}", path.display(), err)),
+		}
+	}
+
+	/// The fonts and look this theme sets out, with the defaults in
+	/// `config` for what it leaves unset, or why they cannot be had. Its
+	/// font and colours are taken.
+	pub fn look(&mut self) -> Result<(Fnt, Look), String> {
+		// Alternate rows and the input are coloured as normal items, and the
+		// prompt as the selected one, unless given colours of their own.
+		for (scm, like) in [
+			(SCHEME_ALT, SCHEME_NORM),
+			(SCHEME_PROMPT, SCHEME_SEL),
+			(SCHEME_INPUT, SCHEME_NORM),
+		] {
+			for col in 0..3 {
+				if self.colors[scm][col].is_none() {
+					self.colors[scm][col] = self.colors[like][col].clone();
+				}
+			}
+		}
+		// With NO_COLOR set, the defaults are shades of grey.
+		let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+		let (defaults, border) = match no_color {
+			true => (config::NO_COLORS, config::NO_BORDER_COLOR),
+			false => (config::COLORS, config::BORDER_COLOR),
+		};
+		let color = |name: &str| {
+			drw::clr_create(name).ok_or_else(|| {
+				format!(
+					"error, cannot allocate color '{}': expected #RRGGBB or \
+					 #RRGGBBAA",
+					name
+				)
+			})
+		};
+		let mut scheme = [[0; 3]; SCHEMES];
+		for (scm, clrs) in scheme.iter_mut().enumerate() {
+			for (col, clr) in clrs.iter_mut().enumerate() {
+				*clr = match self.colors[scm][col].take() {
+					Some(name) => color(&name)?,
+					None => color(defaults[scm][col])?,
+				};
+			}
+		}
+		let border_color = self.border_color.take();
+		let border_color = color(border_color.as_deref().unwrap_or(border))?;
+
+		let font = self.font.take().unwrap_or_else(|| config::FONT.to_string());
+		let mut fonts = Fnt::create(&font);
+		if fonts.is_none() && font != config::FONT {
+			eprintln!("cannot load font '{}', using '{}'", font, config::FONT);
+			fonts = Fnt::create(config::FONT);
+		}
+		let Some(fonts) = fonts else {
+			return Err("no fonts could be loaded.".into());
+		};
+		let look = Look {
+			scheme,
+			border_color,
+			line_height: self.line_height,
+			padding: self.padding,
+			border_width: self.border_width.unwrap_or(0),
+			border_radius: self.border_radius.unwrap_or(0),
+			lines: self.lines.unwrap_or(0),
+		};
+		Ok((fonts, look))
+	}
+
+	/// This theme, with anything it leaves unset taken from `other`.
+	pub fn or(self, other: Theme) -> Theme {
+		let mut colors = other.colors;
+		for (scheme, ours) in colors.iter_mut().zip(self.colors) {
+			for (clr, ours) in scheme.iter_mut().zip(ours) {
+				if ours.is_some() {
+					*clr = ours;
+				}
+			}
+		}
+		let mut keys = self.keys;
+		keys.extend(other.keys);
+		let mut bindings = self.bindings;
+		bindings.extend(other.bindings);
+		let mut statuses = other.statuses;
+		for (status, ours) in statuses.iter_mut().zip(self.statuses) {
+			if ours.is_some() {
+				*status = ours;
+			}
+		}
+		Theme {
+			font: self.font.or(other.font),
+			colors,
+			line_height: self.line_height.or(other.line_height),
+			padding: self.padding.or(other.padding),
+			border_width: self.border_width.or(other.border_width),
+			border_color: self.border_color.or(other.border_color),
+			border_radius: self.border_radius.or(other.border_radius),
+			prompt: self.prompt.or(other.prompt),
+			mask: self.mask.or(other.mask),
+			lines: self.lines.or(other.lines),
+			anchor: self.anchor.or(other.anchor),
+			margin: self.margin.or(other.margin),
+			matching: self.matching.or(other.matching),
+			icase: self.icase.or(other.icase),
+			normalize: self.normalize.or(other.normalize),
+			tokens: self.tokens.or(other.tokens),
+			sort: self.sort.or(other.sort),
+			keys,
+			bindings,
+			statuses,
+		}
+	}
+}
+
+/// The scheme and the colour in it of a part of the menu named as in
+/// `selected-bg`, by the scheme and its `fg`, `bg` or `match`.
+pub fn color(name: &str) -> Option<(usize, usize)> {
+	let (scheme, col) = name.rsplit_once('-')?;
+	let scheme = SCHEME_NAMES.iter().position(|&name| name == scheme)?;
+	let col = match col {
+		"fg" => COL_FG,
+		"bg" => COL_BG,
+		"match" => COL_MARK,
+		_ => return None,
+	};
+	Some((scheme, col))
+}
+
+/// Parses a key and the action it is bound to, as in `ctrl+j` and `down`.
+pub fn binding(key: &str, action: &str) -> Result<(Chord, Action), String> {
+	let chord = chord(key).ok_or_else(|| format!("'{}' is not a key", key))?;
+	let action = ACTIONS
+		.iter()
+		.find(|&&(name, _)| name == action)
+		.ok_or_else(|| format!("'{}' is not an action", action))?;
+	Ok((chord, action.1))
+}
+
+/// Parses a way the menu can end and the status it exits with, as in
+/// `custom` and `2`.
+pub fn status(end: &str, status: &str) -> Result<(usize, i32), String> {
+	let end = EXIT_NAMES
+		.iter()
+		.position(|&name| name == end)
+		.ok_or_else(|| format!("'{}' is not a way to exit", end))?;
+	let status = status
+		.parse()
+		.ok()
+		.filter(|status| (0..=255).contains(status))
+		.ok_or_else(|| format!("'{}' is not a status from 0 to 255", status))?;
+	Ok((end, status))
+}
+
+/// Parses the margins of `--margin`: one number of pixels for every edge,
+/// or four separated by commas for the top, right, bottom and left, as in
+/// CSS.
+pub fn margin(text: &str) -> Option<[i32; 4]> {
+	let pixels = text
+		.split(',')
+		.map(|n| n.trim().parse().ok())
+		.collect::<Option<Vec<i32>>>()?;
+	match pixels[..] {
+		[n] => Some([n; 4]),
+		[top, right, bottom, left] => Some([top, right, bottom, left]),
+		_ => None,
+	}
+}
+
+/// The text of a key or value, without the quotes it may have.
+fn unquote(text: &str) -> String {
+	let text = text.trim();
+	text.strip_prefix('"')
+		.and_then(|text| text.strip_suffix('"'))
+		.unwrap_or(text)
+		.to_string()
+}
+
+/// Parses a key and the modifiers held for it, as in `ctrl+shift+Return`.
+fn chord(text: &str) -> Option<Chord> {
+	let mut mods = Mods::default();
+	let (held, key) = match text.rsplit_once('+') {
+		// A key may itself be `+`.
+		Some((held, "")) => (held.strip_suffix('+')?, "plus"),
+		Some((held, key)) => (held, key),
+		None => ("", text),
+	};
+	for name in held.split('+').filter(|name| !name.is_empty()) {
+		match name.to_ascii_lowercase().as_str() {
+			"ctrl" | "control" => mods.ctrl = true,
+			"shift" => mods.shift = true,
+			"alt" | "mod1" => mods.alt = true,
+			_ => return None,
+		}
+	}
+	Some(Chord { ksym: xkb::keysym(key)?, mods })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		process,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+	use crate::menu::{EXIT_ABORT, EXIT_CHOSEN, EXIT_CUSTOM, EXIT_NO_MATCH};
+
+	/// Reads `text` as a theme file, on top of `theme`.
+	fn read(theme: Theme, text: &str) -> Result<Theme, String> {
+		static N: AtomicUsize = AtomicUsize::new(0);
+		let n = N.fetch_add(1, Ordering::Relaxed);
+		let name = format!("wmenu-theme-{}-{}", process::id(), n);
+		let path = env::temp_dir().join(name);
+		fs::write(&path, text).unwrap();
+		let theme = theme.read(&path, true);
+		fs::remove_file(&path).unwrap();
+		theme
+	}
+
+	#[test]
+	fn the_command_line_overrides_the_file_which_overrides_defaults() {
+		let mut cli = Theme { padding: Some(4), ..Theme::default() };
+		cli.colors[SCHEME_SEL][COL_BG] = Some("#111111".into());
+		let file =
+			"padding = 9\nborder-width = 2\nsb = #222222\nnb = #333333\n";
+		let (_, look) = read(cli, file).unwrap().look().unwrap();
+		assert_eq!((look.padding, look.border_width), (Some(4), 2));
+		assert_eq!((look.line_height, look.lines), (None, 0));
+		assert_eq!(look.scheme[SCHEME_SEL][COL_BG], 0xff111111);
+		assert_eq!(look.scheme[SCHEME_NORM][COL_BG], 0xff333333);
+		let default = drw::clr_create(config::COLORS[SCHEME_SEL][COL_FG]);
+		assert_eq!(Some(look.scheme[SCHEME_SEL][COL_FG]), default);
+	}
+
+	#[test]
+	fn tables_set_colours_keys_and_statuses() {
+		let file = "[colors]\nselected-bg = \"#445566\"\n\
+			[exit]\ncustom = 2\n\
+			[bindings]\n\"ctrl+j\" = \"down\"\n";
+		let mut theme = read(Theme::default(), file).unwrap();
+		assert_eq!(theme.statuses[EXIT_CUSTOM], Some(2));
+		assert!(matches!(theme.bindings[..], [(_, Action::Down)]));
+		let (_, look) = theme.look().unwrap();
+		assert_eq!(look.scheme[SCHEME_SEL][COL_BG], 0xff445566);
+		// The prompt is coloured as the selected item.
+		assert_eq!(look.scheme[SCHEME_PROMPT][COL_BG], 0xff445566);
+	}
+
+	#[test]
+	fn files_need_not_exist_unless_given() {
+		let path = env::temp_dir().join("wmenu-theme-which-is-not-there");
+		let cli = Theme { lines: Some(3), ..Theme::default() };
+		let theme = cli.clone().read(&path, false).unwrap();
+		assert_eq!(theme.lines, Some(3));
+		let err = cli.read(&path, true).err().unwrap();
+		assert!(err.starts_with("cannot read "), "{}", err);
+	}
+
+	#[test]
+	fn malformed_files_say_where_they_are_wrong() {
+		for (file, msg) in [
+			("p = Run\nnonsense\n", "line 2: expected 'name = value'"),
+			("wat = 1\n", "line 1: unknown option 'wat'"),
+			("\n# padding\npadding = -1\n", "line 3: '-1' is not a"),
+			("[table]\n", "line 1: unknown table [table]"),
+			("[colors]\nmauve-bg = #000000\n", "line 2: unknown color"),
+			("i = yes\n", "line 1: 'yes' is not true or false"),
+		] {
+			let err = read(Theme::default(), file).err().unwrap();
+			assert!(err.contains(msg), "{:?}: {}", file, err);
+		}
+	}
+
+	#[test]
+	fn malformed_colours_cannot_be_allocated() {
+		let mut theme = read(Theme::default(), "nb = red\n").unwrap();
+		let err = theme.look().err().unwrap();
+		assert!(err.contains("cannot allocate color 'red'"), "{}", err);
+	}
+
+	#[test]
+	fn exits_are_named_as_under_exit_status() {
+		assert_eq!(status("chosen", "0"), Ok((EXIT_CHOSEN, 0)));
+		assert_eq!(status("custom", "2"), Ok((EXIT_CUSTOM, 2)));
+		assert_eq!(status("abort", "255"), Ok((EXIT_ABORT, 255)));
+		assert_eq!(status("no-match", "3"), Ok((EXIT_NO_MATCH, 3)));
+		assert!(status("cancel", "1").is_err());
+		assert!(status("abort", "256").is_err());
+		assert!(status("abort", "-1").is_err());
+		assert!(status("abort", "one").is_err());
+	}
+}
@@ -0,0 +1,1462 @@
+//! The connection to the compositor: a layer-shell surface to show the menu
+//! on, and the keyboard, pointer and touchscreen to drive it.
+
+use std::{
+	ffi::CString,
+	fs,
+	io::{self, Read},
+	mem,
+	os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+	process::exit,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use memmap2::{Mmap, MmapMut};
+use wayland_client::{
+	Connection,
+	Dispatch,
+	EventQueue,
+	Proxy,
+	QueueHandle,
+	WEnum,
+	backend::WaylandError,
+	delegate_noop,
+	event_created_child,
+	globals::{GlobalListContents, registry_queue_init},
+	protocol::{
+		wl_buffer::{self, WlBuffer},
+		wl_compositor::WlCompositor,
+		wl_data_device::{self, WlDataDevice},
+		wl_data_device_manager::WlDataDeviceManager,
+		wl_data_offer::{self, WlDataOffer},
+		wl_keyboard::{self, KeyState, KeymapFormat, WlKeyboard},
+		wl_output::{self, WlOutput},
+		wl_pointer::{self, Axis, ButtonState, WlPointer},
+		wl_registry::WlRegistry,
+		wl_seat::{self, Capability, WlSeat},
+		wl_shm::{self, WlShm},
+		wl_shm_pool::WlShmPool,
+		wl_surface::{self, WlSurface},
+		wl_touch::{self, WlTouch},
+	},
+};
+use wayland_protocols::wp::{
+	cursor_shape::v1::client::{
+		wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1},
+		wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+	},
+	fractional_scale::v1::client::{
+		wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+		wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+	},
+	idle_inhibit::zv1::client::{
+		zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+		zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+	},
+	primary_selection::zv1::client::{
+		zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+		zwp_primary_selection_device_v1::{self, ZwpPrimarySelectionDeviceV1},
+		zwp_primary_selection_offer_v1::{self, ZwpPrimarySelectionOfferV1},
+	},
+	text_input::zv3::client::{
+		zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+		zwp_text_input_v3::{
+			self,
+			ContentHint,
+			ContentPurpose,
+			ZwpTextInputV3,
+		},
+	},
+	viewporter::client::{
+		wp_viewport::WpViewport,
+		wp_viewporter::WpViewporter,
+	},
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+	zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+	zwlr_layer_surface_v1::{
+		self,
+		Anchor,
+		KeyboardInteractivity,
+		ZwlrLayerSurfaceV1,
+	},
+};
+use xkeysym::Keysym;
+
+use crate::{
+	clipboard,
+	die,
+	drw::Drw,
+	feed::Feed,
+	menu::{self, Chord, Menu, Mods},
+	xkb::Xkb,
+};
+
+/// Types of text which can be pasted, best first.
+pub const MIME_TYPES: &[&str] = &[
+	"text/plain;charset=utf-8",
+	"UTF8_STRING",
+	"text/plain",
+	"TEXT",
+	"STRING",
+];
+
+/// The button which clicks, as Linux numbers it.
+const BTN_LEFT: u32 = 0x110;
+
+/// How far the wheel scrolls to turn a page, which is a notch of most
+/// wheels, and how far a finger is dragged to.
+const SCROLL_STEP: f64 = 10.0;
+const SWIPE: f64 = 32.0;
+
+/// How long to wait for the owner of a selection to send it.
+const PASTE_TIMEOUT: i32 = 1000;
+
+/// The types of data a selection is offered in.
+type Offered = Mutex<Vec<String>>;
+
+/// A key held down, and when it next repeats.
+struct Repeat {
+	key: u32,
+	at: Instant,
+}
+
+/// A finger on the touchscreen, where it went down and where it is now.
+struct Touch {
+	id: i32,
+	from: (f64, f64),
+	at: (f64, f64),
+}
+
+/// What an input method has sent since it was last done, to be applied at
+/// once, and what the menu last told it.
+#[derive(Default)]
+struct Ime {
+	enabled: bool,
+	preedit: (String, Option<usize>),
+	commit: Option<String>,
+	delete: (usize, usize),
+	/// The text, cursor and caret the input method was last sent.
+	sent: Option<(String, usize, [i32; 4])>,
+}
+
+/// An output, and what the compositor calls it.
+struct Output {
+	output: WlOutput,
+	name: Option<String>,
+	description: Option<String>,
+}
+
+/// A buffer the menu is drawn into, the areas of it which have changed
+/// since it was, and whether the compositor has yet to release it.
+struct Buffer {
+	buffer: WlBuffer,
+	stale: Vec<[u32; 4]>,
+	busy: bool,
+}
+
+/// The buffers of a size the menu is drawn into in turn, one after another
+/// in a pool, so that each drawn into again need only have what has changed
+/// since redrawn.
+struct Buffers {
+	size: (u32, u32),
+	file: fs::File,
+	pool: WlShmPool,
+	map: MmapMut,
+	buffers: Vec<Buffer>,
+}
+
+/// Buffers kept before another is added to those the compositor holds, and
+/// areas kept stale in one before the whole of it is redrawn.
+const BUFFERS: usize = 2;
+const MAX_STALE: usize = 16;
+
+impl Buffers {
+	fn new(
+		shm: &WlShm,
+		size: (u32, u32),
+		qh: &QueueHandle<State>,
+	) -> io::Result<Buffers> {
+		let file = memfd()?;
+		let len = Buffers::len(size) * BUFFERS;
+		file.set_len(len as u64)?;
+		// SAFETY: the file is private to this process.
+		let map = unsafe { MmapMut::map_mut(&file)? };
+		let pool = shm.create_pool(file.as_fd(), len as i32, qh, ());
+		let mut buffers =
+			Buffers { size, file, pool, map, buffers: Vec::new() };
+		for _ in 0..BUFFERS {
+			buffers.add(qh);
+		}
+		Ok(buffers)
+	}
+
+	/// The length in bytes of a buffer of `size`.
+	fn len((w, h): (u32, u32)) -> usize {
+		w as usize * h as usize * 4
+	}
+
+	/// Adds a buffer after the others in the pool, all of which is stale.
+	fn add(&mut self, qh: &QueueHandle<State>) {
+		let (w, h) = self.size;
+		let i = self.buffers.len();
+		let buffer = self.pool.create_buffer(
+			(Buffers::len(self.size) * i) as i32,
+			w as i32,
+			h as i32,
+			(w * 4) as i32,
+			wl_shm::Format::Argb8888,
+			qh,
+			(),
+		);
+		let stale = vec![[0, 0, w, h]];
+		self.buffers.push(Buffer { buffer, stale, busy: false });
+	}
+
+	/// The index of a buffer the compositor has released, adding one if it
+	/// holds them all.
+	fn free(&mut self, qh: &QueueHandle<State>) -> io::Result<usize> {
+		if let Some(i) = self.buffers.iter().position(|b| !b.busy) {
+			return Ok(i);
+		}
+		let len = Buffers::len(self.size) * (self.buffers.len() + 1);
+		self.file.set_len(len as u64)?;
+		// SAFETY: as above, the file is this process's alone.
+		self.map = unsafe { MmapMut::map_mut(&self.file)? };
+		self.pool.resize(len as i32);
+		self.add(qh);
+		Ok(self.buffers.len() - 1)
+	}
+
+	/// Draws into the buffer at `i` what has changed since it was last
+	/// drawn into, `damage` having since the last buffer was, which is also
+	/// stale in all the others.
+	fn redraw(&mut self, i: usize, drw: &Drw, damage: &[[u32; 4]]) {
+		let stride = self.size.0 as usize * 4;
+		let start = Buffers::len(self.size) * i;
+		let stale = mem::take(&mut self.buffers[i].stale);
+		for &area in stale.iter().chain(damage) {
+			let [x, y, ..] = area.map(|n| n as usize);
+			let at = start + y * stride + x * 4;
+			drw.map(&mut self.map[at..], stride, area);
+		}
+		let whole = [0, 0, self.size.0, self.size.1];
+		for (j, buffer) in self.buffers.iter_mut().enumerate() {
+			if j != i {
+				buffer.stale.extend(damage);
+				if buffer.stale.len() > MAX_STALE {
+					buffer.stale = vec![whole];
+				}
+			}
+		}
+	}
+}
+
+impl Drop for Buffers {
+	fn drop(&mut self) {
+		for buffer in &self.buffers {
+			buffer.buffer.destroy();
+		}
+		self.pool.destroy();
+	}
+}
+
+struct State {
+	menu: Menu,
+	/// Where items still being read come from.
+	feed: Option<Feed>,
+	outputs: Vec<Output>,
+	shm: WlShm,
+	/// The buffers drawn into, of the size last drawn.
+	buffers: Option<Buffers>,
+	surface: WlSurface,
+	layer_surface: Option<ZwlrLayerSurfaceV1>,
+	/// What the surface is scaled to its size by, where the compositor can
+	/// scale by fractions; otherwise it scales by whole numbers.
+	viewport: Option<WpViewport>,
+	/// The width of the surface, as last configured.
+	width: u32,
+	xkb: Xkb,
+	keyboard: Option<WlKeyboard>,
+	/// Keys repeat `rate` times a second, after being held for `delay`.
+	rate: i32,
+	delay: Duration,
+	repeat: Option<Repeat>,
+	configured: bool,
+	exit: Option<i32>,
+	/// What can be pasted from the clipboard and the primary selection.
+	clipboard: Option<WlDataOffer>,
+	primary: Option<ZwpPrimarySelectionOfferV1>,
+	pointer: Option<WlPointer>,
+	/// What sets the pointer's shape over the menu, where the compositor
+	/// supports it.
+	cursor_shape: Option<WpCursorShapeDeviceV1>,
+	cursor_shapes: Option<WpCursorShapeManagerV1>,
+	/// Where the pointer is on the surface, and how far the wheel has
+	/// scrolled short of turning a page.
+	pointer_at: (f64, f64),
+	scrolled: f64,
+	touch: Option<WlTouch>,
+	touches: Vec<Touch>,
+	/// What input methods compose text through, where the compositor
+	/// supports it.
+	text_input: Option<ZwpTextInputV3>,
+	text_inputs: Option<ZwpTextInputManagerV3>,
+	ime: Ime,
+}
+
+impl State {
+	/// Draws the menu, committing a buffer with only the areas of it which
+	/// have changed damaged, if any have.
+	fn draw(&mut self, qh: &QueueHandle<Self>) {
+		self.menu.draw();
+		let size = self.menu.drw.size();
+		let damage = self.menu.drw.damage();
+		if damage.is_empty() {
+			self.update_ime();
+			return;
+		}
+		// Buffers of another size are dropped once the new one is attached.
+		let mut old = None;
+		if self.buffers.as_ref().is_none_or(|b| b.size != size) {
+			match Buffers::new(&self.shm, size, qh) {
+				Ok(buffers) => old = self.buffers.replace(buffers),
+				Err(err) => die(&format!("cannot create buffer: {}", err)),
+			}
+		}
+		let Some(buffers) = &mut self.buffers else {
+			return;
+		};
+		let i = match buffers.free(qh) {
+			Ok(i) => i,
+			Err(err) => die(&format!("cannot map buffer: {}", err)),
+		};
+		buffers.redraw(i, &self.menu.drw, &damage);
+		buffers.buffers[i].busy = true;
+
+		match &self.viewport {
+			Some(viewport) => viewport
+				.set_destination(self.width as i32, self.menu.height() as i32),
+			None => self.surface.set_buffer_scale(self.menu.scale() as i32),
+		}
+		self.surface.attach(Some(&buffers.buffers[i].buffer), 0, 0);
+		for [x, y, w, h] in damage {
+			self.surface.damage_buffer(x as i32, y as i32, w as i32, h as i32);
+		}
+		self.surface.commit();
+		drop(old);
+		self.update_ime();
+	}
+
+	/// Tells the input method what is typed and where the cursor is, if
+	/// either has changed since it was last told.
+	fn update_ime(&mut self) {
+		let Some(text_input) =
+			self.text_input.as_ref().filter(|_| self.ime.enabled)
+		else {
+			return;
+		};
+		let caret = self.menu.caret();
+		// A password is not shared, and text is only sent while short enough
+		// for a single message.
+		let (text, cursor) = self
+			.menu
+			.surrounding()
+			.filter(|(text, _)| text.len() < 4000)
+			.unwrap_or(("", 0));
+		if self.ime.sent.as_ref().is_some_and(|(sent, at, rect)| {
+			sent == text && *at == cursor && *rect == caret
+		}) {
+			return;
+		}
+		text_input.set_surrounding_text(
+			text.to_string(),
+			cursor as i32,
+			cursor as i32,
+		);
+		let [x, y, w, h] = caret;
+		text_input.set_cursor_rectangle(x, y, w, h);
+		text_input.commit();
+		self.ime.sent = Some((text.to_string(), cursor, caret));
+	}
+
+	fn keypress(
+		&mut self,
+		key: u32,
+		conn: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		let mods = Mods {
+			ctrl: self.xkb.mod_is_active(c"Control"),
+			shift: self.xkb.mod_is_active(c"Shift"),
+			alt: self.xkb.mod_is_active(c"Mod1"),
+		};
+		// Shortcuts are read with a Latin layout where another is active.
+		let ksym = match mods.ctrl || mods.alt {
+			true => self.xkb.key_get_shortcut_sym(key),
+			false => self.xkb.key_get_one_sym(key),
+		};
+		let pressed = Chord { ksym, mods };
+		let Chord { ksym, mods } = self.menu.rebind(pressed);
+		// Pasting needs the compositor, so is handled here.
+		let paste = match ksym {
+			Keysym::v | Keysym::V | Keysym::Y if mods.ctrl && !mods.alt => {
+				Some(false)
+			}
+			Keysym::y if mods.ctrl && !mods.alt && !self.menu.killed() => {
+				Some(true)
+			}
+			Keysym::Insert | Keysym::KP_Insert if mods.shift => Some(true),
+			_ => None,
+		};
+		if let Some(primary) = paste {
+			if let Some(text) = self.paste(primary, conn) {
+				self.menu.paste(&text);
+				self.draw(qh);
+			}
+			return;
+		}
+		// A bound key types what the key it stands for would.
+		let buf = match pressed == (Chord { ksym, mods }) {
+			true => self.xkb.key_get_utf8(key),
+			false => ksym.key_char().map(String::from).unwrap_or_default(),
+		};
+		match self.menu.keypress(ksym, mods, &buf) {
+			Some(status) => self.exit = Some(status),
+			None => self.draw(qh),
+		}
+	}
+
+	/// The text in the primary selection, or in the clipboard.
+	fn paste(&self, primary: bool, conn: &Connection) -> Option<String> {
+		match primary {
+			true => {
+				let offer = self.primary.as_ref()?;
+				receive(conn, offer.data()?, |mime, fd| offer.receive(mime, fd))
+			}
+			false => {
+				let offer = self.clipboard.as_ref()?;
+				receive(conn, offer.data()?, |mime, fd| offer.receive(mime, fd))
+			}
+		}
+	}
+
+	/// Redraws the menu at the scale the compositor prefers.
+	fn rescale(&mut self, scale: f64, qh: &QueueHandle<Self>) {
+		if scale <= 0.0 || scale == self.menu.scale() {
+			return;
+		}
+		self.menu.set_scale(scale);
+		if let Some(layer_surface) = &self.layer_surface {
+			layer_surface.set_size(0, self.menu.height());
+		}
+		if self.configured {
+			self.menu.resize(self.width);
+			self.draw(qh);
+		}
+	}
+
+	/// Adds any items which have arrived to the menu.
+	fn feed(&mut self, qh: &QueueHandle<Self>) {
+		let Some(feed) = &self.feed else {
+			return;
+		};
+		let (items, done) = feed.take();
+		if done {
+			self.feed = None;
+		}
+		if !items.is_empty() {
+			self.menu.extend(items);
+			if self.configured {
+				self.draw(qh);
+			}
+		}
+	}
+
+	/// Shows the matches found in the background, or accepts the one they
+	/// leave no choice of.
+	fn collect(&mut self, qh: &QueueHandle<Self>) {
+		if !self.menu.collect() {
+			return;
+		}
+		match self.menu.decide() {
+			Some(status) => self.exit = Some(status),
+			None if self.configured => self.draw(qh),
+			None => {}
+		}
+	}
+
+	/// Selects what the pointer or a finger is over at `at`.
+	fn hover(&mut self, (x, y): (f64, f64), qh: &QueueHandle<Self>) {
+		if self.menu.hover(x, y) && self.configured {
+			self.draw(qh);
+		}
+	}
+
+	/// Chooses what is clicked or tapped on at `at`.
+	fn click(&mut self, (x, y): (f64, f64), qh: &QueueHandle<Self>) {
+		match self.menu.click(x, y) {
+			Some(status) => self.exit = Some(status),
+			None => self.draw(qh),
+		}
+	}
+
+	fn scroll(&mut self, down: bool, qh: &QueueHandle<Self>) {
+		self.menu.scroll(down);
+		self.draw(qh);
+	}
+
+	/// Carries out any commands sent over `--ipc`.
+	fn receive(&mut self, qh: &QueueHandle<Self>) {
+		let (changed, status) = self.menu.receive();
+		if status.is_some() {
+			self.exit = status;
+		} else if changed && self.configured {
+			self.draw(qh);
+		}
+	}
+
+	/// Shows the menu as the theme file now sets out, once it changes.
+	fn reload(&mut self, qh: &QueueHandle<Self>) {
+		if !self.menu.reload() {
+			return;
+		}
+		// Until first configured, a menu a fraction of the output high is
+		// as high as the output.
+		if let Some(layer_surface) = &self.layer_surface
+			&& self.menu.opts.height.is_none()
+		{
+			layer_surface.set_size(0, self.menu.height());
+		}
+		if self.configured {
+			self.menu.resize(self.width);
+			self.draw(qh);
+		}
+	}
+
+	/// How long until a held key next repeats, if one is held.
+	fn timeout(&self) -> Option<Duration> {
+		self.repeat
+			.as_ref()
+			.map(|repeat| repeat.at.saturating_duration_since(Instant::now()))
+	}
+}
+
+/// Reads a selection offered in the types `offered`, asking for it with
+/// `request`, which is passed the type wanted and a pipe to write it to.
+fn receive(
+	conn: &Connection,
+	offered: &Offered,
+	request: impl FnOnce(String, BorrowedFd),
+) -> Option<String> {
+	let mime = {
+		let offered = offered.lock().ok()?;
+		MIME_TYPES.iter().find(|&&mime| offered.iter().any(|o| o == mime))?
+	};
+	let mut fds = [0; 2];
+	// SAFETY: the array has room for both descriptors.
+	if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+		return None;
+	}
+	// SAFETY: both descriptors were just opened, and are owned here alone.
+	let (read, write) =
+		unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+	request(mime.to_string(), write.as_fd());
+	// The owner of the selection writes to its own copy, and the pipe ends
+	// once that is closed.
+	drop(write);
+	conn.flush().ok()?;
+
+	let mut file = fs::File::from(read);
+	let mut text = Vec::new();
+	let mut buf = [0; 4096];
+	loop {
+		let mut fd = libc::pollfd {
+			fd: file.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		};
+		// SAFETY: a single descriptor is passed.
+		if unsafe { libc::poll(&mut fd, 1, PASTE_TIMEOUT) } <= 0 {
+			break;
+		}
+		match file.read(&mut buf) {
+			Ok(0) | Err(_) => break,
+			Ok(n) => text.extend_from_slice(&buf[..n]),
+		}
+	}
+	Some(String::from_utf8_lossy(&text).into_owned())
+}
+
+/// The index of the output `want` stands for, of those with the names and
+/// descriptions given, in the order they were announced: the one it names,
+/// or else the one it describes, or else the one it numbers from 0, as
+/// dmenu numbers monitors.
+fn choose(
+	outputs: &[(Option<&str>, Option<&str>)],
+	want: &str,
+) -> Option<usize> {
+	let numbered = || want.parse().ok().filter(|&i| i < outputs.len());
+	let want = Some(want);
+	outputs
+		.iter()
+		.position(|&(name, _)| name == want)
+		.or_else(|| {
+			outputs.iter().position(|&(_, description)| description == want)
+		})
+		.or_else(numbered)
+}
+
+/// The edges a menu shown at `anchor` is anchored to: both sides, and the
+/// top or the bottom, or neither to be centered between them.
+fn edge(anchor: menu::Anchor) -> Anchor {
+	let edge = match anchor {
+		menu::Anchor::Top => Anchor::Top,
+		menu::Anchor::Bottom => Anchor::Bottom,
+		menu::Anchor::Center => Anchor::empty(),
+	};
+	edge | Anchor::Left | Anchor::Right
+}
+
+/// The exclusive zone of the surface, and how it takes the keyboard, from
+/// a layer shell of `version`.
+fn layer(opts: &menu::Opts, version: u32) -> (i32, KeyboardInteractivity) {
+	let zone = opts.exclusive_zone.unwrap_or(-1);
+	// Taking the keyboard on demand needs version 4.
+	let keyboard = match opts.on_demand {
+		true if version >= 4 => KeyboardInteractivity::OnDemand,
+		true => {
+			eprintln!("the compositor cannot give the keyboard on demand");
+			KeyboardInteractivity::Exclusive
+		}
+		false => KeyboardInteractivity::Exclusive,
+	};
+	(zone, keyboard)
+}
+
+fn memfd() -> io::Result<fs::File> {
+	// SAFETY: the name is NUL-terminated, and the descriptor is owned by the
+	// returned file alone.
+	let fd =
+		unsafe { libc::memfd_create(c"dmenu".as_ptr(), libc::MFD_CLOEXEC) };
+	match fd {
+		-1 => Err(io::Error::last_os_error()),
+		fd => Ok(unsafe { fs::File::from_raw_fd(fd) }),
+	}
+}
+
+/// Shows the menu until an item is chosen or it is cancelled, then exits.
+/// Items from `feed` are added as they arrive.
+pub fn run(menu: Menu, feed: Option<Feed>) -> ! {
+	let Ok(conn) = Connection::connect_to_env() else {
+		die("cannot connect to the compositor");
+	};
+	let Ok((globals, mut queue)) = registry_queue_init::<State>(&conn) else {
+		die("cannot list globals");
+	};
+	let qh = queue.handle();
+	let bind_error = |name| -> ! { die(&format!("{} is not supported", name)) };
+	let compositor: WlCompositor = globals
+		.bind(&qh, 4..=6, ())
+		.unwrap_or_else(|_| bind_error("wl_compositor"));
+	let shm: WlShm =
+		globals.bind(&qh, 1..=1, ()).unwrap_or_else(|_| bind_error("wl_shm"));
+	let layer_shell: ZwlrLayerShellV1 = globals
+		.bind(&qh, 1..=4, ())
+		.unwrap_or_else(|_| bind_error("zwlr_layer_shell_v1"));
+	let seat: WlSeat =
+		globals.bind(&qh, 4..=9, ()).unwrap_or_else(|_| bind_error("wl_seat"));
+	// Pasting is left out where the compositor does not support it.
+	if let Ok(manager) =
+		globals.bind::<WlDataDeviceManager, _, _>(&qh, 1..=3, ())
+	{
+		manager.get_data_device(&seat, &qh, ());
+	}
+	if let Ok(manager) =
+		globals.bind::<ZwpPrimarySelectionDeviceManagerV1, _, _>(&qh, 1..=1, ())
+	{
+		manager.get_device(&seat, &qh, ());
+	}
+	let cursor_shapes = globals.bind(&qh, 1..=1, ()).ok();
+	let text_inputs = globals.bind(&qh, 1..=1, ()).ok();
+	let Some(mut xkb) = Xkb::new() else {
+		die("cannot load libxkbcommon");
+	};
+	let names = &menu.opts.xkb;
+	if !names.is_empty() && !xkb.set_names(names) {
+		die("cannot compile the keymap from --xkb-layout and the like");
+	}
+
+	let surface = compositor.create_surface(&qh, ());
+	// Without fractional scaling, the compositor asks the surface itself for
+	// a whole scale, from version 6.
+	let viewport = match (
+		globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()),
+		globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()),
+	) {
+		(Ok(manager), Ok(viewporter)) => {
+			manager.get_fractional_scale(&surface, &qh, ());
+			Some(viewporter.get_viewport(&surface, &qh, ()))
+		}
+		_ => None,
+	};
+	// The inhibitor lasts as long as the connection, and only holds while
+	// the surface is shown.
+	if menu.opts.inhibit_idle {
+		match globals.bind::<ZwpIdleInhibitManagerV1, _, _>(&qh, 1..=1, ()) {
+			Ok(manager) => {
+				manager.create_inhibitor(&surface, &qh, ());
+			}
+			Err(_) => eprintln!("the compositor cannot keep the screen on"),
+		}
+	}
+	let mut state = State {
+		menu,
+		feed,
+		outputs: Vec::new(),
+		shm,
+		buffers: None,
+		surface,
+		layer_surface: None,
+		viewport,
+		width: 0,
+		xkb,
+		keyboard: None,
+		rate: 0,
+		delay: Duration::ZERO,
+		repeat: None,
+		configured: false,
+		exit: None,
+		clipboard: None,
+		primary: None,
+		pointer: None,
+		cursor_shape: None,
+		cursor_shapes,
+		pointer_at: (0.0, 0.0),
+		scrolled: 0.0,
+		touch: None,
+		touches: Vec::new(),
+		text_input: None,
+		text_inputs,
+		ime: Ime::default(),
+	};
+
+	let mut output = None;
+	if let Some(want) = state.menu.opts.output.clone() {
+		// Outputs are only named once bound, so bind them all and wait.
+		for global in globals.contents().clone_list() {
+			if global.interface == WlOutput::interface().name {
+				let output = globals.registry().bind(
+					global.name,
+					global.version.min(4),
+					&qh,
+					state.outputs.len(),
+				);
+				state.outputs.push(Output {
+					output,
+					name: None,
+					description: None,
+				});
+			}
+		}
+		if let Err(err) = queue.roundtrip(&mut state) {
+			die(&format!("lost the connection to the compositor: {}", err));
+		}
+		let names: Vec<_> = state
+			.outputs
+			.iter()
+			.map(|o| (o.name.as_deref(), o.description.as_deref()))
+			.collect();
+		output = choose(&names, &want).map(|i| &state.outputs[i].output);
+		if output.is_none() {
+			eprintln!("no output named '{}', using the default", want);
+		}
+	}
+
+	let layer_surface = layer_shell.get_layer_surface(
+		&state.surface,
+		output,
+		Layer::Overlay,
+		"dmenu".into(),
+		&qh,
+		(),
+	);
+	// Anchored to every edge, the menu is first configured as high as the
+	// output, to find the height a fraction of it is.
+	match state.menu.opts.height {
+		Some(menu::Size::Fraction(_)) => {
+			layer_surface.set_anchor(Anchor::all())
+		}
+		_ => layer_surface.set_anchor(edge(state.menu.opts.anchor)),
+	}
+	if let Some(menu::Size::Pixels(height)) = state.menu.opts.height {
+		state.menu.fit(height);
+		state.menu.opts.height = None;
+	}
+	let [top, right, bottom, left] = state.menu.opts.margin;
+	layer_surface.set_margin(top, right, bottom, left);
+	let fill = state.menu.opts.height.is_some();
+	layer_surface.set_size(0, if fill { 0 } else { state.menu.height() });
+	let (zone, keyboard) = layer(&state.menu.opts, layer_shell.version());
+	layer_surface.set_exclusive_zone(zone);
+	layer_surface.set_keyboard_interactivity(keyboard);
+	state.surface.commit();
+	state.layer_surface = Some(layer_surface);
+	loop {
+		if let Err(err) = dispatch(&mut queue, &mut state) {
+			die(&format!("lost the connection to the compositor: {}", err));
+		}
+		state.feed(&qh);
+		state.collect(&qh);
+		state.receive(&qh);
+		state.reload(&qh);
+		if let Some(status) = state.exit {
+			if let Some(layer_surface) = &state.layer_surface {
+				layer_surface.destroy();
+			}
+			state.surface.destroy();
+			let _ = conn.flush();
+			if let Some(text) = state.menu.copied() {
+				clipboard::copy(&text, false);
+			}
+			drop(state);
+			exit(status);
+		}
+		if let Some(repeat) = &mut state.repeat
+			&& repeat.at <= Instant::now()
+		{
+			let key = repeat.key;
+			repeat.at += Duration::from_secs(1) / state.rate.max(1) as u32;
+			state.keypress(key, &conn, &qh);
+		}
+	}
+}
+
+/// Waits for events, for items to arrive or be filtered, or until a held key
+/// is due to repeat, and dispatches any events.
+fn dispatch(
+	queue: &mut EventQueue<State>,
+	state: &mut State,
+) -> Result<(), Box<dyn std::error::Error>> {
+	queue.flush()?;
+	if let Some(guard) = queue.prepare_read() {
+		let timeout = match state.timeout() {
+			Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+			None => -1,
+		};
+		let pollfd = |fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+		let mut fds = [
+			pollfd(guard.connection_fd().as_raw_fd()),
+			// Negative descriptors are ignored.
+			pollfd(
+				state.feed.as_ref().map_or(-1, |feed| feed.as_fd().as_raw_fd()),
+			),
+			pollfd(state.menu.worker().map_or(-1, |w| w.as_fd().as_raw_fd())),
+			pollfd(state.menu.ipc().map_or(-1, |ipc| ipc.as_fd().as_raw_fd())),
+			pollfd(state.menu.reloads().map_or(-1, |r| r.as_fd().as_raw_fd())),
+			pollfd(state.menu.dynamic().map_or(-1, |d| d.as_fd().as_raw_fd())),
+			pollfd(state.menu.preview().map_or(-1, |p| p.as_fd().as_raw_fd())),
+		];
+		// SAFETY: the array outlives the call, and its length is given.
+		let ready = unsafe {
+			libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout)
+		};
+		if ready < 0 {
+			let err = io::Error::last_os_error();
+			if err.kind() != io::ErrorKind::Interrupted {
+				return Err(err.into());
+			}
+		} else if fds[0].revents != 0 {
+			match guard.read() {
+				// Another reader may have taken the events first.
+				Err(WaylandError::Io(err))
+					if err.kind() == io::ErrorKind::WouldBlock => {}
+				result => {
+					result?;
+				}
+			}
+		}
+	}
+	queue.dispatch_pending(state)?;
+	Ok(())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+	fn event(
+		_: &mut Self,
+		_: &WlRegistry,
+		_: <WlRegistry as wayland_client::Proxy>::Event,
+		_: &GlobalListContents,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+	}
+}
+
+impl Dispatch<WlSeat, ()> for State {
+	fn event(
+		state: &mut Self,
+		seat: &WlSeat,
+		event: wl_seat::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		let wl_seat::Event::Capabilities { capabilities: WEnum::Value(caps) } =
+			event
+		else {
+			return;
+		};
+		if caps.contains(Capability::Keyboard) && state.keyboard.is_none() {
+			state.keyboard = Some(seat.get_keyboard(qh, ()));
+			state.text_input = state
+				.text_inputs
+				.as_ref()
+				.map(|inputs| inputs.get_text_input(seat, qh, ()));
+		}
+		if caps.contains(Capability::Pointer) && state.pointer.is_none() {
+			let pointer = seat.get_pointer(qh, ());
+			state.cursor_shape = state
+				.cursor_shapes
+				.as_ref()
+				.map(|shapes| shapes.get_pointer(&pointer, qh, ()));
+			state.pointer = Some(pointer);
+		}
+		if caps.contains(Capability::Touch) && state.touch.is_none() {
+			state.touch = Some(seat.get_touch(qh, ()));
+		}
+	}
+}
+
+impl Dispatch<WlPointer, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlPointer,
+		event: wl_pointer::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		match event {
+			wl_pointer::Event::Enter {
+				serial, surface_x, surface_y, ..
+			} => {
+				if let Some(device) = &state.cursor_shape {
+					device.set_shape(serial, Shape::Default);
+				}
+				state.pointer_at = (surface_x, surface_y);
+				state.hover(state.pointer_at, qh);
+			}
+			wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+				state.pointer_at = (surface_x, surface_y);
+				state.hover(state.pointer_at, qh);
+			}
+			wl_pointer::Event::Button {
+				button: BTN_LEFT,
+				state: WEnum::Value(ButtonState::Pressed),
+				..
+			} => state.click(state.pointer_at, qh),
+			wl_pointer::Event::Axis {
+				axis: WEnum::Value(Axis::VerticalScroll),
+				value,
+				..
+			} => {
+				// Scrolling the other way starts afresh.
+				if state.scrolled * value < 0.0 {
+					state.scrolled = 0.0;
+				}
+				state.scrolled += value;
+				while state.scrolled.abs() >= SCROLL_STEP {
+					let down = state.scrolled > 0.0;
+					state.scrolled -= SCROLL_STEP.copysign(state.scrolled);
+					state.scroll(down, qh);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<WlTouch, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlTouch,
+		event: wl_touch::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		match event {
+			wl_touch::Event::Down { id, x, y, .. } => {
+				state.touches.push(Touch { id, from: (x, y), at: (x, y) });
+				state.hover((x, y), qh);
+			}
+			wl_touch::Event::Motion { id, x, y, .. } => {
+				if let Some(touch) =
+					state.touches.iter_mut().find(|t| t.id == id)
+				{
+					touch.at = (x, y);
+				}
+			}
+			// A tap chooses what it is on, and a swipe up or down turns the
+			// page, as the wheel does.
+			wl_touch::Event::Up { id, .. } => {
+				let Some(i) = state.touches.iter().position(|t| t.id == id)
+				else {
+					return;
+				};
+				let touch = state.touches.swap_remove(i);
+				let (dx, dy) =
+					(touch.at.0 - touch.from.0, touch.at.1 - touch.from.1);
+				if dy.abs() >= SWIPE && dy.abs() > dx.abs() {
+					state.scroll(dy < 0.0, qh);
+				} else if dx.abs() < SWIPE {
+					state.click(touch.from, qh);
+				}
+			}
+			wl_touch::Event::Cancel => state.touches.clear(),
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<ZwpTextInputV3, ()> for State {
+	fn event(
+		state: &mut Self,
+		text_input: &ZwpTextInputV3,
+		event: zwp_text_input_v3::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		match event {
+			zwp_text_input_v3::Event::Enter { .. } => {
+				text_input.enable();
+				let (hint, purpose) = match state.menu.opts.password {
+					true => (
+						ContentHint::HiddenText | ContentHint::SensitiveData,
+						ContentPurpose::Password,
+					),
+					false => (ContentHint::None, ContentPurpose::Normal),
+				};
+				text_input.set_content_type(hint, purpose);
+				state.ime = Ime { enabled: true, ..Ime::default() };
+				state.update_ime();
+			}
+			zwp_text_input_v3::Event::Leave { .. } => {
+				text_input.disable();
+				text_input.commit();
+				state.ime = Ime::default();
+				state.menu.preedit(String::new(), None);
+				if state.configured {
+					state.draw(qh);
+				}
+			}
+			// The cursor is given as a byte offset, or as -1 to hide it.
+			zwp_text_input_v3::Event::PreeditString {
+				text,
+				cursor_begin,
+				..
+			} => {
+				let text = text.unwrap_or_default();
+				let cursor = usize::try_from(cursor_begin)
+					.ok()
+					.filter(|&at| text.is_char_boundary(at));
+				state.ime.preedit = (text, cursor);
+			}
+			zwp_text_input_v3::Event::CommitString { text } => {
+				state.ime.commit = text
+			}
+			zwp_text_input_v3::Event::DeleteSurroundingText {
+				before_length,
+				after_length,
+			} => {
+				state.ime.delete =
+					(before_length as usize, after_length as usize)
+			}
+			// What was sent is applied in the order the protocol sets out:
+			// the deletion, then the text committed, then the new preedit.
+			zwp_text_input_v3::Event::Done { .. } => {
+				let (before, after) = mem::take(&mut state.ime.delete);
+				state.menu.delete_surrounding(before, after);
+				if let Some(text) = state.ime.commit.take() {
+					state.menu.paste(&text);
+				}
+				let (text, cursor) = mem::take(&mut state.ime.preedit);
+				state.menu.preedit(text, cursor);
+				if state.configured {
+					state.draw(qh);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<WlOutput, usize> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlOutput,
+		event: wl_output::Event,
+		&i: &usize,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		match event {
+			wl_output::Event::Name { name } => {
+				state.outputs[i].name = Some(name)
+			}
+			wl_output::Event::Description { description } => {
+				state.outputs[i].description = Some(description)
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<WlKeyboard, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlKeyboard,
+		event: wl_keyboard::Event,
+		_: &(),
+		conn: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		match event {
+			wl_keyboard::Event::Keymap {
+				format: WEnum::Value(KeymapFormat::XkbV1),
+				fd,
+				size,
+			} => {
+				// SAFETY: the compositor does not change the keymap it sent.
+				let map = unsafe { Mmap::map(&fs::File::from(fd)) };
+				let keymap = map.ok().and_then(|map| {
+					let len = (size as usize).min(map.len());
+					let text = map[..len].split(|&b| b == 0).next()?;
+					CString::new(text).ok()
+				});
+				if !keymap.is_some_and(|keymap| state.xkb.set_keymap(&keymap)) {
+					die("cannot compile the keymap");
+				}
+			}
+			// Taking the keyboard on demand, the menu loses it when another
+			// window is clicked on, which dismisses it, as does losing it at
+			// all with --exclusive.
+			wl_keyboard::Event::Leave { .. } => {
+				state.repeat = None;
+				if state.menu.opts.on_demand || state.menu.opts.exclusive {
+					state.exit = Some(state.menu.cancel());
+				}
+			}
+			wl_keyboard::Event::Key { key, state: key_state, .. } => {
+				let pressed = key_state == WEnum::Value(KeyState::Pressed);
+				if !pressed {
+					if state.repeat.as_ref().is_some_and(|r| r.key == key) {
+						state.repeat = None;
+					}
+					return;
+				}
+				state.repeat = (state.rate > 0 && state.xkb.key_repeats(key))
+					.then(|| Repeat { key, at: Instant::now() + state.delay });
+				state.keypress(key, conn, qh);
+			}
+			wl_keyboard::Event::Modifiers {
+				mods_depressed,
+				mods_latched,
+				mods_locked,
+				group,
+				..
+			} => state.xkb.update_mask(
+				mods_depressed,
+				mods_latched,
+				mods_locked,
+				group,
+			),
+			wl_keyboard::Event::RepeatInfo { rate, delay } => {
+				state.rate = rate;
+				state.delay = Duration::from_millis(delay.max(0) as u64);
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
+	fn event(
+		state: &mut Self,
+		layer_surface: &ZwlrLayerSurfaceV1,
+		event: zwlr_layer_surface_v1::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		match event {
+			zwlr_layer_surface_v1::Event::Configure {
+				serial,
+				width,
+				height,
+			} => {
+				layer_surface.ack_configure(serial);
+				// The size of the output is only known once configured, so
+				// a narrower menu is given the margins either side of it
+				// then, and a shorter one its lines, and configured again.
+				let sized = state.menu.opts.width.is_some()
+					|| state.menu.opts.height.is_some();
+				if sized && !state.configured {
+					if let Some(size) = state.menu.opts.width.take() {
+						let pad =
+							width.saturating_sub(size.of(width)) as i32 / 2;
+						let [top, right, bottom, left] = state.menu.opts.margin;
+						state.menu.opts.margin =
+							[top, right + pad, bottom, left + pad];
+						layer_surface.set_margin(
+							top,
+							right + pad,
+							bottom,
+							left + pad,
+						);
+					}
+					if let Some(size) = state.menu.opts.height.take() {
+						state.menu.fit(size.of(height));
+						layer_surface.set_anchor(edge(state.menu.opts.anchor));
+					}
+					layer_surface.set_size(0, state.menu.height());
+					state.surface.commit();
+					return;
+				}
+				if !state.configured || width != state.width {
+					state.configured = true;
+					state.width = width;
+					state.menu.resize(width);
+					state.draw(qh);
+				}
+			}
+			zwlr_layer_surface_v1::Event::Closed => {
+				state.exit = Some(state.menu.cancel())
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<WlSurface, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WlSurface,
+		event: wl_surface::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		if let wl_surface::Event::PreferredBufferScale { factor } = event
+			&& state.viewport.is_none()
+		{
+			state.rescale(factor as f64, qh);
+		}
+	}
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for State {
+	fn event(
+		state: &mut Self,
+		_: &WpFractionalScaleV1,
+		event: wp_fractional_scale_v1::Event,
+		_: &(),
+		_: &Connection,
+		qh: &QueueHandle<Self>,
+	) {
+		// The scale is sent in 120ths.
+		if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+			state.rescale(scale as f64 / 120.0, qh);
+		}
+	}
+}
+
+impl Dispatch<WlBuffer, ()> for State {
+	fn event(
+		state: &mut Self,
+		buffer: &WlBuffer,
+		event: wl_buffer::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let wl_buffer::Event::Release = event
+			&& let Some(buffers) = &mut state.buffers
+			&& let Some(released) =
+				buffers.buffers.iter_mut().find(|b| b.buffer == *buffer)
+		{
+			released.busy = false;
+		}
+	}
+}
+
+impl Dispatch<WlDataDevice, ()> for State {
+	event_created_child!(State, WlDataDevice, [
+		wl_data_device::EVT_DATA_OFFER_OPCODE =>
+			(WlDataOffer, Offered::default()),
+	]);
+
+	fn event(
+		state: &mut Self,
+		_: &WlDataDevice,
+		event: wl_data_device::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let wl_data_device::Event::Selection { id } = event
+			&& let Some(offer) = mem::replace(&mut state.clipboard, id)
+		{
+			offer.destroy();
+		}
+	}
+}
+
+impl Dispatch<WlDataOffer, Offered> for State {
+	fn event(
+		_: &mut Self,
+		_: &WlDataOffer,
+		event: wl_data_offer::Event,
+		offered: &Offered,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let wl_data_offer::Event::Offer { mime_type } = event
+			&& let Ok(mut offered) = offered.lock()
+		{
+			offered.push(mime_type);
+		}
+	}
+}
+
+impl Dispatch<ZwpPrimarySelectionDeviceV1, ()> for State {
+	event_created_child!(State, ZwpPrimarySelectionDeviceV1, [
+		zwp_primary_selection_device_v1::EVT_DATA_OFFER_OPCODE =>
+			(ZwpPrimarySelectionOfferV1, Offered::default()),
+	]);
+
+	fn event(
+		state: &mut Self,
+		_: &ZwpPrimarySelectionDeviceV1,
+		event: zwp_primary_selection_device_v1::Event,
+		_: &(),
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let zwp_primary_selection_device_v1::Event::Selection { id } = event
+			&& let Some(offer) = mem::replace(&mut state.primary, id)
+		{
+			offer.destroy();
+		}
+	}
+}
+
+impl Dispatch<ZwpPrimarySelectionOfferV1, Offered> for State {
+	fn event(
+		_: &mut Self,
+		_: &ZwpPrimarySelectionOfferV1,
+		event: zwp_primary_selection_offer_v1::Event,
+		offered: &Offered,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+		if let zwp_primary_selection_offer_v1::Event::Offer { mime_type } =
+			event && let Ok(mut offered) = offered.lock()
+		{
+			offered.push(mime_type);
+		}
+	}
+}
+
+delegate_noop!(State: WlCompositor);
+delegate_noop!(State: WpCursorShapeManagerV1);
+delegate_noop!(State: WpCursorShapeDeviceV1);
+delegate_noop!(State: WlDataDeviceManager);
+delegate_noop!(State: ZwpTextInputManagerV3);
+delegate_noop!(State: ZwpPrimarySelectionDeviceManagerV1);
+delegate_noop!(State: WlShmPool);
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: WpFractionalScaleManagerV1);
+delegate_noop!(State: WpViewporter);
+delegate_noop!(State: WpViewport);
+delegate_noop!(State: ZwpIdleInhibitManagerV1);
+delegate_noop!(State: ZwpIdleInhibitorV1);
+delegate_noop!(State: ZwlrLayerShellV1);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn menus_span_the_edge_they_are_anchored_to() {
+		let sides = Anchor::Left | Anchor::Right;
+		assert_eq!(edge(menu::Anchor::Top), Anchor::Top | sides);
+		assert_eq!(edge(menu::Anchor::Bottom), Anchor::Bottom | sides);
+	}
+
+	#[test]
+	fn centered_menus_span_only_the_sides() {
+		assert_eq!(edge(menu::Anchor::Center), Anchor::Left | Anchor::Right);
+	}
+
+	/// Outputs as a compositor with two monitors announces them.
+	const OUTPUTS: [(Option<&str>, Option<&str>); 2] = [
+		(Some("eDP-1"), Some("Sharp Corporation 0x14D0 (eDP-1)")),
+		(Some("DP-2"), Some("Dell Inc. DELL U2720Q (DP-2)")),
+	];
+
+	#[test]
+	fn outputs_are_chosen_by_name() {
+		assert_eq!(choose(&OUTPUTS, "DP-2"), Some(1));
+		assert_eq!(choose(&OUTPUTS, "eDP-1"), Some(0));
+	}
+
+	#[test]
+	fn outputs_are_chosen_by_description() {
+		assert_eq!(choose(&OUTPUTS, "Dell Inc. DELL U2720Q (DP-2)"), Some(1));
+	}
+
+	#[test]
+	fn outputs_are_chosen_by_number() {
+		assert_eq!(choose(&OUTPUTS, "0"), Some(0));
+		assert_eq!(choose(&OUTPUTS, "1"), Some(1));
+		assert_eq!(choose(&OUTPUTS, "2"), None);
+	}
+
+	#[test]
+	fn names_come_before_numbers() {
+		let outputs = [(Some("1"), None), (Some("0"), None)];
+		assert_eq!(choose(&outputs, "1"), Some(0));
+	}
+
+	#[test]
+	fn missing_outputs_are_left_to_the_compositor() {
+		assert_eq!(choose(&OUTPUTS, "HDMI-A-1"), None);
+		assert_eq!(choose(&[(None, None)], "eDP-1"), None);
+	}
+
+	#[test]
+	fn menus_are_shown_over_what_reserves_space_by_default() {
+		let opts = menu::Opts::default();
+		assert_eq!(layer(&opts, 4), (-1, KeyboardInteractivity::Exclusive));
+	}
+
+	#[test]
+	fn exclusive_zones_are_passed_through() {
+		for zone in [-1, 0, 30] {
+			let opts =
+				menu::Opts { exclusive_zone: Some(zone), ..Default::default() };
+			assert_eq!(layer(&opts, 4).0, zone);
+		}
+	}
+
+	#[test]
+	fn the_keyboard_is_taken_on_demand_where_it_can_be() {
+		let opts = menu::Opts { on_demand: true, ..Default::default() };
+		assert_eq!(layer(&opts, 4).1, KeyboardInteractivity::OnDemand);
+		assert_eq!(layer(&opts, 3).1, KeyboardInteractivity::Exclusive);
+	}
+}
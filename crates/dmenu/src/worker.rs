@@ -0,0 +1,340 @@
+//! Filtering of long lists on a thread of its own, so that typing is not
+//! held up by it. Queries typed in quick succession are filtered once, for
+//! the last of them, and results for any query but the latest are dropped.
+
+use std::{
+	io,
+	mem,
+	os::fd::{AsFd, BorrowedFd, OwnedFd},
+	sync::{
+		Arc,
+		Condvar,
+		Mutex,
+		MutexGuard,
+		PoisonError,
+		atomic::{AtomicU64, Ordering},
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+use wmenu_core::{Index, Matcher, Matches, Prior};
+
+use crate::feed::{drain, eventfd, signal};
+
+/// How long typing must pause before the query is filtered, and the longest
+/// a query waits for it to.
+const DEBOUNCE: Duration = Duration::from_millis(30);
+const MAX_DELAY: Duration = Duration::from_millis(100);
+
+/// How many items are matched between checks for a newer query.
+const CHUNK: usize = 4096;
+
+/// How many items there must be to match for them to be shared out among
+/// threads, one for each processor, below which starting them costs more
+/// than it saves.
+const PARALLEL: usize = 65536;
+
+struct Job {
+	matcher: Matcher,
+	query: String,
+	generation: u64,
+	/// The matched part of each item.
+	keys: Arc<Index>,
+}
+
+#[derive(Default)]
+struct Shared {
+	job: Option<Job>,
+	/// The matches for the query of a generation.
+	done: Option<(u64, Vec<usize>)>,
+	/// Whether the worker has been dropped, which the thread then ends for.
+	closed: bool,
+}
+
+struct Queue {
+	shared: Mutex<Shared>,
+	ready: Condvar,
+	/// The generation of the latest query, which any other is abandoned
+	/// for.
+	latest: AtomicU64,
+}
+
+impl Queue {
+	fn lock(&self) -> MutexGuard<'_, Shared> {
+		self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+pub struct Worker {
+	queue: Arc<Queue>,
+	keys: Arc<Index>,
+	generation: u64,
+	/// Whether the latest query has yet to be taken the matches of.
+	pending: bool,
+	/// An eventfd which is readable when there are matches to take.
+	fd: OwnedFd,
+}
+
+impl Worker {
+	/// Starts a thread matching `keys`, the matched part of each item.
+	pub fn spawn(keys: Index) -> io::Result<Worker> {
+		let fd = eventfd()?;
+		let queue = Arc::new(Queue {
+			shared: Mutex::new(Shared::default()),
+			ready: Condvar::new(),
+			latest: AtomicU64::new(0),
+		});
+		let (shared, wake) = (queue.clone(), fd.try_clone()?);
+		thread::spawn(move || work(&shared, wake.as_fd()));
+		Ok(Worker {
+			queue,
+			keys: Arc::new(keys),
+			generation: 0,
+			pending: false,
+			fd,
+		})
+	}
+
+	/// Adds the keys of items read since.
+	pub fn extend<'a>(&mut self, keys: impl IntoIterator<Item = &'a str>) {
+		Arc::make_mut(&mut self.keys).extend(keys);
+	}
+
+	/// Starts filtering for `query` as `matcher` does, in place of any
+	/// earlier query.
+	pub fn filter(&mut self, matcher: Matcher, query: &str) {
+		self.generation += 1;
+		self.pending = true;
+		self.queue.latest.store(self.generation, Ordering::Relaxed);
+		let job = Job {
+			matcher,
+			query: query.to_string(),
+			generation: self.generation,
+			keys: self.keys.clone(),
+		};
+		self.queue.lock().job = Some(job);
+		self.queue.ready.notify_one();
+	}
+
+	/// Takes the matches for the latest query, if they are ready.
+	pub fn take(&mut self) -> Option<Vec<usize>> {
+		drain(self.fd.as_fd());
+		let done = self.queue.lock().done.take();
+		match done {
+			Some((generation, matches)) if generation == self.generation => {
+				self.pending = false;
+				Some(matches)
+			}
+			_ => None,
+		}
+	}
+
+	/// Abandons the latest query, returning whether it was yet to be
+	/// taken the matches of.
+	pub fn cancel(&mut self) -> bool {
+		self.generation += 1;
+		self.queue.latest.store(self.generation, Ordering::Relaxed);
+		self.queue.lock().job = None;
+		mem::take(&mut self.pending)
+	}
+}
+
+impl Drop for Worker {
+	fn drop(&mut self) {
+		self.queue.latest.store(u64::MAX, Ordering::Relaxed);
+		self.queue.lock().closed = true;
+		self.queue.ready.notify_one();
+	}
+}
+
+impl AsFd for Worker {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+/// The matches of `job` among `candidates`, sorted, shared out among as
+/// many as `threads` threads once there are enough of them. Matching stops
+/// early once `current` no longer holds.
+fn matches(
+	job: &Job,
+	candidates: &[usize],
+	threads: usize,
+	current: impl Fn() -> bool + Sync,
+) -> Vec<usize> {
+	let part = |part: &[usize]| {
+		let keys = part.iter().enumerate();
+		let keys = keys
+			.take_while(|(i, _)| i % CHUNK != 0 || current())
+			.map(|(_, &i)| (i, job.keys.get(i)));
+		job.matcher.matches(keys, &job.query)
+	};
+	let matches = if candidates.len() < PARALLEL || threads == 1 {
+		part(candidates)
+	} else {
+		// Each thread matches a part of the items in input order, and the
+		// parts are joined in that order, so the matches are sorted as they
+		// would be on one.
+		let size = candidates.len().div_ceil(threads);
+		thread::scope(|scope| {
+			let parts = candidates
+				.chunks(size)
+				.map(|chunk| scope.spawn(|| part(chunk)))
+				.collect::<Vec<_>>();
+			let mut matches = Matches::default();
+			for handle in parts {
+				let part = handle
+					.join()
+					.unwrap_or_else(|err| std::panic::resume_unwind(err));
+				matches.join(part);
+			}
+			matches
+		})
+	};
+	job.matcher.order(matches, |i| job.keys.get(i))
+}
+
+/// Filters for each query sent, once typing pauses, signalling `wake` when
+/// the matches are ready.
+fn work(queue: &Queue, wake: BorrowedFd) {
+	let mut prior = Prior::default();
+	let mut shared = queue.lock();
+	loop {
+		if shared.closed {
+			return;
+		}
+		let Some(mut job) = shared.job.take() else {
+			shared = queue
+				.ready
+				.wait(shared)
+				.unwrap_or_else(PoisonError::into_inner);
+			continue;
+		};
+		let first = Instant::now();
+		loop {
+			let wait = DEBOUNCE.min(MAX_DELAY.saturating_sub(first.elapsed()));
+			let (guard, timeout) = queue
+				.ready
+				.wait_timeout(shared, wait)
+				.unwrap_or_else(PoisonError::into_inner);
+			shared = guard;
+			match shared.job.take() {
+				Some(newer) => job = newer,
+				None if shared.closed => return,
+				None if timeout.timed_out() => break,
+				None => {}
+			}
+		}
+		drop(shared);
+
+		let current = || queue.latest.load(Ordering::Relaxed) == job.generation;
+		// Matching stops early once a newer query is sent, and what it
+		// found is dropped.
+		let len = job.keys.len();
+		let candidates = prior.candidates(&job.matcher, &job.query, len);
+		let candidates = job.keys.sift(&job.matcher, &job.query, candidates);
+		let threads = thread::available_parallelism().map_or(1, usize::from);
+		let matches = matches(&job, &candidates, threads, current);
+
+		shared = queue.lock();
+		if current() {
+			prior.record(&job.matcher, &job.query, len, &matches);
+			shared.done = Some((job.generation, matches));
+			signal(wake);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::fd::AsRawFd;
+
+	use wmenu_core::{Mode, Sort};
+
+	use super::*;
+
+	/// Waits for `fd` to be readable, for at most a second.
+	fn wait(fd: BorrowedFd) -> bool {
+		let mut pollfd = libc::pollfd {
+			fd: fd.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		};
+		// SAFETY: a single pollfd is passed.
+		unsafe { libc::poll(&mut pollfd, 1, 1000) == 1 }
+	}
+
+	/// The matches the worker finds next, in input order.
+	fn found(worker: &mut Worker) -> Vec<usize> {
+		assert!(wait(worker.as_fd()), "no matches were found");
+		let mut matches = worker.take().unwrap();
+		matches.sort();
+		matches
+	}
+
+	#[test]
+	fn the_latest_query_is_filtered() {
+		let keys = ["abc", "xab", "b", "ab", "bca"].into_iter().collect();
+		let mut worker = Worker::spawn(keys).unwrap();
+		for query in ["a", "ab", "b"] {
+			worker.filter(Matcher::default(), query);
+		}
+		assert_eq!(found(&mut worker), [0, 1, 2, 3, 4]);
+		worker.filter(Matcher::default(), "ab");
+		assert_eq!(found(&mut worker), [0, 1, 3]);
+		assert!(worker.take().is_none());
+	}
+
+	#[test]
+	fn keys_read_since_are_filtered_too() {
+		let mut worker = Worker::spawn(["ab"].into_iter().collect()).unwrap();
+		worker.extend(["b", "cab"]);
+		worker.filter(Matcher::default(), "ab");
+		assert_eq!(found(&mut worker), [0, 2]);
+	}
+
+	#[test]
+	fn cancelled_queries_give_no_matches() {
+		let mut worker = Worker::spawn(["ab"].into_iter().collect()).unwrap();
+		worker.filter(Matcher::default(), "a");
+		assert!(worker.cancel());
+		assert!(!worker.cancel());
+		assert!(!wait(worker.as_fd()));
+		assert!(worker.take().is_none());
+	}
+
+	#[test]
+	fn matches_found_on_several_threads_are_those_found_on_one() {
+		let keys: Vec<String> = (0..PARALLEL as u64 + 5000)
+			.map(|n| format!("{:x}", n * 7919))
+			.collect();
+		let index: Index = keys.iter().map(String::as_str).collect();
+		let matchers = [
+			Matcher::default(),
+			Matcher { mode: Mode::Fuzzy, ..Matcher::default() },
+			Matcher { sort: Sort::None, ..Matcher::default() },
+			Matcher { sort: Sort::Alpha, ..Matcher::default() },
+			Matcher {
+				mode: Mode::Fuzzy,
+				sort: Sort::Length,
+				..Matcher::default()
+			},
+		];
+		for matcher in matchers {
+			for query in ["a1", "f0f", "3", "zz"] {
+				let job = Job {
+					matcher,
+					query: query.to_string(),
+					generation: 0,
+					keys: Arc::new(index.clone()),
+				};
+				let candidates: Vec<_> = (0..keys.len()).collect();
+				let parts = matches(&job, &candidates, 4, || true);
+				let one =
+					matcher.filter(keys.iter().map(String::as_str), query);
+				assert_eq!(parts, one, "for {:?}", query);
+			}
+		}
+	}
+}
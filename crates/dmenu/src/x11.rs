@@ -0,0 +1,695 @@
+//! The connection to an X server, for sessions without a compositor: an
+//! override-redirect window to show the menu in, and the keyboard grabbed
+//! to drive it.
+
+use std::{
+	collections::VecDeque,
+	fmt::Display,
+	io,
+	os::fd::{AsFd, AsRawFd, RawFd},
+	process::exit,
+	thread,
+	time::{Duration, Instant},
+};
+
+use x11rb::{
+	CURRENT_TIME,
+	NONE,
+	connection::{Connection, RequestConnection},
+	protocol::{
+		Event,
+		randr::ConnectionExt as _,
+		screensaver::ConnectionExt as _,
+		shape::{self, ConnectionExt as _},
+		xproto::{
+			AtomEnum,
+			ClipOrdering,
+			ConfigureWindowAux,
+			ConnectionExt as _,
+			CreateGCAux,
+			CreateWindowAux,
+			EventMask,
+			Gcontext,
+			GrabMode,
+			GrabStatus,
+			ImageFormat,
+			ImageOrder,
+			InputFocus,
+			KeyButMask,
+			Mapping,
+			NotifyMode,
+			PropMode,
+			Rectangle,
+			Screen,
+			StackMode,
+			Visibility,
+			Window,
+			WindowClass,
+		},
+	},
+	rust_connection::RustConnection,
+	wrapper::ConnectionExt as _,
+};
+use xkeysym::Keysym;
+
+use crate::{
+	clipboard,
+	die,
+	feed::Feed,
+	menu::{self, Chord, Menu, Mods},
+};
+
+/// How long to wait for the owner of a selection to send it.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many times to try to grab the keyboard, a millisecond apart, while
+/// another client has it.
+const GRAB_TRIES: u32 = 1000;
+
+/// The keysyms of each keycode, as the server maps them.
+struct Keymap {
+	min: u8,
+	per: usize,
+	syms: Vec<u32>,
+}
+
+impl Keymap {
+	fn load(conn: &RustConnection) -> Keymap {
+		let setup = conn.setup();
+		let (min, max) = (setup.min_keycode, setup.max_keycode);
+		let cookie = conn.get_keyboard_mapping(min, max - min + 1);
+		let reply = checked(checked(cookie).reply());
+		Keymap {
+			min,
+			per: reply.keysyms_per_keycode as usize,
+			syms: reply.keysyms,
+		}
+	}
+
+	/// The keysym `code` gives with the modifiers in `state`, as the core
+	/// protocol sets out: the second of the first two with Shift, and a
+	/// capital letter with Caps Lock.
+	fn keysym(&self, code: u8, state: KeyButMask) -> Keysym {
+		let i = code.wrapping_sub(self.min) as usize * self.per;
+		let sym = |j: usize| {
+			let sym = self.syms.get(i + j).filter(|_| j < self.per);
+			sym.copied().filter(|&sym| sym != 0).map(Keysym::new)
+		};
+		let lower = sym(0).unwrap_or(Keysym::NoSymbol);
+		let upper = sym(1).unwrap_or_else(|| capital(lower));
+		// Num Lock is taken to be Mod2, as it almost always is.
+		let shift = state.contains(KeyButMask::SHIFT)
+			^ (state.contains(KeyButMask::MOD2) && upper.is_keypad_key());
+		let sym = match shift {
+			true => upper,
+			false => lower,
+		};
+		match state.contains(KeyButMask::LOCK) {
+			true => capital(sym),
+			false => sym,
+		}
+	}
+}
+
+/// The keysym of the capital of a lower-case letter, or `sym` itself.
+fn capital(sym: Keysym) -> Keysym {
+	let upper = sym.key_char().filter(|c| c.is_lowercase()).and_then(|c| {
+		let mut upper = c.to_uppercase();
+		upper.next().filter(|_| upper.next().is_none())
+	});
+	upper.map_or(sym, Keysym::from_char)
+}
+
+struct Atoms {
+	clipboard: u32,
+	utf8_string: u32,
+	/// Where the owner of a selection is asked to put it.
+	property: u32,
+}
+
+struct State {
+	conn: RustConnection,
+	menu: Menu,
+	/// Where items still being read come from.
+	feed: Option<Feed>,
+	win: Window,
+	gc: Gcontext,
+	depth: u8,
+	/// Whether pixels are sent to the server in the other byte order from
+	/// this machine's.
+	swap: bool,
+	/// The longest request the server accepts.
+	max_bytes: usize,
+	keymap: Keymap,
+	atoms: Atoms,
+	/// Events read while waiting for a selection, yet to be handled.
+	pending: VecDeque<Event>,
+	exit: Option<i32>,
+	/// The width of the menu, and the top and height of the monitor or
+	/// parent it is placed on, which it is placed on again when its height
+	/// changes.
+	width: u32,
+	area: (i32, i32),
+}
+
+impl State {
+	/// Draws the menu, sending only the areas of it which have changed.
+	fn draw(&mut self) {
+		self.menu.draw();
+		for area in self.menu.drw.damage() {
+			let [x, y, w, h] = area;
+			let stride = w as usize * 4;
+			let mut buf = vec![0; stride * h as usize];
+			self.menu.drw.map(&mut buf, stride, area);
+			if self.swap {
+				for px in buf.chunks_exact_mut(4) {
+					px.reverse();
+				}
+			}
+			// Requests are limited in length, so the image is sent a strip
+			// of rows at a time.
+			let rows = (self.max_bytes.saturating_sub(32) / stride).max(1);
+			for (i, strip) in buf.chunks(rows * stride).enumerate() {
+				checked(self.conn.put_image(
+					ImageFormat::Z_PIXMAP,
+					self.win,
+					self.gc,
+					w as u16,
+					(strip.len() / stride) as u16,
+					x as i16,
+					(y as usize + i * rows) as i16,
+					0,
+					self.depth,
+					strip,
+				));
+			}
+		}
+	}
+
+	fn keypress(&mut self, code: u8, state: KeyButMask) {
+		let mods = Mods {
+			ctrl: state.contains(KeyButMask::CONTROL),
+			shift: state.contains(KeyButMask::SHIFT),
+			alt: state.contains(KeyButMask::MOD1),
+		};
+		let pressed = Chord { ksym: self.keymap.keysym(code, state), mods };
+		let Chord { ksym, mods } = self.menu.rebind(pressed);
+		// Pasting needs the server, so is handled here.
+		let paste = match ksym {
+			Keysym::v | Keysym::V | Keysym::Y if mods.ctrl && !mods.alt => {
+				Some(false)
+			}
+			Keysym::y if mods.ctrl && !mods.alt && !self.menu.killed() => {
+				Some(true)
+			}
+			Keysym::Insert | Keysym::KP_Insert if mods.shift => Some(true),
+			_ => None,
+		};
+		if let Some(primary) = paste {
+			if let Some(text) = self.paste(primary) {
+				self.menu.paste(&text);
+				self.draw();
+			}
+			return;
+		}
+		// The core protocol gives keys no text, so they type the character
+		// their keysym stands for.
+		let buf = ksym.key_char().map(String::from).unwrap_or_default();
+		match self.menu.keypress(ksym, mods, &buf) {
+			Some(status) => self.exit = Some(status),
+			None => self.draw(),
+		}
+	}
+
+	/// The text in the primary selection, or in the clipboard. Selections
+	/// too long to be sent at once are left out.
+	fn paste(&mut self, primary: bool) -> Option<String> {
+		let selection = match primary {
+			true => AtomEnum::PRIMARY.into(),
+			false => self.atoms.clipboard,
+		};
+		checked(self.conn.convert_selection(
+			self.win,
+			selection,
+			self.atoms.utf8_string,
+			self.atoms.property,
+			CURRENT_TIME,
+		));
+		checked(self.conn.flush());
+		let fd = self.conn.stream().as_raw_fd();
+		let deadline = Instant::now() + PASTE_TIMEOUT;
+		loop {
+			let event = match checked(self.conn.poll_for_event()) {
+				Some(event) => event,
+				None if poll(&[fd], Some(deadline)) => continue,
+				None => return None,
+			};
+			match event {
+				Event::SelectionNotify(event)
+					if event.requestor == self.win =>
+				{
+					if event.property == NONE {
+						return None;
+					}
+					let cookie = self.conn.get_property(
+						true,
+						self.win,
+						event.property,
+						AtomEnum::ANY,
+						0,
+						u32::MAX,
+					);
+					let reply = checked(checked(cookie).reply());
+					if reply.type_ != self.atoms.utf8_string {
+						return None;
+					}
+					return Some(String::from_utf8_lossy(&reply.value).into());
+				}
+				event => self.pending.push_back(event),
+			}
+		}
+	}
+
+	fn handle(&mut self, event: Event) {
+		match event {
+			// What was shown is lost, so all of it is sent again.
+			Event::Expose(event) if event.count == 0 => {
+				self.menu.drw.invalidate();
+				self.draw();
+			}
+			Event::KeyPress(event) => self.keypress(event.detail, event.state),
+			Event::MotionNotify(event) => {
+				let (x, y) = (event.event_x as f64, event.event_y as f64);
+				if self.menu.hover(x, y) {
+					self.draw();
+				}
+			}
+			// The first button clicks, and the fourth and fifth are the
+			// wheel scrolled up and down.
+			Event::ButtonPress(event) => {
+				let (x, y) = (event.event_x as f64, event.event_y as f64);
+				match event.detail {
+					1 => match self.menu.click(x, y) {
+						Some(status) => self.exit = Some(status),
+						None => self.draw(),
+					},
+					4 | 5 => {
+						self.menu.scroll(event.detail == 5);
+						self.draw();
+					}
+					_ => {}
+				}
+			}
+			Event::MappingNotify(event)
+				if event.request != Mapping::POINTER =>
+			{
+				self.keymap = Keymap::load(&self.conn);
+			}
+			// Nothing manages the window to keep it on top, so it raises
+			// itself when covered.
+			Event::VisibilityNotify(event)
+				if event.state != Visibility::UNOBSCURED =>
+			{
+				let aux =
+					ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+				checked(self.conn.configure_window(self.win, &aux));
+			}
+			// Another client can only take the keyboard from the menu by
+			// grabbing it, which with --exclusive cancels it.
+			Event::FocusOut(event)
+				if event.mode == NotifyMode::GRAB
+					&& self.menu.opts.exclusive =>
+			{
+				self.exit = Some(self.menu.cancel());
+			}
+			// The focus is taken back from any window given it, unless the
+			// keyboard is only taken on demand, when losing it to another
+			// window dismisses the menu.
+			Event::FocusOut(event) if event.mode == NotifyMode::NORMAL => {
+				match self.menu.opts.on_demand {
+					true => self.exit = Some(self.menu.cancel()),
+					false => focus(&self.conn, self.win),
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Adds any items which have arrived to the menu.
+	fn feed(&mut self) {
+		let Some(feed) = &self.feed else {
+			return;
+		};
+		let (items, done) = feed.take();
+		if done {
+			self.feed = None;
+		}
+		if !items.is_empty() {
+			self.menu.extend(items);
+			self.draw();
+		}
+	}
+
+	/// Carries out any commands sent over `--ipc`.
+	fn receive(&mut self) {
+		let (changed, status) = self.menu.receive();
+		if status.is_some() {
+			self.exit = status;
+		} else if changed {
+			self.draw();
+		}
+	}
+
+	/// Shows the menu as the theme file now sets out, once it changes,
+	/// keeping it to the edge it is shown along.
+	fn reload(&mut self) {
+		if !self.menu.reload() {
+			return;
+		}
+		self.menu.resize(self.width);
+		let (y, h) = self.area;
+		let aux = ConfigureWindowAux::new()
+			.y(place(&self.menu, y, h))
+			.height(self.menu.height());
+		checked(self.conn.configure_window(self.win, &aux));
+		shape(&self.conn, self.win, &self.menu);
+		self.draw();
+	}
+
+	/// The next event to handle, if any have been read.
+	fn event(&mut self) -> Option<Event> {
+		self.pending.pop_front().or_else(|| checked(self.conn.poll_for_event()))
+	}
+}
+
+/// The value of `result`, or the end of the menu if the connection has
+/// failed.
+fn checked<T>(result: Result<T, impl Display>) -> T {
+	result.unwrap_or_else(|err| {
+		die(&format!("lost the connection to the X server: {}", err))
+	})
+}
+
+/// The top of the menu on the monitor or parent from `y`, `h` high, by the
+/// edge it is shown along and the margin there. Centered between the top
+/// and the bottom, the menu keeps to neither margin.
+fn place(menu: &Menu, y: i32, h: i32) -> i32 {
+	let [top, _, bottom, _] = menu.opts.margin;
+	let height = menu.height() as i32;
+	match menu.opts.anchor {
+		menu::Anchor::Top => y + top,
+		menu::Anchor::Bottom => y + h - height - bottom,
+		menu::Anchor::Center => y + (h - height) / 2,
+	}
+}
+
+/// Cuts the rounded corners of the menu out of `win`, as windows here
+/// cannot be translucent, where the server can shape it, or gives it back
+/// the corners cut from it if it has none.
+fn shape(conn: &RustConnection, win: Window, menu: &Menu) {
+	if menu.opts.border_radius == 0 {
+		let _ = conn.shape_mask(
+			shape::SO::SET,
+			shape::SK::BOUNDING,
+			win,
+			0,
+			0,
+			NONE,
+		);
+		return;
+	}
+	let rects = menu.drw.shape().into_iter().map(|[x, y, w, h]| Rectangle {
+		x: x as i16,
+		y: y as i16,
+		width: w as u16,
+		height: h as u16,
+	});
+	let rects = rects.collect::<Vec<_>>();
+	let _ = conn.shape_rectangles(
+		shape::SO::SET,
+		shape::SK::BOUNDING,
+		ClipOrdering::UNSORTED,
+		win,
+		0,
+		0,
+		&rects,
+	);
+}
+
+/// Waits until any of `fds` is readable, or `deadline` passes, returning
+/// whether it has not.
+fn poll(fds: &[RawFd], deadline: Option<Instant>) -> bool {
+	let timeout = match deadline {
+		Some(deadline) => {
+			let left = deadline.saturating_duration_since(Instant::now());
+			left.as_millis().min(i32::MAX as u128) as i32
+		}
+		None => -1,
+	};
+	let mut fds: Vec<_> = fds
+		.iter()
+		.map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+		.collect();
+	// SAFETY: the array outlives the call, and its length is given.
+	let ready = unsafe {
+		libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout)
+	};
+	if ready < 0 {
+		let err = io::Error::last_os_error();
+		if err.kind() != io::ErrorKind::Interrupted {
+			die(&format!("cannot wait for events: {}", err));
+		}
+		return true;
+	}
+	ready > 0
+}
+
+fn intern(conn: &RustConnection, name: &str) -> u32 {
+	checked(checked(conn.intern_atom(false, name.as_bytes())).reply()).atom
+}
+
+/// The area of the monitor named `want`, or numbered it from 0, or else of
+/// the one the pointer is on; the whole screen where monitors cannot be
+/// listed.
+fn area(
+	conn: &RustConnection,
+	screen: &Screen,
+	want: Option<&str>,
+) -> (i32, i32, i32, i32) {
+	let whole =
+		(0, 0, screen.width_in_pixels as i32, screen.height_in_pixels as i32);
+	let monitors = conn
+		.randr_get_monitors(screen.root, true)
+		.ok()
+		.and_then(|cookie| cookie.reply().ok())
+		.map(|reply| reply.monitors)
+		.unwrap_or_default();
+	let name = |atom| {
+		let reply = conn.get_atom_name(atom).ok()?.reply().ok()?;
+		String::from_utf8(reply.name).ok()
+	};
+	let mut found = None;
+	if let Some(want) = want {
+		found = monitors
+			.iter()
+			.position(|m| name(m.name).as_deref() == Some(want))
+			.or_else(|| want.parse().ok().filter(|&i| i < monitors.len()));
+		if found.is_none() {
+			eprintln!("no output named '{}', using the default", want);
+		}
+	}
+	let pointer = || {
+		let reply = conn.query_pointer(screen.root).ok()?.reply().ok()?;
+		let (x, y) = (reply.root_x as i32, reply.root_y as i32);
+		monitors.iter().position(|m| {
+			let (mx, my) = (m.x as i32, m.y as i32);
+			(mx..mx + m.width as i32).contains(&x)
+				&& (my..my + m.height as i32).contains(&y)
+		})
+	};
+	let found = found
+		.or_else(pointer)
+		.or_else(|| monitors.iter().position(|m| m.primary));
+	match found.map(|i| &monitors[i]) {
+		Some(m) => (m.x as i32, m.y as i32, m.width as i32, m.height as i32),
+		None => whole,
+	}
+}
+
+/// Grabs the keyboard, trying for a while if another client has it, as
+/// dmenu does.
+fn grab(conn: &RustConnection, root: Window) {
+	for _ in 0..GRAB_TRIES {
+		let reply = conn
+			.grab_keyboard(
+				true,
+				root,
+				CURRENT_TIME,
+				GrabMode::ASYNC,
+				GrabMode::ASYNC,
+			)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok());
+		if reply.is_some_and(|reply| reply.status == GrabStatus::SUCCESS) {
+			return;
+		}
+		thread::sleep(Duration::from_millis(1));
+	}
+	die("cannot grab keyboard");
+}
+
+fn focus(conn: &RustConnection, win: Window) {
+	checked(conn.set_input_focus(InputFocus::PARENT, win, CURRENT_TIME));
+}
+
+/// Shows the menu until an item is chosen or it is cancelled, then exits.
+/// Items from `feed` are added as they arrive.
+pub fn run(mut menu: Menu, feed: Option<Feed>) -> ! {
+	let Ok((conn, screen)) = x11rb::connect(None) else {
+		die("cannot open display");
+	};
+	let setup = conn.setup();
+	let screen = &setup.roots[screen];
+	let (root, depth) = (screen.root, screen.root_depth);
+	// Pixels are drawn as 32 bits each, which the root window must take.
+	let format = setup.pixmap_formats.iter().find(|f| f.depth == depth);
+	if format.is_none_or(|f| f.bits_per_pixel != 32) {
+		die(&format!("cannot draw at a depth of {} bits", depth));
+	}
+	let swap = (setup.image_byte_order == ImageOrder::MSB_FIRST)
+		!= cfg!(target_endian = "big");
+
+	// An embedded menu is placed in its parent as it would be on a monitor.
+	let parent = menu.opts.embed.unwrap_or(root);
+	let (x, y, w, h) = match menu.opts.embed {
+		Some(parent) => {
+			let geometry = conn.get_geometry(parent).ok();
+			let Some(g) = geometry.and_then(|cookie| cookie.reply().ok())
+			else {
+				die(&format!("cannot get the geometry of window {}", parent));
+			};
+			(0, 0, g.width as i32, g.height as i32)
+		}
+		None => area(&conn, screen, menu.opts.output.as_deref()),
+	};
+	let [top, right, bottom, left] = menu.opts.margin;
+	let full = (w - left - right).max(1);
+	let width = match menu.opts.width {
+		Some(size) => size.of(full as u32) as i32,
+		None => full,
+	};
+	if let Some(size) = menu.opts.height {
+		menu.fit(size.of((h - top - bottom).max(1) as u32));
+	}
+	// A narrower menu is centred between the margins.
+	let (area, x) = ((y, h), x + (full - width) / 2);
+	menu.resize(width as u32);
+	let height = menu.height() as i32;
+	let y = place(&menu, y, h);
+
+	let win = checked(conn.generate_id());
+	let events = EventMask::EXPOSURE
+		| EventMask::KEY_PRESS
+		| EventMask::VISIBILITY_CHANGE
+		| EventMask::FOCUS_CHANGE
+		| EventMask::BUTTON_PRESS
+		| EventMask::POINTER_MOTION;
+	let aux = CreateWindowAux::new().override_redirect(1).event_mask(events);
+	checked(conn.create_window(
+		depth,
+		win,
+		parent,
+		(x + left) as i16,
+		y as i16,
+		width as u16,
+		height as u16,
+		0,
+		WindowClass::INPUT_OUTPUT,
+		screen.root_visual,
+		&aux,
+	));
+	checked(conn.change_property8(
+		PropMode::REPLACE,
+		win,
+		AtomEnum::WM_CLASS,
+		AtomEnum::STRING,
+		b"dmenu\0dmenu\0",
+	));
+	if menu.opts.border_radius > 0 {
+		shape(&conn, win, &menu);
+	}
+	let gc = checked(conn.generate_id());
+	checked(conn.create_gc(gc, win, &CreateGCAux::new()));
+	// The keyboard of an embedded menu is its parent's to give.
+	if !menu.opts.on_demand && menu.opts.embed.is_none() {
+		grab(&conn, root);
+	}
+	// The server resumes the screensaver once the connection is closed.
+	if menu.opts.inhibit_idle && conn.screensaver_suspend(1).is_err() {
+		eprintln!("the X server cannot keep the screen on");
+	}
+	checked(conn.map_window(win));
+	focus(&conn, win);
+
+	let atoms = Atoms {
+		clipboard: intern(&conn, "CLIPBOARD"),
+		utf8_string: intern(&conn, "UTF8_STRING"),
+		property: intern(&conn, "DMENU_SELECTION"),
+	};
+	let mut state = State {
+		max_bytes: conn.maximum_request_bytes(),
+		keymap: Keymap::load(&conn),
+		conn,
+		menu,
+		feed,
+		win,
+		gc,
+		depth,
+		swap,
+		atoms,
+		pending: VecDeque::new(),
+		exit: None,
+		width: width as u32,
+		area,
+	};
+	loop {
+		while state.exit.is_none()
+			&& let Some(event) = state.event()
+		{
+			state.handle(event);
+		}
+		state.receive();
+		state.reload();
+		if let Some(status) = state.exit {
+			let _ = state.conn.destroy_window(state.win);
+			let _ = state.conn.flush();
+			if let Some(text) = state.menu.copied() {
+				clipboard::copy(&text, true);
+			}
+			drop(state);
+			exit(status);
+		}
+		state.feed();
+		if state.menu.collect() {
+			match state.menu.decide() {
+				Some(status) => state.exit = Some(status),
+				None => state.draw(),
+			}
+		}
+		checked(state.conn.flush());
+		let fd = |fd: Option<RawFd>| fd.unwrap_or(-1);
+		// Negative descriptors are ignored.
+		poll(
+			&[
+				state.conn.stream().as_raw_fd(),
+				fd(state.feed.as_ref().map(|feed| feed.as_fd().as_raw_fd())),
+				fd(state.menu.worker().map(|w| w.as_fd().as_raw_fd())),
+				fd(state.menu.ipc().map(|ipc| ipc.as_fd().as_raw_fd())),
+				fd(state.menu.reloads().map(|r| r.as_fd().as_raw_fd())),
+				fd(state.menu.dynamic().map(|d| d.as_fd().as_raw_fd())),
+				fd(state.menu.preview().map(|p| p.as_fd().as_raw_fd())),
+			],
+			None,
+		);
+	}
+}
@@ -0,0 +1,293 @@
+//! A thin wrapper over libxkbcommon, which is loaded at run time, for
+//! turning the compositor's keycodes into keysyms and text, by its keymap or
+//! by one given with `--xkb-layout`.
+
+use std::{
+	ffi::{CStr, CString, c_char},
+	ptr::{self, NonNull},
+};
+
+use xkbcommon_dl::{
+	XkbCommon,
+	xkb_context,
+	xkb_context_flags,
+	xkb_keymap,
+	xkb_keymap_compile_flags,
+	xkb_keymap_format,
+	xkb_keysym_flags,
+	xkb_keysym_t,
+	xkb_rule_names,
+	xkb_state,
+	xkb_state_component,
+	xkbcommon_option,
+};
+use xkeysym::Keysym;
+
+/// Wayland keycodes are evdev ones, which XKB offsets by 8.
+const EVDEV_OFFSET: u32 = 8;
+
+/// The keysym called `name`, such as `Return` or `j`.
+pub fn keysym(name: &str) -> Option<Keysym> {
+	let xkb = xkbcommon_option()?;
+	let name = CString::new(name).ok()?;
+	// SAFETY: the name is NUL-terminated.
+	let ksym = unsafe {
+		(xkb.xkb_keysym_from_name)(
+			name.as_ptr(),
+			xkb_keysym_flags::XKB_KEYSYM_NO_FLAGS,
+		)
+	};
+	Some(Keysym::new(ksym)).filter(|&ksym| ksym != Keysym::NoSymbol)
+}
+
+/// The layout, variants and options a keymap is compiled from in place of
+/// the compositor's, as in `us`, `intl` and `caps:escape`, each of which
+/// may be left to the defaults of the rules.
+#[derive(Clone, Default)]
+pub struct Names {
+	pub layout: Option<String>,
+	pub variant: Option<String>,
+	pub options: Option<String>,
+}
+
+impl Names {
+	pub fn is_empty(&self) -> bool {
+		self.layout.is_none()
+			&& self.variant.is_none()
+			&& self.options.is_none()
+	}
+}
+
+pub struct Xkb {
+	xkb: &'static XkbCommon,
+	context: NonNull<xkb_context>,
+	keymap: Option<(NonNull<xkb_keymap>, NonNull<xkb_state>)>,
+	/// Whether the keymap was compiled from names, so that the compositor's
+	/// and the layout it has active are ignored.
+	fixed: bool,
+}
+
+impl Xkb {
+	pub fn new() -> Option<Self> {
+		let xkb = xkbcommon_option()?;
+		// SAFETY: the library was loaded, and no flags are needed.
+		let context = unsafe {
+			(xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS)
+		};
+		Some(Xkb {
+			xkb,
+			context: NonNull::new(context)?,
+			keymap: None,
+			fixed: false,
+		})
+	}
+
+	/// Replaces the keymap with one compiled from `keymap`, the text of a
+	/// keymap in the XKB v1 format, resetting the modifiers. One compiled
+	/// from names is kept in its place.
+	pub fn set_keymap(&mut self, keymap: &CStr) -> bool {
+		if self.fixed {
+			return true;
+		}
+		// SAFETY: the string is NUL-terminated, and the context is live.
+		let keymap = unsafe {
+			(self.xkb.xkb_keymap_new_from_string)(
+				self.context.as_ptr(),
+				keymap.as_ptr(),
+				xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+				xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+			)
+		};
+		self.replace(keymap)
+	}
+
+	/// Compiles the keymap from `names` in place of any the compositor
+	/// sends, with the rules' defaults for what they leave out.
+	pub fn set_names(&mut self, names: &Names) -> bool {
+		let cstr = |name: &Option<String>| {
+			name.as_deref().map(CString::new).transpose()
+		};
+		let (Ok(layout), Ok(variant), Ok(options)) =
+			(cstr(&names.layout), cstr(&names.variant), cstr(&names.options))
+		else {
+			return false;
+		};
+		let ptr = |name: &Option<CString>| {
+			name.as_ref().map_or(ptr::null(), |name| name.as_ptr())
+		};
+		let names = xkb_rule_names {
+			rules: ptr::null(),
+			model: ptr::null(),
+			layout: ptr(&layout),
+			variant: ptr(&variant),
+			options: ptr(&options),
+		};
+		// SAFETY: the names are NUL-terminated or null, and outlive the call.
+		let keymap = unsafe {
+			(self.xkb.xkb_keymap_new_from_names)(
+				self.context.as_ptr(),
+				&names,
+				xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+			)
+		};
+		self.fixed = self.replace(keymap);
+		self.fixed
+	}
+
+	/// Takes `keymap`, if it compiled, in place of the keymap.
+	fn replace(&mut self, keymap: *mut xkb_keymap) -> bool {
+		let Some(keymap) = NonNull::new(keymap) else {
+			return false;
+		};
+		// SAFETY: the keymap was just compiled.
+		let state = unsafe { (self.xkb.xkb_state_new)(keymap.as_ptr()) };
+		let Some(state) = NonNull::new(state) else {
+			// SAFETY: nothing else refers to the keymap.
+			unsafe { (self.xkb.xkb_keymap_unref)(keymap.as_ptr()) };
+			return false;
+		};
+		self.unref_keymap();
+		self.keymap = Some((keymap, state));
+		true
+	}
+
+	fn unref_keymap(&mut self) {
+		if let Some((keymap, state)) = self.keymap.take() {
+			// SAFETY: both are owned by this wrapper alone.
+			unsafe {
+				(self.xkb.xkb_state_unref)(state.as_ptr());
+				(self.xkb.xkb_keymap_unref)(keymap.as_ptr());
+			}
+		}
+	}
+
+	fn state(&self) -> Option<*mut xkb_state> {
+		self.keymap.map(|(_, state)| state.as_ptr())
+	}
+
+	pub fn update_mask(
+		&self,
+		depressed: u32,
+		latched: u32,
+		locked: u32,
+		group: u32,
+	) {
+		// The layouts of a keymap compiled from names are not those the
+		// compositor numbers, so only its first is used.
+		let group = match self.fixed {
+			true => 0,
+			false => group,
+		};
+		if let Some(state) = self.state() {
+			// SAFETY: the state is live.
+			unsafe {
+				(self.xkb.xkb_state_update_mask)(
+					state, depressed, latched, locked, 0, 0, group,
+				);
+			}
+		}
+	}
+
+	/// The keysym `key` gives for a shortcut, which while a layout without
+	/// Latin letters is active is that of the first layout with them at the
+	/// same level, so that Ctrl+W works whichever layout is active.
+	pub fn key_get_shortcut_sym(&self, key: u32) -> Keysym {
+		let ksym = self.key_get_one_sym(key);
+		let (Some((keymap, state)), false) = (self.keymap, latin(ksym)) else {
+			return ksym;
+		};
+		let (keymap, state) = (keymap.as_ptr(), state.as_ptr());
+		let code = key + EVDEV_OFFSET;
+		// SAFETY: the keymap and state are live, and the keysyms are read
+		// only as far as the count given.
+		unsafe {
+			let active = (self.xkb.xkb_state_key_get_layout)(state, code);
+			let level = (self.xkb.xkb_state_key_get_level)(state, code, active);
+			let layouts =
+				(self.xkb.xkb_keymap_num_layouts_for_key)(keymap, code);
+			for layout in (0..layouts).filter(|&layout| layout != active) {
+				let mut syms: *const xkb_keysym_t = ptr::null();
+				let n = (self.xkb.xkb_keymap_key_get_syms_by_level)(
+					keymap, code, layout, level, &mut syms,
+				);
+				if n == 1 && latin(Keysym::new(*syms)) {
+					return Keysym::new(*syms);
+				}
+			}
+		}
+		ksym
+	}
+
+	pub fn key_get_one_sym(&self, key: u32) -> Keysym {
+		match self.state() {
+			// SAFETY: the state is live.
+			Some(state) => Keysym::new(unsafe {
+				(self.xkb.xkb_state_key_get_one_sym)(state, key + EVDEV_OFFSET)
+			}),
+			None => Keysym::NoSymbol,
+		}
+	}
+
+	/// The text typed by `key`, which is empty for keys which type nothing.
+	pub fn key_get_utf8(&self, key: u32) -> String {
+		let Some(state) = self.state() else {
+			return String::new();
+		};
+		let mut buf = [0u8; 64];
+		// SAFETY: the state is live, and the length is that of the buffer.
+		let len = unsafe {
+			(self.xkb.xkb_state_key_get_utf8)(
+				state,
+				key + EVDEV_OFFSET,
+				buf.as_mut_ptr() as *mut c_char,
+				buf.len(),
+			)
+		};
+		// The length excludes the NUL, and longer text is truncated.
+		let len = (len.max(0) as usize).min(buf.len() - 1);
+		String::from_utf8_lossy(&buf[..len]).into_owned()
+	}
+
+	pub fn key_repeats(&self, key: u32) -> bool {
+		match self.keymap {
+			// SAFETY: the keymap is live.
+			Some((keymap, _)) => unsafe {
+				(self.xkb.xkb_keymap_key_repeats)(
+					keymap.as_ptr(),
+					key + EVDEV_OFFSET,
+				) == 1
+			},
+			None => false,
+		}
+	}
+
+	/// Whether the modifier called `name`, such as `Control`, is in effect.
+	pub fn mod_is_active(&self, name: &CStr) -> bool {
+		match self.state() {
+			// SAFETY: the state is live and the name is NUL-terminated.
+			Some(state) => unsafe {
+				(self.xkb.xkb_state_mod_name_is_active)(
+					state,
+					name.as_ptr(),
+					xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+				) == 1
+			},
+			None => false,
+		}
+	}
+}
+
+/// Whether `ksym` is a Latin letter, as shortcuts are bound to.
+fn latin(ksym: Keysym) -> bool {
+	let raw = ksym.raw();
+	(b'A' as u32..=b'Z' as u32).contains(&raw)
+		|| (b'a' as u32..=b'z' as u32).contains(&raw)
+}
+
+impl Drop for Xkb {
+	fn drop(&mut self) {
+		self.unref_keymap();
+		// SAFETY: nothing still refers to the context.
+		unsafe { (self.xkb.xkb_context_unref)(self.context.as_ptr()) };
+	}
+}
@@ -0,0 +1,81 @@
+//! Linux file capabilities, as stored in the `security.capability` extended
+//! attribute by setcap(8).
+
+/// The name of each capability, without its `cap_` prefix, by number.
+const NAMES: &[&str] = &[
+	"chown",
+	"dac_override",
+	"dac_read_search",
+	"fowner",
+	"fsetid",
+	"kill",
+	"setgid",
+	"setuid",
+	"setpcap",
+	"linux_immutable",
+	"net_bind_service",
+	"net_broadcast",
+	"net_admin",
+	"net_raw",
+	"ipc_lock",
+	"ipc_owner",
+	"sys_module",
+	"sys_rawio",
+	"sys_chroot",
+	"sys_ptrace",
+	"sys_pacct",
+	"sys_admin",
+	"sys_boot",
+	"sys_nice",
+	"sys_resource",
+	"sys_time",
+	"sys_tty_config",
+	"mknod",
+	"lease",
+	"audit_write",
+	"audit_control",
+	"setfcap",
+	"mac_override",
+	"mac_admin",
+	"syslog",
+	"wake_alarm",
+	"block_suspend",
+	"audit_read",
+	"perfmon",
+	"bpf",
+	"checkpoint_restore",
+];
+
+/// Parses a capability such as `cap_net_raw`, in either case and with or
+/// without its prefix, or its number.
+pub fn parse(name: &str) -> Option<u32> {
+	let name = name.to_ascii_lowercase();
+	let name = name.strip_prefix("cap_").unwrap_or(&name);
+	match NAMES.iter().position(|&known| known == name) {
+		Some(cap) => Some(cap as u32),
+		None => name.parse().ok().filter(|&cap| cap < 64),
+	}
+}
+
+/// The capabilities a `security.capability` value grants, permitted or
+/// inheritable, one bit for each, or `None` if it is not one setcap(8)
+/// writes.
+pub fn decode(value: &[u8]) -> Option<u64> {
+	let word = |i: usize| {
+		let bytes = value.get(i * 4..i * 4 + 4)?;
+		Some(u32::from_le_bytes(bytes.try_into().ok()?))
+	};
+	// The revision is in the top byte of the first word: the first had one
+	// set of 32 bits, the second and third two, the third adding a root ID.
+	let sets = match word(0)? >> 24 {
+		1 => 1,
+		2 | 3 => 2,
+		_ => return None,
+	};
+	let mut caps = 0;
+	for set in 0..sets {
+		let (permitted, inheritable) = (word(1 + set * 2)?, word(2 + set * 2)?);
+		caps |= u64::from(permitted | inheritable) << (set * 32);
+	}
+	Some(caps)
+}
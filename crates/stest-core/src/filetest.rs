@@ -0,0 +1,324 @@
+//! A builder over [`Criteria`] with a method for each of `stest`'s tests,
+//! for filtering lists of paths without running the binary.
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use walkdir::WalkDir;
+
+use crate::{
+	Any,
+	Between,
+	Capability,
+	Criteria,
+	File,
+	Group,
+	MaxSize,
+	Mime,
+	MinSize,
+	Name,
+	Newer,
+	NewerThan,
+	Older,
+	OlderThan,
+	Owner,
+	Predicate,
+	Regex,
+	SameFile,
+	Timestamp,
+	Xattr,
+};
+
+/// The tests paths must pass, and how files are resolved for them, as in
+/// `FileTest::new().dir().readable().newer_than(path)`.
+#[derive(Default)]
+pub struct FileTest {
+	criteria: Criteria,
+	deref_max: Option<usize>,
+	whole_seconds: bool,
+	timestamp: Timestamp,
+	mode_bits: bool,
+	no_follow: bool,
+	invert: bool,
+}
+
+impl FileTest {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a test of the caller's own.
+	pub fn with(
+		mut self,
+		predicate: impl Predicate + Send + Sync + 'static,
+	) -> Self {
+		self.criteria.push(predicate);
+		self
+	}
+
+	pub fn hidden(self) -> Self {
+		self.with(File::is_hidden)
+	}
+
+	pub fn block(self) -> Self {
+		self.with(File::is_block)
+	}
+
+	pub fn char(self) -> Self {
+		self.with(File::is_char)
+	}
+
+	pub fn dir(self) -> Self {
+		self.with(File::is_dir)
+	}
+
+	pub fn exists(self) -> Self {
+		self.with(File::exists)
+	}
+
+	pub fn file(self) -> Self {
+		self.with(File::is_file)
+	}
+
+	pub fn has_setgid(self) -> Self {
+		self.with(File::has_setgid)
+	}
+
+	pub fn symlink(self) -> Self {
+		self.with(File::is_symlink)
+	}
+
+	/// A symbolic link which leads nowhere, as with `--dangling`.
+	pub fn dangling(self) -> Self {
+		self.with(File::is_dangling)
+	}
+
+	pub fn pipe(self) -> Self {
+		self.with(File::is_pipe)
+	}
+
+	pub fn socket(self) -> Self {
+		self.with(File::is_socket)
+	}
+
+	pub fn sticky(self) -> Self {
+		self.with(File::is_sticky)
+	}
+
+	pub fn readable(self) -> Self {
+		self.with(File::is_readable)
+	}
+
+	pub fn non_empty(self) -> Self {
+		self.with(File::is_non_empty)
+	}
+
+	pub fn has_setuid(self) -> Self {
+		self.with(File::has_setuid)
+	}
+
+	pub fn writable(self) -> Self {
+		self.with(File::is_writable)
+	}
+
+	pub fn executable(self) -> Self {
+		self.with(File::is_executable)
+	}
+
+	/// Having capabilities set, as by setcap(8).
+	pub fn has_caps(self) -> Self {
+		self.with(File::has_caps)
+	}
+
+	/// Granted the capability numbered `cap`, as [`crate::caps::parse`]
+	/// gives.
+	pub fn cap(self, cap: u32) -> Self {
+		self.with(Capability(cap))
+	}
+
+	/// Having the extended attribute `name`, with `value` if given.
+	pub fn xattr(
+		self,
+		name: impl Into<String>,
+		value: Option<Vec<u8>>,
+	) -> Self {
+		self.with(Xattr { name: name.into(), value })
+	}
+
+	/// Owned by the effective user ID, as with `-O`.
+	pub fn owned(self) -> Self {
+		self.with(File::is_owned)
+	}
+
+	/// Belonging to the effective group ID, as with `-G`.
+	pub fn group_owned(self) -> Self {
+		self.with(File::is_group_owned)
+	}
+
+	pub fn owner(self, uid: u32) -> Self {
+		self.with(Owner(uid))
+	}
+
+	pub fn group(self, gid: u32) -> Self {
+		self.with(Group(gid))
+	}
+
+	/// The same file as the one at `path`, as with `--same-file`.
+	pub fn same_file(self, path: impl AsRef<Path>) -> Self {
+		let reference = self.resolve(path.as_ref());
+		self.with(SameFile(reference))
+	}
+
+	/// Modified more recently than the file at `path`, as with `-n`.
+	pub fn newer_than(self, path: impl AsRef<Path>) -> Self {
+		let reference = self.resolve(path.as_ref());
+		self.with(Newer(reference))
+	}
+
+	/// Modified less recently than the file at `path`, as with `-o`.
+	pub fn older_than(self, path: impl AsRef<Path>) -> Self {
+		let reference = self.resolve(path.as_ref());
+		self.with(Older(reference))
+	}
+
+	pub fn modified_after(self, time: SystemTime) -> Self {
+		self.with(NewerThan(time))
+	}
+
+	pub fn modified_before(self, time: SystemTime) -> Self {
+		self.with(OlderThan(time))
+	}
+
+	/// Modified no earlier than `start` and no later than `end`.
+	pub fn modified_between(self, start: SystemTime, end: SystemTime) -> Self {
+		self.with(Between(start, end))
+	}
+
+	/// At least `bytes` long.
+	pub fn min_size(self, bytes: u64) -> Self {
+		self.with(MinSize(bytes))
+	}
+
+	/// At most `bytes` long.
+	pub fn max_size(self, bytes: u64) -> Self {
+		self.with(MaxSize(bytes))
+	}
+
+	/// Content of a MIME type matching the wildcard pattern, such as
+	/// `image/*`.
+	pub fn mime(self, pattern: impl Into<String>) -> Self {
+		self.with(Mime(vec![pattern.into()]))
+	}
+
+	/// The basename matches the wildcard pattern, ignoring case if `icase`
+	/// is set.
+	pub fn name(self, pattern: impl Into<String>, icase: bool) -> Self {
+		self.with(Name { patterns: vec![pattern.into()], icase })
+	}
+
+	/// The basename contains a match for the regular expression.
+	pub fn regex(
+		self,
+		pattern: &str,
+		icase: bool,
+	) -> Result<Self, regex::Error> {
+		Ok(self.with(Regex::new(&[pattern.into()], icase, false)?))
+	}
+
+	/// The whole path contains a match for the regular expression.
+	pub fn path_regex(
+		self,
+		pattern: &str,
+		icase: bool,
+	) -> Result<Self, regex::Error> {
+		Ok(self.with(Regex::new(&[pattern.into()], icase, true)?))
+	}
+
+	/// Passes any of `criteria`, rather than all, as with `--any`.
+	pub fn any(self, criteria: Criteria) -> Self {
+		self.with(Any(criteria))
+	}
+
+	/// Passes the paths which fail, as with `-v`.
+	pub fn invert(mut self) -> Self {
+		self.invert = !self.invert;
+		self
+	}
+
+	/// See [`File::dereference_at_most`]. Reference files given since are
+	/// resolved the same way.
+	pub fn dereference_at_most(mut self, max: Option<usize>) -> Self {
+		self.deref_max = max;
+		self
+	}
+
+	/// See [`File::whole_seconds`]. Reference files given since are compared
+	/// the same way.
+	pub fn whole_seconds(mut self, whole_seconds: bool) -> Self {
+		self.whole_seconds = whole_seconds;
+		self
+	}
+
+	/// See [`File::compare_time`]. Reference files given since are compared
+	/// by the same timestamp.
+	pub fn compare_time(mut self, timestamp: Timestamp) -> Self {
+		self.timestamp = timestamp;
+		self
+	}
+
+	/// See [`File::mode_bits`].
+	pub fn mode_bits(mut self, mode_bits: bool) -> Self {
+		self.mode_bits = mode_bits;
+		self
+	}
+
+	/// See [`File::follow_links`].
+	pub fn follow_links(mut self, follow: bool) -> Self {
+		self.no_follow = !follow;
+		self
+	}
+
+	fn resolve(&self, path: &Path) -> File {
+		File::from(path)
+			.dereference_at_most(self.deref_max)
+			.whole_seconds(self.whole_seconds)
+			.compare_time(self.timestamp)
+			.mode_bits(self.mode_bits)
+			.follow_links(!self.no_follow)
+	}
+
+	/// Tests the file at `path`. A test which cannot be decided fails the
+	/// file, unless inverted.
+	pub fn test(&self, path: impl AsRef<Path>) -> bool {
+		self.test_file(&self.resolve(path.as_ref())).unwrap_or(self.invert)
+	}
+
+	/// Tests `file`, giving the error of a test which cannot be decided.
+	pub fn test_file(&self, file: &File) -> io::Result<bool> {
+		Ok(self.criteria.test(file)? != self.invert)
+	}
+
+	/// The paths which pass, in the order given.
+	pub fn filter<P: AsRef<Path>>(
+		&self,
+		paths: impl IntoIterator<Item = P>,
+	) -> impl Iterator<Item = PathBuf> {
+		paths
+			.into_iter()
+			.filter(|path| self.test(path))
+			.map(|path| path.as_ref().to_path_buf())
+	}
+
+	/// The files under `dir`, and `dir` itself, which pass, as with `-l`.
+	/// Entries which cannot be read are left out.
+	pub fn walk(&self, dir: impl AsRef<Path>) -> impl Iterator<Item = PathBuf> {
+		WalkDir::new(dir)
+			.into_iter()
+			.filter_map(Result::ok)
+			.map(walkdir::DirEntry::into_path)
+			.filter(|path| self.test(path))
+	}
+}
@@ -0,0 +1,133 @@
+//! Shell-style wildcard matching, as used by `find -name`.
+//!
+//! `*` matches any run of characters, `?` any single character, and `[...]`
+//! any character in the brackets, with `a-z` ranges and a leading `!` or `^`
+//! negating the set. A backslash matches the character after it literally.
+
+/// Matches the whole of `name` against `pattern`, ignoring case when `icase`
+/// is set.
+pub fn matches(pattern: &str, name: &str, icase: bool) -> bool {
+	let pattern = pattern.chars().collect::<Vec<_>>();
+	let name = name.chars().collect::<Vec<_>>();
+
+	let (mut p, mut n) = (0, 0);
+	// Where to resume after the last `*` if the rest fails to match.
+	let mut backtrack = None;
+	while n < name.len() {
+		let step = match pattern.get(p) {
+			Some('*') => {
+				backtrack = Some((p, n));
+				p += 1;
+				continue;
+			}
+			Some('?') => Some(p + 1),
+			Some('[') => match class(&pattern[p..], name[n], icase) {
+				Some((found, len)) => found.then_some(p + len),
+				None => eq('[', name[n], icase).then_some(p + 1),
+			},
+			Some('\\') if p + 1 < pattern.len() => {
+				eq(pattern[p + 1], name[n], icase).then_some(p + 2)
+			}
+			Some(&c) => eq(c, name[n], icase).then_some(p + 1),
+			None => None,
+		};
+		match (step, backtrack) {
+			(Some(next), _) => {
+				p = next;
+				n += 1;
+			}
+			(None, Some((star, from))) => {
+				backtrack = Some((star, from + 1));
+				p = star + 1;
+				n = from + 1;
+			}
+			(None, None) => return false,
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn eq(a: char, b: char, icase: bool) -> bool {
+	a == b || icase && a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Matches `c` against the bracket expression at the start of `pattern`,
+/// returning whether it matched and the expression's length, or `None` if
+/// the bracket is never closed.
+fn class(pattern: &[char], c: char, icase: bool) -> Option<(bool, usize)> {
+	let mut i = 1;
+	let negated = matches!(pattern.get(i), Some('!' | '^'));
+	if negated {
+		i += 1;
+	}
+	let folded = match icase {
+		true => c.to_lowercase().chain(c.to_uppercase()).collect(),
+		false => vec![c],
+	};
+	let mut found = false;
+	let mut first = true;
+	loop {
+		let &lo = pattern.get(i)?;
+		if lo == ']' && !first {
+			break;
+		}
+		first = false;
+		match pattern.get(i + 1..i + 3) {
+			Some(&['-', hi]) if hi != ']' => {
+				found |= folded.iter().any(|c| (lo..=hi).contains(c));
+				i += 3;
+			}
+			_ => {
+				found |= eq(lo, c, icase);
+				i += 1;
+			}
+		}
+	}
+	Some((found != negated, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wildcards() {
+		assert!(matches("*.rs", "main.rs", false));
+		assert!(!matches("*.rs", "main.rs.orig", false));
+		assert!(matches("a?c", "abc", false));
+		assert!(!matches("a?c", "ac", false));
+		assert!(matches("*a*b*", "xaybz", false));
+		assert!(matches("", "", false));
+		assert!(!matches("", "a", false));
+		assert!(matches("**", "", false));
+	}
+
+	#[test]
+	fn classes() {
+		assert!(matches("[abc]x", "bx", false));
+		assert!(!matches("[abc]x", "dx", false));
+		assert!(matches("[a-c]", "b", false));
+		assert!(matches("[!a-c]", "d", false));
+		assert!(!matches("[^a-c]", "b", false));
+		assert!(matches("[]]", "]", false));
+		assert!(matches("[a-]", "-", false));
+		// A bracket which is never closed is matched literally.
+		assert!(matches("[ab", "[ab", false));
+	}
+
+	#[test]
+	fn escapes() {
+		assert!(matches("\\*", "*", false));
+		assert!(!matches("\\*", "a", false));
+		assert!(matches("a\\", "a\\", false));
+	}
+
+	#[test]
+	fn icase() {
+		assert!(!matches("*.TXT", "notes.txt", false));
+		assert!(matches("*.TXT", "notes.txt", true));
+		assert!(matches("[A-C]x", "bX", true));
+		assert!(!matches("[!A-C]", "b", true));
+		assert!(matches("ÉTÉ", "été", true));
+	}
+}
@@ -0,0 +1,819 @@
+//! Filtering of files by their properties, as done by the `stest` binary.
+//!
+//! A [`Criteria`] is a list of [`Predicate`]s which a [`File`] must all pass.
+//! The tests behind each of `stest`'s flags are methods on [`File`], any of
+//! which can be used as a predicate directly, as can any closure of the same
+//! shape, so callers can mix their own tests in with the built-in ones.
+//! [`FileTest`] wraps all of this in a builder for filtering lists of paths.
+
+use std::{
+	ffi::{CString, OsStr},
+	fs::{self, FileType, Metadata},
+	io::{self, Read},
+	os::unix::{
+		ffi::OsStrExt,
+		fs::{FileTypeExt, MetadataExt},
+	},
+	path::{Path, PathBuf},
+	sync::OnceLock,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub mod caps;
+mod filetest;
+pub mod glob;
+pub mod mime;
+pub mod size;
+pub mod time;
+
+pub use filetest::FileTest;
+
+/// Which of a file's timestamps the time tests compare.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Timestamp {
+	#[default]
+	Modified,
+	/// When the file's inode last changed, as with a new name or mode.
+	Changed,
+	Accessed,
+	/// When the file was created, which not every filesystem records.
+	Born,
+}
+
+pub struct File {
+	path: Box<Path>,
+	deref_max: Option<usize>,
+	whole_seconds: bool,
+	timestamp: Timestamp,
+	mode_bits: bool,
+	follow: bool,
+	meta: OnceLock<io::Result<Metadata>>,
+	link_meta: OnceLock<io::Result<Metadata>>,
+	/// Where the file's symbolic links lead under `dereference_at_most`.
+	resolved: OnceLock<io::Result<Option<PathBuf>>>,
+}
+
+impl File {
+	pub fn new(path: Box<Path>) -> Self {
+		File {
+			path,
+			deref_max: None,
+			whole_seconds: false,
+			timestamp: Timestamp::Modified,
+			mode_bits: false,
+			follow: true,
+			meta: OnceLock::new(),
+			link_meta: OnceLock::new(),
+			resolved: OnceLock::new(),
+		}
+	}
+
+	/// Limits how many symbolic links are followed when resolving the file,
+	/// instead of leaving it to the kernel's own `ELOOP` limit.
+	pub fn dereference_at_most(mut self, max: Option<usize>) -> Self {
+		self.deref_max = max;
+		self
+	}
+
+	/// Truncates timestamps to whole seconds, for comparisons on
+	/// filesystems whose timestamps are coarser than the system clock.
+	pub fn whole_seconds(mut self, whole_seconds: bool) -> Self {
+		self.whole_seconds = whole_seconds;
+		self
+	}
+
+	/// Chooses which timestamp [`File::time`], and so the time tests, use.
+	pub fn compare_time(mut self, timestamp: Timestamp) -> Self {
+		self.timestamp = timestamp;
+		self
+	}
+
+	/// Decides whether the file is readable, writable or executable from
+	/// whether any of its permission bits are set, rather than by asking the
+	/// kernel whether this process may access it.
+	pub fn mode_bits(mut self, mode_bits: bool) -> Self {
+		self.mode_bits = mode_bits;
+		self
+	}
+
+	/// With `follow` unset, tests apply to a symbolic link itself rather than
+	/// to the file it points to.
+	pub fn follow_links(mut self, follow: bool) -> Self {
+		self.follow = follow;
+		self
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Stats the file on first use; every test shares the result. A file
+	/// which is not a symbolic link is stat'd just once, whether or not
+	/// links are followed.
+	pub fn metadata(&self) -> Result<&Metadata, &io::Error> {
+		self.meta
+			.get_or_init(|| {
+				let link = self.link_metadata().map_err(copy)?;
+				if !self.follow || !link.is_symlink() {
+					return Ok(link.clone());
+				}
+				match self.deref_max {
+					Some(_) => match self.resolved()? {
+						Some(path) => path.symlink_metadata(),
+						None => Err(io::ErrorKind::NotFound.into()),
+					},
+					None => self.path.metadata(),
+				}
+			})
+			.as_ref()
+	}
+
+	/// The metadata of the file itself, rather than of what it links to.
+	fn link_metadata(&self) -> Result<&Metadata, &io::Error> {
+		self.link_meta.get_or_init(|| self.path.symlink_metadata()).as_ref()
+	}
+
+	/// The file's metadata, or `None` if it does not exist, which fails
+	/// every test rather than being an error.
+	fn stat(&self) -> io::Result<Option<&Metadata>> {
+		match self.metadata() {
+			Ok(meta) => Ok(Some(meta)),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(copy(err)),
+		}
+	}
+
+	fn file_type(&self) -> io::Result<Option<FileType>> {
+		Ok(self.stat()?.map(|meta| meta.file_type()))
+	}
+
+	fn mode(&self) -> io::Result<Option<u32>> {
+		Ok(self.stat()?.map(|meta| meta.mode()))
+	}
+
+	pub fn is_hidden(&self) -> io::Result<bool> {
+		Ok(self
+			.path
+			.file_name()
+			.is_some_and(|name| name.to_string_lossy().starts_with(".")))
+	}
+
+	pub fn is_block(&self) -> io::Result<bool> {
+		Ok(self.file_type()?.as_ref().is_some_and(FileTypeExt::is_block_device))
+	}
+
+	pub fn is_char(&self) -> io::Result<bool> {
+		Ok(self.file_type()?.as_ref().is_some_and(FileTypeExt::is_char_device))
+	}
+
+	pub fn is_dir(&self) -> io::Result<bool> {
+		Ok(self.stat()?.is_some_and(|meta| meta.is_dir()))
+	}
+
+	pub fn exists(&self) -> io::Result<bool> {
+		Ok(self.stat()?.is_some())
+	}
+
+	pub fn is_file(&self) -> io::Result<bool> {
+		Ok(self.stat()?.is_some_and(|meta| meta.is_file()))
+	}
+
+	pub fn has_setgid(&self) -> io::Result<bool> {
+		Ok(self.mode()?.is_some_and(|mode| mode & 0o2000 != 0))
+	}
+
+	pub fn is_symlink(&self) -> io::Result<bool> {
+		match self.link_metadata() {
+			Ok(meta) => Ok(meta.is_symlink()),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+			Err(err) => Err(copy(err)),
+		}
+	}
+
+	/// A symbolic link which leads nowhere, whether to a file which does not
+	/// exist or round a loop. Links are followed for this whatever
+	/// `follow_links` says.
+	pub fn is_dangling(&self) -> io::Result<bool> {
+		if !self.is_symlink()? {
+			return Ok(false);
+		}
+		match self.path.metadata() {
+			Ok(_) => Ok(false),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+			Err(err) if err.raw_os_error() == Some(libc::ELOOP) => Ok(true),
+			Err(err) => Err(err),
+		}
+	}
+
+	pub fn is_pipe(&self) -> io::Result<bool> {
+		Ok(self.file_type()?.as_ref().is_some_and(FileTypeExt::is_fifo))
+	}
+
+	pub fn is_socket(&self) -> io::Result<bool> {
+		Ok(self.file_type()?.as_ref().is_some_and(FileTypeExt::is_socket))
+	}
+
+	pub fn is_sticky(&self) -> io::Result<bool> {
+		Ok(self.mode()?.is_some_and(|mode| mode & 0o1000 != 0))
+	}
+
+	/// The path to hand to system calls which follow symbolic links
+	/// themselves unless told not to, resolved by hand under
+	/// `dereference_at_most`, or `None` if it leads nowhere.
+	fn target(&self) -> io::Result<Option<CString>> {
+		let Some(path) = self.resolved()? else {
+			return Ok(None);
+		};
+		CString::new(path.as_os_str().as_bytes())
+			.map(Some)
+			.map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+	}
+
+	fn resolved(&self) -> io::Result<Option<&Path>> {
+		let Some(max) = self.deref_max.filter(|_| self.follow) else {
+			return Ok(Some(&self.path));
+		};
+		let resolved =
+			self.resolved.get_or_init(|| match resolve(&self.path, max) {
+				Ok(path) => Ok(Some(path)),
+				Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+				Err(err) => Err(err),
+			});
+		match resolved {
+			Ok(path) => Ok(path.as_deref()),
+			Err(err) => Err(copy(err)),
+		}
+	}
+
+	/// The MIME type guessed from the file's content, as [`mime::sniff`]
+	/// does, or `None` if it is not a regular file.
+	pub fn mime(&self) -> io::Result<Option<&'static str>> {
+		if !self.is_file()? {
+			return Ok(None);
+		}
+		let Some(path) = self.resolved()? else {
+			return Ok(None);
+		};
+		let mut head = Vec::with_capacity(mime::SNIFF_LEN);
+		fs::File::open(path)?
+			.take(mime::SNIFF_LEN as u64)
+			.read_to_end(&mut head)?;
+		Ok(Some(mime::sniff(&head)))
+	}
+
+	/// Whether the process may access the file in the way of `mode`, with its
+	/// effective IDs, as `access(2)` decides, or whether any of the bits of
+	/// `bits` are set under `mode_bits`.
+	fn access(&self, mode: libc::c_int, bits: u32) -> io::Result<bool> {
+		if self.mode_bits {
+			return Ok(self.mode()?.is_some_and(|mode| mode & bits != 0));
+		}
+		let Some(path) = self.target()? else {
+			return Ok(false);
+		};
+		let flags = match self.follow {
+			true => libc::AT_EACCESS,
+			false => libc::AT_EACCESS | libc::AT_SYMLINK_NOFOLLOW,
+		};
+		// SAFETY: the path is NUL-terminated and outlives the call.
+		let ret = unsafe {
+			libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, flags)
+		};
+		if ret == 0 {
+			return Ok(true);
+		}
+		let err = io::Error::last_os_error();
+		match err.raw_os_error() {
+			Some(libc::EACCES | libc::EROFS | libc::ENOENT | libc::ENOTDIR) => {
+				Ok(false)
+			}
+			_ => Err(err),
+		}
+	}
+
+	/// The value of the extended attribute `name`, or `None` if the file
+	/// does not have it, does not exist, or is on a filesystem without
+	/// extended attributes.
+	pub fn xattr(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+		let Some(path) = self.target()? else {
+			return Ok(None);
+		};
+		let name = CString::new(name)
+			.map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+		let get = if self.follow { libc::getxattr } else { libc::lgetxattr };
+		let mut value = Vec::<u8>::new();
+		loop {
+			// SAFETY: both strings are NUL-terminated, and the buffer is
+			// as long as the size given.
+			let len = unsafe {
+				get(
+					path.as_ptr(),
+					name.as_ptr(),
+					value.as_mut_ptr().cast(),
+					value.len(),
+				)
+			};
+			if let Ok(len) = usize::try_from(len) {
+				// With an empty buffer only the length was asked for.
+				if value.is_empty() && len > 0 {
+					value.resize(len, 0);
+					continue;
+				}
+				value.truncate(len);
+				return Ok(Some(value));
+			}
+			let err = io::Error::last_os_error();
+			match err.raw_os_error() {
+				// The value grew since its length was asked for.
+				Some(libc::ERANGE) => value.clear(),
+				Some(libc::ENODATA | libc::ENOTSUP | libc::ENOENT) => {
+					return Ok(None);
+				}
+				_ => return Err(err),
+			}
+		}
+	}
+
+	/// Whether the file has capabilities set, as by setcap(8).
+	pub fn has_caps(&self) -> io::Result<bool> {
+		let caps = self.xattr("security.capability")?;
+		Ok(caps.as_deref().and_then(caps::decode).is_some_and(|c| c != 0))
+	}
+
+	pub fn is_readable(&self) -> io::Result<bool> {
+		self.access(libc::R_OK, 0o0444)
+	}
+
+	pub fn has_setuid(&self) -> io::Result<bool> {
+		Ok(self.mode()?.is_some_and(|mode| mode & 0o4000 != 0))
+	}
+
+	pub fn is_non_empty(&self) -> io::Result<bool> {
+		Ok(self.stat()?.is_some_and(|meta| meta.len() > 0))
+	}
+
+	pub fn is_writable(&self) -> io::Result<bool> {
+		self.access(libc::W_OK, 0o0222)
+	}
+
+	pub fn is_executable(&self) -> io::Result<bool> {
+		self.access(libc::X_OK, 0o0111)
+	}
+
+	/// Whether the file is owned by the effective user ID of the process.
+	pub fn is_owned(&self) -> io::Result<bool> {
+		// SAFETY: geteuid cannot fail.
+		let uid = unsafe { libc::geteuid() };
+		Ok(self.stat()?.is_some_and(|meta| meta.uid() == uid))
+	}
+
+	/// Whether the file's group is the effective group ID of the process.
+	pub fn is_group_owned(&self) -> io::Result<bool> {
+		// SAFETY: getegid cannot fail.
+		let gid = unsafe { libc::getegid() };
+		Ok(self.stat()?.is_some_and(|meta| meta.gid() == gid))
+	}
+
+	pub fn modified(&self) -> io::Result<Option<SystemTime>> {
+		let modified = self.stat()?.map(Metadata::modified).transpose()?;
+		Ok(match self.whole_seconds {
+			true => modified.map(time::truncate),
+			false => modified,
+		})
+	}
+
+	/// The timestamp chosen by [`File::compare_time`], the modification time
+	/// unless another was chosen. A creation time the filesystem does not
+	/// record is an error.
+	pub fn time(&self) -> io::Result<Option<SystemTime>> {
+		let Some(meta) = self.stat()? else {
+			return Ok(None);
+		};
+		let epoch = |secs: i64, nanos: i64| match u64::try_from(secs) {
+			Ok(secs) => UNIX_EPOCH + Duration::new(secs, nanos as u32),
+			Err(_) => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+		};
+		let time = match self.timestamp {
+			Timestamp::Modified => meta.modified()?,
+			Timestamp::Changed => epoch(meta.ctime(), meta.ctime_nsec()),
+			Timestamp::Accessed => epoch(meta.atime(), meta.atime_nsec()),
+			Timestamp::Born => meta.created()?,
+		};
+		Ok(Some(match self.whole_seconds {
+			true => time::truncate(time),
+			false => time,
+		}))
+	}
+}
+
+impl<T: AsRef<OsStr>> From<T> for File {
+	fn from(value: T) -> Self {
+		File::new(Box::from(Path::new(&value)))
+	}
+}
+
+/// Follows the symbolic link chain starting at `path` by hand, giving up once
+/// more than `max` links have been followed.
+fn resolve(path: &Path, max: usize) -> io::Result<PathBuf> {
+	let mut path = path.to_path_buf();
+	for _ in 0..=max {
+		if !path.symlink_metadata()?.is_symlink() {
+			return Ok(path);
+		}
+		let target = fs::read_link(&path)?;
+		path = match path.parent() {
+			Some(parent) => parent.join(target),
+			None => target,
+		};
+	}
+	Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Duplicates a cached error, which cannot be cloned.
+fn copy(err: &io::Error) -> io::Error {
+	match err.raw_os_error() {
+		Some(code) => io::Error::from_raw_os_error(code),
+		None => io::Error::new(err.kind(), err.to_string()),
+	}
+}
+
+/// A single test of a file. An error means the test could not be decided,
+/// which callers usually treat as the file failing it.
+pub trait Predicate {
+	fn test(&self, file: &File) -> io::Result<bool>;
+}
+
+impl<F: Fn(&File) -> io::Result<bool>> Predicate for F {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		self(file)
+	}
+}
+
+/// Modified, or changed, accessed or born as the file chooses with
+/// [`File::compare_time`], more recently than the reference file.
+pub struct Newer(pub File);
+
+impl Predicate for Newer {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		match (file.time()?, self.0.time()?) {
+			(Some(modified), Some(reference)) => Ok(modified > reference),
+			_ => Ok(false),
+		}
+	}
+}
+
+/// Modified, or as for [`Newer`], less recently than the reference file.
+pub struct Older(pub File);
+
+impl Predicate for Older {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		match (file.time()?, self.0.time()?) {
+			(Some(modified), Some(reference)) => Ok(modified < reference),
+			_ => Ok(false),
+		}
+	}
+}
+
+/// Modified, or as for [`Newer`], after the given time.
+pub struct NewerThan(pub SystemTime);
+
+impl Predicate for NewerThan {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.time()?.is_some_and(|modified| modified > self.0))
+	}
+}
+
+/// Modified, or as for [`Newer`], before the given time.
+pub struct OlderThan(pub SystemTime);
+
+impl Predicate for OlderThan {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.time()?.is_some_and(|modified| modified < self.0))
+	}
+}
+
+/// Modified, or as for [`Newer`], no earlier than the first time and no
+/// later than the second.
+pub struct Between(pub SystemTime, pub SystemTime);
+
+impl Predicate for Between {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.time()?.is_some_and(|m| (self.0..=self.1).contains(&m)))
+	}
+}
+
+/// The same file as the reference file, on the same device with the same
+/// inode, as with `test -ef`.
+pub struct SameFile(pub File);
+
+impl Predicate for SameFile {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		match (file.stat()?, self.0.stat()?) {
+			(Some(a), Some(b)) => Ok(a.dev() == b.dev() && a.ino() == b.ino()),
+			_ => Ok(false),
+		}
+	}
+}
+
+/// At least the given number of bytes long.
+pub struct MinSize(pub u64);
+
+impl Predicate for MinSize {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.stat()?.is_some_and(|meta| meta.len() >= self.0))
+	}
+}
+
+/// At most the given number of bytes long.
+pub struct MaxSize(pub u64);
+
+impl Predicate for MaxSize {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.stat()?.is_some_and(|meta| meta.len() <= self.0))
+	}
+}
+
+/// Owned by the given user ID.
+pub struct Owner(pub u32);
+
+impl Predicate for Owner {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.stat()?.is_some_and(|meta| meta.uid() == self.0))
+	}
+}
+
+/// Belonging to the given group ID.
+pub struct Group(pub u32);
+
+impl Predicate for Group {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.stat()?.is_some_and(|meta| meta.gid() == self.0))
+	}
+}
+
+/// Having the extended attribute, with the given value if there is one.
+pub struct Xattr {
+	pub name: String,
+	pub value: Option<Vec<u8>>,
+}
+
+impl Predicate for Xattr {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		let value = file.xattr(&self.name)?;
+		Ok(match &self.value {
+			Some(wanted) => value.as_ref() == Some(wanted),
+			None => value.is_some(),
+		})
+	}
+}
+
+/// Granted the capability of the given number, permitted or inheritable.
+/// No file is granted one past those a set can hold.
+pub struct Capability(pub u32);
+
+impl Predicate for Capability {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		let caps = file.xattr("security.capability")?;
+		let caps = caps.as_deref().and_then(caps::decode).unwrap_or(0);
+		Ok(1u64.checked_shl(self.0).is_some_and(|bit| caps & bit != 0))
+	}
+}
+
+/// Content of a MIME type matching any of the wildcard patterns, such as
+/// `image/*`, ignoring case.
+pub struct Mime(pub Vec<String>);
+
+impl Predicate for Mime {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Ok(file.mime()?.is_some_and(|mime| {
+			self.0.iter().any(|pattern| glob::matches(pattern, mime, true))
+		}))
+	}
+}
+
+/// The basename matches any of the wildcard patterns.
+pub struct Name {
+	pub patterns: Vec<String>,
+	pub icase: bool,
+}
+
+impl Predicate for Name {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+		let name = name.to_string_lossy();
+		Ok(self.patterns.iter().any(|p| glob::matches(p, &name, self.icase)))
+	}
+}
+
+/// The basename, or with `whole_path` the whole path, contains a match for
+/// any of the regular expressions.
+pub struct Regex {
+	patterns: Vec<regex::Regex>,
+	whole_path: bool,
+}
+
+impl Regex {
+	pub fn new(
+		patterns: &[String],
+		icase: bool,
+		whole_path: bool,
+	) -> Result<Self, regex::Error> {
+		let patterns = patterns
+			.iter()
+			.map(|p| {
+				regex::RegexBuilder::new(p).case_insensitive(icase).build()
+			})
+			.collect::<Result<_, _>>()?;
+		Ok(Regex { patterns, whole_path })
+	}
+}
+
+impl Predicate for Regex {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		let name = match self.whole_path {
+			true => file.path.as_os_str(),
+			false => file.path.file_name().unwrap_or(file.path.as_os_str()),
+		};
+		let name = name.to_string_lossy();
+		Ok(self.patterns.iter().any(|p| p.is_match(&name)))
+	}
+}
+
+/// A set of predicates which files must all pass. They may be tested from
+/// several threads at once.
+#[derive(Default)]
+pub struct Criteria {
+	predicates: Vec<Box<dyn Predicate + Send + Sync>>,
+}
+
+impl Criteria {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with(
+		mut self,
+		predicate: impl Predicate + Send + Sync + 'static,
+	) -> Self {
+		self.push(predicate);
+		self
+	}
+
+	pub fn push(&mut self, predicate: impl Predicate + Send + Sync + 'static) {
+		self.predicates.push(Box::new(predicate));
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.predicates.is_empty()
+	}
+
+	/// Tests the predicates in order, stopping at the first which fails or
+	/// cannot be decided.
+	pub fn test(&self, file: &File) -> io::Result<bool> {
+		for predicate in &self.predicates {
+			if !predicate.test(file)? {
+				return Ok(false);
+			}
+		}
+		Ok(true)
+	}
+
+	/// Tests the predicates in order, stopping at the first which passes.
+	/// If none does, the first which could not be decided gives its error.
+	pub fn any(&self, file: &File) -> io::Result<bool> {
+		let mut error = None;
+		for predicate in &self.predicates {
+			match predicate.test(file) {
+				Ok(true) => return Ok(true),
+				Ok(false) => {}
+				Err(err) => {
+					error.get_or_insert(err);
+				}
+			}
+		}
+		error.map_or(Ok(false), Err)
+	}
+}
+
+impl Predicate for Criteria {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		Criteria::test(self, file)
+	}
+}
+
+/// Passes any of the predicates of the criteria, rather than all of them.
+pub struct Any(pub Criteria);
+
+impl Predicate for Any {
+	fn test(&self, file: &File) -> io::Result<bool> {
+		self.0.any(file)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		env,
+		os::unix::fs::symlink,
+		process,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+
+	/// A new, empty directory for a test's files, which is left behind.
+	fn dir() -> PathBuf {
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+		let n = NEXT.fetch_add(1, Ordering::Relaxed);
+		let dir =
+			env::temp_dir().join(format!("stest-core-{}-{}", process::id(), n));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	/// Makes a file in `dir` and `links` symbolic links, each leading to the
+	/// one made before it and the first to the file, giving the last.
+	fn chain(dir: &Path, links: usize) -> PathBuf {
+		fs::write(dir.join("target"), "").unwrap();
+		let mut next = PathBuf::from("target");
+		for i in 0..links {
+			let link = PathBuf::from(format!("link{}", i));
+			symlink(&next, dir.join(&link)).unwrap();
+			next = link;
+		}
+		dir.join(next)
+	}
+
+	#[test]
+	fn resolve_follows_at_most_max_links() {
+		let dir = dir();
+		let link = chain(&dir, 3);
+		assert_eq!(resolve(&link, 3).unwrap(), dir.join("target"));
+		assert!(resolve(&link, 2).is_err());
+	}
+
+	#[test]
+	fn dereference_at_most_fails_longer_chains() {
+		let dir = dir();
+		let link = chain(&dir, 3);
+		let file = |max| File::from(&link).dereference_at_most(max);
+		assert!(file(None).is_file().unwrap());
+		assert!(file(Some(3)).is_file().unwrap());
+		assert!(file(Some(2)).is_file().is_err());
+		assert!(file(Some(2)).is_symlink().unwrap());
+	}
+
+	#[test]
+	fn dereference_at_most_stops_cycles() {
+		let dir = dir();
+		symlink("b", dir.join("a")).unwrap();
+		symlink("a", dir.join("b")).unwrap();
+		let file = File::from(dir.join("a")).dereference_at_most(Some(8));
+		assert!(file.exists().is_err());
+	}
+
+	/// Passes files whose basenames are longer than the given length.
+	struct LongerThan(usize);
+
+	impl Predicate for LongerThan {
+		fn test(&self, file: &File) -> io::Result<bool> {
+			let name = file.path().file_name().unwrap_or_default();
+			Ok(name.len() > self.0)
+		}
+	}
+
+	#[test]
+	fn custom_predicates_mix_with_built_in_ones() {
+		let dir = dir();
+		let (short, long) = (dir.join("a"), dir.join("longer"));
+		fs::write(&short, "").unwrap();
+		fs::write(&long, "").unwrap();
+		let criteria = Criteria::new().with(File::is_file).with(LongerThan(3));
+		assert!(!criteria.test(&File::from(&short)).unwrap());
+		assert!(criteria.test(&File::from(&long)).unwrap());
+		assert!(!criteria.test(&File::from(dir.join("missing"))).unwrap());
+
+		let criteria = Criteria::new()
+			.with(|file: &File| Ok(file.path().ends_with("a")))
+			.with(Any(Criteria::new().with(File::is_dir).with(LongerThan(0))));
+		assert!(criteria.test(&File::from(&short)).unwrap());
+		assert!(!criteria.test(&File::from(&long)).unwrap());
+	}
+
+	#[test]
+	fn undecided_predicates_give_their_error() {
+		let failing = |_: &File| Err(io::Error::other("undecided"));
+		let file = File::from("anything");
+		let all = Criteria::new().with(failing).with(|_: &File| Ok(false));
+		assert!(all.test(&file).is_err());
+		let all = Criteria::new().with(|_: &File| Ok(false)).with(failing);
+		assert!(!all.test(&file).unwrap());
+		let any = Criteria::new().with(failing).with(|_: &File| Ok(true));
+		assert!(any.any(&file).unwrap());
+		let any = Criteria::new().with(failing).with(|_: &File| Ok(false));
+		assert_eq!(any.any(&file).unwrap_err().to_string(), "undecided");
+		assert!(!Criteria::new().any(&file).unwrap());
+		assert!(Criteria::new().test(&file).unwrap());
+	}
+}
@@ -0,0 +1,28 @@
+//! Guessing the MIME type of a file from the first bytes of its content, as
+//! `file --mime-type` does, for the types of common media, archives and
+//! documents.
+
+/// How much of a file is read to guess its type.
+pub const SNIFF_LEN: usize = 8192;
+
+/// The type of a file starting with `head`, read from at most its first
+/// [`SNIFF_LEN`] bytes. Content without a known signature is
+/// `text/plain` if it looks like UTF-8 text, and otherwise
+/// `application/octet-stream`; an empty file is `inode/x-empty`.
+pub fn sniff(head: &[u8]) -> &'static str {
+	if head.is_empty() {
+		return "inode/x-empty";
+	}
+	if let Some(kind) = infer::get(head) {
+		return kind.mime_type();
+	}
+	// A multi-byte character may have been cut off at the end of the head.
+	let text = match std::str::from_utf8(head) {
+		Ok(_) => true,
+		Err(err) => err.error_len().is_none(),
+	};
+	match text && !head.contains(&0) {
+		true => "text/plain",
+		false => "application/octet-stream",
+	}
+}
@@ -0,0 +1,18 @@
+//! Parsing of the file sizes accepted by the size tests.
+
+/// Parses a whole number of bytes, optionally followed by `K`, `M`, `G` or
+/// `T` for that many kibibytes, mebibytes, gibibytes or tebibytes, in either
+/// case.
+pub fn parse(s: &str) -> Option<u64> {
+	let (digits, shift) = match s.char_indices().last()? {
+		(i, 'k' | 'K') => (&s[..i], 10),
+		(i, 'm' | 'M') => (&s[..i], 20),
+		(i, 'g' | 'G') => (&s[..i], 30),
+		(i, 't' | 'T') => (&s[..i], 40),
+		_ => (s, 0),
+	};
+	if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
@@ -0,0 +1,223 @@
+//! Parsing of the timestamps and durations accepted in place of reference
+//! files.
+
+use std::{
+	ops::RangeInclusive,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Parses either `@<seconds>` since the epoch, an RFC 3339 timestamp such
+/// as `2024-01-01T00:00:00Z`, or a duration before now such as `7d` or
+/// `1h30m`. The offset may be omitted, in which case UTC is assumed, and a
+/// space may stand in for the `T`.
+pub fn parse(s: &str) -> Option<SystemTime> {
+	match s.strip_prefix('@') {
+		Some(epoch) => parse_epoch(epoch),
+		None => parse_rfc3339(s)
+			.or_else(|| SystemTime::now().checked_sub(parse_duration(s)?)),
+	}
+}
+
+/// Parses a run of whole numbers each followed by a unit: `s`, `m`, `h`,
+/// `d` or `w`.
+fn parse_duration(s: &str) -> Option<Duration> {
+	let mut total = 0u64;
+	let mut rest = s;
+	while !rest.is_empty() {
+		let end = rest.find(|c: char| !c.is_ascii_digit())?;
+		let n = digits(&rest[..end])?;
+		let unit = match rest[end..].chars().next()? {
+			's' => 1,
+			'm' => 60,
+			'h' => 60 * 60,
+			'd' => 24 * 60 * 60,
+			'w' => 7 * 24 * 60 * 60,
+			_ => return None,
+		};
+		total = total.checked_add(n.checked_mul(unit)?)?;
+		rest = &rest[end + 1..];
+	}
+	match s.is_empty() {
+		true => None,
+		false => Some(Duration::from_secs(total)),
+	}
+}
+
+/// Drops the fractional part of a time since the epoch.
+pub fn truncate(time: SystemTime) -> SystemTime {
+	match time.duration_since(UNIX_EPOCH) {
+		Ok(since) => UNIX_EPOCH + Duration::from_secs(since.as_secs()),
+		// Before the epoch, truncating rounds towards it.
+		Err(err) => UNIX_EPOCH - Duration::from_secs(err.duration().as_secs()),
+	}
+}
+
+fn parse_epoch(s: &str) -> Option<SystemTime> {
+	let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+	let (negative, secs) = match secs.strip_prefix('-') {
+		Some(secs) => (true, secs),
+		None => (false, secs),
+	};
+	let time = Duration::new(digits(secs)?, nanos(frac)?);
+	match negative {
+		true => UNIX_EPOCH.checked_sub(time),
+		false => UNIX_EPOCH.checked_add(time),
+	}
+}
+
+fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+	let (date, time) = s.split_once(['T', 't', ' '])?;
+	let mut date = date.splitn(3, '-');
+	let year = digits(date.next().filter(|y| y.len() == 4)?)? as i64;
+	let month = field(date.next(), 1..=12)?;
+	let day = field(date.next(), 1..=31)?;
+
+	let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+		Some(i) => (&time[..i], offset(&time[i..])?),
+		None => (time, 0),
+	};
+	let (time, frac) = time.split_once('.').unwrap_or((time, ""));
+	let mut time = time.splitn(3, ':');
+	let hour = field(time.next(), 0..=23)?;
+	let minute = field(time.next(), 0..=59)?;
+	// Leap seconds are folded into the following second.
+	let second = field(time.next(), 0..=60)?;
+
+	if day > days_in_month(year, month) {
+		return None;
+	}
+	let secs = days_from_civil(year, month, day) * 86400
+		+ hour * 3600
+		+ minute * 60
+		+ second
+		- offset;
+	let nanos = nanos(frac)?;
+	match u64::try_from(secs) {
+		Ok(secs) => UNIX_EPOCH.checked_add(Duration::new(secs, nanos)),
+		Err(_) => UNIX_EPOCH
+			.checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+			.checked_add(Duration::from_nanos(nanos.into())),
+	}
+}
+
+/// Parses `Z` or `±HH:MM` into seconds east of UTC.
+fn offset(s: &str) -> Option<i64> {
+	if s.eq_ignore_ascii_case("z") {
+		return Some(0);
+	}
+	let sign = if s.starts_with('-') { -1 } else { 1 };
+	let (hours, minutes) = s[1..].split_once(':')?;
+	Some(
+		sign * (field(Some(hours), 0..=23)? * 3600
+			+ field(Some(minutes), 0..=59)? * 60),
+	)
+}
+
+fn field(s: Option<&str>, range: RangeInclusive<i64>) -> Option<i64> {
+	let s = s.filter(|s| s.len() == 2)?;
+	Some(digits(s)? as i64).filter(|n| range.contains(n))
+}
+
+fn digits(s: &str) -> Option<u64> {
+	match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+		true => s.parse().ok(),
+		false => None,
+	}
+}
+
+/// Parses the digits after a decimal point as nanoseconds.
+fn nanos(frac: &str) -> Option<u32> {
+	if frac.is_empty() {
+		return Some(0);
+	}
+	let frac = &frac[..frac.len().min(9)];
+	Some((digits(frac)? * 10u64.pow(9 - frac.len() as u32)) as u32)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+	match month {
+		2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+/// Counts the days from the epoch to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let yoe = year - era * 400;
+	let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn at(secs: u64, nanos: u32) -> Option<SystemTime> {
+		Some(UNIX_EPOCH + Duration::new(secs, nanos))
+	}
+
+	#[test]
+	fn epoch() {
+		assert_eq!(parse("@0"), at(0, 0));
+		assert_eq!(parse("@1700000000.5"), at(1_700_000_000, 500_000_000));
+		assert_eq!(
+			parse("@-1"),
+			UNIX_EPOCH.checked_sub(Duration::from_secs(1))
+		);
+		assert_eq!(parse("@"), None);
+		assert_eq!(parse("@1e3"), None);
+	}
+
+	#[test]
+	fn rfc3339() {
+		assert_eq!(parse("1970-01-01T00:00:00Z"), at(0, 0));
+		assert_eq!(parse("2024-01-01T00:00:00Z"), at(1_704_067_200, 0));
+		assert_eq!(parse("2024-01-01 00:00:00"), at(1_704_067_200, 0));
+		assert_eq!(parse("2024-01-01T01:30:00+01:30"), at(1_704_067_200, 0));
+		assert_eq!(parse("2023-12-31T19:00:00-05:00"), at(1_704_067_200, 0));
+		assert_eq!(
+			parse("2024-02-29T12:00:00.25Z"),
+			at(1_709_208_000, 250_000_000)
+		);
+		assert_eq!(parse("1969-12-31T23:59:59Z"), parse("@-1"));
+	}
+
+	#[test]
+	fn rfc3339_rejects_impossible_dates() {
+		assert_eq!(parse("2023-02-29T00:00:00Z"), None);
+		assert_eq!(parse("2024-13-01T00:00:00Z"), None);
+		assert_eq!(parse("2024-01-01T24:00:00Z"), None);
+		assert_eq!(parse("24-01-01T00:00:00Z"), None);
+		assert_eq!(parse("2024-1-01T00:00:00Z"), None);
+		assert_eq!(parse("2024-01-01"), None);
+	}
+
+	#[test]
+	fn durations() {
+		assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+		assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+		assert_eq!(
+			parse_duration("2w1d"),
+			Some(Duration::from_secs(15 * 86400))
+		);
+		assert_eq!(parse_duration(""), None);
+		assert_eq!(parse_duration("5"), None);
+		assert_eq!(parse_duration("5y"), None);
+		assert_eq!(parse_duration("h"), None);
+		let ago = parse("1d").unwrap();
+		let elapsed = SystemTime::now().duration_since(ago).unwrap();
+		assert!(elapsed >= Duration::from_secs(86400));
+	}
+
+	#[test]
+	fn truncate_drops_fractions() {
+		assert_eq!(truncate(at(5, 999_999_999).unwrap()), at(5, 0).unwrap());
+		let before = UNIX_EPOCH - Duration::new(1, 500_000_000);
+		assert_eq!(truncate(before), UNIX_EPOCH - Duration::from_secs(1));
+	}
+}
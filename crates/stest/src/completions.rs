@@ -0,0 +1,187 @@
+//! Shell completion scripts, generated from the option table.
+
+use crate::options::{Arg, OPTIONS, Opt};
+
+/// What an option's argument should be completed as.
+enum Complete {
+	Nothing,
+	Files,
+	Variables,
+}
+
+fn complete(opt: &Opt) -> Option<Complete> {
+	let hint = match opt.arg {
+		Arg::None => return None,
+		// Either end of a range may be given as a file.
+		Arg::Pair(..) => return Some(Complete::Files),
+		Arg::Required(hint) | Arg::Optional(hint) | Arg::Repeated(hint) => hint,
+	};
+	Some(match hint {
+		"file" => Complete::Files,
+		"var" => Complete::Variables,
+		_ => Complete::Nothing,
+	})
+}
+
+/// The completion script for `shell`, if it is one of the supported ones.
+pub fn script(shell: &str) -> Option<String> {
+	match shell {
+		"bash" => Some(bash()),
+		"zsh" => Some(zsh()),
+		"fish" => Some(fish()),
+		_ => None,
+	}
+}
+
+fn names(opt: &Opt) -> Vec<String> {
+	let mut names = Vec::new();
+	if !opt.short.is_empty() {
+		names.push(format!("-{}", opt.short));
+	}
+	names.push(format!("--{}", opt.long));
+	names
+}
+
+fn bash() -> String {
+	let takes = |kind: fn(&Complete) -> bool| {
+		OPTIONS
+			.iter()
+			.filter(|opt| complete(opt).as_ref().is_some_and(kind))
+			.flat_map(names)
+			.collect::<Vec<_>>()
+			.join("|")
+	};
+	let words = OPTIONS.iter().flat_map(names).collect::<Vec<_>>().join(" ");
+	format!(
+		r#"_stest() {{
+	local cur=${{COMP_WORDS[COMP_CWORD]}}
+	local prev=${{COMP_WORDS[COMP_CWORD-1]}}
+	case $prev in
+	{files})
+		COMPREPLY=($(compgen -f -- "$cur"))
+		return
+		;;
+	{variables})
+		COMPREPLY=($(compgen -v -- "$cur"))
+		return
+		;;
+	{nothing})
+		return
+		;;
+	esac
+	if [[ $cur == -* ]]; then
+		COMPREPLY=($(compgen -W '{words}' -- "$cur"))
+	else
+		COMPREPLY=($(compgen -f -- "$cur"))
+	fi
+}}
+complete -F _stest stest
+"#,
+		files = takes(|c| matches!(c, Complete::Files)),
+		variables = takes(|c| matches!(c, Complete::Variables)),
+		nothing = takes(|c| matches!(c, Complete::Nothing)),
+	)
+}
+
+fn zsh() -> String {
+	let escape = |s: &str| {
+		s.replace('\'', r"'\''").replace('[', r"\[").replace(']', r"\]")
+	};
+	let mut script = String::from("#compdef stest\n\n_arguments -s \\\n");
+	for opt in OPTIONS {
+		let desc = escape(opt.desc);
+		let action = match complete(opt) {
+			None => String::new(),
+			Some(complete) => {
+				let action = match complete {
+					Complete::Files => "_files",
+					Complete::Variables => "_parameters",
+					Complete::Nothing => " ",
+				};
+				let hint = match opt.arg {
+					Arg::Optional(hint) => format!("::{}", hint),
+					Arg::Required(hint) | Arg::Repeated(hint) => {
+						format!(":{}", hint)
+					}
+					Arg::Pair(first, second) => {
+						format!(":{}:{}:{}", first, action, second)
+					}
+					Arg::None => unreachable!(),
+				};
+				format!("{}:{}", hint, action)
+			}
+		};
+		let repeat = if matches!(opt.arg, Arg::Repeated(_)) { "*" } else { "" };
+		let suffix = match opt.arg {
+			Arg::Required(_) | Arg::Repeated(_) if opt.short.is_empty() => "=",
+			// Both arguments of a pair are separate words.
+			Arg::Pair(..) => "",
+			Arg::Optional(_) if opt.short.is_empty() => "=-",
+			_ => "",
+		};
+		for name in names(opt) {
+			script.push_str(&format!(
+				"\t'{}{}{}[{}]{}' \\\n",
+				repeat, name, suffix, desc, action
+			));
+		}
+	}
+	script.push_str("\t'*:file:_files'\n");
+	script
+}
+
+fn fish() -> String {
+	let mut script = String::new();
+	for opt in OPTIONS {
+		let mut line = String::from("complete -c stest");
+		if !opt.short.is_empty() {
+			line.push_str(&format!(" -s {}", opt.short));
+		}
+		line.push_str(&format!(" -l {}", opt.long));
+		match complete(opt) {
+			None => {}
+			Some(Complete::Files) => line.push_str(" -rF"),
+			Some(Complete::Variables) => line.push_str(" -xa '(set --names)'"),
+			Some(Complete::Nothing) => line.push_str(" -x"),
+		}
+		line.push_str(&format!(" -d '{}'", opt.desc.replace('\'', r"\'")));
+		script.push_str(&line);
+		script.push('\n');
+	}
+	script
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_script_names_every_option() {
+		for shell in ["bash", "zsh", "fish"] {
+			let script = script(shell).unwrap();
+			for opt in OPTIONS {
+				let long = match shell {
+					"fish" => format!(" -l {} ", opt.long),
+					_ => format!("--{}", opt.long),
+				};
+				assert!(script.contains(&long), "{} lacks {}", shell, long);
+			}
+		}
+		assert!(script("csh").is_none());
+	}
+
+	#[test]
+	fn bash_completes_arguments_by_kind() {
+		let script = bash();
+		let case = |pattern: &str| {
+			script
+				.lines()
+				.find(|line| line.ends_with(')') && line.contains(pattern))
+				.unwrap()
+				.to_string()
+		};
+		assert!(case("--probe").contains("--mtime-between"));
+		assert!(case("--paths-from-env").starts_with("\t--paths-from-env"));
+		assert!(case("--jobs").contains("--sort"));
+	}
+}
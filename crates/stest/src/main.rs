@@ -1,184 +1,911 @@
 use std::{
-	cmp::{Ordering, PartialOrd},
-	ffi::OsStr,
-	fs::{FileType, Metadata},
-	io,
-	os::unix::fs::{FileTypeExt, MetadataExt},
-	path::Path,
+	borrow::Cow,
+	collections::{HashMap, HashSet},
+	env,
+	ffi::{CStr, CString, OsStr, OsString},
+	fmt::{self, Display},
+	fs,
+	io::{self, BufRead, BufWriter, Write},
+	mem,
+	ops::RangeInclusive,
+	os::{
+		fd::{FromRawFd, RawFd},
+		unix::{
+			ffi::OsStrExt,
+			fs::{FileTypeExt, MetadataExt},
+		},
+	},
+	path::{self, Component, Path, PathBuf},
 	process::exit,
+	sync::{
+		Mutex,
+		PoisonError,
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+	},
+	thread,
+	time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use getopts::{Matches, Options};
+use ignore::WalkBuilder;
+use stest_core::{
+	Any,
+	Between,
+	Capability,
+	Criteria,
+	File,
+	Group,
+	MaxSize,
+	Mime,
+	MinSize,
+	Name,
+	Newer,
+	NewerThan,
+	Older,
+	OlderThan,
+	Owner,
+	Regex,
+	SameFile,
+	Timestamp,
+	Xattr,
+	caps,
+	glob,
+	size,
+	time,
+};
 use walkdir::WalkDir;
 
-static mut MATCH: u8 = 0;
+mod completions;
+mod options;
+mod watch;
+
+/// A test needing nothing but the file itself, by flag, which is its long
+/// name for those without a letter, and long name.
+type Test = (&'static str, &'static str, fn(&File) -> io::Result<bool>);
+
+const TESTS: &[Test] = &[
+	("a", "hidden", File::is_hidden),
+	("b", "block", File::is_block),
+	("c", "char", File::is_char),
+	("d", "dir", File::is_dir),
+	("e", "exists", File::exists),
+	("f", "file", File::is_file),
+	("g", "has-setgid", File::has_setgid),
+	("h", "symlink", File::is_symlink),
+	("k", "sticky", File::is_sticky),
+	("p", "pipe", File::is_pipe),
+	("r", "readable", File::is_readable),
+	("s", "non-empty", File::is_non_empty),
+	("u", "has-setuid", File::has_setuid),
+	("w", "writable", File::is_writable),
+	("x", "executable", File::is_executable),
+	("G", "group-owned", File::is_group_owned),
+	("O", "owned", File::is_owned),
+	("S", "socket", File::is_socket),
+	("dangling", "dangling", File::is_dangling),
+	("has-caps", "has-caps", File::has_caps),
+];
+
+struct Cli {
+	program: String,
+	flags: Matches,
+	criteria: Criteria,
+	since: Option<SystemTime>,
+	deref_max: Option<usize>,
+	seconds: bool,
+	timestamp: Timestamp,
+	mode_bits: bool,
+	/// Whether tests follow symbolic links, and `-l` descends through them,
+	/// as chosen by the last of `-L` and `-P`.
+	follow: Option<bool>,
+	/// The depths below each directory given which `-l` tests, itself being
+	/// at depth 0.
+	depth: RangeInclusive<usize>,
+	/// The patterns of `--exclude`, which `-l` does not test or descend
+	/// into the matches of.
+	excludes: Vec<String>,
+	jobs: usize,
+	/// The order of `--sort`, and whether `--reverse` flips it.
+	sort: Option<(Sort, bool)>,
+	/// Under `--sort`, the files to print and the names to print them as,
+	/// held back until every file is found.
+	sorted: Mutex<Vec<(File, OsString)>>,
+	format: Format,
+	/// Under `--watch`, the files printed which have not since been printed
+	/// as removed.
+	printed: Mutex<HashSet<PathBuf>>,
+	/// How many files have passed, for `-C` and the exit status.
+	passed: AtomicUsize,
+	/// Whether a file could not be read, for `--strict`.
+	failed: AtomicBool,
+	/// Under `--dedup`, the basenames of the files printed so far.
+	seen: Mutex<HashSet<OsString>>,
+	/// The directory of `--relative-to`, canonicalized.
+	relative_to: Option<PathBuf>,
+	/// Whether stest acts as the original did, under `--compat` or
+	/// `POSIXLY_CORRECT`.
+	compat: bool,
+	out: Mutex<Box<dyn Write + Send>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Text,
+	/// One array of every file, printed once all are found.
+	Json,
+	/// One object per line.
+	Jsonl,
+}
 
-#[derive(Clone)]
-struct File {
-	path: Box<Path>,
+#[derive(Clone, Copy)]
+enum Sort {
+	/// Most recently modified first, as `ls -t` does.
+	Mtime,
+	Name,
+	/// Largest first, as `ls -S` does.
+	Size,
 }
 
-impl File {
-	pub fn new(path: Box<Path>) -> Self {
-		File { path }
+impl Cli {
+	fn file(&self, path: impl AsRef<OsStr>) -> File {
+		File::from(path)
+			.dereference_at_most(self.deref_max)
+			.whole_seconds(self.seconds)
+			.compare_time(self.timestamp)
+			.mode_bits(self.mode_bits)
+			.follow_links(self.follow != Some(false))
+	}
+
+	fn test(&self, file: &File) -> bool {
+		let passed = match self.criteria.test(file) {
+			Ok(passed) => passed,
+			Err(err) => {
+				self.error(file.path(), &err);
+				false
+			}
+		};
+		(passed != self.flags.opt_present("v"))
+			&& self.since.is_none_or(|since| {
+				file.modified().ok().flatten().is_some_and(|m| m > since)
+			})
+	}
+
+	fn report(&self, file: File, name: &OsStr) {
+		if !self.first(&file) {
+			return;
+		}
+		self.matched();
+		self.passed.fetch_add(1, Ordering::Relaxed);
+		if self.flags.opts_present_any(["q", "C"]) {
+			return;
+		}
+		if self.flags.opt_present("watch") {
+			let mut printed =
+				self.printed.lock().unwrap_or_else(PoisonError::into_inner);
+			printed.insert(file.path().to_path_buf());
+		}
+		if self.flags.opt_present("removals") {
+			self.print("+", &file, name);
+			return;
+		}
+		if self.sort.is_some() || self.format == Format::Json {
+			let mut sorted =
+				self.sorted.lock().unwrap_or_else(PoisonError::into_inner);
+			sorted.push((file, name.to_os_string()));
+			return;
+		}
+		self.print("", &file, name);
 	}
 
-	fn meta(&self) -> io::Result<Metadata> {
-		self.path.metadata()
+	/// Prints that a file printed under `--watch` no longer passes, if
+	/// `--removals` was given.
+	fn removed(&self, path: &Path) {
+		if self.flags.opt_present("removals") {
+			self.write_name("-", &self.name(path));
+		}
 	}
 
-	fn file_type(&self) -> io::Result<FileType> {
-		self.meta().map(|meta| meta.file_type())
+	/// How the file at `path` is printed, as given unless `--canonicalize`,
+	/// `--absolute` or `--relative-to` say otherwise. A file which cannot be
+	/// canonicalized, such as a dangling link, is made absolute instead.
+	fn name(&self, path: &Path) -> OsString {
+		let canonical = match self.flags.opt_present("canonicalize") {
+			true => fs::canonicalize(path).ok(),
+			false => None,
+		};
+		let path = match canonical {
+			Some(path) => path,
+			None if self.flags.opt_present("canonicalize")
+				|| self.flags.opt_present("absolute")
+				|| self.relative_to.is_some() =>
+			{
+				path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+			}
+			None => return path.as_os_str().to_os_string(),
+		};
+		match &self.relative_to {
+			Some(base) => relative(&path, base).into_os_string(),
+			None => path.into_os_string(),
+		}
 	}
 
-	fn mode(&self) -> io::Result<u32> {
-		self.meta().map(|meta| meta.mode())
+	/// Prints a file which passes after `prefix`, as `name` or as an object.
+	fn print(&self, prefix: &str, file: &File, name: &OsStr) {
+		match self.format {
+			Format::Text => self.write_name(prefix, name),
+			Format::Json | Format::Jsonl => self.write_line(format_args!(
+				"{}{}",
+				prefix,
+				object(file, name)
+			)),
+		}
 	}
 
-	fn is_hidden(&self) -> bool {
-		self.path
-			.file_name()
-			.is_some_and(|name| name.to_string_lossy().starts_with("."))
+	/// Whether no file with the same basename has been printed before, or
+	/// `--dedup` was not given.
+	fn first(&self, file: &File) -> bool {
+		if !self.flags.opt_present("dedup") {
+			return true;
+		}
+		let path = file.path();
+		let name = path.file_name().unwrap_or(path.as_os_str());
+		let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+		seen.insert(name.to_os_string())
 	}
 
-	fn is_block(&self) -> bool {
-		self.file_type().as_ref().is_ok_and(FileTypeExt::is_block_device)
+	/// Prints the files held back by `--sort` or `--format json`, in order,
+	/// or how many files passed under `-C`.
+	fn flush(&self) {
+		if self.flags.opt_present("C") && !self.flags.opt_present("q") {
+			let passed = self.passed.load(Ordering::Relaxed);
+			self.write_line(format_args!("{}", passed));
+			return;
+		}
+		let mut sorted = mem::take(
+			&mut *self.sorted.lock().unwrap_or_else(PoisonError::into_inner),
+		);
+		self.sort(&mut sorted, |(file, name)| (file, name));
+		if self.format == Format::Json {
+			let objects = sorted
+				.iter()
+				.map(|(file, name)| object(file, name))
+				.collect::<Vec<_>>();
+			self.write_line(format_args!("[{}]", objects.join(",")));
+			return;
+		}
+		for (file, name) in sorted {
+			self.print("", &file, &name);
+		}
 	}
 
-	fn is_char(&self) -> bool {
-		self.file_type().as_ref().is_ok_and(FileTypeExt::is_char_device)
+	/// Orders `entries`, each a file and the name it is printed as, by
+	/// `--sort`. Ties, and files whose key cannot be read, keep the order
+	/// they were found in.
+	fn sort<T>(
+		&self,
+		entries: &mut [T],
+		entry: impl Fn(&T) -> (&File, &OsStr),
+	) {
+		let Some((sort, reverse)) = self.sort else {
+			return;
+		};
+		entries.sort_by(|a, b| {
+			let ((a, a_name), (b, b_name)) = (entry(a), entry(b));
+			let order = match sort {
+				Sort::Mtime => {
+					let modified = |file: &File| file.modified().ok().flatten();
+					modified(b).cmp(&modified(a))
+				}
+				Sort::Name => a_name.cmp(b_name),
+				Sort::Size => {
+					let size =
+						|file: &File| file.metadata().ok().map(|m| m.len());
+					size(b).cmp(&size(a))
+				}
+			};
+			if reverse { order.reverse() } else { order }
+		});
 	}
 
-	fn is_dir(&self) -> bool {
-		self.path.is_dir()
+	fn write_line(&self, line: fmt::Arguments) {
+		let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+		let end = if self.flags.opt_present("print0") { '\0' } else { '\n' };
+		if let Err(err) = write!(out, "{}{}", line, end) {
+			eprintln!("{}: {}", self.program, err);
+			exit(2);
+		}
 	}
 
-	fn exists(&self) -> bool {
-		self.path.try_exists().is_ok_and(|x| x)
+	/// Prints `name` after `prefix`, quoted under `--quote` and otherwise
+	/// as its own bytes, which need not be UTF-8, unless in compat mode,
+	/// where the original stest printed them as UTF-8, replacing what is
+	/// not.
+	fn write_name(&self, prefix: &str, name: &OsStr) {
+		if self.flags.opt_present("quote") {
+			let name = name.to_string_lossy();
+			self.write_line(format_args!("{}{}", prefix, quote(&name)));
+			return;
+		}
+		if self.compat {
+			let name = name.to_string_lossy();
+			self.write_line(format_args!("{}{}", prefix, name));
+			return;
+		}
+		let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+		let end = if self.flags.opt_present("print0") { b'\0' } else { b'\n' };
+		let written = out
+			.write_all(prefix.as_bytes())
+			.and_then(|()| out.write_all(name.as_bytes()))
+			.and_then(|()| out.write_all(&[end]));
+		if let Err(err) = written {
+			eprintln!("{}: {}", self.program, err);
+			exit(2);
+		}
 	}
 
-	fn is_file(&self) -> bool {
-		self.path.is_file()
+	/// Writes out what has been printed so far, which is buffered.
+	fn flush_out(&self) {
+		let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Err(err) = out.flush() {
+			eprintln!("{}: {}", self.program, err);
+			exit(2);
+		}
 	}
 
-	fn has_setgid(&self) -> bool {
-		self.mode().is_ok_and(|mode| mode & 0o2000 != 0)
+	fn matched(&self) {
+		// The original stest stopped at the first file to pass; otherwise
+		// every file is still tested, so that any errors are reported.
+		if self.flags.opt_present("q") && self.compat {
+			exit(0)
+		}
 	}
 
-	fn is_symlink(&self) -> bool {
-		self.path.is_symlink()
+	fn display<'a>(&self, name: &'a OsStr) -> Cow<'a, str> {
+		let name = name.to_string_lossy();
+		match self.flags.opt_present("quote") {
+			true => Cow::Owned(quote(&name).into_owned()),
+			false => name,
+		}
 	}
 
-	fn is_pipe(&self) -> bool {
-		self.file_type().as_ref().is_ok_and(FileTypeExt::is_fifo)
+	/// Notes an error reading `path`, printing it under `--verbose` or
+	/// `--strict`, or giving up on the whole run under `--fail-fast`.
+	fn error(&self, path: &Path, err: &dyn Display) {
+		if self.flags.opt_present("fail-fast") {
+			self.abort(path, err);
+		}
+		self.failed.store(true, Ordering::Relaxed);
+		if self.flags.opts_present_any(["verbose", "strict"]) {
+			eprintln!("{}: {}: {}", self.program, path.to_string_lossy(), err);
+		}
 	}
 
-	fn is_readable(&self) -> bool {
-		self.mode().is_ok_and(|mode| mode & 0o0444 != 0)
+	/// Gives up on the whole run under `--fail-fast`, which treats every
+	/// error other than a missing file as fatal.
+	fn abort(&self, path: &Path, err: &dyn Display) -> ! {
+		self.flush_out();
+		eprintln!("{}: {}: {}", self.program, path.to_string_lossy(), err);
+		exit(2);
 	}
 
-	fn has_setuid(&self) -> bool {
-		self.mode().is_ok_and(|mode| mode & 0o4000 != 0)
+	fn walk(&self, dir: File) {
+		let icase = self.flags.opt_present("icase");
+		let paths: Box<dyn Iterator<Item = PathBuf>> = match self
+			.flags
+			.opt_present("respect-gitignore")
+		{
+			true => Box::new(self.walk_gitignored(dir.path())),
+			false => Box::new(
+				WalkDir::new(dir.path())
+					.follow_links(self.follow == Some(true))
+					.min_depth(*self.depth.start())
+					.max_depth(*self.depth.end())
+					.into_iter()
+					.filter_entry(|entry| {
+						entry.depth() == 0
+							|| !excluded(&self.excludes, icase, entry.path())
+					})
+					.filter_map(|entry| match entry {
+						Ok(entry) => Some(entry),
+						Err(err) => {
+							self.error(err.path().unwrap_or(dir.path()), &err);
+							None
+						}
+					})
+					.map(walkdir::DirEntry::into_path),
+			),
+		};
+		let files = paths.map(|path| self.file(path));
+		let files: Box<dyn Iterator<Item = File>> = match self.jobs {
+			1 => Box::new(files.filter(|file| self.test(file))),
+			jobs => Box::new(self.test_all(files.collect(), jobs).into_iter()),
+		};
+		if !self.flags.opt_present("group-by-dir")
+			|| self.flags.opts_present_any(["q", "C"])
+		{
+			files.for_each(|file| {
+				let path = self.name(file.path());
+				self.report(file, &path)
+			});
+			return;
+		}
+
+		// Entries of a directory are not necessarily walked contiguously, so
+		// the groups are gathered first, in the order they are found.
+		let mut groups: Vec<(Box<Path>, Vec<File>)> = Vec::new();
+		let mut index = HashMap::new();
+		for file in files.filter(|file| self.first(file)) {
+			let parent: Box<Path> =
+				Box::from(file.path().parent().unwrap_or(Path::new("")));
+			let i = *index.entry(parent.clone()).or_insert_with(|| {
+				groups.push((parent, Vec::new()));
+				groups.len() - 1
+			});
+			groups[i].1.push(file);
+		}
+
+		let header = self.flags.opt_str("group-header");
+		let header = header.as_deref().unwrap_or("{}");
+		for (parent, mut files) in groups {
+			self.matched();
+			self.passed.fetch_add(files.len(), Ordering::Relaxed);
+			self.sort(&mut files, |file| {
+				let path = file.path();
+				(file, path.file_name().unwrap_or(path.as_os_str()))
+			});
+			if !header.is_empty() {
+				let parent = self.name(&parent);
+				let parent = self.display(&parent);
+				self.write_line(format_args!(
+					"{}",
+					header.replace("{}", &parent)
+				));
+			}
+			for file in files {
+				let path = file.path();
+				let name = path.file_name().unwrap_or(path.as_os_str());
+				self.write_name("\t", name);
+			}
+		}
 	}
 
-	fn is_non_empty(&self) -> bool {
-		self.meta().is_ok_and(|meta| meta.len() > 0)
+	/// The files under `dir` for `-l`, leaving out those git would ignore by
+	/// the `.gitignore` files of `dir`, its parents and its subdirectories,
+	/// the repository's own exclude file and the user's global one, and the
+	/// `.git` directory itself.
+	fn walk_gitignored(&self, dir: &Path) -> impl Iterator<Item = PathBuf> {
+		let (excludes, icase) =
+			(self.excludes.clone(), self.flags.opt_present("icase"));
+		let min_depth = *self.depth.start();
+		WalkBuilder::new(dir)
+			.standard_filters(false)
+			.git_ignore(true)
+			.git_global(true)
+			.git_exclude(true)
+			.parents(true)
+			.require_git(false)
+			.follow_links(self.follow == Some(true))
+			.max_depth(Some(*self.depth.end()))
+			.filter_entry(move |entry| {
+				let git = entry.file_name() == ".git"
+					&& entry.file_type().is_some_and(|kind| kind.is_dir());
+				entry.depth() == 0
+					|| !git && !excluded(&excludes, icase, entry.path())
+			})
+			.build()
+			.filter_map(move |entry| match entry {
+				Ok(entry) => Some(entry),
+				Err(err) => {
+					self.error(dir, &err);
+					None
+				}
+			})
+			.filter(move |entry| entry.depth() >= min_depth)
+			.map(ignore::DirEntry::into_path)
 	}
 
-	fn is_writable(&self) -> bool {
-		self.mode().is_ok_and(|mode| mode & 0o0222 != 0)
+	/// The files which pass, tested on `jobs` threads, which each take the
+	/// next file left until there are none. They stay in the order given.
+	fn test_all(&self, files: Vec<File>, jobs: usize) -> Vec<File> {
+		let next = AtomicUsize::new(0);
+		let mut passed = vec![false; files.len()];
+		thread::scope(|scope| {
+			let workers = (0..jobs.min(files.len()))
+				.map(|_| {
+					scope.spawn(|| {
+						let mut found = Vec::new();
+						loop {
+							let i = next.fetch_add(1, Ordering::Relaxed);
+							let Some(file) = files.get(i) else {
+								return found;
+							};
+							if self.test(file) {
+								found.push(i);
+							}
+						}
+					})
+				})
+				.collect::<Vec<_>>();
+			for worker in workers {
+				let found = worker.join().unwrap_or_else(|err| {
+					std::panic::resume_unwind(err);
+				});
+				for i in found {
+					passed[i] = true;
+				}
+			}
+		});
+		files
+			.into_iter()
+			.zip(passed)
+			.filter_map(|(file, passed)| passed.then_some(file))
+			.collect()
 	}
 
-	fn is_executable(&self) -> bool {
-		self.mode().is_ok_and(|mode| mode & 0o0111 != 0)
+	/// Prints the result of every test against `file`, regardless of which
+	/// tests were requested.
+	fn probe(&self, file: &File) {
+		let print = |flag: &str, name, result: Option<io::Result<bool>>| {
+			let result = match result {
+				Some(Ok(passed)) => passed.to_string(),
+				Some(Err(err)) => format!("error: {}", err),
+				None => "-".to_string(),
+			};
+			// Tests without a letter of their own are given by name.
+			let dash = if flag.len() == 1 { "-" } else { "--" };
+			self.write_line(format_args!(
+				"{}{}\t{}\t{}",
+				dash, flag, name, result
+			));
+		};
+		for (flag, name, test) in TESTS {
+			print(flag, name, Some(test(file)));
+		}
+		for (flag, name, newer) in
+			&[("n", "newer", true), ("o", "older", false)]
+		{
+			let result = self.flags.opt_str(flag).map(|arg| {
+				let mut criteria = Criteria::new();
+				let reference = self.file(&arg);
+				let (seconds, compat) = (self.seconds, self.compat);
+				compare(
+					&mut criteria,
+					&arg,
+					*newer,
+					reference,
+					seconds,
+					compat,
+				);
+				criteria.test(file)
+			});
+			print(flag, name, result);
+		}
+		if let Err(err) = file.metadata() {
+			self.write_line(format_args!("error\t{}", err));
+		}
+	}
+
+	/// Looks each command name up in `$PATH`, like `command -v`, reporting the
+	/// first existing file which passes, or every one of them if `all` is set.
+	fn which(&self, names: &[String], all: bool) {
+		let path = env::var_os("PATH").unwrap_or_default();
+		for name in names {
+			for dir in env::split_paths(&path) {
+				let file = self.file(dir.join(name));
+				if file.exists().unwrap_or(false) && self.test(&file) {
+					let path = self.name(file.path());
+					self.report(file, &path);
+					if !all {
+						break;
+					}
+				}
+			}
+		}
 	}
-}
 
-impl<T: AsRef<OsStr>> From<T> for File {
-	fn from(value: T) -> Self {
-		File::new(Box::from(Path::new(&value)))
+	/// Tests the files in each directory of the PATH-like variable `var`,
+	/// reporting the basename of each which passes, the first found for
+	/// each name. Under `sorted` they are reported in order of name, as
+	/// `sort -u` would give them, and otherwise in order of directory.
+	fn scan_env(&self, var: &str, sorted: bool) {
+		let Some(value) = env::var_os(var) else {
+			return;
+		};
+		let mut seen = HashSet::new();
+		let mut found = Vec::new();
+		for dir in env::split_paths(&value) {
+			// A directory which does not exist has nothing to report.
+			let entries = match dir.read_dir() {
+				Ok(entries) => entries,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+				Err(err) => {
+					self.error(&dir, &err);
+					continue;
+				}
+			};
+			let mut entries = entries
+				.filter_map(|entry| match entry {
+					Ok(entry) => Some(entry),
+					Err(err) => {
+						self.error(&dir, &err);
+						None
+					}
+				})
+				.collect::<Vec<_>>();
+			entries.sort_by_key(|entry| entry.file_name());
+			for entry in entries {
+				let file = self.file(entry.path());
+				if self.test(&file) && seen.insert(entry.file_name()) {
+					match sorted {
+						true => found.push((entry.file_name(), file)),
+						false => self.report(file, &entry.file_name()),
+					}
+				}
+			}
+		}
+		found.sort_by(|(a, _), (b, _)| a.cmp(b));
+		for (name, file) in found {
+			self.report(file, &name);
+		}
 	}
 }
 
-impl PartialEq for File {
-	fn eq(&self, other: &Self) -> bool {
-		self.partial_cmp(other) == Some(Ordering::Equal)
+/// The path to `path` from `base`, which must be canonical so that the `..`
+/// climbing out of it are not led astray by symbolic links. Both are
+/// absolute.
+fn relative(path: &Path, base: &Path) -> PathBuf {
+	let (mut path, mut base) = (path.components(), base.components());
+	let mut relative = PathBuf::new();
+	loop {
+		match (path.clone().next(), base.next()) {
+			(Some(a), Some(b)) if relative.as_os_str().is_empty() && a == b => {
+				path.next();
+			}
+			(_, Some(_)) => relative.push(Component::ParentDir),
+			(_, None) => break,
+		}
+	}
+	relative.extend(path);
+	match relative.as_os_str().is_empty() {
+		true => PathBuf::from("."),
+		false => relative,
 	}
 }
 
-impl PartialOrd for File {
-	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.meta().ok().and_then(|meta| {
-			other.meta().ok().and_then(|other_meta| {
-				meta.mtime().partial_cmp(&other_meta.mtime())
-			})
-		})
+/// Whether the basename of `path` matches any of the patterns of
+/// `--exclude`.
+fn excluded(excludes: &[String], icase: bool, path: &Path) -> bool {
+	let name = path.file_name().unwrap_or(path.as_os_str());
+	let name = name.to_string_lossy();
+	excludes.iter().any(|pattern| glob::matches(pattern, &name, icase))
+}
+
+/// Adds the test of `-n`, or with `newer` unset of `-o`: against the time
+/// `arg` gives if it is a timestamp or duration, and otherwise against the
+/// modification time of `reference`, the file it names. The original stest,
+/// as under `compat`, only took files.
+fn compare(
+	criteria: &mut Criteria,
+	arg: &str,
+	newer: bool,
+	reference: File,
+	seconds: bool,
+	compat: bool,
+) {
+	let time = time::parse(arg).filter(|_| !compat);
+	let time =
+		time.map(|time| if seconds { time::truncate(time) } else { time });
+	match (time, newer) {
+		(Some(time), true) => criteria.push(NewerThan(time)),
+		(Some(time), false) => criteria.push(OlderThan(time)),
+		(None, true) => criteria.push(Newer(reference)),
+		(None, false) => criteria.push(Older(reference)),
 	}
 }
 
-fn test(file: &File, flags: &Matches, new: Option<&File>, old: Option<&File>) {
-	if ((!flags.opt_present("a") || file.is_hidden())                  // hidden files
-		&& (!flags.opt_present("b") || file.is_block())                // block special
-		&& (!flags.opt_present("c") || file.is_char())                 // character special
-		&& (!flags.opt_present("d") || file.is_dir())                  // directory
-		&& (!flags.opt_present("e") || file.exists())                  // exists
-		&& (!flags.opt_present("f") || file.is_file())                 // regular file
-		&& (!flags.opt_present("g") || file.has_setgid())	           // set-group-id flag
-		&& (!flags.opt_present("h") || file.is_symlink())	           // symbolic link
-		&& (!flags.opt_present("n") || new.is_some_and(|n| file > n)) // newer than file
-		&& (!flags.opt_present("o") || old.is_some_and(|o| file < o)) // older than file
-		&& (!flags.opt_present("p") || file.is_pipe())                 // named pipe
-		&& (!flags.opt_present("r") || file.is_readable())	           // readable
-		&& (!flags.opt_present("s") || file.is_non_empty())            // not empty
-		&& (!flags.opt_present("u") || file.has_setuid())	           // set-user-id flag
-		&& (!flags.opt_present("w") || file.is_writable())	           // writable
-		&& (!flags.opt_present("x") || file.is_executable()))          // executable
-		!= flags.opt_present("v")
-	{
-		if flags.opt_present("q") {
-			exit(0)
+/// Describes a file which passes as a JSON object: the name it is printed
+/// as, what it is, and the tests of `TESTS` it passes. Fields which cannot
+/// be read are null.
+fn object(file: &File, name: &OsStr) -> String {
+	let meta = file.metadata().ok();
+	let kind = meta.map(|meta| {
+		let kind = meta.file_type();
+		match () {
+			_ if kind.is_file() => "file",
+			_ if kind.is_dir() => "dir",
+			_ if kind.is_symlink() => "symlink",
+			_ if kind.is_block_device() => "block",
+			_ if kind.is_char_device() => "char",
+			_ if kind.is_fifo() => "pipe",
+			_ if kind.is_socket() => "socket",
+			_ => "unknown",
 		}
-		unsafe {
-			MATCH = 1;
+	});
+	let number = |n: Option<u64>| n.map_or("null".into(), |n| n.to_string());
+	let mtime = file.modified().ok().flatten().map_or("null".into(), |time| {
+		let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+		format!("{}.{:09}", since.as_secs(), since.subsec_nanos())
+	});
+	let owner = meta.and_then(|meta| user_name(meta.uid()));
+	let tests = TESTS
+		.iter()
+		.filter(|(_, _, test)| test(file).unwrap_or(false))
+		.map(|(_, name, _)| json(name))
+		.collect::<Vec<_>>();
+	format!(
+		"{{\"path\":{},\"type\":{},\"size\":{},\"mode\":{},\"mtime\":{},\
+		 \"uid\":{},\"gid\":{},\"owner\":{},\"tests\":[{}]}}",
+		json(&name.to_string_lossy()),
+		kind.map_or("null".into(), json),
+		number(meta.map(|meta| meta.len())),
+		meta.map_or("null".into(), |meta| {
+			json(&format!("{:04o}", meta.mode() & 0o7777))
+		}),
+		mtime,
+		number(meta.map(|meta| meta.uid().into())),
+		number(meta.map(|meta| meta.gid().into())),
+		owner.as_deref().map_or("null".into(), json),
+		tests.join(","),
+	)
+}
+
+fn json(text: &str) -> String {
+	let mut out = String::with_capacity(text.len() + 2);
+	out.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\r' => out.push_str("\\r"),
+			c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
 		}
-		println!("{}", file.path.to_string_lossy());
 	}
+	out.push('"');
+	out
+}
+
+/// Quotes `name` for POSIX shells, leaving names made only of characters
+/// which are never special untouched.
+fn quote(name: &str) -> Cow<'_, str> {
+	let safe = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+	if !name.is_empty() && name.chars().all(safe) {
+		return Cow::Borrowed(name);
+	}
+	Cow::Owned(format!("'{}'", name.replace('\'', "'\\''")))
+}
+
+/// The files listed in the file at `path` for `-F`, or on stdin for `-`, one
+/// per line or, if there are any NULs, separated by them.
+fn read_list(path: &str) -> io::Result<Vec<OsString>> {
+	let text = match path {
+		"-" => {
+			let mut text = Vec::new();
+			io::Read::read_to_end(&mut io::stdin().lock(), &mut text)?;
+			text
+		}
+		path => fs::read(path)?,
+	};
+	let end = if text.contains(&b'\0') { b'\0' } else { b'\n' };
+	Ok(text
+		.split(|&b| b == end)
+		.map(|name| if end == b'\n' { name.trim_ascii() } else { name })
+		.filter(|name| !name.is_empty())
+		.map(|name| OsStr::from_bytes(name).to_os_string())
+		.collect())
+}
+
+/// Reads the timestamp left behind by a previous `--mtime-cache` run, if any.
+fn read_cache(path: &Path) -> Option<SystemTime> {
+	let contents = fs::read_to_string(path).ok()?;
+	let (secs, nanos) = contents.trim().split_once('.')?;
+	let since = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+	UNIX_EPOCH.checked_add(since)
+}
+
+fn write_cache(path: &Path, time: SystemTime) -> io::Result<()> {
+	let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+	fs::write(
+		path,
+		format!("{}.{:09}\n", since.as_secs(), since.subsec_nanos()),
+	)
+}
+
+fn writable(fd: RawFd) -> bool {
+	// SAFETY: F_GETFL only reads the descriptor's flags.
+	let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+	flags != -1
+		&& matches!(flags & libc::O_ACCMODE, libc::O_WRONLY | libc::O_RDWR)
+}
+
+/// The ID named by `arg`, looked up with `lookup` or, failing that, given as
+/// a number, as find(1) does.
+fn id(arg: &str, lookup: fn(&CStr) -> Option<u32>) -> Option<u32> {
+	CString::new(arg)
+		.ok()
+		.and_then(|name| lookup(&name))
+		.or_else(|| arg.parse().ok())
+}
+
+fn user(name: &CStr) -> Option<u32> {
+	// SAFETY: the name is NUL-terminated, and the entry is read before
+	// anything else can look one up.
+	let entry = unsafe { libc::getpwnam(name.as_ptr()).as_ref()? };
+	Some(entry.pw_uid)
+}
+
+/// The name of the user with ID `uid`, if there is one.
+fn user_name(uid: u32) -> Option<String> {
+	// SAFETY: the entry is read before anything else can look one up.
+	let entry = unsafe { libc::getpwuid(uid).as_ref()? };
+	// SAFETY: the name of an entry is NUL-terminated.
+	let name = unsafe { CStr::from_ptr(entry.pw_name) };
+	Some(name.to_string_lossy().into_owned())
+}
+
+fn group(name: &CStr) -> Option<u32> {
+	// SAFETY: as for `user`.
+	let entry = unsafe { libc::getgrnam(name.as_ptr()).as_ref()? };
+	Some(entry.gr_gid)
 }
 
 fn usage(program: &str, opts: Options) {
+	if compat() {
+		let brief = format!(
+			"usage: {} [-abcdefghlpqrsuvwx] [-n file] [-o file] [file...]",
+			program
+		);
+		print!("{}", opts.usage(&brief));
+		return;
+	}
 	let brief = format!(
-		"usage: {} [-abcdefghlpqrsuvwx] [-n file] [-o file] [file...]",
+		"usage: {} [-0abcdefghklpqrsuvwxzCGLOPS] [-j n] [-n file] [-o file] \
+		 [-F file] \
+		 [--min-depth n] [--max-depth n] [--exclude glob] \
+		 [--respect-gitignore] [--path] [--paths-from-env[=var]] \
+		 [--mtime-cache file] \
+		 [--deref-max n] [--probe file] \
+		 [--print0] [--quote] \
+		 [--canonicalize | --absolute] [--relative-to dir] \
+		 [--group-by-dir [--group-header fmt]] \
+		 [--sort mtime|name|size [--reverse]] [--dedup] \
+		 [--format text|json|jsonl] \
+		 [--watch [--removals]] [--which | --which-all] [--name glob] [--iname glob] \
+		 [--regex re] [--path-regex re] [--icase] \
+		 [--newer-than-time time] [--older-than-time time] \
+		 [--mtime-between start end] [--min-size size] [--max-size size] \
+		 [--owner user] [--group group] [--same-file file] \
+		 [--dangling] [--xattr name[=value]] [--has-caps] [--cap cap] [--mime type] \
+		 [--any] [--verbose] [--strict] [--fail-fast] \
+		 [--output-fd n] [--seconds-resolution] \
+		 [--time-field mtime|ctime|atime|birth] \
+		 [--mode-bits] \
+		 [--list-predicates] [--completions shell] [file...]",
 		program
 	);
 	print!("{}", opts.usage(&brief));
 }
 
+/// Whether to behave exactly like the original stest, for scripts which rely
+/// on it: set by `POSIXLY_CORRECT` in the environment or by `--compat`.
+fn compat() -> bool {
+	env::var_os("POSIXLY_CORRECT").is_some()
+		|| env::args()
+			.skip(1)
+			.take_while(|arg| arg != "--")
+			.any(|arg| arg == "--compat")
+}
+
 fn main() {
 	let args: Vec<_> = std::env::args().collect();
 	let program = &args[0];
 
 	let mut opts = Options::new();
-	opts.optflag("a", "hidden", "hidden");
-	opts.optflag("b", "block", "block device");
-	opts.optflag("c", "char", "char device");
-	opts.optflag("d", "dir", "directory");
-	opts.optflag("e", "exists", "exists");
-	opts.optflag("f", "file", "file");
-	opts.optflag("g", "has-setgid", "setgid");
-	opts.optflag("h", "symlink", "symlink");
-	opts.optflag("l", "recurse", "test directory contents");
-	opts.optflagopt("n", "newer", "newer", "file");
-	opts.optflagopt("o", "older", "older", "file");
-	opts.optflag("p", "pipe", "pipe");
-	opts.optflag("q", "quiet", "quiet");
-	opts.optflag("r", "readable", "readable");
-	opts.optflag("s", "non-empty", "non-empty");
-	opts.optflag("u", "has-setuid", "setuid");
-	opts.optflag("v", "inverted", "invert");
-	opts.optflag("w", "writable", "writable");
-	opts.optflag("x", "executable", "executable");
-
-	let matches = match opts.parse(std::env::args()) {
+	options::register(&mut opts);
+
+	let matches = match opts.parse(options::join_pairs(std::env::args())) {
 		Ok(m) => m,
 		_ => {
 			usage(program, opts);
@@ -186,36 +913,498 @@ fn main() {
 		}
 	};
 
-	let newer = matches.opt_str("n").map(File::from);
-	let older = matches.opt_str("o").map(File::from);
-	let mut paths =
-		matches.free.iter().skip(1).map(File::from).collect::<Vec<_>>();
+	let compat = compat();
+	if compat && let Some(long) = options::first_new(&matches) {
+		eprintln!("{}: --{} is not available in compat mode", program, long);
+		exit(2);
+	}
 
-	if paths.is_empty() {
-		let mut line = String::with_capacity(128);
-		let stdin = io::stdin();
-		while let Ok(len) = stdin.read_line(&mut line) {
-			if len == 0 || line == "\n" {
-				break;
+	if matches.opt_present("list-predicates") {
+		options::list_predicates();
+		exit(0);
+	}
+	if let Some(shell) = matches.opt_str("completions") {
+		match completions::script(&shell) {
+			Some(script) => print!("{}", script),
+			None => {
+				eprintln!("{}: unsupported shell: {}", program, shell);
+				exit(2);
 			}
-			paths.push(File::from(line.trim()));
-			line.clear();
 		}
+		exit(0);
 	}
 
-	for path in paths {
-		if matches.opt_present("l") && path.is_dir() {
-			WalkDir::new(path.path)
-				.into_iter()
-				.filter_map(|e| e.ok())
-				.map(|entry| File::from(entry.path()))
-				.for_each(|file| {
-					test(&file, &matches, newer.as_ref(), older.as_ref())
-				});
-		} else {
-			test(&path, &matches, newer.as_ref(), older.as_ref());
+	let cache = matches.opt_str("mtime-cache").map(PathBuf::from);
+	let started = SystemTime::now();
+	let deref_max = match matches.opt_get("deref-max") {
+		Ok(max) => max,
+		Err(_) => {
+			usage(program, opts);
+			exit(2);
 		}
+	};
+	// The original stest compared modification times in whole seconds.
+	let seconds = compat || matches.opt_present("seconds-resolution");
+	let coarse = |time| if seconds { time::truncate(time) } else { time };
+	let timestamp = match matches.opt_str("time-field").as_deref() {
+		None | Some("mtime") => Timestamp::Modified,
+		Some("ctime") => Timestamp::Changed,
+		Some("atime") => Timestamp::Accessed,
+		Some("birth") => Timestamp::Born,
+		Some(field) => {
+			eprintln!("{}: --time-field: unknown field: {}", program, field);
+			exit(2);
+		}
+	};
+	let reference = |path| {
+		File::from(path)
+			.dereference_at_most(deref_max)
+			.whole_seconds(seconds)
+			.compare_time(timestamp)
+	};
+	let time = |name| {
+		matches.opt_str(name).map(|time| match time::parse(&time) {
+			Some(time) => coarse(time),
+			None => {
+				eprintln!("{}: invalid timestamp: {}", program, time);
+				exit(2);
+			}
+		})
+	};
+
+	// Either end of a range may be a timestamp or a file whose modification
+	// time, or other `--time-field`, is used; anything which parses as a
+	// timestamp is taken as one.
+	let endpoint = |arg: &str| match time::parse(arg) {
+		Some(time) => coarse(time),
+		None => match reference(arg.to_string()).time() {
+			Ok(Some(time)) => time,
+			Ok(None) => {
+				eprintln!("{}: {}: no such file or timestamp", program, arg);
+				exit(2);
+			}
+			Err(err) => {
+				eprintln!("{}: {}: {}", program, arg, err);
+				exit(2);
+			}
+		},
+	};
+	let between = matches.opt_str("mtime-between").map(|range| {
+		let Some((start, end)) = options::split_pair(&range) else {
+			eprintln!("{}: --mtime-between needs a start and an end", program);
+			exit(2);
+		};
+		let (start, end) = (endpoint(start), endpoint(end));
+		if start > end {
+			eprintln!("{}: --mtime-between: start is after end", program);
+			exit(2);
+		}
+		Between(start, end)
+	});
+
+	let mut criteria = Criteria::new();
+	// Under `--any` files need only pass one of the single-letter tests, and
+	// be newer or older than one of the references to each of -n and -o.
+	let any = matches.opt_present("any");
+	let mut push = |tests: Criteria| match any {
+		_ if tests.is_empty() => {}
+		true => criteria.push(Any(tests)),
+		false => criteria.push(tests),
+	};
+	// Those given by name alone must all be passed, as the other tests
+	// named are.
+	let (mut tests, mut named) = (Criteria::new(), Criteria::new());
+	for &(flag, _, test) in
+		TESTS.iter().filter(|(flag, ..)| matches.opt_present(flag))
+	{
+		match flag.len() {
+			1 => tests.push(test),
+			_ => named.push(test),
+		}
+	}
+	push(tests);
+	for (flag, newer) in [("n", true), ("o", false)] {
+		// -n and -o may be given without a file, which is an empty one.
+		let mut args = matches.opt_strs(flag);
+		if matches.opt_count(flag) > args.len() {
+			args.push(String::new());
+		}
+		let mut references = Criteria::new();
+		for arg in args {
+			let file = reference(arg.clone());
+			compare(&mut references, &arg, newer, file, seconds, compat);
+		}
+		push(references);
+	}
+	if !named.is_empty() {
+		criteria.push(named);
+	}
+	// `--path` lists the commands which could be run, as dmenu_path does.
+	if matches.opt_present("path") {
+		criteria.push(File::is_file);
+		criteria.push(File::is_executable);
+	}
+	if let Some(arg) = matches.opt_str("same-file") {
+		criteria.push(SameFile(reference(arg)));
+	}
+	if let Some(time) = time("newer-than-time") {
+		criteria.push(NewerThan(time));
+	}
+	if let Some(time) = time("older-than-time") {
+		criteria.push(OlderThan(time));
+	}
+	if let Some(between) = between {
+		criteria.push(between);
+	}
+	let size = |name| {
+		matches.opt_str(name).map(|arg| match size::parse(&arg) {
+			Some(size) => size,
+			None => {
+				eprintln!("{}: --{}: invalid size: {}", program, name, arg);
+				exit(2);
+			}
+		})
+	};
+	if let Some(min) = size("min-size") {
+		criteria.push(MinSize(min));
+	}
+	if let Some(max) = size("max-size") {
+		criteria.push(MaxSize(max));
+	}
+	for arg in matches.opt_strs("xattr") {
+		let (name, value) = match arg.split_once('=') {
+			Some((name, value)) => (name, Some(value.as_bytes().to_vec())),
+			None => (arg.as_str(), None),
+		};
+		criteria.push(Xattr { name: name.to_string(), value });
+	}
+	let types = matches.opt_strs("mime");
+	if !types.is_empty() {
+		criteria.push(Mime(types));
 	}
+	for arg in matches.opt_strs("cap") {
+		match caps::parse(&arg) {
+			Some(cap) => criteria.push(Capability(cap)),
+			None => {
+				eprintln!("{}: --cap: unknown capability: {}", program, arg);
+				exit(2);
+			}
+		}
+	}
+	if let Some(arg) = matches.opt_str("owner") {
+		match id(&arg, user) {
+			Some(uid) => criteria.push(Owner(uid)),
+			None => {
+				eprintln!("{}: --owner: no such user: {}", program, arg);
+				exit(2);
+			}
+		}
+	}
+	if let Some(arg) = matches.opt_str("group") {
+		match id(&arg, group) {
+			Some(gid) => criteria.push(Group(gid)),
+			None => {
+				eprintln!("{}: --group: no such group: {}", program, arg);
+				exit(2);
+			}
+		}
+	}
+	// `--icase` makes `--name` ignore case too, so it behaves exactly like
+	// `--iname`; if both are given, files must pass both.
+	let icase = matches.opt_present("icase");
+	for (name, icase) in [("name", icase), ("iname", true)] {
+		let patterns = matches.opt_strs(name);
+		if !patterns.is_empty() {
+			criteria.push(Name { patterns, icase });
+		}
+	}
+	for (name, whole_path) in [("regex", false), ("path-regex", true)] {
+		let patterns = matches.opt_strs(name);
+		if patterns.is_empty() {
+			continue;
+		}
+		match Regex::new(&patterns, icase, whole_path) {
+			Ok(regex) => criteria.push(regex),
+			Err(err) => {
+				eprintln!("{}: --{}: {}", program, name, err);
+				exit(2);
+			}
+		}
+	}
+
+	let jobs = match matches.opt_get::<usize>("jobs") {
+		Ok(None) => 1,
+		Ok(Some(0)) => thread::available_parallelism().map_or(1, usize::from),
+		Ok(Some(jobs)) => jobs,
+		Err(_) => {
+			usage(program, opts);
+			exit(2);
+		}
+	};
+
+	let depth = match (
+		matches.opt_get::<usize>("min-depth"),
+		matches.opt_get::<usize>("max-depth"),
+	) {
+		(Ok(min), Ok(max)) => min.unwrap_or(0)..=max.unwrap_or(usize::MAX),
+		_ => {
+			usage(program, opts);
+			exit(2);
+		}
+	};
+
+	let last = |name| matches.opt_positions(name).into_iter().max();
+	let follow = match (last("L"), last("P")) {
+		(None, None) => None,
+		(l, p) => Some(l > p),
+	};
 
-	std::process::exit((unsafe { MATCH } == 0) as i32)
+	let sort = match matches.opt_str("sort").as_deref() {
+		None => None,
+		Some("mtime") => Some(Sort::Mtime),
+		Some("name") => Some(Sort::Name),
+		Some("size") => Some(Sort::Size),
+		Some(key) => {
+			eprintln!("{}: --sort: unknown key: {}", program, key);
+			exit(2);
+		}
+	};
+	let sort = sort.map(|sort| (sort, matches.opt_present("reverse")));
+	let format = match matches.opt_str("format").as_deref() {
+		None | Some("text") => Format::Text,
+		Some("json") => Format::Json,
+		Some("jsonl") => Format::Jsonl,
+		Some(format) => {
+			eprintln!("{}: --format: unknown format: {}", program, format);
+			exit(2);
+		}
+	};
+	if format != Format::Text && matches.opt_present("group-by-dir") {
+		eprintln!("{}: --format cannot be used with --group-by-dir", program);
+		exit(2);
+	}
+	// Files are printed as they come to pass, so there is no whole list to
+	// order, count or put in an array.
+	if matches.opt_present("watch") || matches.opt_present("removals") {
+		let held = [
+			("-C", matches.opt_present("C")),
+			("--sort", sort.is_some()),
+			("--format json", format == Format::Json),
+			("--group-by-dir", matches.opt_present("group-by-dir")),
+		];
+		if let Some((name, _)) = held.iter().find(|(_, given)| *given) {
+			eprintln!("{}: {} cannot be used with --watch", program, name);
+			exit(2);
+		}
+	}
+
+	// --fail-fast gives up at the first error, leaving nothing for --strict
+	// to count.
+	if matches.opt_present("strict") && matches.opt_present("fail-fast") {
+		eprintln!("{}: --strict cannot be used with --fail-fast", program);
+		exit(2);
+	}
+	if matches.opt_present("absolute") && matches.opt_present("canonicalize") {
+		eprintln!("{}: --absolute cannot be used with --canonicalize", program);
+		exit(2);
+	}
+	let relative_to = matches.opt_str("relative-to").map(|dir| {
+		match fs::canonicalize(&dir) {
+			Ok(dir) if dir.is_dir() => dir,
+			Ok(_) => {
+				eprintln!(
+					"{}: --relative-to: not a directory: {}",
+					program, dir
+				);
+				exit(2);
+			}
+			Err(err) => {
+				eprintln!("{}: --relative-to: {}: {}", program, dir, err);
+				exit(2);
+			}
+		}
+	});
+
+	let out: Box<dyn Write + Send> = match matches.opt_get::<RawFd>("output-fd")
+	{
+		Ok(None) => Box::new(BufWriter::new(io::stdout())),
+		Ok(Some(fd)) if writable(fd) => {
+			// SAFETY: the descriptor is open, and nothing else in the process
+			// uses it.
+			Box::new(BufWriter::new(unsafe { fs::File::from_raw_fd(fd) }))
+		}
+		Ok(Some(fd)) => {
+			eprintln!(
+				"{}: file descriptor {} is not open for writing",
+				program, fd
+			);
+			exit(2);
+		}
+		Err(_) => {
+			usage(program, opts);
+			exit(2);
+		}
+	};
+
+	let cli = Cli {
+		program: program.clone(),
+		out: Mutex::new(out),
+		criteria,
+		since: cache.as_deref().and_then(read_cache).map(coarse),
+		deref_max,
+		seconds,
+		timestamp,
+		mode_bits: matches.opt_present("mode-bits"),
+		follow,
+		depth,
+		excludes: matches.opt_strs("exclude"),
+		jobs,
+		sort,
+		sorted: Mutex::new(Vec::new()),
+		format,
+		seen: Mutex::new(HashSet::new()),
+		relative_to,
+		compat,
+		passed: AtomicUsize::new(0),
+		failed: AtomicBool::new(false),
+		printed: Mutex::new(HashSet::new()),
+		flags: matches,
+	};
+
+	if let Some(path) = cli.flags.opt_str("probe") {
+		cli.probe(&cli.file(path));
+	} else if cli.flags.opts_present_any(["which", "which-all"]) {
+		let all = cli.flags.opt_present("which-all");
+		cli.which(&cli.flags.free[1..], all);
+	} else if cli.flags.opt_present("path") {
+		cli.scan_env("PATH", true);
+	} else if cli.flags.opt_present("paths-from-env") {
+		let var = cli.flags.opt_str("paths-from-env");
+		cli.scan_env(var.as_deref().unwrap_or("PATH"), false);
+	} else {
+		let mut paths = cli
+			.flags
+			.free
+			.iter()
+			.skip(1)
+			.map(|path| cli.file(path))
+			.collect::<Vec<_>>();
+
+		for list in cli.flags.opt_strs("F") {
+			match read_list(&list) {
+				Ok(names) => paths.extend(names.iter().map(|n| cli.file(n))),
+				Err(err) => {
+					eprintln!("{}: -F: {}: {}", program, list, err);
+					exit(2);
+				}
+			}
+		}
+		let given = !paths.is_empty() || cli.flags.opt_present("F");
+
+		// With -z stdin always contributes, in addition to any files given
+		// as arguments; with -0 it is only read without them, as lines are.
+		let null = cli.flags.opt_present("0") && !given;
+		if cli.flags.opt_present("z") || null {
+			for record in io::stdin().lock().split(b'\0') {
+				match record {
+					Ok(record) if record.is_empty() => continue,
+					Ok(record) => {
+						paths.push(cli.file(OsStr::from_bytes(&record)))
+					}
+					Err(_) => break,
+				}
+			}
+		} else if !given {
+			let mut line = String::with_capacity(128);
+			let stdin = io::stdin();
+			while let Ok(len) = stdin.read_line(&mut line) {
+				// The original stest stopped reading at the first blank line.
+				if len == 0 || compat && line == "\n" {
+					break;
+				}
+				paths.push(cli.file(line.trim()));
+				line.clear();
+			}
+		}
+
+		// Watches are set up before the first pass, so that nothing which
+		// changes during it is missed.
+		let mut watcher = None;
+		if cli.flags.opt_present("watch") {
+			let mut watch = watch::Watcher::new(&cli).unwrap_or_else(|err| {
+				eprintln!("{}: --watch: {}", program, err);
+				exit(2);
+			});
+			for path in &paths {
+				watch.add(path.path());
+			}
+			watcher = Some(watch);
+		}
+
+		for path in paths {
+			// An empty path names no file, so every test would quietly fail
+			// on it, or pass under -v; it is more likely a caller's bug, so
+			// is an error as an unreadable file is.
+			if path.path().as_os_str().is_empty() {
+				cli.error(path.path(), &"empty path");
+				continue;
+			}
+			if cli.flags.opt_present("l") && path.is_dir().unwrap_or(false) {
+				cli.walk(path);
+			} else if cli.test(&path) {
+				let name = cli.name(path.path());
+				cli.report(path, &name);
+			}
+		}
+		if let Some(mut watcher) = watcher
+			&& let Err(err) = watcher.run()
+		{
+			cli.flush_out();
+			eprintln!("{}: --watch: {}", program, err);
+			exit(2);
+		}
+	}
+
+	cli.flush();
+	cli.flush_out();
+
+	if let Some(cache) = cache
+		&& let Err(err) = write_cache(&cache, started)
+	{
+		eprintln!("{}: {}: {}", program, cache.to_string_lossy(), err);
+		exit(2);
+	}
+
+	if cli.flags.opt_present("strict") && cli.failed.load(Ordering::Relaxed) {
+		exit(2);
+	}
+	exit((cli.passed.load(Ordering::Relaxed) == 0) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cache_round_trips() {
+		let path =
+			env::temp_dir().join(format!("stest-cache-{}", std::process::id()));
+		let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+		write_cache(&path, time).unwrap();
+		assert_eq!(read_cache(&path), Some(time));
+		fs::write(&path, "not a time\n").unwrap();
+		assert_eq!(read_cache(&path), None);
+		fs::remove_file(&path).unwrap();
+		assert_eq!(read_cache(&path), None);
+	}
+
+	#[test]
+	fn quote_leaves_safe_names_alone() {
+		assert_eq!(quote("bin/ls-1.0_x+y@z"), "bin/ls-1.0_x+y@z");
+		assert_eq!(quote(""), "''");
+		assert_eq!(quote("a b"), "'a b'");
+		assert_eq!(quote("it's"), "'it'\\''s'");
+		assert_eq!(quote("two\nlines"), "'two\nlines'");
+		assert_eq!(quote("$HOME*"), "'$HOME*'");
+	}
 }
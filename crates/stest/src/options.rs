@@ -0,0 +1,482 @@
+//! The command line options, kept in one table so that `--help`,
+//! `--list-predicates` and anything else describing them cannot disagree.
+
+use getopts::{HasArg, Matches, Occur, Options};
+
+pub enum Arg {
+	None,
+	Required(&'static str),
+	/// An argument which may be left out, as may the option be given more
+	/// than once.
+	Optional(&'static str),
+	Repeated(&'static str),
+	/// Two arguments, which getopts cannot take, so they are joined into one
+	/// by [`join_pairs`] before parsing.
+	Pair(&'static str, &'static str),
+}
+
+pub struct Opt {
+	pub short: &'static str,
+	pub long: &'static str,
+	pub arg: Arg,
+	pub desc: &'static str,
+	/// Whether the option is a test files must pass, rather than changing how
+	/// stest runs.
+	pub test: bool,
+}
+
+const fn test(
+	short: &'static str,
+	long: &'static str,
+	desc: &'static str,
+) -> Opt {
+	Opt { short, long, arg: Arg::None, desc, test: true }
+}
+
+const fn flag(
+	short: &'static str,
+	long: &'static str,
+	desc: &'static str,
+) -> Opt {
+	Opt { short, long, arg: Arg::None, desc, test: false }
+}
+
+pub const OPTIONS: &[Opt] = &[
+	flag("0", "null", "read NUL-delimited files from stdin instead of lines"),
+	test("a", "hidden", "test hidden files"),
+	test("b", "block", "test that files are block specials"),
+	test("c", "char", "test that files are character specials"),
+	test("d", "dir", "test that files are directories"),
+	test("e", "exists", "test that files exist"),
+	test("f", "file", "test that files are regular files"),
+	test("g", "has-setgid", "test that files have their set-group-ID flag set"),
+	test("h", "symlink", "test that files are symbolic links"),
+	test("k", "sticky", "test that files have their sticky bit set"),
+	Opt {
+		short: "j",
+		long: "jobs",
+		arg: Arg::Required("n"),
+		desc: "test the files found by -l on n threads, or one per CPU with 0",
+		test: false,
+	},
+	flag("l", "recurse", "test the contents of directories"),
+	Opt {
+		short: "n",
+		long: "newer",
+		arg: Arg::Optional("file"),
+		desc: "test that files are newer than file, or a time or duration",
+		test: true,
+	},
+	Opt {
+		short: "o",
+		long: "older",
+		arg: Arg::Optional("file"),
+		desc: "test that files are older than file, or a time or duration",
+		test: true,
+	},
+	test("p", "pipe", "test that files are named pipes"),
+	flag("q", "quiet", "print nothing, only set the exit status"),
+	test("r", "readable", "test that files are readable"),
+	test("s", "non-empty", "test that files are not empty"),
+	test("u", "has-setuid", "test that files have their set-user-ID flag set"),
+	flag("v", "inverted", "invert the sense of tests"),
+	test("w", "writable", "test that files are writable"),
+	test("x", "executable", "test that files are executable"),
+	flag("z", "null-input", "also read NUL-delimited files from stdin"),
+	flag("C", "count", "print only how many files pass"),
+	Opt {
+		short: "F",
+		long: "files-from",
+		arg: Arg::Repeated("file"),
+		desc: "also test the files listed in file, or on stdin for -",
+		test: false,
+	},
+	test("G", "group-owned", "test that files belong to the effective group"),
+	flag("L", "follow", "follow symbolic links, also when recursing"),
+	test("O", "owned", "test that files are owned by the effective user"),
+	flag("P", "no-follow", "test symbolic links themselves"),
+	test("S", "socket", "test that files are sockets"),
+	Opt {
+		short: "",
+		long: "min-depth",
+		arg: Arg::Required("n"),
+		desc: "test only files at least n levels below the directories of -l",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "max-depth",
+		arg: Arg::Required("n"),
+		desc: "test only files at most n levels below the directories of -l",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "exclude",
+		arg: Arg::Repeated("glob"),
+		desc: "skip what -l finds whose basenames match, and their contents",
+		test: false,
+	},
+	flag("", "respect-gitignore", "skip what -l finds that git ignores"),
+	flag("", "path", "print the executables in $PATH by name, as dmenu_path"),
+	Opt {
+		short: "",
+		long: "paths-from-env",
+		arg: Arg::Optional("var"),
+		desc: "test the basenames found in a PATH-like variable",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "mtime-cache",
+		arg: Arg::Required("file"),
+		desc: "only print files modified since the last run recorded in file",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "deref-max",
+		arg: Arg::Required("n"),
+		desc: "follow at most n symbolic links when resolving a file",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "probe",
+		arg: Arg::Required("file"),
+		desc: "print the result of every test on file",
+		test: false,
+	},
+	flag("", "print0", "end each printed file with NUL rather than newline"),
+	flag("", "quote", "quote printed files for the shell"),
+	flag(
+		"",
+		"canonicalize",
+		"print files as absolute paths with links resolved",
+	),
+	flag("", "absolute", "print files as absolute paths"),
+	Opt {
+		short: "",
+		long: "relative-to",
+		arg: Arg::Required("dir"),
+		desc: "print files as paths relative to dir",
+		test: false,
+	},
+	flag("", "group-by-dir", "group -l output by directory"),
+	Opt {
+		short: "",
+		long: "group-header",
+		arg: Arg::Required("fmt"),
+		desc: "header printed before each group, {} being the directory",
+		test: false,
+	},
+	Opt {
+		short: "",
+		long: "sort",
+		arg: Arg::Required("key"),
+		desc: "print files ordered by mtime, name or size",
+		test: false,
+	},
+	flag("", "reverse", "reverse the order of --sort"),
+	flag("", "dedup", "print only the first file with each basename"),
+	Opt {
+		short: "",
+		long: "format",
+		arg: Arg::Required("format"),
+		desc: "print files as text, a json array or jsonl objects",
+		test: false,
+	},
+	flag("", "watch", "keep printing files as they come to pass"),
+	flag("", "removals", "with --watch, also print files which stop passing"),
+	flag("", "which", "find the first file for each name in $PATH"),
+	flag("", "which-all", "find every file for each name in $PATH"),
+	Opt {
+		short: "",
+		long: "name",
+		arg: Arg::Repeated("glob"),
+		desc: "test that basenames match the wildcard pattern",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "iname",
+		arg: Arg::Repeated("glob"),
+		desc: "as --name, ignoring case",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "regex",
+		arg: Arg::Repeated("re"),
+		desc: "test that basenames contain a match for the regular expression",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "path-regex",
+		arg: Arg::Repeated("re"),
+		desc: "as --regex, against the whole path",
+		test: true,
+	},
+	flag("", "icase", "make every pattern ignore case"),
+	Opt {
+		short: "",
+		long: "newer-than-time",
+		arg: Arg::Required("time"),
+		desc: "test that files were modified after time",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "older-than-time",
+		arg: Arg::Required("time"),
+		desc: "test that files were modified before time",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "mtime-between",
+		arg: Arg::Pair("start", "end"),
+		desc: "test that files were modified between start and end inclusive",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "min-size",
+		arg: Arg::Required("size"),
+		desc: "test that files are at least size bytes, or K, M, G or T",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "max-size",
+		arg: Arg::Required("size"),
+		desc: "test that files are at most size bytes, or K, M, G or T",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "owner",
+		arg: Arg::Required("user"),
+		desc: "test that files are owned by user, a name or ID",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "group",
+		arg: Arg::Required("group"),
+		desc: "test that files belong to group, a name or ID",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "same-file",
+		arg: Arg::Required("file"),
+		desc: "test that files are the same file as file, as test -ef",
+		test: true,
+	},
+	test("", "dangling", "test that files are symbolic links leading nowhere"),
+	Opt {
+		short: "",
+		long: "xattr",
+		arg: Arg::Repeated("name[=value]"),
+		desc: "test that files have the extended attribute, with value",
+		test: true,
+	},
+	test("", "has-caps", "test that files have capabilities set"),
+	Opt {
+		short: "",
+		long: "cap",
+		arg: Arg::Repeated("cap"),
+		desc: "test that files are granted the capability",
+		test: true,
+	},
+	Opt {
+		short: "",
+		long: "mime",
+		arg: Arg::Repeated("type"),
+		desc: "test that file content is of a MIME type, such as image/*",
+		test: true,
+	},
+	flag("", "any", "pass files which pass any single-letter test, not all"),
+	flag("", "verbose", "report files which cannot be read on stderr"),
+	flag("", "strict", "as --verbose, exiting with status 2 after any error"),
+	flag("", "fail-fast", "exit on the first error reading a file"),
+	Opt {
+		short: "",
+		long: "output-fd",
+		arg: Arg::Required("n"),
+		desc: "write results to file descriptor n",
+		test: false,
+	},
+	flag(
+		"",
+		"seconds-resolution",
+		"compare modification times in whole seconds",
+	),
+	Opt {
+		short: "",
+		long: "time-field",
+		arg: Arg::Required("field"),
+		desc: "compare mtime, ctime, atime or birth in the time tests",
+		test: false,
+	},
+	flag("", "mode-bits", "test -r, -w and -x by permission bits alone"),
+	flag("", "list-predicates", "list every test and exit"),
+	flag("", "compat", "accept only the options of the original stest"),
+	Opt {
+		short: "",
+		long: "completions",
+		arg: Arg::Required("shell"),
+		desc: "print a completion script for bash, zsh or fish and exit",
+		test: false,
+	},
+];
+
+/// The short options of the original stest, each of which kept its long
+/// name. Everything else is rejected in compatibility mode.
+const ORIGINAL: &str = "abcdefghlnopqrsuvwx";
+
+/// The first option given which the original stest did not have, other than
+/// `--compat` itself.
+pub fn first_new(matches: &Matches) -> Option<&'static str> {
+	OPTIONS
+		.iter()
+		.filter(|opt| opt.long != "compat")
+		.filter(|opt| opt.short.is_empty() || !ORIGINAL.contains(opt.short))
+		.find(|opt| matches.opt_present(opt.long))
+		.map(|opt| opt.long)
+}
+
+pub fn register(opts: &mut Options) {
+	for opt in OPTIONS {
+		let Opt { short, long, desc, .. } = *opt;
+		match opt.arg {
+			Arg::None => opts.optflag(short, long, desc),
+			Arg::Required(hint) => opts.optopt(short, long, desc, hint),
+			Arg::Optional(hint) => {
+				opts.opt(short, long, desc, hint, HasArg::Maybe, Occur::Multi)
+			}
+			Arg::Repeated(hint) => opts.optmulti(short, long, desc, hint),
+			Arg::Pair(first, second) => {
+				opts.optopt(short, long, desc, &format!("{} {}", first, second))
+			}
+		};
+	}
+}
+
+/// Joins the two arguments after each option taking a pair into a single
+/// NUL-separated one, which no real argument can contain. An option given
+/// too few arguments is left alone, for [`split_pair`] to reject.
+pub fn join_pairs(args: impl IntoIterator<Item = String>) -> Vec<String> {
+	let mut args = args.into_iter();
+	let mut joined = Vec::new();
+	while let Some(arg) = args.next() {
+		if arg == "--" {
+			joined.push(arg);
+			joined.extend(args);
+			break;
+		}
+		let pair = OPTIONS.iter().any(|opt| {
+			matches!(opt.arg, Arg::Pair(..))
+				&& arg.strip_prefix("--") == Some(opt.long)
+		});
+		joined.push(arg);
+		if pair {
+			let mut rest = args.by_ref().take(2).collect::<Vec<_>>();
+			if rest.len() == 2 {
+				rest = vec![rest.join("\0")];
+			}
+			joined.extend(rest);
+		}
+	}
+	joined
+}
+
+/// Splits an argument joined by [`join_pairs`] back into its two halves.
+pub fn split_pair(arg: &str) -> Option<(&str, &str)> {
+	arg.split_once('\0')
+}
+
+/// Prints each test as tab-separated short flag, long flag, argument and
+/// description, leaving a field empty where it does not apply.
+pub fn list_predicates() {
+	for opt in OPTIONS.iter().filter(|opt| opt.test) {
+		let short = match opt.short {
+			"" => String::new(),
+			short => format!("-{}", short),
+		};
+		let arg = match opt.arg {
+			Arg::None => String::new(),
+			Arg::Required(hint) | Arg::Optional(hint) | Arg::Repeated(hint) => {
+				hint.to_string()
+			}
+			Arg::Pair(first, second) => format!("{} {}", first, second),
+		};
+		println!("{}\t--{}\t{}\t{}", short, opt.long, arg, opt.desc);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use super::*;
+
+	#[test]
+	fn names_are_unique() {
+		let mut seen = HashSet::new();
+		for opt in OPTIONS {
+			assert!(seen.insert(opt.long), "--{} given twice", opt.long);
+			if !opt.short.is_empty() {
+				assert!(seen.insert(opt.short), "-{} given twice", opt.short);
+			}
+		}
+	}
+
+	#[test]
+	fn every_option_parses() {
+		let mut opts = Options::new();
+		register(&mut opts);
+		for opt in OPTIONS {
+			let mut args = vec![format!("--{}", opt.long)];
+			match opt.arg {
+				Arg::None | Arg::Optional(_) => {}
+				Arg::Required(_) | Arg::Repeated(_) => args.push("1".into()),
+				Arg::Pair(..) => args.extend(["1".into(), "2".into()]),
+			}
+			let matches = opts.parse(join_pairs(args)).unwrap();
+			assert!(matches.opt_present(opt.long), "--{}", opt.long);
+		}
+	}
+
+	#[test]
+	fn first_new_skips_the_original_options() {
+		let mut opts = Options::new();
+		register(&mut opts);
+		let first = |args: &[&str]| {
+			let matches =
+				opts.parse(join_pairs(args.iter().map(|a| a.to_string())));
+			first_new(&matches.unwrap())
+		};
+		assert_eq!(first(&["--compat", "-f", "-l", "--newer", "x"]), None);
+		assert_eq!(first(&["--compat", "-f", "--sort", "name"]), Some("sort"));
+		assert_eq!(first(&["-z", "--compat"]), Some("null-input"));
+		assert_eq!(first(&["--file", "--recurse"]), None);
+	}
+
+	#[test]
+	fn pairs_are_joined_and_split() {
+		let args = ["stest", "--mtime-between", "a", "b", "-f", "c"];
+		let joined = join_pairs(args.map(String::from));
+		assert_eq!(joined, ["stest", "--mtime-between", "a\0b", "-f", "c"]);
+		assert_eq!(split_pair(&joined[2]), Some(("a", "b")));
+		assert_eq!(split_pair("a"), None);
+
+		let short = join_pairs(["--mtime-between", "a"].map(String::from));
+		assert_eq!(short, ["--mtime-between", "a"]);
+		let after =
+			join_pairs(["--", "--mtime-between", "a", "b"].map(String::from));
+		assert_eq!(after, ["--", "--mtime-between", "a", "b"]);
+	}
+}
@@ -0,0 +1,273 @@
+//! `--watch`, which keeps testing the files given as they change, by way of
+//! inotify(7), printing those which come to pass and, under `--removals`,
+//! those which no longer do.
+
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::{CString, OsStr, OsString},
+	io,
+	os::{
+		fd::{AsRawFd, FromRawFd, OwnedFd},
+		unix::ffi::OsStrExt,
+	},
+	path::{Path, PathBuf},
+	sync::{MutexGuard, PoisonError},
+};
+
+use walkdir::WalkDir;
+
+use crate::{Cli, excluded};
+
+const MASK: u32 = libc::IN_CREATE
+	| libc::IN_MOVED_TO
+	| libc::IN_CLOSE_WRITE
+	| libc::IN_ATTRIB
+	| libc::IN_DELETE
+	| libc::IN_MOVED_FROM;
+
+/// A watched directory.
+struct Dir {
+	path: PathBuf,
+	/// How far below a directory given to `-l` it is.
+	depth: usize,
+	/// The only names in it which were given, if it is being watched for
+	/// them rather than as part of a tree under `-l`.
+	names: Option<HashSet<OsString>>,
+}
+
+pub struct Watcher<'a> {
+	cli: &'a Cli,
+	fd: OwnedFd,
+	dirs: HashMap<i32, Dir>,
+}
+
+impl<'a> Watcher<'a> {
+	pub fn new(cli: &'a Cli) -> io::Result<Self> {
+		// SAFETY: inotify_init1 takes no pointers.
+		let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// SAFETY: the descriptor was just opened, and nothing else owns it.
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+		Ok(Watcher { cli, fd, dirs: HashMap::new() })
+	}
+
+	/// Watches `path`, a file given to test: the tree under it if it is
+	/// a directory to walk with `-l`, and otherwise its name in its parent.
+	pub fn add(&mut self, path: &Path) {
+		if self.cli.flags.opt_present("l") && path.is_dir() {
+			self.tree(path, 0);
+			return;
+		}
+		if let Some(name) = path.file_name() {
+			let parent = path.parent().unwrap_or(Path::new(""));
+			let names = HashSet::from([name.to_os_string()]);
+			self.watch(parent, 0, Some(names));
+		}
+	}
+
+	/// Watches `dir`, `depth` levels below a directory given, and the
+	/// directories under it which `-l` would descend into.
+	fn tree(&mut self, dir: &Path, depth: usize) {
+		let max = *self.cli.depth.end();
+		// The contents of a directory at the deepest level are not tested.
+		if depth >= max {
+			return;
+		}
+		let icase = self.cli.flags.opt_present("icase");
+		let dirs = WalkDir::new(dir)
+			.follow_links(self.cli.follow == Some(true))
+			.max_depth(max - depth - 1)
+			.into_iter()
+			.filter_entry(|entry| {
+				entry.depth() == 0
+					|| !excluded(&self.cli.excludes, icase, entry.path())
+			})
+			.filter_map(Result::ok)
+			.filter(|entry| entry.file_type().is_dir())
+			.map(|entry| (depth + entry.depth(), entry.into_path()))
+			.collect::<Vec<_>>();
+		for (depth, dir) in dirs {
+			self.watch(&dir, depth, None);
+		}
+	}
+
+	fn watch(
+		&mut self,
+		dir: &Path,
+		depth: usize,
+		names: Option<HashSet<OsString>>,
+	) {
+		// Files given without a directory are in the current one, and are
+		// printed as they were given.
+		let watched = match dir.as_os_str().is_empty() {
+			true => Path::new("."),
+			false => dir,
+		};
+		let Ok(path) = CString::new(watched.as_os_str().as_bytes()) else {
+			return;
+		};
+		// SAFETY: the path is NUL-terminated and outlives the call.
+		let wd = unsafe {
+			libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK)
+		};
+		// Directories which cannot be watched are left out, as those which
+		// cannot be read are by -l.
+		if wd < 0 {
+			return;
+		}
+		// A directory moved within a tree keeps its watch, so its path is
+		// brought up to date.
+		let watched = self.dirs.entry(wd).or_insert(Dir {
+			path: dir.to_path_buf(),
+			depth,
+			names: Some(HashSet::new()),
+		});
+		if watched.names.is_none() || names.is_none() {
+			(watched.path, watched.depth) = (dir.to_path_buf(), depth);
+		}
+		match (&mut watched.names, names) {
+			(Some(watched), Some(names)) => watched.extend(names),
+			(watched, None) => *watched = None,
+			(None, Some(_)) => {}
+		}
+	}
+
+	/// Handles events until an error stops it.
+	pub fn run(&mut self) -> io::Result<()> {
+		let mut buf = vec![0; 64 * 1024];
+		loop {
+			// SAFETY: the buffer is as long as the size given.
+			let len = unsafe {
+				libc::read(
+					self.fd.as_raw_fd(),
+					buf.as_mut_ptr().cast(),
+					buf.len(),
+				)
+			};
+			let Ok(len) = usize::try_from(len) else {
+				let err = io::Error::last_os_error();
+				match err.kind() {
+					io::ErrorKind::Interrupted => continue,
+					_ => return Err(err),
+				}
+			};
+			let mut events = &buf[..len];
+			// Each event is a `struct inotify_event` followed by its name,
+			// padded with NULs.
+			while events.len() >= 16 {
+				let word = |i: usize| {
+					u32::from_ne_bytes(events[i..i + 4].try_into().unwrap())
+				};
+				let (wd, mask, len) =
+					(word(0) as i32, word(4), word(12) as usize);
+				let name = &events[16..16 + len];
+				let name =
+					&name[..name.iter().position(|&b| b == 0).unwrap_or(len)];
+				self.event(wd, mask, OsStr::from_bytes(name));
+				events = &events[16 + len..];
+			}
+			self.cli.flush_out();
+		}
+	}
+
+	fn event(&mut self, wd: i32, mask: u32, name: &OsStr) {
+		if mask & libc::IN_IGNORED != 0 {
+			self.dirs.remove(&wd);
+			return;
+		}
+		let Some(dir) = self.dirs.get(&wd) else {
+			return;
+		};
+		if name.is_empty()
+			|| dir.names.as_ref().is_some_and(|n| !n.contains(name))
+		{
+			return;
+		}
+		let path = dir.path.join(name);
+		let (tree, depth) = (dir.names.is_none(), dir.depth + 1);
+		if mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+			self.forget(&path, mask & libc::IN_ISDIR != 0);
+			return;
+		}
+		if !tree {
+			self.consider(path);
+			return;
+		}
+		let icase = self.cli.flags.opt_present("icase");
+		if depth > *self.cli.depth.end()
+			|| excluded(&self.cli.excludes, icase, &path)
+		{
+			return;
+		}
+		let arrived = mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0;
+		if arrived && mask & libc::IN_ISDIR != 0 {
+			self.grow(&path, depth);
+		} else if depth >= *self.cli.depth.start() {
+			self.consider(path);
+		}
+	}
+
+	/// Watches a directory which has appeared in a tree, and tests what is
+	/// already in it, which may have arrived before it was watched.
+	fn grow(&mut self, dir: &Path, depth: usize) {
+		self.tree(dir, depth);
+		let (min, max) = (*self.cli.depth.start(), *self.cli.depth.end());
+		let icase = self.cli.flags.opt_present("icase");
+		let paths = WalkDir::new(dir)
+			.follow_links(self.cli.follow == Some(true))
+			.max_depth(max - depth)
+			.into_iter()
+			.filter_entry(|entry| {
+				entry.depth() == 0
+					|| !excluded(&self.cli.excludes, icase, entry.path())
+			})
+			.filter_map(Result::ok)
+			.filter(|entry| depth + entry.depth() >= min)
+			.map(walkdir::DirEntry::into_path)
+			.collect::<Vec<_>>();
+		for path in paths {
+			self.consider(path);
+		}
+	}
+
+	/// Tests a file which has appeared or changed, printing it if it has
+	/// come to pass, or as removed if it no longer does.
+	fn consider(&self, path: PathBuf) {
+		let printed = self.printed().contains(&path);
+		let file = self.cli.file(&path);
+		match (self.cli.test(&file), printed) {
+			(true, false) => {
+				let name = self.cli.name(&path);
+				self.cli.report(file, &name);
+			}
+			(false, true) => {
+				self.printed().remove(&path);
+				self.cli.removed(&path);
+			}
+			_ => {}
+		}
+	}
+
+	/// Prints as removed the file at `path` if it was printed, and if it was a
+	/// directory every file under it which was.
+	fn forget(&self, path: &Path, dir: bool) {
+		let mut gone = Vec::new();
+		self.printed().retain(|printed| {
+			let under = printed == path || dir && printed.starts_with(path);
+			if under {
+				gone.push(printed.clone());
+			}
+			!under
+		});
+		gone.sort();
+		for path in gone {
+			self.cli.removed(&path);
+		}
+	}
+
+	fn printed(&self) -> MutexGuard<'_, HashSet<PathBuf>> {
+		self.cli.printed.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
@@ -0,0 +1,702 @@
+//! Runs stest over files made for each test in a directory of its own.
+
+use std::{
+	env,
+	ffi::OsStr,
+	fs,
+	io::{self, Write},
+	os::unix::{
+		ffi::OsStrExt,
+		fs::{PermissionsExt, symlink},
+	},
+	path::{Path, PathBuf},
+	process::{self, Command, Output, Stdio},
+	sync::atomic::{AtomicUsize, Ordering},
+	time::{Duration, SystemTime},
+};
+
+/// A directory under the system's temporary one, removed with everything
+/// in it once dropped.
+struct Dir(PathBuf);
+
+impl Dir {
+	fn new() -> Dir {
+		static NEXT: AtomicUsize = AtomicUsize::new(0);
+		let n = NEXT.fetch_add(1, Ordering::Relaxed);
+		let path =
+			env::temp_dir().join(format!("stest-{}-{}", process::id(), n));
+		fs::create_dir_all(&path).unwrap();
+		Dir(path)
+	}
+
+	fn path(&self, name: &str) -> PathBuf {
+		self.0.join(name)
+	}
+
+	/// Writes `contents` to the file `name`, making any directories it is in.
+	fn write(&self, name: &str, contents: &str) -> PathBuf {
+		let path = self.path(name);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, contents).unwrap();
+		path
+	}
+
+	fn file(&self, name: &str) -> PathBuf {
+		self.write(name, "")
+	}
+
+	fn executable(&self, name: &str) -> PathBuf {
+		let path = self.file(name);
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+		path
+	}
+}
+
+impl Drop for Dir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+fn stest() -> Command {
+	let mut stest = Command::new(env!("CARGO_BIN_EXE_stest"));
+	stest.env_remove("POSIXLY_CORRECT");
+	stest
+}
+
+/// Runs `stest` with `input` on stdin, which it need not read.
+fn run(stest: &mut Command, input: &[u8]) -> Output {
+	let mut child = stest
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+	let written = child.stdin.take().unwrap().write_all(input);
+	if let Err(err) = written {
+		assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+	}
+	child.wait_with_output().unwrap()
+}
+
+/// Sets the modification time of the file at `path` to `secs` seconds from
+/// now, or before it if negative.
+fn touch(path: &Path, secs: i64) {
+	let now = SystemTime::now();
+	let offset = Duration::from_secs(secs.unsigned_abs());
+	let time = match secs < 0 {
+		true => now - offset,
+		false => now + offset,
+	};
+	let file = fs::File::options().write(true).open(path).unwrap();
+	file.set_modified(time).unwrap();
+}
+
+fn lines(output: &Output) -> Vec<String> {
+	String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect()
+}
+
+/// The paths as stest prints them.
+fn names(paths: &[PathBuf]) -> Vec<String> {
+	paths.iter().map(|path| path.to_string_lossy().into_owned()).collect()
+}
+
+#[test]
+fn paths_from_env_dedups_basenames() {
+	let dir = Dir::new();
+	dir.executable("a/foo");
+	dir.executable("a/bar");
+	dir.executable("b/foo");
+	dir.executable("b/baz");
+	dir.file("b/plain");
+	let var = env::join_paths([dir.path("a"), dir.path("b")]).unwrap();
+	let output = run(
+		stest().arg("-x").arg("--paths-from-env=DIRS").env("DIRS", var),
+		b"",
+	);
+	assert_eq!(lines(&output), ["bar", "foo", "baz"]);
+	assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn paths_from_env_defaults_to_path() {
+	let dir = Dir::new();
+	dir.executable("bin/tool");
+	let output = run(
+		stest().args(["-x", "--paths-from-env"]).env("PATH", dir.path("bin")),
+		b"",
+	);
+	assert_eq!(lines(&output), ["tool"]);
+}
+
+#[test]
+fn paths_from_env_reports_unreadable_directories_under_strict() {
+	let dir = Dir::new();
+	dir.executable("bin/tool");
+	let file = dir.file("plain");
+	let var =
+		env::join_paths([file, dir.path("missing"), dir.path("bin")]).unwrap();
+	let quiet = run(stest().arg("-x").arg("--path").env("PATH", &var), b"");
+	assert_eq!(lines(&quiet), ["tool"]);
+	assert!(quiet.stderr.is_empty());
+	let strict = run(
+		stest()
+			.args(["-x", "--strict", "--paths-from-env=DIRS"])
+			.env("DIRS", var),
+		b"",
+	);
+	assert_eq!(lines(&strict), ["tool"]);
+	assert_eq!(strict.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&strict.stderr);
+	assert!(stderr.contains("plain"), "{}", stderr);
+	assert!(!stderr.contains("missing"), "{}", stderr);
+}
+
+#[test]
+fn mtime_cache_prints_only_what_changed_since() {
+	let dir = Dir::new();
+	let (old, new) = (dir.file("old"), dir.file("new"));
+	let cache = dir.path("cache");
+	let mtime_cache = || {
+		let mut stest = stest();
+		stest.arg("-f").arg("--mtime-cache").arg(&cache).arg(&old).arg(&new);
+		stest
+	};
+	let output = run(&mut mtime_cache(), b"");
+	assert_eq!(lines(&output).len(), 2);
+	assert!(cache.exists());
+
+	let output = run(&mut mtime_cache(), b"");
+	assert!(lines(&output).is_empty());
+	assert_eq!(output.status.code(), Some(1));
+
+	touch(&new, 60);
+	let output = run(&mut mtime_cache(), b"");
+	assert_eq!(lines(&output), [new.to_string_lossy()]);
+}
+
+#[test]
+fn deref_max_fails_longer_chains() {
+	let dir = Dir::new();
+	dir.file("target");
+	symlink("target", dir.path("one")).unwrap();
+	symlink("one", dir.path("two")).unwrap();
+	let two = dir.path("two");
+	let output = run(stest().args(["-f", "--deref-max", "2"]).arg(&two), b"");
+	assert_eq!(lines(&output), [two.to_string_lossy()]);
+	let output = run(stest().args(["-f", "--deref-max", "1"]).arg(&two), b"");
+	assert!(lines(&output).is_empty());
+	assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn deref_max_rejects_a_bad_count() {
+	let output = run(stest().args(["--deref-max", "many", "."]), b"");
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn null_input_reads_stdin_alongside_arguments() {
+	let dir = Dir::new();
+	let (arg, first, second) =
+		(dir.file("arg"), dir.file("first"), dir.file("with\nnewline"));
+	let mut input = Vec::new();
+	for path in [&first, &second] {
+		input.extend_from_slice(path.as_os_str().as_encoded_bytes());
+		input.push(0);
+	}
+	let output = run(stest().args(["-z", "--print0", "-f"]).arg(&arg), &input);
+	let printed = output.stdout.split(|&b| b == 0).collect::<Vec<_>>();
+	let expected =
+		[&arg, &first, &second].map(|path| path.as_os_str().as_encoded_bytes());
+	assert_eq!(printed[..3], expected);
+	assert_eq!(printed[3..], [b""]);
+}
+
+#[test]
+fn null_reads_stdin_only_without_arguments() {
+	let dir = Dir::new();
+	let (arg, read) = (dir.file("arg"), dir.file("read"));
+	let mut input = read.as_os_str().as_encoded_bytes().to_vec();
+	input.push(0);
+	let output = run(stest().args(["-0", "-f"]).arg(&arg), &input);
+	assert_eq!(lines(&output), [arg.to_string_lossy()]);
+	let output = run(stest().args(["-0", "-f"]), &input);
+	assert_eq!(lines(&output), [read.to_string_lossy()]);
+}
+
+#[test]
+fn probe_prints_every_test() {
+	let dir = Dir::new();
+	let file = dir.write("file", "contents");
+	let output = run(stest().arg("--probe").arg(&file), b"");
+	let lines = lines(&output);
+	let mut flags: Vec<_> =
+		"abcdefghkprsuwxGOS".chars().map(|c| format!("-{}", c)).collect();
+	flags.extend(["--dangling", "--has-caps", "-n", "-o"].map(String::from));
+	assert_eq!(lines.len(), flags.len());
+	for (line, flag) in lines.iter().zip(&flags) {
+		assert!(line.starts_with(&format!("{}\t", flag)), "{}", line);
+	}
+	assert!(lines.contains(&"-f\tfile\ttrue".to_string()));
+	assert!(lines.contains(&"-d\tdir\tfalse".to_string()));
+	assert!(lines.contains(&"-s\tnon-empty\ttrue".to_string()));
+	assert!(lines.contains(&"-n\tnewer\t-".to_string()));
+	assert!(lines.contains(&"--dangling\tdangling\tfalse".to_string()));
+	assert!(lines.contains(&"--has-caps\thas-caps\tfalse".to_string()));
+}
+
+#[test]
+fn dangling_links_are_tested_as_the_tests_of_letters_are() {
+	let dir = Dir::new();
+	let file = dir.file("file");
+	let link = dir.path("link");
+	symlink(dir.path("missing"), &link).unwrap();
+	let paths = [file, link.clone()];
+	let dangling = run(stest().arg("--dangling").args(&paths), b"");
+	assert_eq!(lines(&dangling), names(&paths[1..]));
+	let json =
+		run(stest().args(["--dangling", "--format", "jsonl"]).arg(&link), b"");
+	let json = String::from_utf8_lossy(&json.stdout);
+	assert!(json.contains(r#""tests":["symlink","dangling"]"#), "{}", json);
+	// Under --any, only the tests of letters are passed one of.
+	let any =
+		run(stest().args(["--any", "-f", "--dangling"]).args(&paths), b"");
+	assert!(lines(&any).is_empty());
+}
+
+#[test]
+fn probe_reports_why_a_file_cannot_be_read() {
+	let dir = Dir::new();
+	let output = run(stest().arg("--probe").arg(dir.path("missing")), b"");
+	let lines = lines(&output);
+	assert!(lines.contains(&"-e\texists\tfalse".to_string()));
+	assert!(lines.last().unwrap().starts_with("error\t"));
+}
+
+#[test]
+fn probe_tests_against_references_given() {
+	let dir = Dir::new();
+	let (old, new) = (dir.file("old"), dir.file("new"));
+	touch(&old, -60);
+	let output = run(stest().arg("-n").arg(&old).arg("--probe").arg(&new), b"");
+	let lines = lines(&output);
+	assert!(lines.contains(&"-n\tnewer\ttrue".to_string()));
+	assert!(lines.contains(&"-o\tolder\t-".to_string()));
+}
+
+#[test]
+fn recurse_descends_into_directories_read_from_stdin() {
+	let dir = Dir::new();
+	let (top, nested) = (dir.file("tree/top"), dir.file("tree/sub/nested"));
+	let lone = dir.file("lone");
+	let input = format!("{}\n{}\n", dir.path("tree").display(), lone.display());
+	let output = run(stest().args(["-l", "-f"]), input.as_bytes());
+	let mut printed = lines(&output);
+	printed.sort();
+	let mut expected =
+		[&lone, &nested, &top].map(|path| path.to_string_lossy());
+	expected.sort();
+	assert_eq!(printed, expected);
+
+	let output = run(stest().args(["-f"]), input.as_bytes());
+	assert_eq!(lines(&output), [lone.to_string_lossy()]);
+}
+
+#[test]
+fn quote_prints_names_the_shell_reads_back() {
+	let dir = Dir::new();
+	let names = ["it's", "plain", "two\nlines", "with space"];
+	for name in names {
+		dir.file(name);
+	}
+	let output = run(
+		stest()
+			.args(["-l", "-f", "--quote", "--print0", "--sort", "name"])
+			.arg(&dir.0),
+		b"",
+	);
+	let quoted = String::from_utf8(output.stdout).unwrap();
+	let quoted = quoted.strip_suffix('\0').unwrap().split('\0');
+	let base = dir.0.to_string_lossy();
+	let expected = names.map(|name| format!("{}/{}", base, name));
+	assert_eq!(quoted.clone().count(), names.len());
+	for (quoted, expected) in quoted.zip(&expected) {
+		assert_eq!(quoted.starts_with('\''), !expected.ends_with("/plain"));
+		let echoed = Command::new("sh")
+			.arg("-c")
+			.arg(format!("printf %s {}", quoted))
+			.output()
+			.unwrap();
+		assert_eq!(String::from_utf8_lossy(&echoed.stdout), *expected);
+	}
+}
+
+/// The names printed under each header of `--group-by-dir`, by header.
+fn groups(output: &Output) -> Vec<(String, Vec<String>)> {
+	let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+	for line in lines(output) {
+		match line.strip_prefix('\t') {
+			Some(name) => groups.last_mut().unwrap().1.push(name.to_string()),
+			None => groups.push((line, Vec::new())),
+		}
+	}
+	groups.sort();
+	groups
+}
+
+#[test]
+fn group_by_dir_prints_each_directory_once() {
+	let dir = Dir::new();
+	for name in ["one/b", "one/a", "two/c", "top"] {
+		dir.file(name);
+	}
+	let base = dir.0.to_string_lossy();
+	let output = run(
+		stest()
+			.args(["-l", "-f", "--group-by-dir", "--sort", "name"])
+			.arg(&dir.0),
+		b"",
+	);
+	assert_eq!(groups(&output), [
+		(base.to_string(), vec!["top".to_string()]),
+		(format!("{}/one", base), vec!["a".to_string(), "b".to_string()]),
+		(format!("{}/two", base), vec!["c".to_string()]),
+	]);
+
+	let output = run(
+		stest()
+			.args(["-l", "-f", "--group-by-dir", "--group-header", "[{}]"])
+			.arg(dir.path("two")),
+		b"",
+	);
+	assert_eq!(lines(&output), [format!("[{}/two]", base), "\tc".to_string()]);
+}
+
+#[test]
+fn group_by_dir_rejects_json() {
+	let output = run(
+		stest().args(["-l", "--group-by-dir", "--format", "json", "."]),
+		b"",
+	);
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn which_finds_the_first_command_in_path() {
+	let dir = Dir::new();
+	let (first, second) = (dir.executable("a/cmd"), dir.executable("b/cmd"));
+	dir.file("a/other");
+	let path = env::join_paths([dir.path("a"), dir.path("b")]).unwrap();
+	let which = |flag| {
+		let mut stest = stest();
+		stest.args(["-x", flag, "cmd", "other", "missing"]).env("PATH", &path);
+		run(&mut stest, b"")
+	};
+	let output = which("--which");
+	assert_eq!(lines(&output), [first.to_string_lossy()]);
+	let output = which("--which-all");
+	assert_eq!(lines(&output), [
+		first.to_string_lossy(),
+		second.to_string_lossy()
+	]);
+
+	let output =
+		run(stest().args(["--which", "missing"]).env("PATH", &path), b"");
+	assert!(lines(&output).is_empty());
+	assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn icase_makes_every_pattern_ignore_case() {
+	let dir = Dir::new();
+	let (upper, lower) = (dir.file("README.md"), dir.file("notes.md"));
+	dir.file("main.rs");
+	let base = dir.0.to_string_lossy();
+	let find = |args: &[&str]| {
+		let mut stest = stest();
+		stest.args(["-l", "-f", "--sort", "name"]).args(args).arg(&dir.0);
+		lines(&run(&mut stest, b""))
+	};
+	assert_eq!(find(&["--name", "*.MD"]), Vec::<String>::new());
+	assert_eq!(find(&["--name", "readme*"]), Vec::<String>::new());
+	let both = [upper.to_string_lossy(), lower.to_string_lossy()];
+	assert_eq!(find(&["--icase", "--name", "*.MD"]), both);
+	assert_eq!(find(&["--iname", "*.MD"]), both);
+	assert_eq!(find(&["--regex", "^readme"]), Vec::<String>::new());
+	assert_eq!(find(&["--icase", "--regex", "^readme"]), [format!(
+		"{}/README.md",
+		base
+	)]);
+}
+
+/// Sets the modification time of the file at `path` to `secs` seconds
+/// since the epoch.
+fn set_mtime(path: &Path, secs: u64) {
+	set_mtime_nanos(path, secs, 0);
+}
+
+fn set_mtime_nanos(path: &Path, secs: u64, nanos: u32) {
+	let file = fs::File::options().write(true).open(path).unwrap();
+	let time = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+	file.set_modified(time).unwrap();
+}
+
+#[test]
+fn newer_than_time_takes_timestamps() {
+	let dir = Dir::new();
+	let (old, new) = (dir.file("old"), dir.file("new"));
+	// 2020-01-01 and 2024-01-01.
+	set_mtime(&old, 1_577_836_800);
+	set_mtime(&new, 1_704_067_200);
+	let test = |args: &[&str]| {
+		lines(&run(stest().args(args).arg(&old).arg(&new), b""))
+	};
+	let new = [new.to_string_lossy()];
+	let old = [old.to_string_lossy()];
+	assert_eq!(test(&["--newer-than-time", "2022-06-01T00:00:00Z"]), new);
+	assert_eq!(test(&["--newer-than-time", "@1600000000"]), new);
+	assert_eq!(test(&["--older-than-time", "2022-06-01 00:00:00+02:00"]), old);
+	assert_eq!(test(&["-n", "@1600000000"]), new);
+	assert_eq!(test(&["-o", "2022-06-01T00:00:00Z"]), old);
+	assert_eq!(test(&["-o", "1d"]).len(), 2);
+
+	let output =
+		run(stest().args(["--newer-than-time", "yesterday", "."]), b"");
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn fail_fast_stops_at_an_unreadable_entry() {
+	let dir = Dir::new();
+	dir.file("tree/file");
+	symlink("loop", dir.path("tree/loop")).unwrap();
+	let tree = dir.path("tree");
+	let output = run(stest().args(["-l", "-f"]).arg(&tree), b"");
+	assert_eq!(lines(&output), [dir.path("tree/file").to_string_lossy()]);
+	assert_eq!(output.status.code(), Some(0));
+	assert!(output.stderr.is_empty());
+
+	let output = run(stest().args(["-l", "-f", "--fail-fast"]).arg(&tree), b"");
+	assert_eq!(output.status.code(), Some(2));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(stderr.contains(&*dir.path("tree/loop").to_string_lossy()));
+}
+
+#[test]
+fn fail_fast_passes_over_missing_files() {
+	let dir = Dir::new();
+	let file = dir.file("file");
+	let output = run(
+		stest().args(["-f", "--fail-fast"]).arg(dir.path("missing")).arg(&file),
+		b"",
+	);
+	assert_eq!(lines(&output), [file.to_string_lossy()]);
+	assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn output_fd_writes_to_another_descriptor() {
+	let dir = Dir::new();
+	let file = dir.file("file");
+	// The results go to descriptor 3, which is the pipe read for stdout,
+	// while stest's own stdout goes nowhere.
+	let output = run(
+		Command::new("sh")
+			.arg("-c")
+			.arg("\"$0\" -f --output-fd 3 \"$1\" 3>&1 >/dev/null")
+			.arg(env!("CARGO_BIN_EXE_stest"))
+			.arg(&file)
+			.env_remove("POSIXLY_CORRECT"),
+		b"",
+	);
+	assert_eq!(lines(&output), [file.to_string_lossy()]);
+	assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn output_fd_must_be_open_for_writing() {
+	let output = run(stest().args(["--output-fd", "9", "."]), b"");
+	assert_eq!(output.status.code(), Some(2));
+	let output = run(stest().args(["--output-fd", "0", "."]), b"");
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn seconds_resolution_ties_files_in_the_same_second() {
+	let dir = Dir::new();
+	let (early, late) = (dir.file("early"), dir.file("late"));
+	set_mtime_nanos(&early, 1_700_000_000, 100_000_000);
+	set_mtime_nanos(&late, 1_700_000_000, 900_000_000);
+	let output = run(stest().arg("-n").arg(&early).arg(&late), b"");
+	assert_eq!(lines(&output), [late.to_string_lossy()]);
+	let output = run(stest().arg("-o").arg(&late).arg(&early), b"");
+	assert_eq!(lines(&output), [early.to_string_lossy()]);
+
+	let coarse = |flag| {
+		let mut stest = stest();
+		stest.args(["--seconds-resolution", flag]);
+		stest
+	};
+	let output = run(coarse("-n").arg(&early).arg(&late), b"");
+	assert!(lines(&output).is_empty());
+	assert_eq!(output.status.code(), Some(1));
+	let output = run(coarse("-o").arg(&late).arg(&early), b"");
+	assert!(lines(&output).is_empty());
+	let output =
+		run(coarse("--newer-than-time").arg("@1700000000.5").arg(&late), b"");
+	assert!(lines(&output).is_empty());
+}
+
+#[test]
+fn empty_names_are_never_tested() {
+	let dir = Dir::new();
+	let file = dir.file("file");
+	let output = run(stest().args(["-e", ""]).arg(&file), b"");
+	assert_eq!(lines(&output), [file.to_string_lossy()]);
+	assert_eq!(output.status.code(), Some(0));
+	assert!(output.stderr.is_empty());
+
+	// Inverted, a name which is no file would otherwise pass.
+	let output = run(stest().args(["-v", "-e", ""]), b"");
+	assert!(lines(&output).is_empty());
+	assert_eq!(output.status.code(), Some(1));
+
+	let input = format!("\n  \n{}\n\n", file.display());
+	let output = run(stest().args(["-e", "--verbose"]), input.as_bytes());
+	assert_eq!(lines(&output), [file.to_string_lossy()]);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert_eq!(stderr.matches("empty path").count(), 3);
+}
+
+#[test]
+fn list_predicates_lists_each_test_once() {
+	let output = run(stest().arg("--list-predicates"), b"");
+	assert_eq!(output.status.code(), Some(0));
+	let lines = lines(&output);
+	let mut longs = Vec::new();
+	for line in &lines {
+		let fields = line.split('\t').collect::<Vec<_>>();
+		assert_eq!(fields.len(), 4, "{}", line);
+		assert!(fields[0].is_empty() || fields[0].starts_with('-'));
+		assert!(fields[1].starts_with("--"));
+		assert!(!fields[3].is_empty());
+		longs.push(fields[1]);
+	}
+	for long in ["--file", "--newer", "--name", "--mtime-between", "--mime"] {
+		assert_eq!(longs.iter().filter(|&&l| l == long).count(), 1, "{}", long);
+	}
+	for long in ["--recurse", "--sort", "--quiet", "--list-predicates"] {
+		assert!(!longs.contains(&long), "{}", long);
+	}
+	let mut unique = longs.clone();
+	unique.sort();
+	unique.dedup();
+	assert_eq!(unique.len(), longs.len());
+	assert!(
+		lines.contains(
+			&"\t--mtime-between\tstart end\ttest that files \
+		were modified between start and end inclusive"
+				.to_string()
+		)
+	);
+}
+
+#[test]
+fn bash_completions_parse() {
+	let output = run(stest().args(["--completions", "bash"]), b"");
+	assert_eq!(output.status.code(), Some(0));
+	let script = String::from_utf8(output.stdout).unwrap();
+	for long in ["--paths-from-env", "--mtime-between", "--completions"] {
+		assert!(script.contains(long), "{}", long);
+	}
+	let dir = Dir::new();
+	let path = dir.write("stest.bash", &script);
+	if let Ok(status) = Command::new("bash").arg("-n").arg(&path).status() {
+		assert!(status.success());
+	}
+	let output = run(stest().args(["--completions", "tcsh"]), b"");
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn mtime_between_is_inclusive() {
+	let dir = Dir::new();
+	let files =
+		["before", "start", "inside", "end", "after"].map(|n| dir.file(n));
+	for (file, secs) in files.iter().zip([100, 200, 250, 300, 400]) {
+		set_mtime(file, 1_700_000_000 + secs);
+	}
+	let between = |start: &str, end: &str| {
+		let mut stest = stest();
+		stest.arg("--mtime-between").args([start, end]).args(&files);
+		run(&mut stest, b"")
+	};
+	let output = between("@1700000200", "@1700000300");
+	assert_eq!(lines(&output), names(&files[1..4]));
+
+	// Either end may be a file, whose own time is then inside the range.
+	let start = files[2].to_str().unwrap();
+	let output = between(start, "@1700000400");
+	assert_eq!(lines(&output), names(&files[2..]));
+
+	let output = between("@1700000300", "@1700000200");
+	assert_eq!(output.status.code(), Some(2));
+	assert!(
+		String::from_utf8_lossy(&output.stderr).contains("start is after end")
+	);
+	let output = run(stest().args(["--mtime-between", "@0"]), b"");
+	assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn compat_rejects_new_options() {
+	for args in [&["--compat", "--quote", "."][..], &["-z", "--compat", "."]] {
+		let output = run(stest().args(args), b"");
+		assert_eq!(output.status.code(), Some(2));
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		assert!(
+			stderr.contains("is not available in compat mode"),
+			"{}",
+			stderr
+		);
+	}
+	let output = run(
+		stest().args(["--sort", "name", "."]).env("POSIXLY_CORRECT", "1"),
+		b"",
+	);
+	assert_eq!(output.status.code(), Some(2));
+
+	let dir = Dir::new();
+	let file = dir.file("file");
+	let output = run(stest().args(["--compat", "-f"]).arg(&file), b"");
+	assert_eq!(lines(&output), [file.to_string_lossy()]);
+}
+
+#[test]
+fn compat_behaves_as_the_original() {
+	let dir = Dir::new();
+	let (first, second) = (dir.file("first"), dir.file("second"));
+	let input = format!("{}\n\n{}\n", first.display(), second.display());
+	let output = run(stest().args(["--compat", "-f"]), input.as_bytes());
+	assert_eq!(lines(&output), [first.to_string_lossy()]);
+	let output = run(stest().arg("-f"), input.as_bytes());
+	assert_eq!(lines(&output), names(&[first.clone(), second.clone()]));
+
+	// -n takes only files, so a timestamp names one which does not exist.
+	let output = run(stest().args(["--compat", "-n", "@0"]).arg(&first), b"");
+	assert_eq!(output.status.code(), Some(1));
+	let output = run(stest().args(["-n", "@0"]).arg(&first), b"");
+	assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn compat_prints_names_as_utf8() {
+	let dir = Dir::new();
+	let name = dir.0.join(OsStr::from_bytes(b"caf\xe9"));
+	fs::write(&name, "").unwrap();
+	let output = run(stest().args(["-l", "-f"]).arg(&dir.0), b"");
+	assert!(output.stdout.ends_with(b"/caf\xe9\n"));
+	let output = run(stest().args(["--compat", "-l", "-f"]).arg(&dir.0), b"");
+	assert!(output.stdout.ends_with("/caf\u{fffd}\n".as_bytes()));
+}
@@ -0,0 +1,183 @@
+//! The items chosen in earlier runs, kept with `--history`, which are ranked
+//! above others by how often and how recently they were chosen.
+
+use std::{
+	collections::HashMap,
+	fs,
+	io::{self, Write},
+	path::PathBuf,
+	process,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How long it takes for a choice to count half as much as a new one.
+const HALF_LIFE: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+struct Entry {
+	/// How many times the item was chosen, and when it last was, in seconds
+	/// since the epoch.
+	count: u32,
+	last: u64,
+}
+
+pub struct History {
+	path: PathBuf,
+	entries: HashMap<String, Entry>,
+	now: u64,
+}
+
+fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl History {
+	/// Reads the history kept at `path`, which is empty if there is no file
+	/// there yet. Lines which cannot be parsed are left out.
+	pub fn load(path: PathBuf) -> io::Result<History> {
+		let text = match fs::read_to_string(&path) {
+			Ok(text) => text,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(err) => return Err(err),
+		};
+		let mut entries = HashMap::new();
+		for line in text.lines() {
+			let mut fields = line.splitn(3, '\t');
+			let (Some(count), Some(last), Some(text)) =
+				(fields.next(), fields.next(), fields.next())
+			else {
+				continue;
+			};
+			if let (Ok(count), Ok(last)) = (count.parse(), last.parse()) {
+				entries.insert(text.to_string(), Entry { count, last });
+			}
+		}
+		Ok(History { path, entries, now: now() })
+	}
+
+	/// The frecency of `text`: each time it was chosen counts for one, decayed
+	/// by how long ago it last was. Items never chosen score zero.
+	pub fn score(&self, text: &str) -> f64 {
+		self.entries.get(text).map_or(0.0, |entry| {
+			let age = self.now.saturating_sub(entry.last) as f64;
+			entry.count as f64 * 0.5f64.powf(age / HALF_LIFE)
+		})
+	}
+
+	pub fn record(&mut self, text: &str) {
+		let entry = self
+			.entries
+			.entry(text.to_string())
+			.or_insert(Entry { count: 0, last: 0 });
+		entry.count = entry.count.saturating_add(1);
+		entry.last = self.now;
+	}
+
+	/// Writes the history back, to a temporary file which then replaces the
+	/// old one, so that it is never left half written. The directory it is
+	/// kept in is made if there is none yet, as for a new `~/.cache/wmenu`.
+	pub fn save(&self) -> io::Result<()> {
+		if let Some(dir) =
+			self.path.parent().filter(|d| !d.as_os_str().is_empty())
+		{
+			fs::create_dir_all(dir)?;
+		}
+		let mut tmp = self.path.clone().into_os_string();
+		tmp.push(format!(".{}.tmp", process::id()));
+		let tmp = PathBuf::from(tmp);
+		let result = (|| {
+			let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+			for (text, entry) in &self.entries {
+				writeln!(file, "{}\t{}\t{}", entry.count, entry.last, text)?;
+			}
+			file.into_inner()?.sync_all()?;
+			fs::rename(&tmp, &self.path)
+		})();
+		if result.is_err() {
+			let _ = fs::remove_file(&tmp);
+		}
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		env,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+
+	/// A path in a directory of its own, which has yet to be made.
+	fn path() -> PathBuf {
+		static N: AtomicUsize = AtomicUsize::new(0);
+		let n = N.fetch_add(1, Ordering::Relaxed);
+		let dir = format!("wmenu-history-{}-{}", process::id(), n);
+		env::temp_dir().join(dir).join("history")
+	}
+
+	fn load(text: &str) -> History {
+		let path = path();
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, text).unwrap();
+		let history = History::load(path.clone()).unwrap();
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+		history
+	}
+
+	#[test]
+	fn choices_are_ranked_by_how_often_and_recently_they_were_made() {
+		let now = now();
+		let weeks = |n: u64| now - n * HALF_LIFE as u64;
+		let history = load(&format!(
+			"4\t{}\told\n1\t{}\tnew\n4\t{}\toften\n3\t{}\tlately\n",
+			weeks(4),
+			now,
+			now,
+			weeks(1),
+		));
+		let score = |text| history.score(text);
+		assert!(score("often") > score("lately"));
+		assert!(score("lately") > score("new"));
+		assert!(score("new") > score("old"));
+		assert!(score("old") > score("never"));
+		assert_eq!(score("never"), 0.0);
+		assert!((score("old") - 4.0 / 16.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn recording_a_choice_ranks_it_higher() {
+		let mut history = load("3\t0\tlong ago\n");
+		history.record("now");
+		assert!(history.score("now") > history.score("long ago"));
+		let once = history.score("now");
+		history.record("now");
+		assert_eq!(history.score("now"), 2.0 * once);
+	}
+
+	#[test]
+	fn lines_which_cannot_be_parsed_are_left_out() {
+		let history =
+			load("x\t0\tbad count\n1\tx\tbad time\n1\t0\n2\t0\ta\ttab\n");
+		assert_eq!(history.entries.len(), 1);
+		assert_eq!(history.entries["a\ttab"].count, 2);
+	}
+
+	#[test]
+	fn histories_are_saved_and_read_back() {
+		let path = path();
+		let dir = path.parent().unwrap().to_path_buf();
+		let mut history = History::load(path.clone()).unwrap();
+		assert!(history.entries.is_empty());
+		history.record("one");
+		history.record("two");
+		history.record("two");
+		history.save().unwrap();
+		let saved = History::load(path.clone()).unwrap();
+		assert_eq!(saved.score("two"), 2.0 * saved.score("one"));
+		// Nothing but the history itself is left behind.
+		let names: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+		assert_eq!(names.len(), 1);
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
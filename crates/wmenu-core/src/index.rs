@@ -0,0 +1,218 @@
+//! The matched parts of many items, kept in one buffer along with a mask of
+//! the characters in each, by which most items are ruled out before they
+//! are matched.
+
+use std::ops::Range;
+
+use crate::matcher::{Matcher, Mode};
+
+/// The items' keys, one after another in `text`, and a mask of the
+/// characters each has in any of the ways they may be folded.
+#[derive(Clone, Default)]
+pub struct Index {
+	text: String,
+	ends: Vec<usize>,
+	masks: Vec<u64>,
+}
+
+impl Index {
+	/// Adds the key of an item after those of the others.
+	pub fn push(&mut self, key: &str) {
+		self.text.push_str(key);
+		self.ends.push(self.text.len());
+		self.masks.push(key.chars().fold(0, |mask, c| mask | variants(c)));
+	}
+
+	pub fn len(&self) -> usize {
+		self.ends.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ends.is_empty()
+	}
+
+	/// The key of the item at `i`.
+	pub fn get(&self, i: usize) -> &str {
+		&self.text[self.range(i)]
+	}
+
+	fn range(&self, i: usize) -> Range<usize> {
+		let start = match i {
+			0 => 0,
+			i => self.ends[i - 1],
+		};
+		start..self.ends[i]
+	}
+
+	/// Those of `candidates` which have every character `query` needs, as
+	/// `matcher` folds them. Those left may still not match, but those
+	/// dropped cannot. Regular expressions need no character in particular,
+	/// so rule nothing out.
+	pub fn sift(
+		&self,
+		matcher: &Matcher,
+		query: &str,
+		mut candidates: Vec<usize>,
+	) -> Vec<usize> {
+		if matcher.mode == Mode::Regex {
+			return candidates;
+		}
+		let chars = matcher.tokens(query).into_iter().flatten();
+		let need = chars.fold(0, |mask, c| mask | bit(c));
+		if need != 0 {
+			candidates.retain(|&i| self.masks[i] & need == need);
+		}
+		candidates
+	}
+}
+
+impl<'a> Extend<&'a str> for Index {
+	fn extend<T: IntoIterator<Item = &'a str>>(&mut self, keys: T) {
+		for key in keys {
+			self.push(key);
+		}
+	}
+}
+
+impl<'a> FromIterator<&'a str> for Index {
+	fn from_iter<T: IntoIterator<Item = &'a str>>(keys: T) -> Index {
+		let mut index = Index::default();
+		index.extend(keys);
+		index
+	}
+}
+
+/// The bits of `c` as each way of folding it, with and without case and
+/// marks, gives it, so that the mask holds whichever the matcher needs.
+fn variants(c: char) -> u64 {
+	if c.is_ascii() {
+		return bit(c) | bit(c.to_ascii_lowercase());
+	}
+	let mut mask = 0;
+	for icase in [false, true] {
+		for normalize in [false, true] {
+			let matcher = Matcher { icase, normalize, ..Matcher::default() };
+			mask |= matcher.fold(c).map_or(0, bit);
+		}
+	}
+	mask
+}
+
+/// The bit of a character: one for each letter in either case and each
+/// digit, and two shared by everything else.
+fn bit(c: char) -> u64 {
+	let i = match c {
+		'a'..='z' => c as u32 - 'a' as u32,
+		'A'..='Z' => 26 + c as u32 - 'A' as u32,
+		'0'..='9' => 52 + c as u32 - '0' as u32,
+		c => 62 + c as u32 % 2,
+	};
+	1 << i
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::matcher::Sort;
+
+	const ITEMS: &[&str] = &[
+		"Foo Bar",
+		"foobar",
+		"\u{c9}COLE",
+		"e\u{301}cole",
+		"stra\u{df}e",
+		"\u{fb01}le",
+		"na\u{ef}ve",
+		"NAIVE",
+		"x-ray 42",
+		"\u{3c3}\u{3bf}\u{3c6}\u{3cc}\u{3c2}",
+		"\u{3a3}\u{39f}\u{3a6}\u{39f}\u{3a3}",
+		"\u{130}stanbul",
+		"\u{65e5}\u{672c}\u{8a9e}",
+		"",
+	];
+
+	/// Every query of up to three characters taken in order from an item,
+	/// with each of them upper- and lowercased, and a few with spaces.
+	fn queries() -> Vec<String> {
+		let mut queries = vec![String::new(), " ".into(), "foo bar".into()];
+		queries.push("bar foo".into());
+		queries.push("e cole".into());
+		for item in ITEMS {
+			let chars = item.chars().collect::<Vec<_>>();
+			for i in 0..chars.len() {
+				for j in i + 1..=(i + 3).min(chars.len()) {
+					let query = chars[i..j].iter().collect::<String>();
+					queries.push(query.to_uppercase());
+					queries.push(query.to_lowercase());
+					queries.push(query);
+				}
+			}
+		}
+		queries
+	}
+
+	#[test]
+	fn sift_never_drops_a_match() {
+		let index = ITEMS.iter().copied().collect::<Index>();
+		let queries = queries();
+		let modes = [Mode::Substring, Mode::Fuzzy, Mode::Prefix, Mode::Exact];
+		for mode in modes {
+			for icase in [false, true] {
+				for normalize in [false, true] {
+					for tokens in [false, true] {
+						let matcher = Matcher {
+							mode,
+							icase,
+							normalize,
+							tokens,
+							sort: Sort::None,
+						};
+						for query in &queries {
+							let all = (0..index.len()).collect();
+							let sifted = index.sift(&matcher, query, all);
+							let matched =
+								matcher.filter(ITEMS.iter().copied(), query);
+							for i in matched {
+								assert!(
+									sifted.contains(&i),
+									"{:?} dropped for {:?}",
+									ITEMS[i],
+									query,
+								);
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn sift_drops_items_lacking_a_character() {
+		let index = ["foo", "bar", "baz", "Zed"].into_iter().collect::<Index>();
+		let plain = Matcher::default();
+		// Masks hold every way a key may be folded, so Zed is left for the
+		// matcher to rule out.
+		assert_eq!(index.sift(&plain, "z", vec![0, 1, 2, 3]), [2, 3]);
+		assert_eq!(index.sift(&plain, "Z", vec![0, 1, 2, 3]), [3]);
+		assert_eq!(
+			index.sift(&plain, "q", vec![0, 1, 2, 3]),
+			Vec::<usize>::new()
+		);
+		assert_eq!(index.sift(&plain, "a", vec![0, 2, 3]), [2]);
+		assert_eq!(index.sift(&plain, "", vec![0, 1, 2, 3]), [0, 1, 2, 3]);
+		let regex = Matcher { mode: Mode::Regex, ..plain };
+		assert_eq!(index.sift(&regex, "q", vec![0, 1, 2, 3]), [0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn keys_are_kept_in_order() {
+		let mut index = ["one", "", "three"].into_iter().collect::<Index>();
+		index.push("four");
+		assert_eq!(index.len(), 4);
+		let keys = (0..index.len()).map(|i| index.get(i)).collect::<Vec<_>>();
+		assert_eq!(keys, ["one", "", "three", "four"]);
+		assert!(Index::default().is_empty());
+	}
+}
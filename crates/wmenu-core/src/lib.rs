@@ -0,0 +1,20 @@
+//! The logic of the menu which is not tied to a display, as used by the
+//! `dmenu` binary, for those who draw a menu on surfaces of their own.
+//!
+//! A [`Matcher`] filters and ranks items by the text typed so far, as set
+//! by its [`Mode`] and [`Sort`]; a [`Prior`] keeps its last matches so that
+//! typing on only matches those again; its [`Matches`] may be found in
+//! parts, on threads of their own, and joined before they are sorted. A
+//! [`History`] ranks the items chosen in earlier runs above others, and is
+//! given to the matcher's callers to sort by. An [`Index`] keeps the keys
+//! of many items compactly, and sifts out those which lack a character the
+//! query needs before the matcher is run on them. Reading keys, drawing and
+//! the selection are left to the menu which embeds these.
+
+pub mod history;
+pub mod index;
+pub mod matcher;
+
+pub use history::History;
+pub use index::Index;
+pub use matcher::{Matcher, Matches, Mode, Prior, Sort};
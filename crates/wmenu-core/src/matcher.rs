@@ -0,0 +1,856 @@
+//! Filtering of items by the text typed so far.
+
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+/// Scores for fuzzy matching: every matched character scores, more so at
+/// the start of a word or straight after the previous match, and gaps
+/// between matches cost a little, more to open than to extend.
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+/// The first character of the query counts double towards its bonus, so
+/// that where a match starts matters most.
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// How the query is matched against items.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Mode {
+	/// Items contain the query.
+	#[default]
+	Substring,
+	/// Items contain the characters of the query in order, and are scored
+	/// by how well.
+	Fuzzy,
+	/// Items start with the query.
+	Prefix,
+	/// Items are the query.
+	Exact,
+	/// Items contain a match for the query as a regular expression.
+	Regex,
+}
+
+impl Mode {
+	/// Every mode, in the order Ctrl+Tab cycles through them.
+	const ALL: [Mode; 5] =
+		[Mode::Substring, Mode::Fuzzy, Mode::Prefix, Mode::Exact, Mode::Regex];
+
+	pub fn parse(name: &str) -> Option<Mode> {
+		let names = ["substring", "fuzzy", "prefix", "exact", "regex"];
+		let i = names.iter().position(|&n| n == name)?;
+		Some(Mode::ALL[i])
+	}
+
+	/// The mode after this one.
+	pub fn next(self) -> Mode {
+		let i = Mode::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+		Mode::ALL[(i + 1) % Mode::ALL.len()]
+	}
+}
+
+/// The order matches are listed in.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Sort {
+	/// The order items were read in.
+	None,
+	/// By their text, and then in the order read.
+	Alpha,
+	/// Shortest first, and then in the order read.
+	Length,
+	/// Best first, as the mode ranks them and then by `--history`.
+	#[default]
+	Score,
+}
+
+impl Sort {
+	pub fn parse(name: &str) -> Option<Sort> {
+		match name {
+			"none" => Some(Sort::None),
+			"alpha" => Some(Sort::Alpha),
+			"length" => Some(Sort::Length),
+			"score" => Some(Sort::Score),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Matcher {
+	pub mode: Mode,
+	/// Compare characters regardless of case.
+	pub icase: bool,
+	/// Split the query at spaces into tokens, which items must match all of
+	/// in any order, rather than matching the query as a whole. Only
+	/// substring and fuzzy matching split it.
+	pub tokens: bool,
+	/// The order of the matches, which are only ranked with `Sort::Score`.
+	pub sort: Sort,
+	/// Compare characters by their decomposed forms, without accents or
+	/// other marks, so that `u` matches `ü` however it is written. Regular
+	/// expressions are matched as they are.
+	pub normalize: bool,
+}
+
+/// The items which matched the last query, so that when it is typed on, only
+/// they need to be matched again.
+#[derive(Default)]
+pub struct Prior {
+	matcher: Matcher,
+	query: String,
+	/// How many items there were, and the indices of those matching, in
+	/// input order.
+	len: usize,
+	matches: Vec<usize>,
+}
+
+impl Prior {
+	/// The indices of the items of the first `len` which can match `query`,
+	/// in input order. Typing on only rules items out, unless items must be
+	/// the query or match it as a regular expression, so if `query` starts
+	/// with the last, these are its matches and any items read since.
+	pub fn candidates(
+		&self,
+		matcher: &Matcher,
+		query: &str,
+		len: usize,
+	) -> Vec<usize> {
+		if *matcher != self.matcher
+			|| matches!(matcher.mode, Mode::Exact | Mode::Regex)
+			|| !query.starts_with(&self.query)
+			|| len < self.len
+		{
+			return (0..len).collect();
+		}
+		let mut candidates = self.matches.clone();
+		candidates.extend(self.len..len);
+		candidates
+	}
+
+	/// Remembers `matches` as those of the first `len` items for `query`,
+	/// matched by `matcher`.
+	pub fn record(
+		&mut self,
+		matcher: &Matcher,
+		query: &str,
+		len: usize,
+		matches: &[usize],
+	) {
+		self.matcher = *matcher;
+		self.query.clear();
+		self.query.push_str(query);
+		self.len = len;
+		self.matches.clear();
+		self.matches.extend(matches);
+		self.matches.sort_unstable();
+	}
+}
+
+/// The matches among some items, each with its index and how well it
+/// ranks, in input order. Those among items read later may be joined on,
+/// so that items can be matched in parts, and the whole sorted after.
+#[derive(Default)]
+pub struct Matches(Vec<(Rank, usize)>);
+
+/// Where a match is put when ranked, lowest first.
+type Rank = (i64, usize);
+
+impl Matches {
+	/// Joins on `later`, the matches among items after these.
+	pub fn join(&mut self, later: Matches) {
+		self.0.extend(later.0);
+	}
+}
+
+impl Matcher {
+	/// The indices of the items matching `query`, in the order sorted.
+	pub fn filter<'a>(
+		&self,
+		items: impl IntoIterator<Item = &'a str>,
+		query: &str,
+	) -> Vec<usize> {
+		if !matches!(self.sort, Sort::Alpha | Sort::Length) {
+			let matches = self.matches(items.into_iter().enumerate(), query);
+			return self.order(matches, |_| "");
+		}
+		let items = items.into_iter().collect::<Vec<_>>();
+		let matches = self.matches(items.iter().copied().enumerate(), query);
+		self.order(matches, |i| items[i])
+	}
+
+	/// The items matching `query` among `items`, given with their indices
+	/// in input order, for [`order`](Matcher::order) to sort.
+	pub fn matches<'a>(
+		&self,
+		items: impl IntoIterator<Item = (usize, &'a str)>,
+		query: &str,
+	) -> Matches {
+		// Only the query is folded up front; items are folded a character at
+		// a time as they are compared, so that nothing is allocated for them.
+		let tokens = self.tokens(query);
+		let folded = query.chars().filter_map(|c| self.fold(c));
+		let folded = folded.collect::<Vec<_>>();
+		let items = items.into_iter();
+		let unranked = |(i, _): (usize, &str)| ((0, 0), i);
+		Matches(match self.mode {
+			Mode::Substring => self.substring(items, &folded, &tokens),
+			Mode::Fuzzy => self.fuzzy(items, &tokens),
+			Mode::Prefix => items
+				.filter(|(_, item)| self.starts_with(item, &folded))
+				.map(unranked)
+				.collect(),
+			Mode::Exact => items
+				.filter(|(_, item)| {
+					self.starts_with(item, &folded)
+						&& self.folded(item).count() == folded.len()
+				})
+				.map(unranked)
+				.collect(),
+			// A query which is not yet a whole expression matches nothing.
+			Mode::Regex => match self.regex(query) {
+				Some(regex) => items
+					.filter(|(_, item)| regex.is_match(item))
+					.map(unranked)
+					.collect(),
+				None => Vec::new(),
+			},
+		})
+	}
+
+	/// The indices of `matches` in the order sorted, best first if ranked,
+	/// where `text` is the item at an index.
+	pub fn order<'a>(
+		&self,
+		matches: Matches,
+		text: impl Fn(usize) -> &'a str,
+	) -> Vec<usize> {
+		let Matches(mut matches) = matches;
+		// Matches are found in input order, and the sorts are stable, so
+		// items alike keep it.
+		match self.sort {
+			Sort::None => {}
+			Sort::Alpha => matches.sort_by_key(|&(_, i)| text(i)),
+			Sort::Length => {
+				matches.sort_by_key(|&(_, i)| text(i).chars().count())
+			}
+			Sort::Score => matches.sort_by_key(|&(rank, _)| rank),
+		}
+		matches.into_iter().map(|(_, i)| i).collect()
+	}
+
+	/// The folded query, split at spaces into tokens if tokens are matched.
+	pub(crate) fn tokens(&self, query: &str) -> Vec<Vec<char>> {
+		let fold =
+			|token: &str| token.chars().filter_map(|c| self.fold(c)).collect();
+		match self.tokens && matches!(self.mode, Mode::Substring | Mode::Fuzzy)
+		{
+			true => {
+				query.split(' ').filter(|t| !t.is_empty()).map(fold).collect()
+			}
+			false => vec![fold(query)],
+		}
+	}
+
+	/// The query compiled as a regular expression, if it is one.
+	fn regex(&self, query: &str) -> Option<Regex> {
+		RegexBuilder::new(query).case_insensitive(self.icase).build().ok()
+	}
+
+	/// The byte ranges of `text` which match `query`, in order, for showing
+	/// why it matched.
+	pub fn ranges(&self, text: &str, query: &str) -> Vec<Range<usize>> {
+		if self.mode == Mode::Regex {
+			let Some(regex) = self.regex(query) else {
+				return Vec::new();
+			};
+			let ranges = regex.find_iter(text).map(|m| m.range());
+			return ranges.filter(|range| !range.is_empty()).collect();
+		}
+		let mut ranges = Vec::new();
+		for token in self.tokens(query).iter().filter(|t| !t.is_empty()) {
+			let found = match self.mode {
+				Mode::Substring => self.find(text, token),
+				_ => self.starts_with(text, token).then_some(0),
+			};
+			if self.mode == Mode::Fuzzy {
+				let Some(window) = self.window(token, text) else {
+					continue;
+				};
+				// The characters scored are those matched front to back in
+				// the window.
+				let mut q = token.iter().peekable();
+				for (i, c) in text[window.clone()].char_indices() {
+					if q.next_if(|&&q| self.fold(c) == Some(q)).is_some() {
+						mark(&mut ranges, window.start + i, c);
+					}
+				}
+			} else if let Some(j) = found {
+				// Marks left out of matching are marked with the characters
+				// they follow.
+				let mut n = 0;
+				for (i, c) in text[j..].char_indices() {
+					if self.fold(c).is_some() {
+						if n == token.len() {
+							break;
+						}
+						n += 1;
+					}
+					mark(&mut ranges, j + i, c);
+				}
+			}
+		}
+		// Tokens may match the same characters, so the ranges of each are
+		// sorted and joined where they meet.
+		ranges.sort_by_key(|range| range.start);
+		let mut joined: Vec<Range<usize>> = Vec::new();
+		for range in ranges {
+			match joined.last_mut() {
+				Some(last) if range.start <= last.end => {
+					last.end = last.end.max(range.end)
+				}
+				_ => joined.push(range),
+			}
+		}
+		joined
+	}
+
+	/// The character `c` is compared as, or `None` for a mark which is
+	/// left out of comparing.
+	pub(crate) fn fold(&self, c: char) -> Option<char> {
+		let c = match self.normalize {
+			true if is_combining_mark(c) => return None,
+			true => base(c),
+			false => c,
+		};
+		if !self.icase {
+			return Some(c);
+		}
+		Some(match c {
+			// Final sigma lowercases to itself, but is the same letter as σ.
+			'ς' => 'σ',
+			// Where lowercasing gives several characters, as for İ, the first
+			// is the letter itself and the rest are combining marks.
+			c => c.to_lowercase().next().unwrap_or(c),
+		})
+	}
+
+	/// The characters of `text` as they are compared.
+	fn folded<'a>(&self, text: &'a str) -> impl Iterator<Item = char> + 'a {
+		let matcher = *self;
+		text.chars().filter_map(move |c| matcher.fold(c))
+	}
+
+	/// Whether `text` starts with the folded `query`.
+	fn starts_with(&self, text: &str, query: &[char]) -> bool {
+		let mut chars = self.folded(text);
+		query.iter().all(|&q| chars.next() == Some(q))
+	}
+
+	/// The byte offset of the first place `text` contains the folded `query`.
+	fn find(&self, text: &str, query: &[char]) -> Option<usize> {
+		// The end is tried as well, where only an empty query is found.
+		text.char_indices()
+			.map(|(j, _)| j)
+			.chain([text.len()])
+			.find(|&j| self.starts_with(&text[j..], query))
+	}
+
+	/// Matches items containing every token, ranking those which are the
+	/// whole query first, then those starting with the first token.
+	fn substring<'a>(
+		&self,
+		items: impl Iterator<Item = (usize, &'a str)>,
+		query: &[char],
+		tokens: &[Vec<char>],
+	) -> Vec<(Rank, usize)> {
+		let first = tokens.first().map_or(&[][..], Vec::as_slice);
+		items
+			.filter(|(_, item)| {
+				tokens.iter().all(|token| self.find(item, token).is_some())
+			})
+			.map(|(i, item)| {
+				// Without ranking, every match is put with the exact ones.
+				let rank = if self.sort != Sort::Score
+					|| self.starts_with(item, query)
+						&& item.chars().count() == query.len()
+				{
+					0
+				} else if self.starts_with(item, first) {
+					1
+				} else {
+					2
+				};
+				((rank, 0), i)
+			})
+			.collect()
+	}
+
+	/// Matches items matching every token, scored by the sum of the scores
+	/// of each. Of items scoring the same, shorter ones come first, as they
+	/// have less besides the query in them.
+	fn fuzzy<'a>(
+		&self,
+		items: impl Iterator<Item = (usize, &'a str)>,
+		tokens: &[Vec<char>],
+	) -> Vec<(Rank, usize)> {
+		items
+			.filter_map(|(i, item)| {
+				let scores = tokens.iter().map(|token| self.score(token, item));
+				Some(((-scores.sum::<Option<i64>>()?, item.len()), i))
+			})
+			.collect()
+	}
+
+	/// Scores `candidate` against `query` if it contains the query's
+	/// characters in order.
+	fn score(&self, query: &[char], candidate: &str) -> Option<i64> {
+		if query.is_empty() {
+			return Some(0);
+		}
+		let window = self.window(query, candidate)?;
+
+		let mut score = 0;
+		let mut q = 0;
+		let mut consecutive = false;
+		let mut prev = candidate[..window.start]
+			.chars()
+			.rfind(|&c| self.fold(c).is_some());
+		for c in candidate[window].chars() {
+			let Some(folded) = self.fold(c) else {
+				continue;
+			};
+			if q < query.len() && folded == query[q] {
+				let mut bonus = 0;
+				if prev.is_none_or(|prev| boundary(prev, c)) {
+					bonus += BONUS_BOUNDARY;
+				}
+				if consecutive {
+					bonus += BONUS_CONSECUTIVE;
+				}
+				if q == 0 {
+					bonus *= BONUS_FIRST_CHAR_MULTIPLIER;
+				}
+				score += SCORE_MATCH + bonus;
+				q += 1;
+				consecutive = true;
+			} else {
+				// The window starts with a match, so a gap always follows one.
+				score += match consecutive {
+					true => SCORE_GAP_START,
+					false => SCORE_GAP_EXTENSION,
+				};
+				consecutive = false;
+			}
+			prev = Some(c);
+		}
+		Some(score)
+	}
+
+	/// The byte range of `candidate` the query is matched in, if it contains
+	/// the query's characters in order.
+	///
+	/// This is the shortest window ending at the first complete match: the
+	/// query is found front to back, then back to front from where it ended,
+	/// which gives a tighter match than the first found without searching
+	/// every alignment.
+	fn window(&self, query: &[char], candidate: &str) -> Option<Range<usize>> {
+		let mut q = query.iter();
+		let mut want = q.next();
+		let mut end = None;
+		for (i, c) in candidate.char_indices() {
+			if self.fold(c).is_some_and(|c| want == Some(&c)) {
+				want = q.next();
+				if want.is_none() {
+					end = Some(i + c.len_utf8());
+					break;
+				}
+			}
+		}
+		let end = end?;
+		let mut q = query.iter().rev();
+		let mut want = q.next();
+		let mut start = end;
+		for (i, c) in candidate[..end].char_indices().rev() {
+			if self.fold(c).is_some_and(|c| want == Some(&c)) {
+				want = q.next();
+				if want.is_none() {
+					start = i;
+					break;
+				}
+			}
+		}
+		Some(start..end)
+	}
+}
+
+/// The first character `c` decomposes to, which is the letter without its
+/// marks, and for ligatures and other compatibility characters the first of
+/// those they stand for, as `f` for `ﬁ`.
+fn base(c: char) -> char {
+	let mut first = None;
+	decompose_compatible(c, |d| {
+		first.get_or_insert(d);
+	});
+	first.unwrap_or(c)
+}
+
+/// Adds the character `c`, at byte `i`, to the ranges, joining it onto the
+/// last if it follows straight on.
+fn mark(ranges: &mut Vec<Range<usize>>, i: usize, c: char) {
+	match ranges.last_mut() {
+		Some(range) if range.end == i => range.end += c.len_utf8(),
+		_ => ranges.push(i..i + c.len_utf8()),
+	}
+}
+
+/// Whether `c` starts a word, coming after a separator or as the first
+/// capital of a camel-cased word.
+fn boundary(prev: char, c: char) -> bool {
+	!prev.is_alphanumeric() && c.is_alphanumeric()
+		|| prev.is_lowercase() && c.is_uppercase()
+		|| !prev.is_numeric() && c.is_numeric()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn matcher(mode: Mode) -> Matcher {
+		Matcher { mode, ..Matcher::default() }
+	}
+
+	#[test]
+	fn substring() {
+		let items = ["foobar", "barfoo", "bar", "afoob"];
+		let matcher = matcher(Mode::Substring);
+		assert_eq!(matcher.filter(items, "foo"), [0, 1, 3]);
+		assert_eq!(matcher.filter(items, ""), [0, 1, 2, 3]);
+		assert_eq!(matcher.filter(items, "baz"), Vec::<usize>::new());
+	}
+
+	#[test]
+	fn substring_ranks_whole_then_prefix_matches_first() {
+		let items = ["xfoo", "foobar", "foo", "afoo"];
+		assert_eq!(matcher(Mode::Substring).filter(items, "foo"), [2, 1, 0, 3]);
+	}
+
+	#[test]
+	fn prefix() {
+		let items = ["foobar", "barfoo", "fo", "foo"];
+		assert_eq!(matcher(Mode::Prefix).filter(items, "foo"), [0, 3]);
+		assert_eq!(matcher(Mode::Prefix).filter(items, "")[..], [0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn exact() {
+		let items = ["foo", "food", "fo", "foo", "Foo"];
+		assert_eq!(matcher(Mode::Exact).filter(items, "foo"), [0, 3]);
+		let icase = Matcher { icase: true, ..matcher(Mode::Exact) };
+		assert_eq!(icase.filter(items, "FOO"), [0, 3, 4]);
+	}
+
+	#[test]
+	fn regex() {
+		let items = ["foo", "fao", "food", "bar"];
+		assert_eq!(matcher(Mode::Regex).filter(items, "^f.o$"), [0, 1]);
+		// A query which does not yet compile matches nothing.
+		assert_eq!(
+			matcher(Mode::Regex).filter(items, "f("),
+			Vec::<usize>::new()
+		);
+	}
+
+	#[test]
+	fn fuzzy_matches_subsequences() {
+		let items = ["foobar", "fizzbuzz", "barfoo", "fb"];
+		let matcher = matcher(Mode::Fuzzy);
+		let mut found = matcher.filter(items, "fb");
+		found.sort();
+		assert_eq!(found, [0, 1, 3]);
+		assert!(matcher.score(&['b', 'f'], "foobar").is_none());
+		assert_eq!(matcher.score(&[], "anything"), Some(0));
+	}
+
+	#[test]
+	fn fuzzy_prefers_runs_and_word_starts() {
+		let matcher = matcher(Mode::Fuzzy);
+		assert_eq!(matcher.filter(["fxoxo", "foo"], "foo"), [1, 0]);
+		assert_eq!(matcher.filter(["cab", "c-a-b"], "ab"), [1, 0]);
+		assert_eq!(matcher.filter(["xmain", "main"], "main"), [1, 0]);
+		let icase = Matcher { icase: true, ..matcher };
+		assert_eq!(icase.filter(["fizbar", "fizBar"], "fb"), [1, 0]);
+	}
+
+	#[test]
+	fn fuzzy_breaks_ties_by_length_then_input_order() {
+		let matcher = matcher(Mode::Fuzzy);
+		assert_eq!(matcher.filter(["abxx", "ab"], "ab"), [1, 0]);
+		assert_eq!(matcher.filter(["ab", "ab", "ab"], "ab"), [0, 1, 2]);
+	}
+
+	#[test]
+	fn fuzzy_scores_the_tightest_window() {
+		let matcher = matcher(Mode::Fuzzy);
+		let query = ['a', 'b'];
+		assert_eq!(matcher.window(&query, "a_a_ab"), Some(4..6));
+		assert_eq!(matcher.window(&query, "ba"), None);
+	}
+
+	#[test]
+	fn icase_matches_across_case_in_every_mode() {
+		let modes = [Mode::Substring, Mode::Fuzzy, Mode::Prefix, Mode::Regex];
+		for mode in modes {
+			let plain = matcher(mode);
+			let icase = Matcher { icase: true, ..plain };
+			assert_eq!(plain.filter(["foobar"], "Foo"), Vec::<usize>::new());
+			assert_eq!(icase.filter(["foobar"], "Foo"), [0]);
+			assert_eq!(icase.filter(["FOOBAR"], "foo"), [0]);
+		}
+	}
+
+	#[test]
+	fn icase_folds_case() {
+		let items = ["FooBar", "foobar", "ΣΟΦΟΣ", "σοφος", "İstanbul"];
+		let plain = matcher(Mode::Substring);
+		let icase = Matcher { icase: true, ..plain };
+		assert_eq!(plain.filter(items, "foo"), [1]);
+		assert_eq!(icase.filter(items, "FOO"), [0, 1]);
+		assert_eq!(icase.filter(items, "σοφοσ"), [2, 3]);
+		assert_eq!(icase.filter(items, "σοφος"), [2, 3]);
+		assert_eq!(icase.filter(items, "istan"), [4]);
+		assert_eq!(plain.filter(items, "istan"), Vec::<usize>::new());
+	}
+
+	#[test]
+	fn normalize_leaves_out_marks() {
+		// ü precomposed, and u with a combining diaeresis.
+		let items = ["m\u{fc}nchen", "mu\u{308}nchen", "munchen", "\u{fb01}le"];
+		let plain = matcher(Mode::Substring);
+		let normalize = Matcher { normalize: true, ..plain };
+		assert_eq!(plain.filter(items, "mun"), [2]);
+		assert_eq!(normalize.filter(items, "mun"), [0, 1, 2]);
+		assert_eq!(normalize.filter(items, "m\u{fc}n"), [0, 1, 2]);
+		// A ligature is compared as the first letter it stands for.
+		assert_eq!(plain.filter(items, "fle"), Vec::<usize>::new());
+		assert_eq!(normalize.filter(items, "fle"), [3]);
+		let both = Matcher { icase: true, ..normalize };
+		assert_eq!(both.filter(["M\u{dc}NCHEN"], "mun"), [0]);
+		assert_eq!(
+			normalize.filter(["M\u{dc}NCHEN"], "mun"),
+			Vec::<usize>::new()
+		);
+	}
+
+	#[test]
+	fn joined_parts_keep_input_order() {
+		let items =
+			["b", "ab", "abc", "a", "cab", "ba", "bab", "x", "abab", "b a"];
+		for mode in [Mode::Substring, Mode::Fuzzy, Mode::Prefix] {
+			for sort in [Sort::None, Sort::Alpha, Sort::Length, Sort::Score] {
+				let matcher = Matcher { mode, sort, ..Matcher::default() };
+				let whole = matcher.filter(items, "ab");
+				for parts in 1..=items.len() {
+					let indexed = items.iter().copied().enumerate();
+					let indexed = indexed.collect::<Vec<_>>();
+					let mut matches = Matches::default();
+					for part in indexed.chunks(items.len().div_ceil(parts)) {
+						let part = part.iter().copied();
+						matches.join(matcher.matches(part, "ab"));
+					}
+					let found = matches.0.iter().map(|&(_, i)| i);
+					assert!(
+						found.clone().is_sorted(),
+						"{:?}",
+						found.collect::<Vec<_>>()
+					);
+					assert_eq!(matcher.order(matches, |i| items[i]), whole);
+				}
+			}
+		}
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_of_substrings() {
+		let matcher = matcher(Mode::Substring);
+		assert_eq!(matcher.ranges("xfoofoo", "foo"), [1..4]);
+		assert!(matcher.ranges("foo", "bar").is_empty());
+		assert!(matcher.ranges("foo", "").is_empty());
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_of_prefixes_and_exact_matches() {
+		assert_eq!(matcher(Mode::Prefix).ranges("foobar", "foo"), [0..3]);
+		assert_eq!(matcher(Mode::Exact).ranges("foo", "foo"), [0..3]);
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_of_fuzzy_matches_are_the_characters_matched() {
+		let matcher = matcher(Mode::Fuzzy);
+		assert_eq!(matcher.ranges("foo_bar", "fbr"), [0..1, 4..5, 6..7]);
+		assert_eq!(matcher.ranges("foobar", "oba"), [2..5]);
+		// The characters are those of the tightest window, rather than the
+		// first found.
+		assert_eq!(matcher.ranges("a_ab_b", "ab"), [2..4]);
+	}
+
+	#[test]
+	fn ranges_of_regular_expressions() {
+		let matcher = matcher(Mode::Regex);
+		assert_eq!(matcher.ranges("a1b22c", "[0-9]+"), [1..2, 3..5]);
+		assert!(matcher.ranges("abc", "x*").is_empty());
+		assert!(matcher.ranges("abc", "(").is_empty());
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_are_of_bytes() {
+		let matcher = matcher(Mode::Fuzzy);
+		assert_eq!(matcher.ranges("äöü", "äü"), [0..2, 4..6]);
+		let icase = Matcher { icase: true, ..matcher };
+		assert_eq!(icase.ranges("ÄÖÜ", "ö"), [2..4]);
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_take_in_marks_left_out() {
+		let matcher = Matcher { normalize: true, ..matcher(Mode::Substring) };
+		// The accent follows the e it is on, and is marked with it.
+		assert_eq!(matcher.ranges("cafe\u{301}s", "es"), [3..7]);
+		assert_eq!(matcher.ranges("café", "cafe"), [0..5]);
+	}
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn ranges_of_tokens_are_joined() {
+		let matcher = Matcher { tokens: true, ..matcher(Mode::Substring) };
+		assert_eq!(matcher.ranges("bar foo", "foo bar"), [0..3, 4..7]);
+		assert_eq!(matcher.ranges("foobar", "foo ob bar"), [0..6]);
+	}
+
+	#[test]
+	fn tokens_match_in_any_order() {
+		let items = ["bar and foo", "foo bar", "foobar", "foo", "bar"];
+		for mode in [Mode::Substring, Mode::Fuzzy] {
+			let tokens = Matcher { tokens: true, ..matcher(mode) };
+			let mut found = tokens.filter(items, "foo bar");
+			found.sort();
+			assert_eq!(found, [0, 1, 2]);
+		}
+	}
+
+	#[test]
+	fn queries_are_matched_whole_without_tokens() {
+		let items = ["bar and foo", "foo bar", "foobar"];
+		let literal = matcher(Mode::Substring);
+		assert_eq!(literal.filter(items, "foo bar"), [1]);
+	}
+
+	#[test]
+	fn extra_spaces_are_no_tokens() {
+		let items = ["bar foo", "baz"];
+		let tokens = Matcher { tokens: true, ..matcher(Mode::Substring) };
+		assert_eq!(tokens.filter(items, "  foo   bar "), [0]);
+		assert_eq!(tokens.filter(items, "   "), [0, 1]);
+	}
+
+	#[test]
+	fn only_substrings_and_fuzzy_matches_split_tokens() {
+		let items = ["foo bar", "bar foo"];
+		for mode in [Mode::Prefix, Mode::Exact] {
+			let tokens = Matcher { tokens: true, ..matcher(mode) };
+			assert_eq!(tokens.filter(items, "foo bar"), [0]);
+		}
+	}
+
+	#[test]
+	fn ranges_cover_every_token() {
+		let tokens = Matcher { tokens: true, ..matcher(Mode::Substring) };
+		let ranges = tokens.ranges("bar...foo", "foo bar");
+		assert_eq!(ranges, [0..3, 6..9]);
+	}
+
+	#[test]
+	fn unsorted_matches_keep_input_order() {
+		let items = ["fxoxo", "food", "xfoo", "foo", "of"];
+		for mode in Mode::ALL {
+			let unsorted = Matcher { sort: Sort::None, ..matcher(mode) };
+			let found = unsorted.filter(items, "foo");
+			assert!(found.is_sorted(), "{:?}", found);
+		}
+		let fuzzy = matcher(Mode::Fuzzy);
+		assert_eq!(fuzzy.filter(items, "foo"), [3, 1, 0, 2]);
+		let unsorted = Matcher { sort: Sort::None, ..fuzzy };
+		assert_eq!(unsorted.filter(items, "foo"), [0, 1, 2, 3]);
+	}
+
+	/// Matches the first `len` of `items` for `query` among the candidates
+	/// `prior` gives, and records them.
+	fn narrowed(
+		prior: &mut Prior,
+		matcher: &Matcher,
+		items: &[&str],
+		len: usize,
+		query: &str,
+	) -> Vec<usize> {
+		let candidates = prior.candidates(matcher, query, len);
+		let keys = candidates.into_iter().map(|i| (i, items[i]));
+		let matches = matcher.matches(keys, query);
+		let matches = matcher.order(matches, |i| items[i]);
+		prior.record(matcher, query, len, &matches);
+		matches
+	}
+
+	#[test]
+	fn narrowing_matches_as_a_full_rescan_does() {
+		let items = ["foo", "Fob", "afoxo", "bar", "fo o", "ofo", "xfoob"];
+		let queries = ["f", "fo", "foo", "fo", "", "o", "ob", "fob", "fx"];
+		let modes = [Mode::Substring, Mode::Fuzzy, Mode::Prefix, Mode::Exact];
+		for mode in modes.into_iter().chain([Mode::Regex]) {
+			for sort in [Sort::Score, Sort::None, Sort::Alpha] {
+				let matcher = Matcher { sort, ..matcher(mode) };
+				let mut prior = Prior::default();
+				for query in queries {
+					let len = items.len();
+					assert_eq!(
+						narrowed(&mut prior, &matcher, &items, len, query),
+						matcher.filter(items, query),
+						"for {:?}",
+						query
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn typing_on_rules_out_the_last_non_matches() {
+		let items = ["foo", "bar", "food", "fob"];
+		let matcher = matcher(Mode::Substring);
+		let mut prior = Prior::default();
+		assert_eq!(prior.candidates(&matcher, "fo", 4), [0, 1, 2, 3]);
+		narrowed(&mut prior, &matcher, &items, 4, "fo");
+		assert_eq!(prior.candidates(&matcher, "foo", 4), [0, 2, 3]);
+		// Items read since have not been ruled out.
+		assert_eq!(prior.candidates(&matcher, "foo", 6), [0, 2, 3, 4, 5]);
+		assert_eq!(prior.candidates(&matcher, "f", 4), [0, 1, 2, 3]);
+		let icase = Matcher { icase: true, ..matcher };
+		assert_eq!(prior.candidates(&icase, "foo", 4), [0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn exact_and_regex_queries_are_matched_afresh() {
+		let items = ["foo", "bar"];
+		for mode in [Mode::Exact, Mode::Regex] {
+			let matcher = matcher(mode);
+			let mut prior = Prior::default();
+			narrowed(&mut prior, &matcher, &items, 2, "fo");
+			assert_eq!(prior.candidates(&matcher, "foo", 2), [0, 1]);
+		}
+	}
+}
@@ -0,0 +1,301 @@
+//! The programs in `$PATH`, as `wmenu_path` lists them for launchers.
+//!
+//! Reading every directory of a long `$PATH` is slow from a cold cache on a
+//! spinning disk or a network home, so what each held is kept in a cache,
+//! along with the time the directory was last modified. A directory is only
+//! read again once that time has changed, as it does whenever a file is
+//! added to it, removed or renamed. A program made executable in place
+//! leaves the time as it was, and is listed once anything else changes.
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	env,
+	ffi::OsStr,
+	fs,
+	io::{self, BufRead, BufReader, BufWriter, Write},
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+	process,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use stest_core::FileTest;
+
+/// The first line of the cache, which a cache of another format lacks, and
+/// is read again from scratch.
+const HEADER: &str = "wmenu_path 1";
+
+/// How recently a directory may have been modified for its listing to be
+/// kept. Another change within the resolution of its time could modify it
+/// again without the time changing.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// What a directory of `$PATH` held when it was last read.
+struct Listing {
+	/// When the directory had last been modified, as seconds and
+	/// nanoseconds since the epoch.
+	modified: (u64, u32),
+	names: Vec<String>,
+}
+
+/// Where programs are cached: `wmenu_path` in `$XDG_CACHE_HOME`, or in
+/// `~/.cache`.
+pub fn cache_path() -> Option<PathBuf> {
+	let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+	let dir = var("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.or_else(|| Some(PathBuf::from(var("HOME")?).join(".cache")))?;
+	Some(dir.join("wmenu_path"))
+}
+
+/// The names of the programs in the directories of `path`, as in
+/// `$PATH`, sorted and each given once. Directories unchanged since they
+/// were cached at `cache` are not read again, and the cache is brought up
+/// to date with those which are.
+pub fn commands(path: &OsStr, cache: Option<&Path>) -> Vec<String> {
+	let mut cached = cache.map(load).unwrap_or_default();
+	let mut listings = Vec::new();
+	let mut stale = false;
+	for dir in env::split_paths(path) {
+		if listings.iter().any(|(seen, _)| *seen == dir) {
+			continue;
+		}
+		let Some(modified) = modified(&dir) else {
+			stale |= cached.contains_key(&dir);
+			continue;
+		};
+		let listing = match cached.remove(&dir) {
+			Some(listing) if listing.modified == modified => listing,
+			_ => {
+				stale = true;
+				Listing { modified, names: scan(&dir) }
+			}
+		};
+		listings.push((dir, listing));
+	}
+	// Directories no longer in `$PATH` are dropped from the cache.
+	stale |= !cached.is_empty();
+	if stale
+		&& let Some(cache) = cache
+		&& let Err(err) = save(cache, &listings)
+	{
+		eprintln!("cannot write {}: {}", cache.display(), err);
+	}
+	let names = listings.iter().flat_map(|(_, listing)| &listing.names);
+	let names: BTreeSet<_> = names.collect();
+	names.into_iter().cloned().collect()
+}
+
+/// When `dir` was last modified, if it can be read.
+fn modified(dir: &Path) -> Option<(u64, u32)> {
+	let since = fs::metadata(dir).ok()?.modified().ok()?;
+	let since = since.duration_since(UNIX_EPOCH).ok()?;
+	Some((since.as_secs(), since.subsec_nanos()))
+}
+
+/// The names of the programs in `dir`. Those with a newline in them could
+/// not be listed one to a line, and those which are not UTF-8 could not be
+/// run as listed, so are left out.
+fn scan(dir: &Path) -> Vec<String> {
+	let tests = FileTest::new().file().executable();
+	let Ok(children) = fs::read_dir(dir) else {
+		return Vec::new();
+	};
+	let mut names = Vec::new();
+	for child in children.flatten() {
+		let Ok(name) = child.file_name().into_string() else {
+			continue;
+		};
+		if !name.contains('\n') && tests.test(child.path()) {
+			names.push(name);
+		}
+	}
+	names
+}
+
+/// The listings in the cache at `cache`, by directory, or none if it
+/// cannot be read or is of another format. Each directory is on a line of
+/// its own, after the time it was modified, followed by its programs each
+/// on a line begun with a tab.
+fn load(cache: &Path) -> HashMap<PathBuf, Listing> {
+	let mut listings = HashMap::new();
+	let Ok(file) = fs::File::open(cache) else {
+		return listings;
+	};
+	let mut lines = BufReader::new(file).split(b'\n');
+	if !lines.next().is_some_and(|l| l.is_ok_and(|l| l == HEADER.as_bytes())) {
+		return listings;
+	}
+	let mut current: Option<(PathBuf, Listing)> = None;
+	for line in lines {
+		let Ok(line) = line else {
+			return HashMap::new();
+		};
+		if let Some(name) = line.strip_prefix(b"\t") {
+			if let Some((_, listing)) = &mut current {
+				listing.names.push(String::from_utf8_lossy(name).into_owned());
+			}
+			continue;
+		}
+		let Some(dir) = directory(&line) else {
+			return HashMap::new();
+		};
+		listings.extend(current.replace(dir));
+	}
+	listings.extend(current);
+	listings
+}
+
+/// The directory a line of the cache names, as in `1700000000 5 /usr/bin`,
+/// with none of its programs yet.
+fn directory(line: &[u8]) -> Option<(PathBuf, Listing)> {
+	let mut fields = line.splitn(3, |&b| b == b' ');
+	let mut number = || str::from_utf8(fields.next()?).ok()?.parse().ok();
+	let modified = (number()?, number()? as u32);
+	let path = PathBuf::from(OsStr::from_bytes(fields.next()?));
+	Some((path, Listing { modified, names: Vec::new() }))
+}
+
+/// Writes `listings` to the cache at `cache`, in place of what it held.
+/// Directories modified too recently to be sure of are left out, so that
+/// they are read again next time.
+fn save(cache: &Path, listings: &[(PathBuf, Listing)]) -> io::Result<()> {
+	if let Some(parent) = cache.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	// The cache is replaced whole, so that it is never read half written,
+	// from a file of each process's own, so that launchers started together
+	// do not write over each other's.
+	let mut tmp = cache.as_os_str().to_owned();
+	tmp.push(format!(".{}.tmp", process::id()));
+	let tmp = PathBuf::from(tmp);
+	let result = (|| {
+		let mut out = BufWriter::new(fs::File::create(&tmp)?);
+		writeln!(out, "{}", HEADER)?;
+		let now =
+			SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+		for (dir, listing) in listings {
+			let (secs, nanos) = listing.modified;
+			let modified = Duration::new(secs, nanos);
+			let path = dir.as_os_str().as_bytes();
+			if now.saturating_sub(modified) < SETTLE || path.contains(&b'\n') {
+				continue;
+			}
+			write!(out, "{} {} ", secs, nanos)?;
+			out.write_all(path)?;
+			writeln!(out)?;
+			for name in &listing.names {
+				writeln!(out, "\t{}", name)?;
+			}
+		}
+		out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+		fs::rename(&tmp, cache)
+	})();
+	if result.is_err() {
+		let _ = fs::remove_file(&tmp);
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		os::unix::fs::PermissionsExt,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+
+	use super::*;
+
+	/// A directory of its own, with `bin` in it holding the programs
+	/// `names`, modified long enough ago to be cached.
+	fn dir(names: &[&str]) -> PathBuf {
+		static N: AtomicUsize = AtomicUsize::new(0);
+		let n = N.fetch_add(1, Ordering::Relaxed);
+		let dir = format!("wmenu-path-{}-{}", process::id(), n);
+		let dir = env::temp_dir().join(dir);
+		let bin = dir.join("bin");
+		fs::create_dir_all(&bin).unwrap();
+		for name in names {
+			program(&bin.join(name));
+		}
+		settle(&bin, 60);
+		dir
+	}
+
+	fn program(path: &Path) {
+		fs::write(path, "").unwrap();
+		fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+	}
+
+	/// Sets when `dir` was modified to `secs` seconds ago.
+	fn settle(dir: &Path, secs: u64) {
+		let time = SystemTime::now() - Duration::from_secs(secs);
+		fs::File::open(dir).unwrap().set_modified(time).unwrap();
+	}
+
+	fn listed(dir: &Path) -> Vec<String> {
+		commands(dir.join("bin").as_os_str(), Some(&dir.join("cache")))
+	}
+
+	#[test]
+	fn cached_directories_are_not_read_again() {
+		let dir = dir(&["foo", "bar"]);
+		assert_eq!(listed(&dir), ["bar", "foo"]);
+		let cache = fs::read_to_string(dir.join("cache")).unwrap();
+		assert!(cache.starts_with(&format!("{}\n", HEADER)));
+		assert!(cache.contains("\n\tfoo\n") && cache.contains("\n\tbar\n"));
+		// What the cache holds is listed while the directory is unchanged,
+		// rather than what the directory does.
+		let cache = cache.replace("\tbar\n", "\tbaz\n");
+		fs::write(dir.join("cache"), cache).unwrap();
+		assert_eq!(listed(&dir), ["baz", "foo"]);
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn directories_modified_since_are_read_again() {
+		let dir = dir(&["foo"]);
+		assert_eq!(listed(&dir), ["foo"]);
+		program(&dir.join("bin/bar"));
+		settle(&dir.join("bin"), 30);
+		assert_eq!(listed(&dir), ["bar", "foo"]);
+		let cache = fs::read_to_string(dir.join("cache")).unwrap();
+		assert!(cache.contains("\tbar\n"));
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn directories_modified_just_now_are_not_cached() {
+		let dir = dir(&["foo"]);
+		settle(&dir.join("bin"), 0);
+		assert_eq!(listed(&dir), ["foo"]);
+		let cache = fs::read_to_string(dir.join("cache")).unwrap();
+		assert_eq!(cache, format!("{}\n", HEADER));
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn caches_of_another_format_are_read_from_scratch() {
+		let dir = dir(&["foo"]);
+		let bin = dir.join("bin");
+		let (secs, nanos) = modified(&bin).unwrap();
+		let listing = format!("{} {} {}\n\tbaz\n", secs, nanos, bin.display());
+		fs::write(dir.join("cache"), format!("wmenu_path 0\n{}", listing))
+			.unwrap();
+		assert_eq!(listed(&dir), ["foo"]);
+		// The cache is written again in this format.
+		let cache = fs::read_to_string(dir.join("cache")).unwrap();
+		assert!(cache.starts_with(&format!("{}\n", HEADER)));
+		assert!(!cache.contains("baz"));
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn names_which_are_not_utf8_are_left_out() {
+		let dir = dir(&["foo"]);
+		let name = OsStr::from_bytes(b"b\xffr");
+		program(&dir.join("bin").join(name));
+		assert_eq!(scan(&dir.join("bin")), ["foo"]);
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
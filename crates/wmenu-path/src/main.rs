@@ -0,0 +1,27 @@
+//! Prints the names of the programs in `$PATH`, one to a line and sorted,
+//! as `dmenu_path` does, reading only the directories changed since they
+//! were cached.
+
+use std::{
+	env,
+	io::{self, BufWriter, Write},
+	process::exit,
+};
+
+fn main() {
+	if env::args().len() > 1 {
+		eprintln!("usage: wmenu_path");
+		exit(1);
+	}
+	let path = env::var_os("PATH").unwrap_or_default();
+	let cache = wmenu_path::cache_path();
+	let mut out = BufWriter::new(io::stdout().lock());
+	for name in wmenu_path::commands(&path, cache.as_deref()) {
+		if writeln!(out, "{}", name).is_err() {
+			exit(1);
+		}
+	}
+	if out.flush().is_err() {
+		exit(1);
+	}
+}